@@ -1,94 +1,790 @@
+use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use std::cell::Cell;
 use std::fs::File;
-use std::io::{self, Read};
-use std::path::{Path, PathBuf};
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Component, Path, PathBuf};
 use tar::Archive as TarArchive;
+use thiserror::Error;
+use tracing::debug;
+use xz2::read::XzDecoder;
 use zip::ZipArchive;
 
+/// Archive formats, recognised by their leading bytes rather than their extension.
+/// Compressed streams (gzip, bzip2, xz, zstd) are expected to hold a tar archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+    TarBz2,
+    TarXz,
+    TarZst,
+    SevenZ,
+    /// Recognised so it is not mistaken for source, but not extracted.
+    Rar,
+}
+
+/// Bytes needed to recognise every format; tar's `ustar` magic sits at offset 257.
+const SNIFF_LEN: usize = 262;
+
+impl ArchiveFormat {
+    /// The format `header` (the start of a file) belongs to.
+    pub fn sniff(header: &[u8]) -> Option<Self> {
+        const MAGIC: &[(&[u8], ArchiveFormat)] = &[
+            (b"PK\x03\x04", ArchiveFormat::Zip),
+            (b"PK\x05\x06", ArchiveFormat::Zip),
+            (b"\x1f\x8b", ArchiveFormat::TarGz),
+            (b"BZh", ArchiveFormat::TarBz2),
+            (b"\xfd7zXZ\x00", ArchiveFormat::TarXz),
+            (b"\x28\xb5\x2f\xfd", ArchiveFormat::TarZst),
+            (b"7z\xbc\xaf\x27\x1c", ArchiveFormat::SevenZ),
+            (b"Rar!\x1a\x07", ArchiveFormat::Rar),
+        ];
+
+        MAGIC
+            .iter()
+            .find(|(magic, _)| header.starts_with(magic))
+            .map(|&(_, format)| format)
+            .or_else(|| {
+                header
+                    .get(257..262)
+                    .filter(|magic| *magic == b"ustar")
+                    .map(|_| ArchiveFormat::Tar)
+            })
+    }
+
+    /// The format of the file at `path`, if it is an archive.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        File::open(path)
+            .ok()?
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut header)
+            .ok()?;
+        Self::sniff(&header)
+    }
+}
+
 pub fn is_archive(path: &Path) -> bool {
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    ArchiveFormat::detect(path).is_some()
+}
+
+/// Why an archive could not be extracted safely.
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Unknown archive format")]
+    UnknownFormat,
+
+    #[error("{0} archives are not supported")]
+    Unsupported(&'static str),
+
+    #[error("Entry escapes the extraction directory: {}", .0.display())]
+    UnsafePath(PathBuf),
+
+    #[error("Archive contains a link entry: {}", .0.display())]
+    Link(PathBuf),
+
+    #[error("Archive has more than {limit} entries")]
+    TooManyEntries { limit: usize },
+
+    #[error("Archive expands to more than {limit} bytes")]
+    TooLarge { limit: u64 },
+
+    #[error("Archive expands more than {limit}x its own size; possible decompression bomb")]
+    CompressionRatio { limit: u64 },
+}
 
-    matches!(ext, "zip" | "tar" | "gz" | "tgz") || name.ends_with(".tar.gz")
+impl From<ArchiveError> for io::Error {
+    fn from(err: ArchiveError) -> Self {
+        match err {
+            ArchiveError::Io(err) => err,
+            ArchiveError::Unsupported(_) => io::Error::new(io::ErrorKind::Unsupported, err),
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
 }
 
-pub fn extract_archive(path: &Path, dest: &Path) -> io::Result<Vec<PathBuf>> {
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+/// What to do with symbolic and hard link entries. Links are never recreated on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LinkPolicy {
+    /// Leave links out of the extracted tree.
+    #[default]
+    Skip,
+    /// Fail with [`ArchiveError::Link`].
+    Reject,
+}
 
-    if ext == "zip" {
-        extract_zip(path, dest)
-    } else if ext == "tgz" || name.ends_with(".tar.gz") {
-        extract_tar_gz(path, dest)
-    } else if ext == "tar" {
-        extract_tar(path, dest)
-    } else if ext == "gz" {
-        extract_tar_gz(path, dest)
-    } else {
-        Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Unknown archive format",
-        ))
+/// Bounds on what extracting one archive may write.
+#[derive(Debug, Clone)]
+pub struct ExtractLimits {
+    /// Most entries (files, directories and links) the archive may hold.
+    pub max_entries: usize,
+    /// Most bytes all extracted files may add up to.
+    pub max_bytes: u64,
+    /// Largest ratio of extracted bytes to archive size, checked once more than
+    /// [`RATIO_GRACE_BYTES`] have been written.
+    pub max_ratio: u64,
+    /// Most bytes [`read_tar_stream`] holds in memory for one file. Larger entries are passed
+    /// on cut to this size plus one byte, so the caller can tell they are too large.
+    pub max_file_bytes: Option<u64>,
+    pub links: LinkPolicy,
+}
+
+/// Output below this size is never treated as a decompression bomb: small archives of tar
+/// headers and short files legitimately compress far beyond any sensible ratio.
+pub const RATIO_GRACE_BYTES: u64 = 1024 * 1024;
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 100_000,
+            max_bytes: 4 * 1024 * 1024 * 1024,
+            max_ratio: 200,
+            max_file_bytes: None,
+            links: LinkPolicy::Skip,
+        }
     }
 }
 
-fn extract_zip(path: &Path, dest: &Path) -> io::Result<Vec<PathBuf>> {
-    let file = File::open(path)?;
-    let mut archive = ZipArchive::new(file)?;
-    let mut extracted = Vec::new();
+/// Running totals for one extraction, checked against its [`ExtractLimits`].
+struct Extraction<'a> {
+    limits: &'a ExtractLimits,
+    dest: &'a Path,
+    archive_len: u64,
+    entries: usize,
+    bytes: u64,
+    extracted: Vec<PathBuf>,
+}
+
+impl<'a> Extraction<'a> {
+    fn new(path: &Path, dest: &'a Path, limits: &'a ExtractLimits) -> Result<Self, ArchiveError> {
+        Ok(Self {
+            limits,
+            dest,
+            archive_len: std::fs::metadata(path)?.len().max(1),
+            entries: 0,
+            bytes: 0,
+            extracted: Vec::new(),
+        })
+    }
 
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = match file.enclosed_name() {
-            Some(path) => dest.join(path),
-            None => continue,
+    /// Count one entry named `name` and return where it belongs below `dest`.
+    fn entry(&mut self, name: &Path) -> Result<PathBuf, ArchiveError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(ArchiveError::TooManyEntries {
+                limit: self.limits.max_entries,
+            });
+        }
+        enclosed_path(self.dest, name)
+    }
+
+    fn link(&mut self, name: &Path) -> Result<(), ArchiveError> {
+        self.entry(name)?;
+        match self.limits.links {
+            LinkPolicy::Skip => {
+                debug!(entry = %name.display(), "skipping link");
+                Ok(())
+            }
+            LinkPolicy::Reject => Err(ArchiveError::Link(name.to_path_buf())),
+        }
+    }
+
+    fn dir(&mut self, name: &Path) -> Result<(), ArchiveError> {
+        let outpath = self.entry(name)?;
+        std::fs::create_dir_all(outpath)?;
+        Ok(())
+    }
+
+    /// Write `reader` to the entry named `name`, stopping as soon as a limit is crossed
+    /// rather than trusting the size the archive declares.
+    fn file(&mut self, name: &Path, reader: &mut dyn Read) -> Result<(), ArchiveError> {
+        let outpath = self.entry(name)?;
+        if let Some(parent) = outpath.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut outfile = File::create(&outpath)?;
+        self.extracted.push(outpath);
+        let archive_len = self.archive_len;
+        copy_limited(reader, &mut outfile, &mut self.bytes, self.limits, &|| {
+            archive_len
+        })?;
+        Ok(())
+    }
+}
+
+/// Copy `reader` to `writer`, adding to the running total `bytes` and failing before any
+/// chunk that takes it past `limits.max_bytes`, or past `limits.max_ratio` times
+/// `archive_len()` once more than [`RATIO_GRACE_BYTES`] have been read.
+fn copy_limited(
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    bytes: &mut u64,
+    limits: &ExtractLimits,
+    archive_len: &dyn Fn() -> u64,
+) -> Result<(), ArchiveError> {
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
         };
+        *bytes += n as u64;
+        if *bytes > limits.max_bytes {
+            return Err(ArchiveError::TooLarge {
+                limit: limits.max_bytes,
+            });
+        }
+        if *bytes > RATIO_GRACE_BYTES && *bytes / archive_len().max(1) > limits.max_ratio {
+            return Err(ArchiveError::CompressionRatio {
+                limit: limits.max_ratio,
+            });
+        }
+        writer.write_all(&buf[..n])?;
+    }
+}
 
-        if file.is_dir() {
-            std::fs::create_dir_all(&outpath)?;
-        } else {
-            if let Some(parent) = outpath.parent() {
-                std::fs::create_dir_all(parent)?;
+/// `dest` joined with an entry name, or [`ArchiveError::UnsafePath`] if the name is
+/// absolute or climbs out with `..`.
+fn enclosed_path(dest: &Path, name: &Path) -> Result<PathBuf, ArchiveError> {
+    let mut path = dest.to_path_buf();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(ArchiveError::UnsafePath(name.to_path_buf()));
             }
-            let mut outfile = File::create(&outpath)?;
-            io::copy(&mut file, &mut outfile)?;
-            extracted.push(outpath);
         }
     }
+    Ok(path)
+}
 
-    Ok(extracted)
+/// Extract `path` into `dest` with the default [`ExtractLimits`].
+pub fn extract_archive(path: &Path, dest: &Path) -> Result<Vec<PathBuf>, ArchiveError> {
+    extract_archive_with(path, dest, &ExtractLimits::default())
 }
 
-fn extract_tar(path: &Path, dest: &Path) -> io::Result<Vec<PathBuf>> {
-    let file = File::open(path)?;
-    extract_tar_from_reader(file, dest)
+/// Extract `path` into `dest`, returning the files written. Entries outside `dest` and
+/// archives crossing `limits` fail; files written before the failure are left in place.
+pub fn extract_archive_with(
+    path: &Path,
+    dest: &Path,
+    limits: &ExtractLimits,
+) -> Result<Vec<PathBuf>, ArchiveError> {
+    let _span = tracing::debug_span!("extract", archive = %path.display()).entered();
+    let format = ArchiveFormat::detect(path).ok_or(ArchiveError::UnknownFormat)?;
+    let mut extraction = Extraction::new(path, dest, limits)?;
+    let open = || File::open(path);
+    match format {
+        ArchiveFormat::Zip => extract_zip(open()?, &mut extraction)?,
+        ArchiveFormat::Tar => extract_tar_from_reader(open()?, &mut extraction)?,
+        ArchiveFormat::TarGz => extract_tar_from_reader(GzDecoder::new(open()?), &mut extraction)?,
+        ArchiveFormat::TarBz2 => extract_tar_from_reader(BzDecoder::new(open()?), &mut extraction)?,
+        ArchiveFormat::TarXz => extract_tar_from_reader(XzDecoder::new(open()?), &mut extraction)?,
+        ArchiveFormat::TarZst => {
+            extract_tar_from_reader(zstd::stream::read::Decoder::new(open()?)?, &mut extraction)?
+        }
+        ArchiveFormat::SevenZ => extract_7z(path, &mut extraction)?,
+        ArchiveFormat::Rar => return Err(ArchiveError::Unsupported("RAR")),
+    }
+    debug!(
+        ?format,
+        files = extraction.extracted.len(),
+        bytes = extraction.bytes,
+        "extracted archive"
+    );
+    Ok(extraction.extracted)
 }
 
-fn extract_tar_gz(path: &Path, dest: &Path) -> io::Result<Vec<PathBuf>> {
-    let file = File::open(path)?;
-    let decoder = GzDecoder::new(file);
-    extract_tar_from_reader(decoder, dest)
+/// Extract `path` into `dest` and return the directory holding its contents: `dest`, or the
+/// single top-level directory every entry lives in (`project-1.2/` in a release tarball).
+pub fn extract_archive_root(path: &Path, dest: &Path) -> Result<PathBuf, ArchiveError> {
+    extract_archive(path, dest)?;
+    let mut entries = std::fs::read_dir(dest)?.collect::<io::Result<Vec<_>>>()?;
+    match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => Ok(entries.remove(0).path()),
+        _ => Ok(dest.to_path_buf()),
+    }
 }
 
-fn extract_tar_from_reader<R: Read>(reader: R, dest: &Path) -> io::Result<Vec<PathBuf>> {
+/// Read a tar archive, plain or compressed, from `reader` without writing anything to disk,
+/// calling `visit` with the name and contents of each regular file until it breaks.
+///
+/// Entry and byte counts and the expansion ratio are bounded by `limits` as for
+/// [`extract_archive_with`], the ratio measured against the bytes read from `reader` so far.
+/// Links are skipped or rejected. Zip and 7z archives cannot be read as a stream.
+pub fn read_tar_stream(
+    mut reader: impl Read,
+    limits: &ExtractLimits,
+    mut visit: impl FnMut(&Path, Vec<u8>) -> ControlFlow<()>,
+) -> Result<(), ArchiveError> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    reader
+        .by_ref()
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)?;
+    let format = ArchiveFormat::sniff(&header).ok_or(ArchiveError::UnknownFormat)?;
+    let read = Cell::new(0);
+    let stream = CountingReader {
+        inner: io::Cursor::new(header).chain(reader),
+        count: &read,
+    };
+    let visit = &mut visit;
+    let stream_len = &|| read.get();
+    match format {
+        ArchiveFormat::Tar => visit_tar(stream, limits, stream_len, visit),
+        ArchiveFormat::TarGz => visit_tar(GzDecoder::new(stream), limits, stream_len, visit),
+        ArchiveFormat::TarBz2 => visit_tar(BzDecoder::new(stream), limits, stream_len, visit),
+        ArchiveFormat::TarXz => visit_tar(XzDecoder::new(stream), limits, stream_len, visit),
+        ArchiveFormat::TarZst => visit_tar(
+            zstd::stream::read::Decoder::new(stream)?,
+            limits,
+            stream_len,
+            visit,
+        ),
+        ArchiveFormat::Zip => Err(ArchiveError::Unsupported("Streamed zip")),
+        ArchiveFormat::SevenZ => Err(ArchiveError::Unsupported("Streamed 7z")),
+        ArchiveFormat::Rar => Err(ArchiveError::Unsupported("RAR")),
+    }
+}
+
+/// Counts the bytes read through it, for the ratio check of a streamed archive.
+struct CountingReader<'a, R> {
+    inner: R,
+    count: &'a Cell<u64>,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+fn visit_tar(
+    reader: impl Read,
+    limits: &ExtractLimits,
+    stream_len: &dyn Fn() -> u64,
+    visit: &mut dyn FnMut(&Path, Vec<u8>) -> ControlFlow<()>,
+) -> Result<(), ArchiveError> {
     let mut archive = TarArchive::new(reader);
-    let mut extracted = Vec::new();
+    let (mut entries, mut bytes) = (0, 0);
 
     for entry in archive.entries()? {
         let mut entry = entry?;
-        let path = entry.path()?;
-        let outpath = dest.join(&path);
-
-        if entry.header().entry_type().is_dir() {
-            std::fs::create_dir_all(&outpath)?;
-        } else if entry.header().entry_type().is_file() {
-            if let Some(parent) = outpath.parent() {
-                std::fs::create_dir_all(parent)?;
+        entries += 1;
+        if entries > limits.max_entries {
+            return Err(ArchiveError::TooManyEntries {
+                limit: limits.max_entries,
+            });
+        }
+        let name = entry.path()?.into_owned();
+        let entry_type = entry.header().entry_type();
+
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            if limits.links == LinkPolicy::Reject {
+                return Err(ArchiveError::Link(name));
+            }
+            debug!(entry = %name.display(), "skipping link");
+        } else if entry_type.is_file() {
+            let mut content = Vec::new();
+            let cap = limits
+                .max_file_bytes
+                .map_or(u64::MAX, |max| max.saturating_add(1));
+            copy_limited(
+                &mut (&mut entry).take(cap),
+                &mut content,
+                &mut bytes,
+                limits,
+                stream_len,
+            )?;
+            if visit(&name, content).is_break() {
+                break;
             }
-            entry.unpack(&outpath)?;
-            extracted.push(outpath);
         }
     }
 
-    Ok(extracted)
+    Ok(())
+}
+
+fn extract_zip(file: File, extraction: &mut Extraction) -> Result<(), ArchiveError> {
+    let mut archive = ZipArchive::new(file).map_err(io::Error::from)?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(io::Error::from)?;
+        let name = PathBuf::from(file.name());
+        if file.is_symlink() {
+            extraction.link(&name)?;
+        } else if file.is_dir() {
+            extraction.dir(&name)?;
+        } else {
+            extraction.file(&name, &mut file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Unix file type bits 7z keeps in the high half of an entry's attributes.
+const SEVENZ_UNIX_EXTENSION: u32 = 0x8000;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+fn extract_7z(path: &Path, extraction: &mut Extraction) -> Result<(), ArchiveError> {
+    let sevenz_err =
+        |e: sevenz_rust::Error| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let mut reader = SevenZReader::open(path, Password::empty()).map_err(sevenz_err)?;
+
+    // The callback can only fail with the library's error type, so keep ours aside.
+    let mut failure = None;
+    let result = reader.for_each_entries(|entry, reader| {
+        let name = PathBuf::from(entry.name());
+        let attributes = entry.windows_attributes();
+        let is_link =
+            attributes & SEVENZ_UNIX_EXTENSION != 0 && (attributes >> 16) & S_IFMT == S_IFLNK;
+        let step = if is_link {
+            extraction.link(&name)
+        } else if entry.is_directory() {
+            extraction.dir(&name)
+        } else {
+            extraction.file(&name, reader)
+        };
+        match step {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                failure = Some(err);
+                Ok(false)
+            }
+        }
+    });
+
+    match failure {
+        Some(err) => Err(err),
+        None => result.map_err(|e| sevenz_err(e).into()),
+    }
+}
+
+fn extract_tar_from_reader<R: Read>(
+    reader: R,
+    extraction: &mut Extraction,
+) -> Result<(), ArchiveError> {
+    let mut archive = TarArchive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let entry_type = entry.header().entry_type();
+
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            extraction.link(&name)?;
+        } else if entry_type.is_dir() {
+            extraction.dir(&name)?;
+        } else if entry_type.is_file() {
+            extraction.file(&name, &mut entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn tar_bytes() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let content = b"fn main() {}\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "app/main.rs", &content[..])
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_sniff_magic_bytes() {
+        assert_eq!(
+            ArchiveFormat::sniff(b"PK\x03\x04rest"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(
+            ArchiveFormat::sniff(b"\x1f\x8b\x08"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::sniff(b"BZh91AY"),
+            Some(ArchiveFormat::TarBz2)
+        );
+        assert_eq!(
+            ArchiveFormat::sniff(b"7z\xbc\xaf\x27\x1c\x00"),
+            Some(ArchiveFormat::SevenZ)
+        );
+        assert_eq!(
+            ArchiveFormat::sniff(b"Rar!\x1a\x07\x00"),
+            Some(ArchiveFormat::Rar)
+        );
+        assert_eq!(ArchiveFormat::sniff(&tar_bytes()), Some(ArchiveFormat::Tar));
+        assert_eq!(ArchiveFormat::sniff(b"fn main() {}\n"), None);
+    }
+
+    #[test]
+    fn test_extract_mislabeled_archives() {
+        let dir = TempDir::new().unwrap();
+        let tar = tar_bytes();
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&tar).unwrap();
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(&tar).unwrap();
+        let archives = [
+            ("release.zip", xz.finish().unwrap()),
+            ("release.tar.gz", zstd::encode_all(&tar[..], 0).unwrap()),
+            ("release.bin", bz.finish().unwrap()),
+        ];
+
+        for (name, bytes) in archives {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            assert!(is_archive(&path));
+
+            let dest = dir.path().join(format!("{name}.out"));
+            let extracted = extract_archive(&path, &dest).unwrap();
+            assert_eq!(extracted, vec![dest.join("app/main.rs")], "{name}");
+            assert_eq!(
+                extract_archive_root(&path, &dest).unwrap(),
+                dest.join("app")
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_tar_stream() {
+        use tar::EntryType::{Regular, Symlink};
+        let tar = raw_tar(&[
+            ("app/main.rs", Regular, b"fn main() {}\n"),
+            ("app/link", Symlink, b""),
+            ("app/lib.rs", Regular, b"pub fn f() {}\n"),
+        ]);
+        let read = |stream: &[u8], limits: &ExtractLimits, stop_after: usize| {
+            let mut files = Vec::new();
+            let result = read_tar_stream(stream, limits, |name, content| {
+                files.push((name.to_path_buf(), content));
+                if files.len() == stop_after {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+            (result, files)
+        };
+
+        let gz = {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+            encoder.write_all(&tar).unwrap();
+            encoder.finish().unwrap()
+        };
+        for stream in [&tar, &gz] {
+            let (result, files) = read(stream, &ExtractLimits::default(), 0);
+            result.unwrap();
+            assert_eq!(
+                files,
+                [
+                    (PathBuf::from("app/main.rs"), b"fn main() {}\n".to_vec()),
+                    (PathBuf::from("app/lib.rs"), b"pub fn f() {}\n".to_vec()),
+                ]
+            );
+        }
+
+        let (result, files) = read(&tar, &ExtractLimits::default(), 1);
+        assert!(result.is_ok() && files.len() == 1);
+
+        let limits = ExtractLimits {
+            links: LinkPolicy::Reject,
+            ..ExtractLimits::default()
+        };
+        assert!(matches!(
+            read(&tar, &limits, 0).0,
+            Err(ArchiveError::Link(_))
+        ));
+        let limits = ExtractLimits {
+            max_bytes: 20,
+            ..ExtractLimits::default()
+        };
+        assert!(matches!(
+            read(&tar, &limits, 0).0,
+            Err(ArchiveError::TooLarge { limit: 20 })
+        ));
+        let limits = ExtractLimits {
+            max_file_bytes: Some(5),
+            ..ExtractLimits::default()
+        };
+        let (result, files) = read(&tar, &limits, 0);
+        result.unwrap();
+        assert!(files.iter().all(|(_, content)| content.len() == 6));
+        assert!(matches!(
+            read(b"fn main() {}\n", &ExtractLimits::default(), 0).0,
+            Err(ArchiveError::UnknownFormat)
+        ));
+        assert!(matches!(
+            read(b"PK\x03\x04", &ExtractLimits::default(), 0).0,
+            Err(ArchiveError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_rar_is_unsupported() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("release.rar");
+        std::fs::write(&path, b"Rar!\x1a\x07\x01\x00").unwrap();
+        assert!(is_archive(&path));
+        let err = extract_archive(&path, dir.path()).unwrap_err();
+        assert!(matches!(err, ArchiveError::Unsupported("RAR")));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Unsupported);
+    }
+
+    /// A tar archive holding `entries` of (name, type, content), written without the
+    /// path checks `tar::Builder::append_data` applies.
+    fn raw_tar(entries: &[(&str, tar::EntryType, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for &(name, entry_type, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            if entry_type.is_symlink() {
+                header.set_link_name("/etc/passwd").unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn extract_raw_tar(
+        entries: &[(&str, tar::EntryType, &[u8])],
+        limits: &ExtractLimits,
+    ) -> (TempDir, Result<Vec<PathBuf>, ArchiveError>) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("release.tar");
+        std::fs::write(&path, raw_tar(entries)).unwrap();
+        let result = extract_archive_with(&path, &dir.path().join("out/inner"), limits);
+        (dir, result)
+    }
+
+    #[test]
+    fn test_rejects_entries_outside_dest() {
+        use tar::EntryType::Regular;
+        for name in ["../evil.rs", "app/../../evil.rs", "/tmp/evil.rs"] {
+            let (dir, result) =
+                extract_raw_tar(&[(name, Regular, b"x\n")], &ExtractLimits::default());
+            assert!(
+                matches!(result, Err(ArchiveError::UnsafePath(_))),
+                "{name}: {result:?}"
+            );
+            assert!(!dir.path().join("out/evil.rs").exists());
+        }
+
+        let (dir, result) = extract_raw_tar(
+            &[("./app/./main.rs", Regular, b"x\n")],
+            &ExtractLimits::default(),
+        );
+        assert_eq!(
+            result.unwrap(),
+            vec![dir.path().join("out/inner/app/main.rs")]
+        );
+    }
+
+    #[test]
+    fn test_link_policy() {
+        use tar::EntryType::{Regular, Symlink};
+        let entries: &[(&str, tar::EntryType, &[u8])] =
+            &[("passwd", Symlink, b""), ("main.rs", Regular, b"x\n")];
+
+        let (dir, result) = extract_raw_tar(entries, &ExtractLimits::default());
+        assert_eq!(result.unwrap(), vec![dir.path().join("out/inner/main.rs")]);
+        assert!(!dir.path().join("out/inner/passwd").exists());
+
+        let limits = ExtractLimits {
+            links: LinkPolicy::Reject,
+            ..ExtractLimits::default()
+        };
+        let (_dir, result) = extract_raw_tar(entries, &limits);
+        assert!(matches!(result, Err(ArchiveError::Link(_))));
+    }
+
+    #[test]
+    fn test_entry_and_size_limits() {
+        use tar::EntryType::Regular;
+        let entries: &[(&str, tar::EntryType, &[u8])] = &[
+            ("a.rs", Regular, b"0123456789"),
+            ("b.rs", Regular, b"0123456789"),
+        ];
+
+        let limits = ExtractLimits {
+            max_entries: 1,
+            ..ExtractLimits::default()
+        };
+        let (_dir, result) = extract_raw_tar(entries, &limits);
+        assert!(matches!(
+            result,
+            Err(ArchiveError::TooManyEntries { limit: 1 })
+        ));
+
+        let limits = ExtractLimits {
+            max_bytes: 15,
+            ..ExtractLimits::default()
+        };
+        let (dir, result) = extract_raw_tar(entries, &limits);
+        assert!(matches!(result, Err(ArchiveError::TooLarge { limit: 15 })));
+        assert!(
+            std::fs::metadata(dir.path().join("out/inner/b.rs"))
+                .unwrap()
+                .len()
+                <= 6
+        );
+    }
+
+    #[test]
+    fn test_rejects_decompression_bomb() {
+        let zeros = vec![0u8; 4 * RATIO_GRACE_BYTES as usize];
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(zeros.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "zeros.rs", &zeros[..])
+            .unwrap();
+        let bomb = zstd::encode_all(&builder.into_inner().unwrap()[..], 19).unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("bomb.tar.zst");
+        std::fs::write(&path, bomb).unwrap();
+        let result = extract_archive(&path, &dir.path().join("out"));
+        assert!(matches!(result, Err(ArchiveError::CompressionRatio { .. })));
+        // Extraction stops at the first chunk over the ratio, not after the whole entry.
+        let written = std::fs::metadata(dir.path().join("out/zeros.rs"))
+            .unwrap()
+            .len();
+        assert!(written <= RATIO_GRACE_BYTES);
+
+        let bomb = std::fs::read(&path).unwrap();
+        let result = read_tar_stream(&bomb[..], &ExtractLimits::default(), |_, _| {
+            ControlFlow::Continue(())
+        });
+        assert!(matches!(result, Err(ArchiveError::CompressionRatio { .. })));
+    }
 }
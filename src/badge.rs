@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+/// A shields.io endpoint badge.
+///
+/// Serializes to the JSON shape expected by `https://img.shields.io/endpoint?url=...`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Badge {
+    pub schema_version: u8,
+    pub label: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub color: Option<String>,
+}
+
+impl Badge {
+    pub fn new(label: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            schema_version: 1,
+            label: label.into(),
+            message: message.into(),
+            color: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: impl Into<String>) -> Self {
+        self.color = Some(color.into());
+        self
+    }
+
+    /// Badge showing the total lines of code, e.g. `lines of code | 123k`.
+    pub fn lines_of_code(code: u64) -> Self {
+        Self::new("lines of code", format_count(code)).with_color("blue")
+    }
+
+    /// Badge showing the top language and its share of the code, e.g. `top language | Rust 87%`.
+    pub fn top_language(name: &str, code: u64, total_code: u64) -> Self {
+        let message = if total_code == 0 {
+            name.to_string()
+        } else {
+            format!("{} {:.0}%", name, (code as f64 / total_code as f64) * 100.0)
+        };
+        Self::new("top language", message).with_color("blue")
+    }
+}
+
+/// Format a count compactly (`999`, `1.2k`, `123k`, `4.5M`) for badge messages.
+pub fn format_count(n: u64) -> String {
+    const UNITS: [(u64, &str); 3] = [(1_000_000_000, "B"), (1_000_000, "M"), (1_000, "k")];
+
+    for (scale, suffix) in UNITS {
+        if n >= scale {
+            let value = n as f64 / scale as f64;
+            return if value < 10.0 {
+                let formatted = format!("{:.1}", value);
+                format!("{}{}", formatted.trim_end_matches(".0"), suffix)
+            } else {
+                format!("{:.0}{}", value.floor(), suffix)
+            };
+        }
+    }
+
+    n.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_000), "1k");
+        assert_eq!(format_count(1_234), "1.2k");
+        assert_eq!(format_count(123_456), "123k");
+        assert_eq!(format_count(4_500_000), "4.5M");
+    }
+
+    #[test]
+    fn test_badge_json_shape() {
+        let json = serde_json::to_string(&Badge::lines_of_code(123_456)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"schemaVersion":1,"label":"lines of code","message":"123k","color":"blue"}"#
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Repeated, timed scans for `rloc bench`.
+//!
+//! A normal count reads and counts files while the walk is still running, which hides where
+//! the time goes. [`Bench::run`] instead runs the phases one after another so each can be
+//! timed on its own: walking, reading (and hashing for duplicate detection), classifying
+//! lines, and aggregating the summary. Reading and classifying run on a rayon pool of each
+//! thread count being compared; the walk and the aggregation are single-threaded.
+
+use crate::counter::{self, CountConfig};
+use crate::stats::Summary;
+use crate::walker::{self, WalkerConfig};
+use crate::{Error, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Wall time of each phase of one scan, in seconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PhaseTimes {
+    pub walk: f64,
+    pub read: f64,
+    pub classify: f64,
+    pub aggregate: f64,
+}
+
+impl PhaseTimes {
+    pub fn total(&self) -> f64 {
+        self.walk + self.read + self.classify + self.aggregate
+    }
+
+    /// The median of each phase on its own across `runs`.
+    fn median(runs: &[PhaseTimes]) -> PhaseTimes {
+        let phase = |get: fn(&PhaseTimes) -> f64| {
+            let mut times: Vec<f64> = runs.iter().map(get).collect();
+            times.sort_by(f64::total_cmp);
+            match times.len() {
+                0 => 0.0,
+                n if n % 2 == 1 => times[n / 2],
+                n => (times[n / 2 - 1] + times[n / 2]) / 2.0,
+            }
+        };
+        PhaseTimes {
+            walk: phase(|t| t.walk),
+            read: phase(|t| t.read),
+            classify: phase(|t| t.classify),
+            aggregate: phase(|t| t.aggregate),
+        }
+    }
+}
+
+/// The timed runs with one thread count.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadRun {
+    pub threads: usize,
+    /// Median of each phase over [`Self::runs`].
+    pub median: PhaseTimes,
+    /// Bytes read per second of the median total time.
+    pub bytes_per_sec: f64,
+    /// Lines counted per second of the median total time.
+    pub lines_per_sec: f64,
+    pub runs: Vec<PhaseTimes>,
+}
+
+/// Timings for every thread count compared.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    /// Files counted by each scan.
+    pub files: u64,
+    pub bytes: u64,
+    pub lines: u64,
+    pub results: Vec<ThreadRun>,
+}
+
+impl BenchReport {
+    /// The thread count with the lowest median total time.
+    pub fn fastest(&self) -> Option<&ThreadRun> {
+        self.results
+            .iter()
+            .min_by(|a, b| a.median.total().total_cmp(&b.median.total()))
+    }
+}
+
+/// Benchmarks scans under one walker and count configuration.
+pub struct Bench<'a> {
+    walker: &'a WalkerConfig,
+    count: &'a CountConfig,
+    runs: usize,
+    warmup: usize,
+    threads: Vec<usize>,
+}
+
+impl<'a> Bench<'a> {
+    pub fn new(walker: &'a WalkerConfig, count: &'a CountConfig) -> Self {
+        Self {
+            walker,
+            count,
+            runs: 3,
+            warmup: 1,
+            threads: default_thread_counts(num_cpus::get()),
+        }
+    }
+
+    /// Timed scans per thread count (at least one).
+    pub fn runs(mut self, runs: usize) -> Self {
+        self.runs = runs.max(1);
+        self
+    }
+
+    /// Untimed scans before the first thread count, so every count sees a warm file cache.
+    pub fn warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Thread counts to compare, in the order given. Zero means one per CPU.
+    pub fn threads(mut self, threads: Vec<usize>) -> Self {
+        if !threads.is_empty() {
+            self.threads = threads;
+        }
+        self
+    }
+
+    pub fn run(&self) -> Result<BenchReport> {
+        let mut report = BenchReport {
+            files: 0,
+            bytes: 0,
+            lines: 0,
+            results: Vec::with_capacity(self.threads.len()),
+        };
+        for (index, &threads) in self.threads.iter().enumerate() {
+            let threads = if threads == 0 {
+                num_cpus::get()
+            } else {
+                threads
+            };
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .map_err(|e| Error::InvalidConfig(format!("Failed to build thread pool: {}", e)))?;
+            let warmup = if index == 0 { self.warmup } else { 0 };
+            for _ in 0..warmup {
+                pool.install(|| self.scan());
+            }
+
+            let mut runs = Vec::with_capacity(self.runs);
+            for _ in 0..self.runs {
+                let (times, summary) = pool.install(|| self.scan());
+                report.files = summary.total_files;
+                report.bytes = summary.total_bytes;
+                report.lines = summary.total_lines();
+                runs.push(times);
+            }
+            let median = PhaseTimes::median(&runs);
+            let per_sec = |amount: u64| match median.total() {
+                total if total > 0.0 => amount as f64 / total,
+                _ => 0.0,
+            };
+            report.results.push(ThreadRun {
+                threads,
+                median,
+                bytes_per_sec: per_sec(report.bytes),
+                lines_per_sec: per_sec(report.lines),
+                runs,
+            });
+        }
+        Ok(report)
+    }
+
+    /// One scan with each phase finished before the next starts.
+    fn scan(&self) -> (PhaseTimes, Summary) {
+        let start = Instant::now();
+        let files = walker::walk_files(self.walker);
+        let walk = start.elapsed();
+
+        let start = Instant::now();
+        let sources: Vec<_> = files
+            .into_par_iter()
+            .filter_map(|entry| {
+                let source = walker::read_file(self.walker, &entry.path).ok()?;
+                let hash = counter::hash_bytes(&source);
+                Some((entry, source, hash))
+            })
+            .collect();
+        // Deduplicated in walk order, as the first copy found is the one counted.
+        let mut seen = HashSet::new();
+        let sources: Vec<_> = sources
+            .into_iter()
+            .filter(|(_, _, hash)| self.walker.skip_uniqueness || seen.insert(*hash))
+            .collect();
+        let read = start.elapsed();
+
+        let start = Instant::now();
+        let stats: Vec<_> = sources
+            .par_iter()
+            .filter_map(|(entry, source, _)| {
+                counter::count_bytes(&entry.path, source, entry.language, self.count)
+                    .ok()
+                    .filter(|stats| !stats.binary)
+            })
+            .collect();
+        let classify = start.elapsed();
+
+        let start = Instant::now();
+        let summary = Summary::from_file_stats(stats);
+        let aggregate = start.elapsed();
+
+        let times = PhaseTimes {
+            walk: secs(walk),
+            read: secs(read),
+            classify: secs(classify),
+            aggregate: secs(aggregate),
+        };
+        (times, summary)
+    }
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs_f64()
+}
+
+/// 1, 2, 4, ... up to `cpus`, and `cpus` itself.
+fn default_thread_counts(cpus: usize) -> Vec<usize> {
+    let cpus = cpus.max(1);
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |&n| Some(n * 2))
+        .take_while(|&n| n < cpus)
+        .collect();
+    counts.push(cpus);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_default_thread_counts() {
+        assert_eq!(default_thread_counts(1), vec![1]);
+        assert_eq!(default_thread_counts(6), vec![1, 2, 4, 6]);
+        assert_eq!(default_thread_counts(8), vec![1, 2, 4, 8]);
+    }
+
+    #[test]
+    fn test_median() {
+        let run = |walk| PhaseTimes {
+            walk,
+            ..Default::default()
+        };
+        assert_eq!(
+            PhaseTimes::median(&[run(3.0), run(1.0), run(2.0)]).walk,
+            2.0
+        );
+        assert_eq!(PhaseTimes::median(&[run(4.0), run(1.0)]).walk, 2.5);
+    }
+
+    #[test]
+    fn test_bench() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n\n// done\n").unwrap();
+        fs::write(temp.path().join("copy.rs"), "fn main() {}\n\n// done\n").unwrap();
+        fs::write(temp.path().join("app.py"), "x = 1\n").unwrap();
+
+        let walker = WalkerConfig {
+            paths: vec![temp.path().to_path_buf()],
+            ..Default::default()
+        };
+        let count = CountConfig::default();
+        let report = Bench::new(&walker, &count)
+            .runs(2)
+            .warmup(0)
+            .threads(vec![1, 2])
+            .run()
+            .unwrap();
+
+        // The duplicate is counted once, as in a normal scan.
+        assert_eq!((report.files, report.lines), (2, 4));
+        assert_eq!(report.bytes, 28);
+        let threads: Vec<_> = report.results.iter().map(|r| r.threads).collect();
+        assert_eq!(threads, vec![1, 2]);
+        assert!(report.results.iter().all(|r| r.runs.len() == 2));
+        assert!(report.fastest().is_some());
+    }
+}
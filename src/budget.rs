@@ -0,0 +1,273 @@
+//! Line-count budgets for CI gates (`rloc check`, `rloc hook run`).
+
+use crate::stats::Summary;
+use crate::{Error, Result};
+use std::fmt;
+
+/// Limits a [`Summary`] is checked against.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    /// Maximum total lines of code.
+    pub max_code: Option<u64>,
+    /// Maximum lines of code per language (matched case-insensitively).
+    pub max_language: Vec<(String, u64)>,
+    /// Report to compare growth against.
+    pub baseline: Option<Summary>,
+    /// Allowed growth in total code versus the baseline, in percent.
+    pub max_growth_percent: f64,
+    /// Maximum lines of code in any one file.
+    pub max_file_code: Option<u64>,
+    /// Report the added code is measured against (e.g. the staged files as of `HEAD`).
+    pub added_baseline: Option<Summary>,
+    /// Maximum lines of code added versus `added_baseline`.
+    pub max_added: u64,
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_code(mut self, limit: u64) -> Self {
+        self.max_code = Some(limit);
+        self
+    }
+
+    pub fn max_language(mut self, name: impl Into<String>, limit: u64) -> Self {
+        self.max_language.push((name.into(), limit));
+        self
+    }
+
+    pub fn baseline(mut self, baseline: Summary, max_growth_percent: f64) -> Self {
+        self.baseline = Some(baseline);
+        self.max_growth_percent = max_growth_percent;
+        self
+    }
+
+    pub fn max_file_code(mut self, limit: u64) -> Self {
+        self.max_file_code = Some(limit);
+        self
+    }
+
+    pub fn max_added(mut self, baseline: Summary, limit: u64) -> Self {
+        self.added_baseline = Some(baseline);
+        self.max_added = limit;
+        self
+    }
+
+    /// Evaluate every configured limit, in the order they were added.
+    pub fn check(&self, summary: &Summary) -> Vec<BudgetResult> {
+        let mut results = Vec::new();
+
+        if let Some(limit) = self.max_code {
+            results.push(BudgetResult {
+                rule: "total code".to_string(),
+                actual: summary.total_code as f64,
+                limit: limit as f64,
+                percent: false,
+            });
+        }
+
+        for (name, limit) in &self.max_language {
+            let code = summary
+                .languages
+                .iter()
+                .find(|l| l.name.eq_ignore_ascii_case(name))
+                .map_or(0, |l| l.code);
+            results.push(BudgetResult {
+                rule: format!("{} code", name),
+                actual: code as f64,
+                limit: *limit as f64,
+                percent: false,
+            });
+        }
+
+        if let Some(ref baseline) = self.baseline {
+            let growth = if baseline.total_code == 0 {
+                if summary.total_code == 0 {
+                    0.0
+                } else {
+                    f64::INFINITY
+                }
+            } else {
+                (summary.total_code as f64 - baseline.total_code as f64)
+                    / baseline.total_code as f64
+                    * 100.0
+            };
+            results.push(BudgetResult {
+                rule: "code growth".to_string(),
+                actual: growth,
+                limit: self.max_growth_percent,
+                percent: true,
+            });
+        }
+
+        if let Some(limit) = self.max_file_code {
+            let largest = summary.file_stats.iter().max_by_key(|file| file.code);
+            results.push(BudgetResult {
+                rule: match largest {
+                    Some(file) => format!("largest file ({})", file.path),
+                    None => "largest file".to_string(),
+                },
+                actual: largest.map_or(0, |file| file.code) as f64,
+                limit: limit as f64,
+                percent: false,
+            });
+        }
+
+        if let Some(ref baseline) = self.added_baseline {
+            results.push(BudgetResult {
+                rule: "added code".to_string(),
+                actual: summary.total_code as f64 - baseline.total_code as f64,
+                limit: self.max_added as f64,
+                percent: false,
+            });
+        }
+
+        results
+    }
+}
+
+/// `Err(Error::BudgetExceeded)` when any of `results` failed.
+pub fn ensure_passed(results: &[BudgetResult]) -> Result<()> {
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    if failed > 0 {
+        return Err(Error::BudgetExceeded {
+            failed,
+            total: results.len(),
+        });
+    }
+    Ok(())
+}
+
+/// The outcome of one budget rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetResult {
+    pub rule: String,
+    pub actual: f64,
+    pub limit: f64,
+    /// Whether `actual` and `limit` are percentages.
+    pub percent: bool,
+}
+
+impl BudgetResult {
+    pub fn passed(&self) -> bool {
+        self.actual <= self.limit
+    }
+}
+
+impl fmt::Display for BudgetResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed() { "ok" } else { "FAIL" };
+        if self.percent {
+            write!(
+                f,
+                "{:<4} {}: {:+.1}% (limit {:.1}%)",
+                status, self.rule, self.actual, self.limit
+            )
+        } else {
+            write!(
+                f,
+                "{:<4} {}: {} (limit {})",
+                status, self.rule, self.actual, self.limit
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::FileStats;
+
+    fn summary(files: &[(&str, u64)]) -> Summary {
+        Summary::from_file_stats(
+            files
+                .iter()
+                .enumerate()
+                .map(|(i, &(language, code))| FileStats {
+                    path: format!("file{}", i),
+                    language: language.into(),
+                    code,
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_budget_limits() {
+        let current = summary(&[("Rust", 100), ("Generated", 5)]);
+        let results = Budget::new()
+            .max_code(200)
+            .max_language("generated", 0)
+            .max_language("Go", 10)
+            .check(&current);
+
+        let passed: Vec<_> = results
+            .iter()
+            .map(|r| (r.rule.as_str(), r.passed()))
+            .collect();
+        assert_eq!(
+            passed,
+            [
+                ("total code", true),
+                ("generated code", false),
+                ("Go code", true)
+            ]
+        );
+        assert_eq!(results[1].to_string(), "FAIL generated code: 5 (limit 0)");
+
+        let error = ensure_passed(&results).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::BudgetExceeded {
+                failed: 1,
+                total: 3
+            }
+        ));
+        assert_eq!(error.exit_code(), 3);
+        assert!(ensure_passed(&results[..1]).is_ok());
+    }
+
+    #[test]
+    fn test_budget_growth() {
+        let baseline = summary(&[("Rust", 100)]);
+        let current = summary(&[("Rust", 100), ("Rust", 10)]);
+
+        let strict = Budget::new()
+            .baseline(baseline.clone(), 5.0)
+            .check(&current);
+        assert!(!strict[0].passed());
+        assert_eq!(
+            strict[0].to_string(),
+            "FAIL code growth: +10.0% (limit 5.0%)"
+        );
+
+        let loose = Budget::new().baseline(baseline, 10.0).check(&current);
+        assert!(loose[0].passed());
+    }
+
+    #[test]
+    fn test_budget_added_and_file_code() {
+        let head = summary(&[("Rust", 100)]);
+        let staged = summary(&[("Rust", 130), ("Generated", 5000)]);
+
+        let results = Budget::new()
+            .max_file_code(1000)
+            .max_added(head.clone(), 50)
+            .check(&staged);
+        let results: Vec<_> = results.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            results,
+            [
+                "FAIL largest file (file1): 5000 (limit 1000)",
+                "FAIL added code: 5030 (limit 50)",
+            ]
+        );
+
+        // Removing code never fails the budget.
+        let results = Budget::new().max_added(staged, 0).check(&head);
+        assert_eq!(results[0].to_string(), "ok   added code: -5030 (limit 0)");
+    }
+}
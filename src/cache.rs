@@ -0,0 +1,168 @@
+//! Per-file count cache shared between analyses (see [`AnalyzeConfig::cache`]).
+//!
+//! A long-running process such as `rloc serve` analyzes the same trees over and over. Files
+//! whose size and modification time are unchanged since they were last counted, under the
+//! same counting settings, are taken from the cache instead of being read again. The cache
+//! holds at most [`DEFAULT_CAPACITY`] files unless built with [`FileCache::with_capacity`];
+//! stale entries are dropped when they are looked up, and once full an arbitrary entry
+//! makes room for each new file.
+//!
+//! [`AnalyzeConfig::cache`]: crate::AnalyzeConfig::cache
+
+use crate::counter::FileStats;
+use dashmap::DashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Files a [`FileCache::new`] cache holds.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Cache of file counts. Clones share the same entries.
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    entries: Arc<DashMap<PathBuf, CachedFile>>,
+    capacity: usize,
+}
+
+impl Default for FileCache {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedFile {
+    modified: SystemTime,
+    len: u64,
+    /// Fingerprint of the counting settings and language the file was counted with.
+    settings: u64,
+    /// Content hash, for duplicate detection without reading the file.
+    hash: u64,
+    stats: FileStats,
+}
+
+impl FileCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache holding at most `capacity` files (at least one).
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(DashMap::new()),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// The content hash and counts of `path`, if it is cached under `settings` and its
+    /// size and modification time have not changed since.
+    pub fn get(&self, path: &Path, settings: u64) -> Option<(u64, FileStats)> {
+        let Some((modified, len)) = stamp(path) else {
+            self.entries.remove(path);
+            return None;
+        };
+        let cached = self.entries.get(path)?;
+        if cached.modified != modified || cached.len != len {
+            drop(cached);
+            self.entries.remove(path);
+            return None;
+        }
+        (cached.settings == settings).then(|| (cached.hash, cached.stats.clone()))
+    }
+
+    /// Remember the counts of `path`, stamped with its current size and modification time.
+    pub fn insert(&self, path: &Path, settings: u64, hash: u64, stats: &FileStats) {
+        if let Some((modified, len)) = stamp(path) {
+            if self.entries.len() >= self.capacity && !self.entries.contains_key(path) {
+                // The guard of the iterator must be dropped before removing.
+                let evicted = self.entries.iter().next().map(|entry| entry.key().clone());
+                if let Some(evicted) = evicted {
+                    self.entries.remove(&evicted);
+                }
+            }
+            self.entries.insert(
+                path.to_path_buf(),
+                CachedFile {
+                    modified,
+                    len,
+                    settings,
+                    hash,
+                    stats: stats.clone(),
+                },
+            );
+        }
+    }
+
+    /// Number of cached files.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_invalidation() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("main.rs");
+        fs::write(&path, "fn main() {}\n").unwrap();
+
+        let cache = FileCache::new();
+        let stats = FileStats {
+            code: 1,
+            ..Default::default()
+        };
+        cache.insert(&path, 7, 42, &stats);
+        assert_eq!(
+            cache.get(&path, 7).map(|(hash, s)| (hash, s.code)),
+            Some((42, 1))
+        );
+        assert!(cache.get(&path, 8).is_none());
+        assert_eq!(cache.clone().len(), 1);
+
+        fs::write(&path, "fn main() {}\nfn other() {}\n").unwrap();
+        assert!(cache.get(&path, 7).is_none());
+        // The stale entry is dropped on lookup.
+        assert!(cache.is_empty());
+
+        cache.insert(&path, 7, 42, &stats);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cache_capacity() {
+        let temp = TempDir::new().unwrap();
+        let cache = FileCache::with_capacity(2);
+        let stats = FileStats::default();
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            let path = temp.path().join(name);
+            fs::write(&path, "fn main() {}\n").unwrap();
+            cache.insert(&path, 7, 42, &stats);
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&temp.path().join("c.rs"), 7).is_some());
+
+        // Replacing a cached file does not evict another.
+        cache.insert(&temp.path().join("c.rs"), 7, 43, &stats);
+        assert_eq!(cache.len(), 2);
+    }
+}
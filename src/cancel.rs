@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "fs")]
+use std::{sync::atomic::AtomicUsize, sync::mpsc, time::Duration};
+
+/// Cooperative cancellation flag shared between an embedder and a running analysis.
+///
+/// Clones share the same flag. Once [`cancel`](Self::cancel) is called, walking and
+/// counting stop at the next check and the analysis returns `Error::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    flag: Arc<AtomicBool>,
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that is cancelled with this one, but can also be cancelled on its own
+    /// without affecting this one.
+    pub fn child_token(&self) -> Self {
+        Self {
+            flag: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+}
+
+/// `true` if `token` is set and has been cancelled.
+pub(crate) fn is_cancelled(token: &Option<CancellationToken>) -> bool {
+    token.as_ref().is_some_and(CancellationToken::is_cancelled)
+}
+
+/// Enforces `--max-files` and `--timeout` on one analysis.
+///
+/// Counting watches [`count_token`](Self::count_token), cancelled on timeout (or when the
+/// embedder's own token is); walking watches [`walk_token`](Self::walk_token), which is
+/// also cancelled once `max_files` files were admitted, so files already being counted
+/// finish. Unlike a cancellation, hitting a limit keeps the files counted so far and marks
+/// the result as [`truncated`](Self::truncated).
+#[cfg(feature = "fs")]
+#[derive(Debug)]
+pub struct Limiter {
+    count_token: CancellationToken,
+    walk_token: CancellationToken,
+    max_files: Option<usize>,
+    timeout: Option<Duration>,
+    admitted: AtomicUsize,
+    truncated: AtomicBool,
+}
+
+#[cfg(feature = "fs")]
+impl Limiter {
+    pub fn new(
+        max_files: Option<usize>,
+        timeout: Option<Duration>,
+        cancel: Option<&CancellationToken>,
+    ) -> Self {
+        let count_token =
+            cancel.map_or_else(CancellationToken::new, CancellationToken::child_token);
+        Self {
+            walk_token: count_token.child_token(),
+            count_token,
+            max_files,
+            timeout,
+            admitted: AtomicUsize::new(0),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    /// Token to stop counting with.
+    pub fn count_token(&self) -> CancellationToken {
+        self.count_token.clone()
+    }
+
+    /// Token to stop walking with.
+    pub fn walk_token(&self) -> CancellationToken {
+        self.walk_token.clone()
+    }
+
+    /// Claim a slot for one more file; past `max_files` the walk is stopped instead.
+    pub fn admit(&self) -> bool {
+        let admitted = self.admitted.fetch_add(1, Ordering::Relaxed);
+        if self.max_files.is_some_and(|max| admitted >= max) {
+            self.truncated.store(true, Ordering::Relaxed);
+            self.walk_token.cancel();
+            return false;
+        }
+        true
+    }
+
+    /// Run `f`, stopping walking and counting once `timeout` has elapsed.
+    pub fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let Some(timeout) = self.timeout else {
+            return f();
+        };
+        let (done, finished) = mpsc::channel::<()>();
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                if finished.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+                    self.truncated.store(true, Ordering::Relaxed);
+                    self.count_token.cancel();
+                }
+            });
+            let result = f();
+            drop(done);
+            result
+        })
+    }
+
+    /// Whether a limit was hit, so the files counted are only part of the input.
+    pub fn truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter() {
+        let parent = CancellationToken::new();
+        let limiter = Limiter::new(Some(2), None, Some(&parent));
+        assert!(limiter.admit() && limiter.admit());
+        assert!(!limiter.truncated());
+        assert!(!limiter.admit());
+        assert!(limiter.truncated() && limiter.walk_token().is_cancelled());
+        assert!(!limiter.count_token().is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let limiter = Limiter::new(None, Some(Duration::from_millis(10)), None);
+        let token = limiter.walk_token();
+        limiter.run(|| {
+            while !token.is_cancelled() {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+        assert!(limiter.truncated());
+
+        let limiter = Limiter::new(None, Some(Duration::from_secs(60)), Some(&parent));
+        limiter.run(|| parent.cancel());
+        assert!(limiter.walk_token().is_cancelled() && !limiter.truncated());
+    }
+}
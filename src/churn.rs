@@ -0,0 +1,231 @@
+//! Change frequency from git history, for `--churn`.
+//!
+//! Hotspots are files that are both large and often changed: each counted file is scored
+//! by its code lines times the number of commits that touched it, following code-maat's
+//! hotspot analysis. Commits are read with `git log`, so only files in a git working copy
+//! get a count.
+
+use crate::counter::FileStats;
+use crate::stats::SCHEMA_VERSION;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// One file ranked by [`hotspots`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hotspot {
+    pub path: String,
+    pub language: String,
+    pub code: u64,
+    /// Commits that touched the file in the analyzed period.
+    pub commits: u64,
+    /// `code * commits`; hotspots are ranked by it.
+    pub score: u64,
+}
+
+/// `--churn --json`: the ranked hotspots and the period they cover.
+#[derive(Debug, Clone, Serialize)]
+pub struct HotspotReport {
+    pub schema_version: u32,
+    /// The `--since` period as given; absent for the whole history.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    pub hotspots: Vec<Hotspot>,
+}
+
+impl HotspotReport {
+    pub fn new(hotspots: &[Hotspot], since: Option<&str>) -> Self {
+        HotspotReport {
+            schema_version: SCHEMA_VERSION,
+            since: since.map(str::to_string),
+            hotspots: hotspots.to_vec(),
+        }
+    }
+}
+
+/// Turn a `--since` period like `30d`, `2w`, `6m` or `1y` into a date git understands.
+///
+/// Anything else (`2024-01-01`, `last.monday`, ...) is handed to git unchanged.
+pub fn since_arg(period: &str) -> String {
+    let period = period.trim();
+    let unit = match period.chars().last() {
+        Some('d') => "days",
+        Some('w') => "weeks",
+        Some('m') => "months",
+        Some('y') => "years",
+        _ => return period.to_string(),
+    };
+    match period[..period.len() - 1].parse::<u32>() {
+        Ok(n) => format!("{} {} ago", n, unit),
+        Err(_) => period.to_string(),
+    }
+}
+
+/// Commits per file below `root`, optionally only since `since` (see [`since_arg`]).
+///
+/// Paths are `root` joined with the path git reports, so they match the paths a walk of
+/// `root` produces. `None` if git is unavailable or `root` is not in a git working copy.
+pub fn commit_counts(root: &Path, since: Option<&str>) -> Option<HashMap<PathBuf, u64>> {
+    let (dir, pathspec) = if root.is_file() {
+        (root.parent()?, root.file_name().map(PathBuf::from))
+    } else {
+        (root, None)
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+
+    let mut command = Command::new("git");
+    command.arg("-C").arg(dir).args([
+        "log",
+        "--format=",
+        "--name-only",
+        "--no-renames",
+        "--relative",
+        "-z",
+    ]);
+    if let Some(since) = since {
+        command.arg(format!("--since={}", since_arg(since)));
+    }
+    command.arg("--");
+    if let Some(pathspec) = pathspec {
+        command.arg(pathspec);
+    }
+
+    let output = command.output().ok().filter(|out| out.status.success())?;
+    let mut counts = HashMap::new();
+    // NUL-separated, so names git would otherwise quote (`"caf\303\251.rs"`) come through as is.
+    for path in output
+        .stdout
+        .split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+    {
+        *counts
+            .entry(dir.join(crate::path_from_bytes(path)))
+            .or_insert(0) += 1;
+    }
+    Some(counts)
+}
+
+/// Counted files that changed at least once, highest score first; ties go to the file with
+/// more commits, then by path.
+pub fn hotspots(files: &[FileStats], commits: &HashMap<PathBuf, u64>) -> Vec<Hotspot> {
+    let mut hotspots: Vec<Hotspot> = files
+        .iter()
+        .filter_map(|file| {
+            let commits = *commits.get(Path::new(&file.path))?;
+            Some(Hotspot {
+                path: file.path.clone(),
+                language: file.language.clone(),
+                code: file.code,
+                commits,
+                score: file.code * commits,
+            })
+        })
+        .collect();
+    hotspots.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(b.commits.cmp(&a.commits))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    hotspots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn file(path: &str, code: u64) -> FileStats {
+        FileStats {
+            path: path.into(),
+            language: "Rust".into(),
+            code,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_since_arg() {
+        assert_eq!(since_arg("6m"), "6 months ago");
+        assert_eq!(since_arg("30d"), "30 days ago");
+        assert_eq!(since_arg("2w"), "2 weeks ago");
+        assert_eq!(since_arg("1y"), "1 years ago");
+        assert_eq!(since_arg("2024-01-01"), "2024-01-01");
+        assert_eq!(since_arg("yesterday"), "yesterday");
+    }
+
+    #[test]
+    fn test_hotspot_ranking() {
+        let files = [
+            file("a.rs", 100),
+            file("b.rs", 10),
+            file("c.rs", 50),
+            file("d.rs", 500),
+        ];
+        let commits = HashMap::from([
+            (PathBuf::from("a.rs"), 1),
+            (PathBuf::from("b.rs"), 10),
+            (PathBuf::from("c.rs"), 4),
+        ]);
+        let ranked: Vec<_> = hotspots(&files, &commits)
+            .into_iter()
+            .map(|h| (h.path, h.score))
+            .collect();
+        // b.rs and a.rs tie on score; b.rs changed more often.
+        assert_eq!(
+            ranked,
+            [
+                ("c.rs".into(), 200),
+                ("b.rs".into(), 100),
+                ("a.rs".into(), 100)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commit_counts() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(root)
+                .args([
+                    "-c",
+                    "user.name=t",
+                    "-c",
+                    "user.email=t@t",
+                    "-c",
+                    "commit.gpgsign=false",
+                ])
+                .args(args)
+                .output()
+                .is_ok_and(|out| out.status.success())
+        };
+        if !git(&["init"]) {
+            return;
+        }
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/a.rs"), "fn a() {}\n").unwrap();
+        fs::write(root.join("b.rs"), "fn b() {}\n").unwrap();
+        fs::write(root.join("café.rs"), "fn d() {}\n").unwrap();
+        assert!(git(&["add", "."]) && git(&["commit", "-m", "one"]));
+        fs::write(root.join("src/a.rs"), "fn a() {}\nfn c() {}\n").unwrap();
+        assert!(git(&["commit", "-am", "two"]));
+
+        let counts = commit_counts(root, None).unwrap();
+        assert_eq!(counts.get(&root.join("src/a.rs")), Some(&2));
+        assert_eq!(counts.get(&root.join("b.rs")), Some(&1));
+        assert_eq!(counts.get(&root.join("café.rs")), Some(&1));
+
+        let counts = commit_counts(&root.join("src"), Some("1y")).unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts.get(&root.join("src/a.rs")), Some(&2));
+    }
+}
@@ -1,8 +1,20 @@
-use crate::output::{OutputConfig, OutputFormat, SortBy};
-use crate::walker::{VcsMode, WalkerConfig};
-use clap::{Parser, ValueEnum};
+use crate::budget::Budget;
+use crate::config::{ConfigFile, ProfileConfig, resolve_profile};
+use crate::counter::{CountConfig, TestMode};
+use crate::custom_langs::CustomLanguageRegistry;
+use crate::languages::{Language, get_language_ignore_case, list_languages};
+use crate::output::{Column, OutputConfig, OutputFormat, SortBy};
+use crate::policy::CountPolicy;
+use crate::stats::Summary;
+use crate::trend::DEFAULT_STORE;
+use crate::walker::{VENDOR_DIRS, VcsMode, WalkerConfig, detect_path_language};
+use clap::{ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use comfy_table::Color;
 use regex::Regex;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -12,7 +24,13 @@ use std::path::PathBuf;
     about = "A fast, modern Rust implementation of cloc (Count Lines of Code)",
     long_about = "rloc counts lines of code, comments, and blanks in source files.\n\n\
                   It automatically detects programming languages by file extension\n\
-                  and uses language-specific comment syntax for accurate counting."
+                  and uses language-specific comment syntax for accurate counting.",
+    after_help = "Exit codes:\n  \
+                  0  Success\n  \
+                  1  Error (invalid arguments, unreadable input, ...)\n  \
+                  2  No source files found (with --fail-on-empty)\n  \
+                  3  A budget was exceeded (rloc check)",
+    group(ArgGroup::new("strip").args(["strip_comments", "strip_code"]).multiple(true))
 )]
 pub struct Cli {
     #[arg(
@@ -24,77 +42,243 @@ pub struct Cli {
 
     #[arg(
         long,
-        value_name = "PATH",
-        help = "Compare against another set of files/directories"
+        global = true,
+        value_name = "NAME",
+        help = "File name used to detect the language of source read from stdin (PATH '-')"
+    )]
+    pub stdin_name: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "FORMAT",
+        help = "What stdin holds: one source file (PATH '-'), or a tar archive to count without extracting it"
     )]
-    pub diff: Option<PathBuf>,
+    pub stdin_format: Option<StdinFormat>,
 
-    #[arg(long, help = "Report results for every source file")]
+    #[arg(
+        long,
+        global = true,
+        num_args = 1..=2,
+        value_names = ["OLD", "NEW"],
+        help = "Compare PATH (old) with NEW, or OLD with NEW; archives are extracted first"
+    )]
+    pub diff: Vec<PathBuf>,
+
+    #[arg(long, global = true, help = "Report results for every source file")]
     pub by_file: bool,
 
-    #[arg(long, help = "Report by file and by language")]
+    #[arg(
+        long,
+        global = true,
+        help = "Report files grouped by language, with per-language sub-totals"
+    )]
     pub by_file_by_lang: bool,
 
-    #[arg(long, value_enum, help = "Output format")]
+    #[arg(
+        long,
+        global = true,
+        help = "Roll languages up into categories (Programming, Markup, Data/Config, ...)"
+    )]
+    pub by_category: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "by_category",
+        help = "Report one row per file extension instead of per language"
+    )]
+    pub by_ext: bool,
+
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "rename-lang",
+        value_name = "FROM=INTO",
+        value_parser = parse_language_merge,
+        help = "Report language FROM under INTO in the summary (e.g. 'C Header=C', 'TSX=Web'); per-file rows keep FROM"
+    )]
+    pub merge_lang: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Report C and C++ headers under C and C++ (--merge-lang 'C Header=C' --merge-lang 'C++ Header=C++')"
+    )]
+    pub group_headers: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Report each PATH separately, followed by the grand total"
+    )]
+    pub group_by_path: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with_all = ["group_by_path", "nested_repos"],
+        help = "Break languages down by project (Cargo, npm, Go and Maven manifests)"
+    )]
+    pub by_project: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "exclude_nested_repos",
+        help = "Report nested git repositories and submodules in separate sections"
+    )]
+    pub nested_repos: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Skip nested git repositories and submodules"
+    )]
+    pub exclude_nested_repos: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with_all = ["group_by_path", "nested_repos", "by_project"],
+        help = "Rank files by code lines times git commits to find hotspots"
+    )]
+    pub churn: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PERIOD",
+        requires = "churn",
+        help = "Only count commits since PERIOD (e.g. 30d, 2w, 6m, 1y or a date)"
+    )]
+    pub since: Option<String>,
+
+    #[arg(long, global = true, value_enum, help = "Output format")]
     pub format: Option<Format>,
 
-    #[arg(long, help = "Write results as JSON")]
+    #[arg(long, global = true, help = "Write results as JSON")]
     pub json: bool,
 
-    #[arg(long, help = "Write results as CSV")]
+    #[arg(long, global = true, help = "Write results as CSV")]
     pub csv: bool,
 
     #[arg(
         long,
+        global = true,
         value_name = "C",
         help = "Use character <C> as CSV delimiter (default: ',')"
     )]
     pub csv_delimiter: Option<char>,
 
-    #[arg(long, help = "Write results as YAML")]
+    #[arg(long, global = true, help = "Write results as YAML")]
     pub yaml: bool,
 
-    #[arg(long, help = "Write results as Markdown")]
+    #[arg(long, global = true, help = "Write results as Markdown")]
     pub md: bool,
 
-    #[arg(long, help = "Write results as SQL CREATE and INSERT statements")]
+    #[arg(
+        long,
+        global = true,
+        help = "Write results as SQL CREATE and INSERT statements"
+    )]
     pub sql: bool,
 
-    #[arg(long, help = "Write results as XML")]
+    #[arg(long, global = true, help = "Write results as XML")]
     pub xml: bool,
 
     #[arg(
         long,
+        global = true,
+        value_name = "FILE",
+        help = "Render results through a Handlebars template file"
+    )]
+    pub template: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
         value_name = "DIR",
         help = "Exclude directories matching these names"
     )]
     pub exclude_dir: Vec<String>,
 
-    #[arg(long, value_name = "EXT", help = "Exclude files with these extensions")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "EXT",
+        help = "Exclude files with these extensions"
+    )]
     pub exclude_ext: Vec<String>,
 
-    #[arg(long, value_name = "LANG", help = "Exclude these languages")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "LANG",
+        help = "Exclude these languages"
+    )]
     pub exclude_lang: Vec<String>,
 
     #[arg(
         long,
+        global = true,
         value_name = "EXT",
         help = "Only count files with these extensions"
     )]
     pub include_ext: Vec<String>,
 
-    #[arg(long, value_name = "LANG", help = "Only count these languages")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "LANG",
+        help = "Only count these languages"
+    )]
     pub include_lang: Vec<String>,
 
     #[arg(
         long,
+        global = true,
         value_name = "LANG,EXT",
-        help = "Treat files with extension EXT as language LANG (e.g. Rust,txt)"
+        help = "Treat files with extension EXT as language LANG (e.g. Rust,txt); LANG alone sets the language of stdin"
     )]
     pub force_lang: Vec<String>,
 
     #[arg(
         long,
+        global = true,
+        value_name = "GLOB:LANG",
+        help = "Treat files whose path below the root matches GLOB as language LANG (e.g. '**/Jenkinsfile*:Groovy')"
+    )]
+    pub force_lang_def: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "EXT=LANG",
+        help = "Map extension EXT to language LANG for this run (e.g. h='C++ Header')"
+    )]
+    pub map_ext: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "EXT",
+        value_delimiter = ',',
+        help = "Stop recognizing extension EXT (files may still match by name)"
+    )]
+    pub unmap_ext: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Let Emacs/Vim modelines (-*- mode: python -*-, vim: ft=sh) override the language detected from the file name"
+    )]
+    pub modelines: bool,
+
+    #[arg(
+        long,
+        global = true,
         value_name = "REGEX",
         help = "Only count files in directories matching regex"
     )]
@@ -102,19 +286,47 @@ pub struct Cli {
 
     #[arg(
         long,
+        global = true,
         value_name = "REGEX",
         help = "Exclude directories matching regex"
     )]
     pub not_match_d: Vec<String>,
 
-    #[arg(long, value_name = "REGEX", help = "Only count files matching regex")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "REGEX",
+        help = "Only count files matching regex"
+    )]
     pub match_f: Option<String>,
 
-    #[arg(long, value_name = "REGEX", help = "Exclude files matching regex")]
+    #[arg(
+        long,
+        global = true,
+        value_name = "REGEX",
+        help = "Exclude files matching regex"
+    )]
     pub not_match_f: Vec<String>,
 
     #[arg(
         long,
+        global = true,
+        value_name = "GLOB",
+        help = "Only count files whose path below the root matches GLOB (e.g. 'src/**/*.rs')"
+    )]
+    pub include_glob: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "GLOB",
+        help = "Exclude files whose path below the root matches GLOB (e.g. '**/generated/**')"
+    )]
+    pub exclude_glob: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
         value_name = "REGEX",
         help = "Only count files containing content matching regex"
     )]
@@ -122,144 +334,971 @@ pub struct Cli {
 
     #[arg(
         long,
+        global = true,
         value_name = "REGEX",
         help = "Exclude files containing content matching regex"
     )]
     pub exclude_content: Option<String>,
 
-    #[arg(long, help = "Use full path in regex matching")]
+    #[arg(long, global = true, help = "Use full path in regex matching")]
     pub fullpath: bool,
 
-    #[arg(long, value_enum, help = "Use version control to find files")]
+    #[arg(
+        long,
+        global = true,
+        help = "Exclude generated files instead of reporting them as 'Generated'"
+    )]
+    pub no_generated: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "REGEX",
+        help = "Additional marker identifying generated files (searched in the first lines)"
+    )]
+    pub generated_marker: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Skip minified JavaScript/CSS instead of reporting it as '<Language> (minified)'"
+    )]
+    pub skip_minified: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Also count logical lines (statements) and show them in a 'Logical' column"
+    )]
+    pub logical_lines: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Find comments with a tree-sitter parse where a grammar is available (slower)"
+    )]
+    pub accurate: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "docstrings_as_comments",
+        help = "Count Python docstrings as code instead of comments"
+    )]
+    pub docstrings_as_code: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Attribute generated C to the sources named by its #line directives"
+    )]
+    pub follow_origins: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "LANG",
+        value_delimiter = ',',
+        help = "Count comment lines of these languages as code (e.g. SQL)"
+    )]
+    pub comments_as_code: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Count comment lines as blank (non-code) lines"
+    )]
+    pub ignore_comments: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only count lines ended by a newline, like wc -l (a last line without one is not counted)"
+    )]
+    pub require_final_newline: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Count LF, CRLF and CR line endings and show them in 'LF', 'CRLF' and 'CR' columns"
+    )]
+    pub line_endings: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Count tab- and space-indented lines and the usual indent width, shown in 'Tabs', 'Spaces' and 'Indent' columns"
+    )]
+    pub indent_stats: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Count Python docstrings as comments (the default; overrides the config file)"
+    )]
+    pub docstrings_as_comments: bool,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "exclude_vendored",
+        help = "Also walk vendor directories skipped by default (vendor, node_modules) and report them as 'Vendored'"
+    )]
+    pub include_vendored: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Exclude vendored files instead of reporting them as 'Vendored'"
+    )]
+    pub exclude_vendored: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "GLOB",
+        help = "Additional path glob classifying files as vendored (e.g. 'extern/**')"
+    )]
+    pub vendor_pattern: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "exclude_tests",
+        help = "Report test code (test files, #[cfg(test)] modules) in a separate 'Tests' column"
+    )]
+    pub split_tests: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Exclude test code (test files, #[cfg(test)] modules) from the counts"
+    )]
+    pub exclude_tests: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "GLOB",
+        help = "Additional path glob classifying files as tests (e.g. 'qa/**')"
+    )]
+    pub test_pattern: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Use version control to find files"
+    )]
     pub vcs: Option<Vcs>,
 
-    #[arg(long, help = "Synonym for --vcs")]
+    #[arg(long, global = true, help = "Synonym for --vcs")]
     pub files_from: Option<Vcs>,
 
-    #[arg(long, help = "Follow symbolic links")]
-    pub follow_symlinks: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Follow symbolic links (each directory is still walked only once)"
+    )]
+    pub follow_symlinks: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't cross file system boundaries (mount points)"
+    )]
+    pub one_file_system: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Process archive files (zip, tar, tar.gz/bz2/xz/zst, 7z)"
+    )]
+    pub extract_archives: bool,
+
+    #[arg(long, global = true, help = "Include hidden files and directories")]
+    pub hidden: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Disable default directory exclusions (node_modules, target, etc.)"
+    )]
+    pub no_ignore: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Directory exclusion profile: minimal, default, aggressive, or one defined in the config file"
+    )]
+    pub profile: Option<String>,
+
+    /// `[profiles]` defined in the config file, see [`Cli::load_config_file`].
+    #[arg(skip)]
+    pub profiles: HashMap<String, ProfileConfig>,
+
+    #[arg(long, global = true, help = "Don't respect .gitignore files")]
+    pub skip_gitignore: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't apply the global git excludes file (core.excludesFile)"
+    )]
+    pub no_global_gitignore: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Don't apply the repository's .git/info/exclude"
+    )]
+    pub no_git_exclude: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Skip files matching the gitignore patterns in FILE, relative to each PATH (repeatable)"
+    )]
+    pub custom_ignore: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Skip file uniqueness check (count duplicate files multiple times)"
+    )]
+    pub skip_uniqueness: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Include files in git submodules (requires Git 2.11+)"
+    )]
+    pub include_submodules: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        conflicts_with = "list_file0",
+        help = "Read file paths from FILE (one per line, '#' comments, '-' for stdin)"
+    )]
+    pub list_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Read NUL-delimited file paths from FILE (e.g. find -print0; '-' for stdin)"
+    )]
+    pub list_file0: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with_all = ["list_file", "list_file0"],
+        help = "Only count files with staged changes (git diff --cached), e.g. in a pre-commit hook"
+    )]
+    pub staged: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Maximum directory depth"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[arg(long, global = true, help = "Do not recurse into subdirectories")]
+    pub no_recurse: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "MB",
+        help = "Skip files larger than <MB> megabytes"
+    )]
+    pub max_file_size: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "BYTES",
+        help = "Bytes inspected when detecting binary files, 0 to disable [default: 8192]"
+    )]
+    pub binary_sample_size: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "RATIO",
+        help = "Fraction of NUL bytes above which a file is binary [default: 0.1]"
+    )]
+    pub binary_null_ratio: Option<f64>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "BYTES",
+        help = "Classify lines longer than this in chunks of this size, 0 to disable [default: 1048576]"
+    )]
+    pub long_line_limit: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Sort output by [default: code]"
+    )]
+    pub sort: Option<SortField>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "WHEN",
+        help = "Color table output: auto (terminal without NO_COLOR), always or never [default: auto]"
+    )]
+    pub color: Option<ColorChoice>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "COLOR",
+        help = "Color of the code column in table output [default: green]"
+    )]
+    pub code_color: Option<CodeColor>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "COLS",
+        help = "Columns to show, in order (e.g. code,comments,files)"
+    )]
+    pub columns: Vec<ColumnField>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Aggregate languages with fewer than N files into 'Other'"
+    )]
+    pub summary_cutoff: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Show only the N largest languages by code, folding the rest into 'Other'"
+    )]
+    pub top: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PCT",
+        help = "Fold languages with less than PCT percent of the code into 'Other'"
+    )]
+    pub cutoff_percent: Option<f64>,
+
+    #[arg(long, global = true, help = "Do not show rate statistics")]
+    pub hide_rate: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Add comment-to-code ratio, blank ratio and average lines per file per language"
+    )]
+    pub ratios: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "List the N largest files by code, overall and per language, after the table"
+    )]
+    pub show_largest: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Add total, bytes and avg_line_len to JSON/YAML language entries (not cloc-compatible)"
+    )]
+    pub extended_json: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Show counts as percentages of column totals; machine formats add files/code percentages"
+    )]
+    pub by_percent: bool,
+
+    #[arg(long, global = true, help = "Suppress progress output")]
+    pub quiet: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Exit with code 2 when no source files are counted"
+    )]
+    pub fail_on_empty: bool,
+
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, help = "Verbose output")]
+    pub verbose: u8,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "LEVEL",
+        help = "Log walk/count/aggregate phases to stderr; overrides RLOC_LOG filter directives"
+    )]
+    pub log_level: Option<LogLevel>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Write output to file"
+    )]
+    pub out: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        alias = "report-file",
+        value_name = "FILE",
+        help = "Write output to file"
+    )]
+    pub report_file: Option<PathBuf>,
+
+    #[arg(long, global = true, help = "Show an extra column with total lines")]
+    pub show_total: bool,
+
+    #[arg(long, global = true, help = "Print all known languages and exit")]
+    pub show_lang: bool,
+
+    #[arg(long, global = true, help = "Print all known file extensions and exit")]
+    pub show_ext: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "List files no language matched, with a histogram of their extensions, and exit"
+    )]
+    pub show_unrecognized: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "List the files that would be counted, with their detected language, and exit"
+    )]
+    pub list_only: bool,
+
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "langs-file",
+        value_name = "FILE",
+        help = "Load custom language definitions from a YAML, JSON or TOML file"
+    )]
+    pub read_lang_def: Option<PathBuf>,
+
+    /// Custom languages loaded from `--read-lang-def`, see [`Cli::load_custom_languages`].
+    #[arg(skip)]
+    pub custom_languages: Option<Arc<CustomLanguageRegistry>>,
+
+    #[arg(
+        long,
+        value_name = "EXT",
+        help = "Write files with comments removed (output to <file>.<EXT>)"
+    )]
+    pub strip_comments: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "EXT",
+        help = "Write files with code removed, keeping only comments (output to <file>.<EXT>)"
+    )]
+    pub strip_code: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        requires = "strip",
+        conflicts_with = "stdout",
+        help = "Write stripped files under <DIR>, mirroring their paths and keeping their names"
+    )]
+    pub strip_output_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "strip",
+        help = "Write stripped output to stdout instead of files (single file only)"
+    )]
+    pub stdout: bool,
+
+    #[arg(
+        long,
+        requires = "strip",
+        conflicts_with_all = ["stdout", "strip_output_dir"],
+        help = "Overwrite files with their stripped contents"
+    )]
+    pub in_place: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "EXT",
+        default_value = "bak",
+        help = "Back up files changed by --in-place to <file>.<EXT>; empty to disable"
+    )]
+    pub backup_ext: String,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Sum saved JSON reports (rloc or cloc format, '-' for stdin) and print the totals"
+    )]
+    pub sum_reports: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with = "sum_reports",
+        help = "Compare two saved JSON reports and print the signed per-language changes"
+    )]
+    pub diff_report: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        default_value = "0",
+        help = "Number of threads (0 = auto)"
+    )]
+    pub threads: usize,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        help = "Stop after counting N files and report the partial results"
+    )]
+    pub max_files: Option<usize>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = parse_seconds,
+        help = "Stop after SECS seconds (fractions allowed) and report the partial results"
+    )]
+    pub timeout: Option<Duration>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Read options from FILE instead of discovering rloc.toml/.rlocrc"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Ignore rloc.toml/.rlocrc and user config files"
+    )]
+    pub no_config: bool,
+
+    /// Config-file flags turned off with `--no-<flag>`, see [`Cli::try_parse_args_from`].
+    #[arg(skip)]
+    pub negated_flags: Vec<&'static str>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Answer JSON-RPC requests (analyze, analyzeBuffer, detectLanguage, cancel) on stdin/stdout"
+    )]
+    pub stdio_rpc: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Count lines and exit non-zero when a budget is exceeded (for CI)
+    ///
+    /// Counting options (--exclude-dir, --vcs, ...) apply as usual.
+    Check(CheckArgs),
+    /// Record line counts over time and show how they changed
+    #[command(subcommand)]
+    Trend(TrendCommand),
+    /// Save per-file counts and hashes, and later report which files changed since
+    #[command(subcommand)]
+    Snapshot(SnapshotCommand),
+    /// Install or run a git pre-commit hook checking the staged files against budgets
+    #[command(subcommand)]
+    Hook(HookCommand),
+    /// Serve counts over HTTP/JSON (POST /analyze, GET /languages) with a shared cache
+    Serve(ServeArgs),
+    /// Print a file with each line marked as code, comment, doc, blank or test
+    ///
+    /// Counting options (--force-lang, --split-tests, --accurate, ...) apply as usual.
+    Annotate(AnnotateArgs),
+    /// Show step by step why a file is counted or not, and as which language
+    ///
+    /// Counting options (--exclude-dir, --vcs, ...) apply as usual.
+    Explain(ExplainArgs),
+    /// Time repeated scans phase by phase and compare thread counts
+    ///
+    /// Counting options (--exclude-dir, --vcs, ...) apply as usual.
+    Bench(BenchArgs),
+    /// Compare the files under OLD with those under NEW; archives are extracted first
+    ///
+    /// Filters and output options (--exclude-dir, --by-file, --json, ...) apply to both sides.
+    Diff(DiffArgs),
+    /// Print a shell completion script
+    ///
+    /// e.g. `rloc completions bash > /usr/share/bash-completion/completions/rloc`.
+    Completions(CompletionsArgs),
+    /// Print the man page, or write one per subcommand with --dir
+    Manpage(ManpageArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct AnnotateArgs {
+    #[arg(value_name = "FILE", help = "Source file to annotate")]
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    #[arg(value_name = "FILE", help = "File to explain")]
+    pub file: PathBuf,
+
+    #[arg(
+        value_name = "PATH",
+        help = "Files or directories the count would start from [default: .]"
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    #[arg(long, default_value_t = 3, help = "Timed scans per thread count")]
+    pub runs: usize,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Untimed scans first, to warm the file system cache"
+    )]
+    pub warmup: usize,
+
+    #[arg(
+        long,
+        value_name = "N,...",
+        value_delimiter = ',',
+        help = "Thread counts to compare [default: 1, 2, 4, ... up to the CPU count]"
+    )]
+    pub compare_threads: Vec<usize>,
+
+    #[arg(
+        value_name = "PATH",
+        help = "Files or directories to scan [default: .]"
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[arg(value_name = "OLD", help = "Old side: a directory, file or archive")]
+    pub old: PathBuf,
 
-    #[arg(long, help = "Process archive files (zip, tar, tar.gz)")]
-    pub extract_archives: bool,
+    #[arg(value_name = "NEW", help = "New side: a directory, file or archive")]
+    pub new: PathBuf,
+}
 
-    #[arg(long, help = "Include hidden files and directories")]
-    pub hidden: bool,
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    #[arg(value_enum, value_name = "SHELL", help = "Shell to complete for")]
+    pub shell: clap_complete::Shell,
+}
 
+#[derive(Args, Debug)]
+pub struct ManpageArgs {
     #[arg(
         long,
-        help = "Disable default directory exclusions (node_modules, target, etc.)"
+        value_name = "DIR",
+        help = "Write rloc.1 and a page per subcommand (rloc-check.1, ...) into DIR"
     )]
-    pub no_ignore: bool,
-
-    #[arg(long, help = "Don't respect .gitignore files")]
-    pub skip_gitignore: bool,
+    pub dir: Option<PathBuf>,
+}
 
+#[derive(Args, Debug)]
+pub struct ServeArgs {
     #[arg(
         long,
-        help = "Skip file uniqueness check (count duplicate files multiple times)"
+        value_name = "ADDR",
+        default_value = "127.0.0.1:7070",
+        help = "Address to listen on"
     )]
-    pub skip_uniqueness: bool,
+    pub listen: String,
+}
 
-    #[arg(long, help = "Include files in git submodules (requires Git 2.11+)")]
-    pub include_submodules: bool,
+#[derive(Subcommand, Debug)]
+pub enum TrendCommand {
+    /// Count lines and append the totals, with time and git commit, to the store
+    ///
+    /// Counting options (--exclude-dir, --vcs, ...) apply as usual.
+    Record(TrendRecordArgs),
+    /// Show recorded runs with their deltas and sparkline trends
+    Show(TrendShowArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TrendRecordArgs {
+    #[arg(value_name = "PATH", help = "Files or directories to analyze")]
+    pub paths: Vec<PathBuf>,
 
     #[arg(
         long,
         value_name = "FILE",
-        help = "Read file paths from FILE (one per line)"
+        default_value = DEFAULT_STORE,
+        help = "JSON Lines file the run is appended to"
     )]
-    pub list_file: Option<PathBuf>,
-
-    #[arg(long, value_name = "N", help = "Maximum directory depth")]
-    pub max_depth: Option<usize>,
-
-    #[arg(long, help = "Do not recurse into subdirectories")]
-    pub no_recurse: bool,
+    pub store: PathBuf,
+}
 
+#[derive(Args, Debug)]
+pub struct TrendShowArgs {
     #[arg(
         long,
-        value_name = "MB",
-        help = "Skip files larger than <MB> megabytes"
+        value_name = "FILE",
+        default_value = DEFAULT_STORE,
+        help = "JSON Lines file written by `trend record`"
     )]
-    pub max_file_size: Option<u64>,
+    pub store: PathBuf,
 
-    #[arg(long, value_enum, default_value = "code", help = "Sort output by")]
-    pub sort: SortField,
+    #[arg(long, value_name = "N", help = "Only show the N most recent runs")]
+    pub last: Option<usize>,
+}
 
-    #[arg(
-        long,
-        value_name = "N",
-        help = "Aggregate languages with fewer than N files into 'Other'"
-    )]
-    pub summary_cutoff: Option<usize>,
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommand {
+    /// Count lines and save each file's counts and content hash
+    ///
+    /// Counting options (--exclude-dir, --vcs, ...) apply as usual.
+    Save(SnapshotArgs),
+    /// Count lines and list the files added, removed or changed since a saved snapshot
+    ///
+    /// Counting and output options (--json, --exclude-dir, ...) apply as usual.
+    Compare(SnapshotArgs),
+}
 
-    #[arg(long, help = "Do not show rate statistics")]
-    pub hide_rate: bool,
+impl SnapshotCommand {
+    pub fn args(&self) -> &SnapshotArgs {
+        match self {
+            SnapshotCommand::Save(args) | SnapshotCommand::Compare(args) => args,
+        }
+    }
+}
 
-    #[arg(long, help = "Show counts as percentages of column totals")]
-    pub by_percent: bool,
+#[derive(Args, Debug)]
+pub struct SnapshotArgs {
+    #[arg(value_name = "FILE", help = "Snapshot JSON file")]
+    pub file: PathBuf,
 
-    #[arg(long, help = "Suppress progress output")]
-    pub quiet: bool,
+    #[arg(value_name = "PATH", help = "Files or directories to analyze")]
+    pub paths: Vec<PathBuf>,
+}
 
-    #[arg(short, long, action = clap::ArgAction::Count, help = "Verbose output")]
-    pub verbose: u8,
+#[derive(Subcommand, Debug)]
+pub enum HookCommand {
+    /// Write a pre-commit hook running `rloc hook run` with these budgets
+    Install(HookInstallArgs),
+    /// Count the staged files and exit non-zero when a budget is exceeded (the hook body)
+    ///
+    /// Counting options (--exclude-dir, --split-tests, ...) apply as usual.
+    Run(HookBudgetArgs),
+}
 
-    #[arg(long, value_name = "FILE", help = "Write output to file")]
-    pub out: Option<PathBuf>,
+#[derive(Args, Debug)]
+pub struct HookInstallArgs {
+    #[command(flatten)]
+    pub budget: HookBudgetArgs,
 
     #[arg(
         long,
-        alias = "report-file",
-        value_name = "FILE",
-        help = "Write output to file"
+        help = "Print a .pre-commit-config.yaml entry instead of writing .git/hooks/pre-commit"
     )]
-    pub report_file: Option<PathBuf>,
-
-    #[arg(long, help = "Show an extra column with total lines")]
-    pub show_total: bool,
+    pub pre_commit_config: bool,
 
-    #[arg(long, help = "Print all known languages and exit")]
-    pub show_lang: bool,
+    #[arg(long, help = "Replace an existing pre-commit hook not written by rloc")]
+    pub force: bool,
+}
 
-    #[arg(long, help = "Print all known file extensions and exit")]
-    pub show_ext: bool,
+#[derive(Args, Debug)]
+#[group(required = true, multiple = true)]
+pub struct HookBudgetArgs {
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fail when the staged files add more than N lines of code over HEAD"
+    )]
+    pub max_added: Option<u64>,
 
     #[arg(
         long,
-        value_name = "FILE",
-        help = "Load custom language definitions from YAML file"
+        value_name = "N",
+        help = "Fail when a staged file has more than N lines of code"
     )]
-    pub read_lang_def: Option<PathBuf>,
+    pub max_file_code: Option<u64>,
 
     #[arg(
         long,
-        value_name = "EXT",
-        help = "Write files with comments removed (output to <file>.<EXT>)"
+        value_name = "LANG=N",
+        value_parser = parse_language_budget,
+        help = "Fail when the staged files of a language (or Generated/Vendored) exceed N lines of code"
     )]
-    pub strip_comments: Option<String>,
+    pub max_lang: Vec<(String, u64)>,
+}
+
+impl HookBudgetArgs {
+    /// The arguments that reproduce these budgets, for the installed hook.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(limit) = self.max_added {
+            args.extend(["--max-added".to_string(), limit.to_string()]);
+        }
+        if let Some(limit) = self.max_file_code {
+            args.extend(["--max-file-code".to_string(), limit.to_string()]);
+        }
+        for (name, limit) in &self.max_lang {
+            args.extend(["--max-lang".to_string(), format!("{}={}", name, limit)]);
+        }
+        args
+    }
+
+    /// Build the budget; `head` is the staged files counted as of `HEAD`, needed for
+    /// `--max-added`.
+    pub fn to_budget(&self, head: Option<Summary>) -> Budget {
+        let mut budget = Budget::new();
+        for (name, limit) in &self.max_lang {
+            budget = budget.max_language(name.clone(), *limit);
+        }
+        if let Some(limit) = self.max_file_code {
+            budget = budget.max_file_code(limit);
+        }
+        if let (Some(limit), Some(head)) = (self.max_added, head) {
+            budget = budget.max_added(head, limit);
+        }
+        budget
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(group(
+    ArgGroup::new("budget")
+        .args(["max_code", "max_lang", "fail_on_growth"])
+        .required(true)
+        .multiple(true)
+))]
+pub struct CheckArgs {
+    #[arg(value_name = "PATH", help = "Files or directories to analyze")]
+    pub paths: Vec<PathBuf>,
+
+    #[arg(long, value_name = "N", help = "Fail when total code exceeds N lines")]
+    pub max_code: Option<u64>,
 
     #[arg(
         long,
-        value_name = "EXT",
-        help = "Write files with code removed, keeping only comments (output to <file>.<EXT>)"
+        value_name = "LANG=N",
+        value_parser = parse_language_budget,
+        help = "Fail when a language (or Generated/Vendored) exceeds N lines of code"
     )]
-    pub strip_code: Option<String>,
+    pub max_lang: Vec<(String, u64)>,
 
     #[arg(
         long,
         value_name = "FILE",
-        help = "Read and sum JSON reports from files"
+        help = "Fail when total code grows versus this saved JSON report"
     )]
-    pub sum_reports: Vec<PathBuf>,
+    pub fail_on_growth: Option<PathBuf>,
 
     #[arg(
         long,
-        value_name = "N",
-        default_value = "0",
-        help = "Number of threads (0 = auto)"
+        value_name = "PERCENT",
+        default_value_t = 0.0,
+        requires = "fail_on_growth",
+        help = "Growth allowed by --fail-on-growth, in percent"
     )]
-    pub threads: usize,
+    pub max_growth: f64,
+}
+
+impl CheckArgs {
+    /// Build the budget; `baseline` is the loaded `--fail-on-growth` report.
+    pub fn to_budget(&self, baseline: Option<Summary>) -> Budget {
+        let mut budget = Budget::new();
+        if let Some(limit) = self.max_code {
+            budget = budget.max_code(limit);
+        }
+        for (name, limit) in &self.max_lang {
+            budget = budget.max_language(name.clone(), *limit);
+        }
+        if let Some(baseline) = baseline {
+            budget = budget.baseline(baseline, self.max_growth);
+        }
+        budget
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid number of seconds '{}'", value))
+}
+
+/// `FROM=INTO` for `--merge-lang`.
+fn parse_language_merge(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(from, into)| (from.trim(), into.trim()))
+        .filter(|(from, into)| !from.is_empty() && !into.is_empty())
+        .map(|(from, into)| language_merge(from, into))
+        .ok_or_else(|| format!("expected FROM=INTO, got '{}'", value))
+}
+
+/// A `(from, into)` merge; an INTO naming a language in another case gets the language's
+/// spelling. Aliases are not resolved, so INTO may be any new name (`TS`).
+fn language_merge(from: &str, into: &str) -> (String, String) {
+    let into = list_languages()
+        .find(|(name, _)| name.eq_ignore_ascii_case(into))
+        .map_or(into, |(name, _)| name);
+    (from.to_string(), into.to_string())
+}
+
+fn parse_language_budget(value: &str) -> Result<(String, u64), String> {
+    let (name, limit) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected LANG=N, got '{}'", value))?;
+    let limit = limit
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid line limit '{}'", limit))?;
+    Ok((name.trim().to_string(), limit))
 }
 
 #[derive(ValueEnum, Clone, Debug, Copy)]
@@ -271,12 +1310,27 @@ pub enum Format {
     Md,
     Sql,
     Xml,
+    Badge,
+    Ndjson,
+}
+
+/// Most detailed log events shown with `--log-level`.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
 }
 
 #[derive(ValueEnum, Clone, Debug, Copy)]
 pub enum Vcs {
     Auto,
     Git,
+    Hg,
+    Svn,
     None,
 }
 
@@ -290,7 +1344,372 @@ pub enum SortField {
     Total,
 }
 
+/// Contents of stdin for `--stdin-format`.
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum StdinFormat {
+    /// A single source file.
+    Source,
+    /// A tar archive, plain or compressed (`git archive HEAD | rloc --stdin-format tar`).
+    Tar,
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum CodeColor {
+    Green,
+    Cyan,
+    Blue,
+    Magenta,
+    Yellow,
+    Red,
+    /// Leave the code column unstyled.
+    None,
+}
+
+#[derive(ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum ColumnField {
+    Files,
+    #[value(alias = "blank")]
+    Blanks,
+    #[value(alias = "comment")]
+    Comments,
+    Code,
+    Total,
+    #[value(name = "doc-comments", alias = "doc")]
+    DocComments,
+    Logical,
+    Tests,
+    Ignored,
+    Bytes,
+    #[value(name = "avg-line")]
+    AvgLineLength,
+    #[value(name = "max-line")]
+    MaxLineLength,
+    Lf,
+    Crlf,
+    Cr,
+    Tabs,
+    Spaces,
+    #[value(name = "indent")]
+    IndentWidth,
+}
+
+/// Defines [`Cli::NEGATABLE_FLAGS`] and clears the ones negated on the command line.
+macro_rules! negatable_flags {
+    ($($field:ident => $negation:literal),* $(,)?) => {
+        impl Cli {
+            /// Flags the config file can turn on, each with the long option that turns it back off.
+            pub const NEGATABLE_FLAGS: &'static [(&'static str, &'static str)] =
+                &[$((stringify!($field), $negation)),*];
+
+            fn clear_negated_flags(&mut self) {
+                $(
+                    if self.negated_flags.contains(&stringify!($field)) {
+                        self.$field = false;
+                    }
+                )*
+            }
+        }
+    };
+}
+
+negatable_flags!(
+    modelines => "no-modelines",
+    fullpath => "no-fullpath",
+    no_generated => "generated",
+    skip_minified => "no-skip-minified",
+    logical_lines => "no-logical-lines",
+    accurate => "no-accurate",
+    follow_origins => "no-follow-origins",
+    ignore_comments => "no-ignore-comments",
+    require_final_newline => "no-require-final-newline",
+    line_endings => "no-line-endings",
+    indent_stats => "no-indent-stats",
+    include_vendored => "no-include-vendored",
+    exclude_vendored => "no-exclude-vendored",
+    split_tests => "no-split-tests",
+    exclude_tests => "no-exclude-tests",
+    follow_symlinks => "no-follow-symlinks",
+    one_file_system => "no-one-file-system",
+    hidden => "no-hidden",
+    no_ignore => "ignore",
+    skip_gitignore => "no-skip-gitignore",
+    no_global_gitignore => "global-gitignore",
+    no_git_exclude => "git-exclude",
+    skip_uniqueness => "no-skip-uniqueness",
+    include_submodules => "no-include-submodules",
+    no_recurse => "recurse",
+    by_file => "no-by-file",
+    by_file_by_lang => "no-by-file-by-lang",
+    by_category => "no-by-category",
+    by_ext => "no-by-ext",
+    group_headers => "no-group-headers",
+    group_by_path => "no-group-by-path",
+    by_project => "no-by-project",
+    nested_repos => "no-nested-repos",
+    exclude_nested_repos => "no-exclude-nested-repos",
+    churn => "no-churn",
+    by_percent => "no-by-percent",
+    hide_rate => "no-hide-rate",
+    extended_json => "no-extended-json",
+    ratios => "no-ratios",
+    show_total => "no-show-total",
+    quiet => "no-quiet",
+    fail_on_empty => "no-fail-on-empty",
+);
+
 impl Cli {
+    /// The command with a hidden negation for each of [`Cli::NEGATABLE_FLAGS`] (`--no-<flag>`,
+    /// or `--<flag>` for the `--no-*` ones); the last of a flag and its negation wins.
+    pub fn command_with_negations() -> clap::Command {
+        let mut command = Self::command();
+        for &(flag, negation) in Self::NEGATABLE_FLAGS {
+            let global = command
+                .get_arguments()
+                .find(|arg| arg.get_id() == flag)
+                .is_some_and(|arg| arg.is_global_set());
+            command = command
+                .mut_arg(flag, |arg| arg.overrides_with(negation))
+                .arg(
+                    clap::Arg::new(negation)
+                        .long(negation)
+                        .action(clap::ArgAction::SetTrue)
+                        .overrides_with(flag)
+                        .global(global)
+                        .hide(true),
+                );
+        }
+        command
+    }
+
+    /// Parse `args` like [`Parser::try_parse_from`], also accepting the negations of
+    /// [`Cli::command_with_negations`].
+    pub fn try_parse_args_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let mut command = Self::command_with_negations();
+        let matches = command.try_get_matches_from_mut(args)?;
+        let mut cli = Self::from_arg_matches(&matches).map_err(|e| e.format(&mut command))?;
+        cli.negated_flags = Self::NEGATABLE_FLAGS
+            .iter()
+            .filter(|(_, negation)| matches.get_flag(negation))
+            .map(|&(flag, _)| flag)
+            .collect();
+        Ok(cli)
+    }
+
+    /// Load `--config FILE`, or discover `rloc.toml`/`.rlocrc` in the first path and
+    /// the user config directory, filling in any option not given on the command line.
+    pub fn load_config_file(&mut self) -> Result<(), String> {
+        if self.no_config {
+            return Ok(());
+        }
+
+        let file = if let Some(ref path) = self.config {
+            Some(ConfigFile::load(path).map_err(|e| e.to_string())?)
+        } else {
+            let first = self.paths.first().cloned().unwrap_or_else(|| ".".into());
+            let dir = if first.is_dir() {
+                first
+            } else {
+                first
+                    .parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| ".".into())
+            };
+            ConfigFile::discover(&dir).map_err(|e| e.to_string())?
+        };
+
+        if let Some(file) = file {
+            self.apply_config(file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Load `--read-lang-def`/`--langs-file` once so every walk and `--show-lang` sees it.
+    pub fn load_custom_languages(&mut self) -> Result<(), String> {
+        if let Some(ref path) = self.read_lang_def {
+            self.custom_languages = Some(Arc::new(CustomLanguageRegistry::from_file(path)?));
+        }
+        Ok(())
+    }
+
+    fn apply_config(&mut self, file: ConfigFile) -> Result<(), String> {
+        fn fill<T>(target: &mut Option<T>, value: Option<T>) {
+            if target.is_none() {
+                *target = value;
+            }
+        }
+        fn fill_vec<T>(target: &mut Vec<T>, value: Option<Vec<T>>) {
+            if target.is_empty() {
+                *target = value.unwrap_or_default();
+            }
+        }
+        fn fill_flag(target: &mut bool, value: Option<bool>) {
+            *target = *target || value.unwrap_or(false);
+        }
+        fn parse_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T, String> {
+            T::from_str(value, true)
+                .map_err(|_| format!("Invalid value '{}' for '{}' in config file", value, key))
+        }
+
+        fill_vec(&mut self.exclude_dir, file.exclude_dir);
+        fill_vec(&mut self.exclude_ext, file.exclude_ext);
+        fill_vec(&mut self.exclude_lang, file.exclude_lang);
+        fill_vec(&mut self.include_ext, file.include_ext);
+        fill_vec(&mut self.include_lang, file.include_lang);
+        fill_vec(&mut self.force_lang, file.force_lang);
+        fill_vec(&mut self.force_lang_def, file.force_lang_def);
+        fill_vec(&mut self.map_ext, file.map_ext);
+        fill_vec(&mut self.unmap_ext, file.unmap_ext);
+        fill_flag(&mut self.modelines, file.modelines);
+        fill(&mut self.match_d, file.match_d);
+        fill_vec(&mut self.not_match_d, file.not_match_d);
+        fill(&mut self.match_f, file.match_f);
+        fill_vec(&mut self.not_match_f, file.not_match_f);
+        fill_vec(&mut self.include_glob, file.include_glob);
+        fill_vec(&mut self.exclude_glob, file.exclude_glob);
+        fill(&mut self.include_content, file.include_content);
+        fill(&mut self.exclude_content, file.exclude_content);
+        fill_flag(&mut self.fullpath, file.fullpath);
+        fill_flag(&mut self.no_generated, file.no_generated);
+        fill_vec(&mut self.generated_marker, file.generated_marker);
+        fill_flag(&mut self.skip_minified, file.skip_minified);
+        fill_flag(&mut self.logical_lines, file.logical_lines);
+        fill_flag(&mut self.accurate, file.accurate);
+        if !self.docstrings_as_comments {
+            fill_flag(&mut self.docstrings_as_code, file.docstrings_as_code);
+        }
+        fill_flag(&mut self.follow_origins, file.follow_origins);
+        fill_vec(&mut self.comments_as_code, file.comments_as_code);
+        fill_flag(&mut self.ignore_comments, file.ignore_comments);
+        fill_flag(&mut self.require_final_newline, file.require_final_newline);
+        fill_flag(&mut self.line_endings, file.line_endings);
+        fill_flag(&mut self.indent_stats, file.indent_stats);
+        fill_flag(&mut self.include_vendored, file.include_vendored);
+        fill_flag(&mut self.exclude_vendored, file.exclude_vendored);
+        fill_vec(&mut self.vendor_pattern, file.vendor_pattern);
+        fill_flag(&mut self.split_tests, file.split_tests);
+        fill_flag(&mut self.exclude_tests, file.exclude_tests);
+        fill_vec(&mut self.test_pattern, file.test_pattern);
+        fill_flag(&mut self.follow_symlinks, file.follow_symlinks);
+        fill_flag(&mut self.one_file_system, file.one_file_system);
+        fill_flag(&mut self.hidden, file.hidden);
+        fill_flag(&mut self.no_ignore, file.no_ignore);
+        fill(&mut self.profile, file.profile);
+        self.profiles = file.profiles.unwrap_or_default();
+        fill_flag(&mut self.skip_gitignore, file.skip_gitignore);
+        fill_flag(&mut self.no_global_gitignore, file.no_global_gitignore);
+        fill_flag(&mut self.no_git_exclude, file.no_git_exclude);
+        fill_vec(&mut self.custom_ignore, file.custom_ignore);
+        fill_flag(&mut self.skip_uniqueness, file.skip_uniqueness);
+        fill_flag(&mut self.include_submodules, file.include_submodules);
+        fill(&mut self.max_depth, file.max_depth);
+        fill_flag(&mut self.no_recurse, file.no_recurse);
+        fill(&mut self.max_file_size, file.max_file_size);
+        fill(&mut self.binary_sample_size, file.binary_sample_size);
+        fill(&mut self.long_line_limit, file.long_line_limit);
+        fill(&mut self.binary_null_ratio, file.binary_null_ratio);
+        fill(&mut self.summary_cutoff, file.summary_cutoff);
+        fill(&mut self.top, file.top);
+        fill(&mut self.cutoff_percent, file.cutoff_percent);
+        fill_flag(&mut self.by_file, file.by_file);
+        fill_flag(&mut self.by_file_by_lang, file.by_file_by_lang);
+        fill_flag(&mut self.by_category, file.by_category);
+        fill_flag(&mut self.by_ext, file.by_ext);
+        if self.merge_lang.is_empty() {
+            for spec in file.merge_lang.unwrap_or_default() {
+                let merge = parse_language_merge(&spec)
+                    .map_err(|e| format!("Invalid 'merge_lang' in config file: {}", e))?;
+                self.merge_lang.push(merge);
+            }
+        }
+        // `[rename]` entries come last, so `--merge-lang` wins for the same language.
+        let mut renames: Vec<_> = file.rename.unwrap_or_default().into_iter().collect();
+        renames.sort();
+        for (from, into) in renames {
+            if !self
+                .merge_lang
+                .iter()
+                .any(|(merged, _)| merged.eq_ignore_ascii_case(&from))
+            {
+                self.merge_lang.push(language_merge(&from, &into));
+            }
+        }
+        fill_flag(&mut self.group_headers, file.group_headers);
+        fill_flag(&mut self.group_by_path, file.group_by_path);
+        fill_flag(&mut self.by_project, file.by_project);
+        fill_flag(&mut self.nested_repos, file.nested_repos);
+        fill_flag(&mut self.exclude_nested_repos, file.exclude_nested_repos);
+        fill_flag(&mut self.churn, file.churn);
+        fill(&mut self.since, file.since);
+        fill_flag(&mut self.by_percent, file.by_percent);
+        fill_flag(&mut self.hide_rate, file.hide_rate);
+        fill_flag(&mut self.extended_json, file.extended_json);
+        fill_flag(&mut self.ratios, file.ratios);
+        fill(&mut self.show_largest, file.show_largest);
+        fill_flag(&mut self.show_total, file.show_total);
+        fill(&mut self.csv_delimiter, file.csv_delimiter);
+        fill(&mut self.template, file.template);
+        fill_flag(&mut self.quiet, file.quiet);
+        fill_flag(&mut self.fail_on_empty, file.fail_on_empty);
+        fill(&mut self.read_lang_def, file.read_lang_def);
+
+        fill(&mut self.max_files, file.max_files);
+        if let (None, Some(secs)) = (self.timeout, file.timeout) {
+            self.timeout = Some(parse_seconds(&secs.to_string())?);
+        }
+        if self.threads == 0 {
+            self.threads = file.threads.unwrap_or(0);
+        }
+        if let Some(ref vcs) = file.vcs {
+            if self.vcs.is_none() && self.files_from.is_none() {
+                self.vcs = Some(parse_enum("vcs", vcs)?);
+            }
+        }
+        if let Some(ref format) = file.format {
+            if self.format.is_none() {
+                self.format = Some(parse_enum("format", format)?);
+            }
+        }
+        if let Some(ref sort) = file.sort {
+            if self.sort.is_none() {
+                self.sort = Some(parse_enum("sort", sort)?);
+            }
+        }
+        if let Some(ref color) = file.color {
+            if self.color.is_none() {
+                self.color = Some(parse_enum("color", color)?);
+            }
+        }
+        if let Some(ref code_color) = file.code_color {
+            if self.code_color.is_none() {
+                self.code_color = Some(parse_enum("code-color", code_color)?);
+            }
+        }
+        if let Some(ref columns) = file.columns {
+            if self.columns.is_empty() {
+                self.columns = columns
+                    .iter()
+                    .map(|c| parse_enum("columns", c))
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+        self.clear_negated_flags();
+
+        Ok(())
+    }
+
     pub fn to_walker_config(&self) -> Result<WalkerConfig, String> {
         let mut config = WalkerConfig::default();
 
@@ -298,8 +1717,14 @@ impl Cli {
             config.paths = self.paths.clone();
         }
 
-        config.list_file = self.list_file.clone();
+        config.list_file = self.list_file.clone().or_else(|| self.list_file0.clone());
+        config.list_file_nul = self.list_file0.is_some();
+        config.staged = self.staged;
 
+        if let Some(ref name) = self.profile {
+            config.exclude_dirs =
+                resolve_profile(&self.profiles, name).map_err(|e| e.to_string())?;
+        }
         if self.no_ignore {
             config.exclude_dirs.clear();
         }
@@ -327,6 +1752,35 @@ impl Cli {
             }
         }
 
+        for spec in &self.force_lang_def {
+            let (pattern, lang) = spec.rsplit_once(':').ok_or_else(|| {
+                format!(
+                    "Invalid --force-lang-def format '{}', expected GLOB:LANG",
+                    spec
+                )
+            })?;
+            globset::Glob::new(pattern)
+                .map_err(|e| format!("Invalid --force-lang-def glob: {}", e))?;
+            let language = self.language(lang, "--force-lang-def")?;
+            config
+                .force_lang_globs
+                .push((pattern.to_string(), language.name.to_string()));
+        }
+
+        for spec in &self.map_ext {
+            let (ext, lang) = spec
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid --map-ext format '{}', expected EXT=LANG", spec))?;
+            let language = self.language(lang, "--map-ext")?;
+            config.force_lang.insert(
+                ext.trim_start_matches('.').to_lowercase(),
+                language.name.to_string(),
+            );
+        }
+
+        config.unmapped_exts = self.unmap_ext.clone();
+        config.modelines = self.modelines;
+
         if let Some(ref pattern) = self.match_d {
             config.match_dir =
                 Some(Regex::new(pattern).map_err(|e| format!("Invalid --match-d regex: {}", e))?);
@@ -366,10 +1820,13 @@ impl Cli {
         config.vcs = self.vcs.or(self.files_from).map(|v| match v {
             Vcs::Auto => VcsMode::Auto,
             Vcs::Git => VcsMode::Git,
+            Vcs::Hg => VcsMode::Hg,
+            Vcs::Svn => VcsMode::Svn,
             Vcs::None => VcsMode::None,
         });
 
         config.follow_symlinks = self.follow_symlinks;
+        config.one_file_system = self.one_file_system;
         config.hidden = self.hidden;
         config.fullpath = self.fullpath;
         config.max_depth = if self.no_recurse {
@@ -378,13 +1835,145 @@ impl Cli {
             self.max_depth
         };
         config.skip_gitignore = self.skip_gitignore;
+        config.skip_global_gitignore = self.no_global_gitignore;
+        config.skip_git_exclude = self.no_git_exclude;
+        if let Some(file) = self.custom_ignore.iter().find(|file| !file.is_file()) {
+            return Err(format!("Ignore file '{}' not found", file.display()));
+        }
+        config.custom_ignore_files = self.custom_ignore.clone();
         config.skip_uniqueness = self.skip_uniqueness;
         config.include_submodules = self.include_submodules;
         config.max_file_size = self.max_file_size;
 
+        config.custom_languages = self.custom_languages.clone();
+
+        if self.include_vendored {
+            config
+                .exclude_dirs
+                .retain(|dir| !VENDOR_DIRS.contains(&dir.as_str()));
+        }
+        config.exclude_vendored = self.exclude_vendored;
+        for (flag, patterns, target) in [
+            (
+                "--include-glob",
+                &self.include_glob,
+                &mut config.include_globs,
+            ),
+            (
+                "--exclude-glob",
+                &self.exclude_glob,
+                &mut config.exclude_globs,
+            ),
+        ] {
+            for pattern in patterns {
+                globset::Glob::new(pattern).map_err(|e| format!("Invalid {} glob: {}", flag, e))?;
+                target.push(pattern.clone());
+            }
+        }
+        for pattern in &self.vendor_pattern {
+            globset::Glob::new(pattern)
+                .map_err(|e| format!("Invalid --vendor-pattern glob: {}", e))?;
+            config.vendor_patterns.push(pattern.clone());
+        }
+        config.exclude_tests = self.exclude_tests;
+        config.exclude_nested_repos = self.exclude_nested_repos;
+        for pattern in &self.test_pattern {
+            globset::Glob::new(pattern)
+                .map_err(|e| format!("Invalid --test-pattern glob: {}", e))?;
+            config.test_patterns.push(pattern.clone());
+        }
+
+        Ok(config)
+    }
+
+    pub fn to_count_config(&self) -> Result<CountConfig, String> {
+        let mut config = CountConfig::default();
+
+        for pattern in &self.generated_marker {
+            config.generated_markers.push(
+                Regex::new(pattern)
+                    .map_err(|e| format!("Invalid --generated-marker regex: {}", e))?,
+            );
+        }
+
+        config.logical_lines = self.logical_lines || self.columns.contains(&ColumnField::Logical);
+        if self.accurate && !cfg!(feature = "tree-sitter") {
+            return Err(
+                "--accurate requires rloc to be built with the 'tree-sitter' feature".to_string(),
+            );
+        }
+        config.accurate = self.accurate;
+        config.docstrings_as_code = self.docstrings_as_code;
+        config.follow_origins = self.follow_origins;
+        let comments_as_code = self
+            .comments_as_code
+            .iter()
+            .map(|name| Ok(self.language(name, "--comments-as-code")?.name.to_string()))
+            .collect::<Result<Vec<_>, String>>()?;
+        config.policy = CountPolicy {
+            ignore_comments: self.ignore_comments,
+            ..Default::default()
+        }
+        .with_comments_as_code(&comments_as_code);
+        config.require_final_newline = self.require_final_newline;
+        config.line_endings = self.line_endings
+            || self.columns.iter().any(|column| {
+                matches!(
+                    column,
+                    ColumnField::Lf | ColumnField::Crlf | ColumnField::Cr
+                )
+            });
+        config.indent_stats = self.indent_stats
+            || self.columns.iter().any(|column| {
+                matches!(
+                    column,
+                    ColumnField::Tabs | ColumnField::Spaces | ColumnField::IndentWidth
+                )
+            });
+        config.content_hash = matches!(self.command, Some(Command::Snapshot(_)));
+        config.tests = if self.exclude_tests {
+            TestMode::Exclude
+        } else if self.split_tests || self.columns.contains(&ColumnField::Tests) {
+            TestMode::Split
+        } else {
+            TestMode::Include
+        };
+
+        if let Some(size) = self.binary_sample_size {
+            config.binary_sample_size = size;
+        }
+        if let Some(limit) = self.long_line_limit {
+            config.long_line_limit = limit;
+        }
+        if let Some(ratio) = self.binary_null_ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(format!(
+                    "--binary-null-ratio must be between 0 and 1, got {}",
+                    ratio
+                ));
+            }
+            config.binary_null_ratio = ratio;
+        }
+
         Ok(config)
     }
 
+    /// Whether table output gets ANSI styling: `--color always|never`, or with `auto` only
+    /// when writing to a terminal and `NO_COLOR` is unset or empty.
+    pub fn use_color(&self) -> bool {
+        use std::io::IsTerminal;
+
+        match self.color.unwrap_or(ColorChoice::Auto) {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+                    && self.output_path().is_none()
+                    && std::io::stdout().is_terminal()
+            }
+        }
+    }
+
     pub fn to_output_config(&self) -> OutputConfig {
         let format = if self.json {
             OutputFormat::Json
@@ -406,11 +1995,13 @@ impl Cli {
                 Some(Format::Md) => OutputFormat::Markdown,
                 Some(Format::Sql) => OutputFormat::Sql,
                 Some(Format::Xml) => OutputFormat::Xml,
+                Some(Format::Badge) => OutputFormat::Badge,
+                Some(Format::Ndjson) => OutputFormat::Ndjson,
                 Some(Format::Table) | None => OutputFormat::Table,
             }
         };
 
-        let sort_by = match self.sort {
+        let sort_by = match self.sort.unwrap_or(SortField::Code) {
             SortField::Language => SortBy::Language,
             SortField::Files => SortBy::Files,
             SortField::Code => SortBy::Code,
@@ -419,16 +2010,165 @@ impl Cli {
             SortField::Total => SortBy::Total,
         };
 
+        // Left empty when not given so per-file output can add its default metadata columns.
+        let columns = self
+            .columns
+            .iter()
+            .map(|c| match c {
+                ColumnField::Files => Column::Files,
+                ColumnField::Blanks => Column::Blanks,
+                ColumnField::Comments => Column::Comments,
+                ColumnField::Code => Column::Code,
+                ColumnField::Total => Column::Total,
+                ColumnField::DocComments => Column::DocComments,
+                ColumnField::Logical => Column::Logical,
+                ColumnField::Tests => Column::Tests,
+                ColumnField::Ignored => Column::Ignored,
+                ColumnField::Bytes => Column::Bytes,
+                ColumnField::AvgLineLength => Column::AvgLineLength,
+                ColumnField::MaxLineLength => Column::MaxLineLength,
+                ColumnField::Lf => Column::Lf,
+                ColumnField::Crlf => Column::Crlf,
+                ColumnField::Cr => Column::Cr,
+                ColumnField::Tabs => Column::Tabs,
+                ColumnField::Spaces => Column::Spaces,
+                ColumnField::IndentWidth => Column::IndentWidth,
+            })
+            .collect();
+
+        let code_color = match self.code_color.unwrap_or(CodeColor::Green) {
+            CodeColor::Green => Some(Color::Green),
+            CodeColor::Cyan => Some(Color::Cyan),
+            CodeColor::Blue => Some(Color::Blue),
+            CodeColor::Magenta => Some(Color::Magenta),
+            CodeColor::Yellow => Some(Color::Yellow),
+            CodeColor::Red => Some(Color::Red),
+            CodeColor::None => None,
+        };
+
         OutputConfig {
             format,
+            color: self.use_color(),
+            code_color,
             by_file: self.by_file,
             by_file_by_lang: self.by_file_by_lang,
             hide_rate: self.hide_rate,
             sort_by,
             show_total_column: self.show_total,
+            show_logical_column: self.logical_lines,
+            show_tests_column: self.split_tests,
+            show_line_ending_columns: self.line_endings,
+            show_indent_columns: self.indent_stats,
+            extended_json: self.extended_json,
+            show_ratios: self.ratios,
+            show_largest: self.show_largest,
+            show_extensions: self.verbose > 0,
             csv_delimiter: self.csv_delimiter.map(|c| c as u8).unwrap_or(b','),
             by_percent: self.by_percent,
             summary_cutoff: self.summary_cutoff,
+            top: self.top,
+            cutoff_percent: self.cutoff_percent,
+            template: self.template.clone(),
+            columns,
+            by_category: self.by_category,
+            by_extension: self.by_ext,
+            merge_languages: self.language_merges(),
+        }
+    }
+
+    /// `--merge-lang` pairs, followed by the header merges of `--group-headers`.
+    fn language_merges(&self) -> Vec<(String, String)> {
+        let mut merges = self.merge_lang.clone();
+        if self.group_headers {
+            merges.extend(
+                [("C Header", "C"), ("C++ Header", "C++")]
+                    .map(|(from, into)| (from.to_string(), into.to_string())),
+            );
+        }
+        merges
+    }
+
+    /// Reject output options the chosen format would silently ignore.
+    pub fn check_output_options(&self) -> Result<(), String> {
+        let table =
+            self.template.is_none() && self.to_output_config().format == OutputFormat::Table;
+        if self.show_largest.is_some() && !table {
+            return Err("--show-largest only applies to the table format".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a single file is read from stdin: the only path is `-` or
+    /// `--stdin-format source` was given.
+    pub fn reads_stdin(&self) -> bool {
+        match self.stdin_format {
+            Some(StdinFormat::Source) => true,
+            Some(StdinFormat::Tar) => false,
+            None => self.paths.len() == 1 && self.paths[0] == Path::new("-"),
+        }
+    }
+
+    /// Whether a tar archive is read from stdin (`--stdin-format tar`). Paths other than
+    /// `-` are an error, since nothing else is counted.
+    pub fn reads_tar_stdin(&self) -> Result<bool, String> {
+        if self.stdin_format != Some(StdinFormat::Tar) {
+            return Ok(false);
+        }
+        match self.paths.as_slice() {
+            [path] if path == Path::new(".") || path == Path::new("-") => Ok(true),
+            _ => Err("--stdin-format tar reads stdin and takes no PATH arguments".to_string()),
+        }
+    }
+
+    /// Language of the source read from stdin: a `--force-lang LANG` without extension,
+    /// otherwise detected from `--stdin-name` (honouring `--force-lang LANG,EXT` and
+    /// `--map-ext`).
+    pub fn stdin_language(&self) -> Result<&'static Language, String> {
+        if self.stdin_name.is_none() && !self.force_lang.iter().any(|spec| !spec.contains(',')) {
+            return Err(
+                "Reading from stdin needs --stdin-name NAME or --force-lang LANG to detect the language"
+                    .to_string(),
+            );
+        }
+        self.file_language(self.stdin_name.as_deref().unwrap_or(Path::new("-")))
+    }
+
+    /// Language of the single file `path` (stdin, `rloc annotate`): a bare `--force-lang
+    /// LANG`, otherwise what a walk would detect from the name.
+    pub fn file_language(&self, path: &Path) -> Result<&'static Language, String> {
+        if let Some(name) = self.force_lang.iter().find(|spec| !spec.contains(',')) {
+            return self.language(name, "--force-lang");
+        }
+
+        let config = self.to_walker_config()?;
+        detect_path_language(&config, path).ok_or_else(|| {
+            format!(
+                "Cannot detect the language of '{}'; use --force-lang LANG",
+                path.display()
+            )
+        })
+    }
+
+    /// The custom or built-in language `name` names (aliases and any case), or an error
+    /// naming `option`.
+    fn language(&self, name: &str, option: &str) -> Result<&'static Language, String> {
+        self.custom_languages
+            .as_deref()
+            .and_then(|registry| registry.get(name))
+            .or_else(|| get_language_ignore_case(name))
+            .ok_or_else(|| format!("Unknown language '{}' in {}", name, option))
+    }
+
+    /// The old and new side of `rloc diff OLD NEW` or `--diff`: `--diff OLD NEW`, or PATH
+    /// compared with `--diff NEW`.
+    pub fn diff_sides(&self) -> Option<(Vec<PathBuf>, PathBuf)> {
+        if let Some(Command::Diff(ref diff)) = self.command {
+            return Some((vec![diff.old.clone()], diff.new.clone()));
+        }
+        match self.diff.as_slice() {
+            [old, new] => Some((vec![old.clone()], new.clone())),
+            [new] => Some((self.paths.clone(), new.clone())),
+            _ => None,
         }
     }
 
@@ -437,8 +2177,8 @@ impl Cli {
     }
 }
 
-pub fn show_languages() {
-    use crate::languages::list_languages;
+pub fn show_languages(custom: Option<&CustomLanguageRegistry>) {
+    use crate::languages::{Language, list_languages};
     use comfy_table::{Table, presets::UTF8_FULL_CONDENSED};
 
     let mut table = Table::new();
@@ -451,13 +2191,29 @@ pub fn show_languages() {
         "Nested",
     ]);
 
-    let mut langs: Vec<_> = list_languages().collect();
-    langs.sort_by_key(|(name, _)| *name);
+    let mut langs: Vec<(String, &Language)> = list_languages()
+        .filter(|(name, _)| custom.is_none_or(|c| c.get(name).is_none()))
+        .map(|(name, lang)| (name.to_string(), lang))
+        .chain(
+            custom
+                .into_iter()
+                .flat_map(|c| c.languages())
+                .map(|(name, lang)| (format!("{} (custom)", name), lang)),
+        )
+        .collect();
+    langs.sort_by(|a, b| a.0.cmp(&b.0));
 
     for (name, lang) in langs {
         table.add_row([
-            name,
-            &lang.line_comments.join(", "),
+            name.as_str(),
+            &lang
+                .line_comments
+                .iter()
+                .chain(lang.word_comments)
+                .chain(lang.line_start_comments)
+                .copied()
+                .collect::<Vec<_>>()
+                .join(", "),
             lang.block_comment_start.unwrap_or("-"),
             lang.block_comment_end.unwrap_or("-"),
             if lang.nested_comments { "yes" } else { "no" },
@@ -467,7 +2223,7 @@ pub fn show_languages() {
     println!("{}", table);
 }
 
-pub fn show_extensions() {
+pub fn show_extensions(custom: Option<&CustomLanguageRegistry>) {
     use crate::languages::list_extensions;
     use comfy_table::{Table, presets::UTF8_FULL_CONDENSED};
 
@@ -475,8 +2231,17 @@ pub fn show_extensions() {
     table.load_preset(UTF8_FULL_CONDENSED);
     table.set_header(["Extension", "Language"]);
 
-    let mut exts: Vec<_> = list_extensions().collect();
-    exts.sort_by_key(|(ext, _)| *ext);
+    let mut exts: Vec<(String, String)> = list_extensions()
+        .filter(|(ext, _)| custom.is_none_or(|c| c.get_by_extension(ext).is_none()))
+        .map(|(ext, lang)| (ext.to_string(), lang.to_string()))
+        .chain(
+            custom
+                .into_iter()
+                .flat_map(|c| c.extensions())
+                .map(|(ext, lang)| (ext.to_string(), format!("{} (custom)", lang))),
+        )
+        .collect();
+    exts.sort();
 
     for (ext, lang) in exts {
         table.add_row([ext, lang]);
@@ -484,3 +2249,28 @@ pub fn show_extensions() {
 
     println!("{}", table);
 }
+
+/// Print unrecognized files followed by a histogram of their extensions.
+pub fn show_unrecognized(paths: &[PathBuf]) {
+    use crate::stats::extension_histogram;
+    use comfy_table::{Table, presets::UTF8_FULL_CONDENSED};
+
+    if paths.is_empty() {
+        println!("No unrecognized files.");
+        return;
+    }
+
+    for path in paths {
+        println!("{}", path.display());
+    }
+
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL_CONDENSED);
+    table.set_header(["Extension", "Files"]);
+    for (ext, count) in extension_histogram(paths) {
+        table.add_row([ext, count.to_string()]);
+    }
+
+    println!();
+    println!("{}", table);
+}
@@ -0,0 +1,616 @@
+use crate::walker::{ExcludeProfile, VcsMode};
+use crate::{AnalyzeConfig, Error, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// File names looked up in the analyzed directory, in order of preference.
+pub const PROJECT_CONFIG_FILES: [&str; 2] = ["rloc.toml", ".rlocrc"];
+
+/// Settings read from a `rloc.toml` / `.rlocrc` file.
+///
+/// Keys mirror the long CLI flags (`exclude-dir`, `include-lang`, `format`, ...).
+/// Every field is optional; unset fields leave the defaults untouched.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigFile {
+    pub exclude_dir: Option<Vec<String>>,
+    pub exclude_ext: Option<Vec<String>>,
+    pub exclude_lang: Option<Vec<String>>,
+    pub include_ext: Option<Vec<String>>,
+    pub include_lang: Option<Vec<String>>,
+    pub force_lang: Option<Vec<String>>,
+    pub force_lang_def: Option<Vec<String>>,
+    pub map_ext: Option<Vec<String>>,
+    pub unmap_ext: Option<Vec<String>>,
+    pub modelines: Option<bool>,
+    pub match_d: Option<String>,
+    pub not_match_d: Option<Vec<String>>,
+    pub match_f: Option<String>,
+    pub not_match_f: Option<Vec<String>>,
+    pub include_glob: Option<Vec<String>>,
+    pub exclude_glob: Option<Vec<String>>,
+    pub include_content: Option<String>,
+    pub exclude_content: Option<String>,
+    pub fullpath: Option<bool>,
+    pub no_generated: Option<bool>,
+    pub generated_marker: Option<Vec<String>>,
+    pub skip_minified: Option<bool>,
+    pub logical_lines: Option<bool>,
+    pub accurate: Option<bool>,
+    pub docstrings_as_code: Option<bool>,
+    pub follow_origins: Option<bool>,
+    pub comments_as_code: Option<Vec<String>>,
+    pub ignore_comments: Option<bool>,
+    pub require_final_newline: Option<bool>,
+    pub line_endings: Option<bool>,
+    pub indent_stats: Option<bool>,
+    pub include_vendored: Option<bool>,
+    pub exclude_vendored: Option<bool>,
+    pub vendor_pattern: Option<Vec<String>>,
+    pub split_tests: Option<bool>,
+    pub exclude_tests: Option<bool>,
+    pub test_pattern: Option<Vec<String>>,
+    pub vcs: Option<String>,
+    pub follow_symlinks: Option<bool>,
+    pub one_file_system: Option<bool>,
+    pub hidden: Option<bool>,
+    pub no_ignore: Option<bool>,
+    pub profile: Option<String>,
+    pub profiles: Option<HashMap<String, ProfileConfig>>,
+    pub skip_gitignore: Option<bool>,
+    pub no_global_gitignore: Option<bool>,
+    pub no_git_exclude: Option<bool>,
+    pub custom_ignore: Option<Vec<PathBuf>>,
+    pub skip_uniqueness: Option<bool>,
+    pub include_submodules: Option<bool>,
+    pub max_depth: Option<usize>,
+    pub no_recurse: Option<bool>,
+    pub max_file_size: Option<u64>,
+    pub binary_sample_size: Option<usize>,
+    pub binary_null_ratio: Option<f64>,
+    pub long_line_limit: Option<usize>,
+    pub format: Option<String>,
+    pub sort: Option<String>,
+    pub columns: Option<Vec<String>>,
+    pub summary_cutoff: Option<usize>,
+    pub top: Option<usize>,
+    pub cutoff_percent: Option<f64>,
+    pub by_file: Option<bool>,
+    pub by_file_by_lang: Option<bool>,
+    pub by_category: Option<bool>,
+    pub by_ext: Option<bool>,
+    pub merge_lang: Option<Vec<String>>,
+    /// `[rename]` table of language rows to report under another name, like `merge-lang`.
+    pub rename: Option<HashMap<String, String>>,
+    pub group_headers: Option<bool>,
+    pub group_by_path: Option<bool>,
+    pub nested_repos: Option<bool>,
+    pub exclude_nested_repos: Option<bool>,
+    pub churn: Option<bool>,
+    pub since: Option<String>,
+    pub by_project: Option<bool>,
+    pub by_percent: Option<bool>,
+    pub hide_rate: Option<bool>,
+    pub extended_json: Option<bool>,
+    pub ratios: Option<bool>,
+    pub show_largest: Option<usize>,
+    pub show_total: Option<bool>,
+    pub csv_delimiter: Option<char>,
+    pub color: Option<String>,
+    pub code_color: Option<String>,
+    pub template: Option<PathBuf>,
+    pub quiet: Option<bool>,
+    pub fail_on_empty: Option<bool>,
+    pub threads: Option<usize>,
+    pub max_files: Option<usize>,
+    /// Seconds, fractions allowed.
+    pub timeout: Option<f64>,
+    pub read_lang_def: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let mut config: ConfigFile = toml::from_str(&content).map_err(|e| {
+            Error::InvalidConfig(format!("Failed to parse {}: {}", path.display(), e))
+        })?;
+        config.resolve_paths(path.parent().unwrap_or(Path::new(".")));
+        Ok(config)
+    }
+
+    /// Find and merge the user-level config and the config in `dir`.
+    ///
+    /// The user-level file (`$XDG_CONFIG_HOME/rloc/config.toml`) is applied first so
+    /// project settings win.
+    pub fn discover(dir: &Path) -> Result<Option<Self>> {
+        let mut merged: Option<ConfigFile> = None;
+
+        let candidates = user_config_path()
+            .into_iter()
+            .chain(find_project_config(dir));
+
+        for path in candidates {
+            if path.is_file() {
+                let config = Self::load(&path)?;
+                merged = Some(match merged {
+                    Some(base) => base.merge(config),
+                    None => config,
+                });
+            }
+        }
+
+        Ok(merged)
+    }
+
+    /// Overlay `other` on top of `self`; fields set in `other` win. Profiles are merged by
+    /// name, so user-level profiles stay available in projects that define their own.
+    pub fn merge(mut self, mut other: ConfigFile) -> Self {
+        if let Some(profiles) = other.profiles.as_mut() {
+            for (name, profile) in self.profiles.take().unwrap_or_default() {
+                profiles.entry(name).or_insert(profile);
+            }
+        }
+        macro_rules! merge_fields {
+            ($($field:ident),* $(,)?) => {
+                ConfigFile { $($field: other.$field.or(self.$field)),* }
+            };
+        }
+
+        merge_fields!(
+            exclude_dir,
+            exclude_ext,
+            exclude_lang,
+            include_ext,
+            include_lang,
+            force_lang,
+            force_lang_def,
+            map_ext,
+            unmap_ext,
+            modelines,
+            match_d,
+            not_match_d,
+            match_f,
+            not_match_f,
+            include_glob,
+            exclude_glob,
+            include_content,
+            exclude_content,
+            fullpath,
+            no_generated,
+            generated_marker,
+            skip_minified,
+            logical_lines,
+            accurate,
+            docstrings_as_code,
+            follow_origins,
+            comments_as_code,
+            ignore_comments,
+            require_final_newline,
+            line_endings,
+            indent_stats,
+            include_vendored,
+            exclude_vendored,
+            vendor_pattern,
+            split_tests,
+            exclude_tests,
+            test_pattern,
+            vcs,
+            follow_symlinks,
+            one_file_system,
+            hidden,
+            no_ignore,
+            profile,
+            profiles,
+            skip_gitignore,
+            no_global_gitignore,
+            no_git_exclude,
+            custom_ignore,
+            skip_uniqueness,
+            include_submodules,
+            max_depth,
+            no_recurse,
+            max_file_size,
+            binary_sample_size,
+            long_line_limit,
+            binary_null_ratio,
+            format,
+            sort,
+            columns,
+            summary_cutoff,
+            top,
+            cutoff_percent,
+            by_file,
+            by_file_by_lang,
+            by_category,
+            by_ext,
+            merge_lang,
+            rename,
+            group_headers,
+            group_by_path,
+            nested_repos,
+            exclude_nested_repos,
+            churn,
+            since,
+            by_project,
+            by_percent,
+            hide_rate,
+            extended_json,
+            ratios,
+            show_largest,
+            show_total,
+            csv_delimiter,
+            color,
+            code_color,
+            template,
+            quiet,
+            fail_on_empty,
+            threads,
+            max_files,
+            timeout,
+            read_lang_def,
+        )
+    }
+
+    /// Make file paths in the config relative to the directory containing it.
+    fn resolve_paths(&mut self, base: &Path) {
+        for path in [&mut self.template, &mut self.read_lang_def]
+            .into_iter()
+            .flatten()
+            .chain(self.custom_ignore.iter_mut().flatten())
+        {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        }
+    }
+}
+
+/// A named set of excluded directories, defined as `[profiles.NAME]` and selected with
+/// `profile = "NAME"` or `--profile NAME`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Built-in profile (`minimal`, `default` or `aggressive`) whose directories come first.
+    pub extends: Option<String>,
+    #[serde(default)]
+    pub exclude_dir: Vec<String>,
+}
+
+/// Directories excluded by the profile `name`: one defined in `profiles`, which may shadow a
+/// built-in profile, or else the built-in [`ExcludeProfile`] of that name.
+pub fn resolve_profile(
+    profiles: &HashMap<String, ProfileConfig>,
+    name: &str,
+) -> Result<Vec<String>> {
+    let builtin = |name: &str| {
+        ExcludeProfile::from_name(name)
+            .map(ExcludeProfile::exclude_dirs)
+            .ok_or_else(|| {
+                let known: Vec<_> = ExcludeProfile::ALL.iter().map(|p| p.name()).collect();
+                Error::InvalidConfig(format!(
+                    "Unknown profile '{}' (built-in profiles: {})",
+                    name,
+                    known.join(", ")
+                ))
+            })
+    };
+    let Some(profile) = profiles.get(name) else {
+        return builtin(name);
+    };
+    let mut dirs = match profile.extends {
+        Some(ref base) => builtin(base)?,
+        None => Vec::new(),
+    };
+    dirs.extend(profile.exclude_dir.iter().cloned());
+    Ok(dirs)
+}
+
+/// `$XDG_CONFIG_HOME/rloc/config.toml`, falling back to `~/.config/rloc/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("rloc").join("config.toml"))
+}
+
+/// The first project config file present in `dir`.
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    PROJECT_CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+impl AnalyzeConfig {
+    /// Build an analysis config from a `rloc.toml` file, analyzing the directory
+    /// that contains it.
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = ConfigFile::load(path)?;
+        let root = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        let mut config = AnalyzeConfig::new(root);
+        if let Some(ref name) = file.profile {
+            config.exclude_dirs = resolve_profile(&file.profiles.unwrap_or_default(), name)?;
+        }
+        if file.no_ignore.unwrap_or(false) {
+            config.exclude_dirs.clear();
+        }
+        config
+            .exclude_dirs
+            .extend(file.exclude_dir.unwrap_or_default());
+        config.exclude_exts = file.exclude_ext.unwrap_or_default();
+        config.exclude_langs = file.exclude_lang.unwrap_or_default();
+        config.include_exts = file.include_ext.unwrap_or_default();
+        config.include_langs = file.include_lang.unwrap_or_default();
+        config.include_globs = file.include_glob.unwrap_or_default();
+        config.exclude_globs = file.exclude_glob.unwrap_or_default();
+        config.follow_symlinks = file.follow_symlinks.unwrap_or(false);
+        config.one_file_system = file.one_file_system.unwrap_or(false);
+        config.hidden = file.hidden.unwrap_or(false);
+        config.modelines = file.modelines.unwrap_or(false);
+        config.max_depth = if file.no_recurse.unwrap_or(false) {
+            Some(1)
+        } else {
+            file.max_depth
+        };
+        config.skip_gitignore = file.skip_gitignore.unwrap_or(false);
+        config.skip_global_gitignore = file.no_global_gitignore.unwrap_or(false);
+        config.skip_git_exclude = file.no_git_exclude.unwrap_or(false);
+        config.custom_ignore_files = file.custom_ignore.unwrap_or_default();
+        config.skip_uniqueness = file.skip_uniqueness.unwrap_or(false);
+        config.max_file_size = file.max_file_size;
+        config.binary_sample_size = file.binary_sample_size;
+        config.long_line_limit = file.long_line_limit;
+        config.binary_null_ratio = file.binary_null_ratio;
+        config.threads = file.threads;
+        config.max_files = file.max_files;
+        if let Some(secs) = file.timeout {
+            config.timeout = Some(std::time::Duration::try_from_secs_f64(secs).map_err(|_| {
+                Error::InvalidConfig(format!("Invalid timeout {} in {}", secs, path.display()))
+            })?);
+        }
+        config.exclude_generated = file.no_generated.unwrap_or(false);
+        config.skip_minified = file.skip_minified.unwrap_or(false);
+        config.logical_lines = file.logical_lines.unwrap_or(false);
+        config.accurate = file.accurate.unwrap_or(false);
+        config.docstrings_as_code = file.docstrings_as_code.unwrap_or(false);
+        config.follow_origins = file.follow_origins.unwrap_or(false);
+        config.comments_as_code = file.comments_as_code.unwrap_or_default();
+        config.ignore_comments = file.ignore_comments.unwrap_or(false);
+        config.require_final_newline = file.require_final_newline.unwrap_or(false);
+        config.line_endings = file.line_endings.unwrap_or(false);
+        config.indent_stats = file.indent_stats.unwrap_or(false);
+        if file.include_vendored.unwrap_or(false) {
+            config
+                .exclude_dirs
+                .retain(|dir| !crate::walker::VENDOR_DIRS.contains(&dir.as_str()));
+        }
+        config.exclude_vendored = file.exclude_vendored.unwrap_or(false);
+        if let Some(ref path) = file.read_lang_def {
+            let registry = crate::custom_langs::CustomLanguageRegistry::from_file(path)
+                .map_err(Error::InvalidConfig)?;
+            config.custom_languages = Some(std::sync::Arc::new(registry));
+        }
+        let invalid = |key: &str, value: &str, expected: &str| {
+            Error::InvalidConfig(format!(
+                "Invalid '{}' entry '{}' in {}, expected {}",
+                key,
+                value,
+                path.display(),
+                expected
+            ))
+        };
+        for spec in file.force_lang.unwrap_or_default() {
+            let (lang, ext) = spec
+                .split_once(',')
+                .ok_or_else(|| invalid("force-lang", &spec, "LANG,EXT"))?;
+            config
+                .force_lang
+                .insert(ext.to_lowercase(), lang.to_string());
+        }
+        let custom = config.custom_languages.clone();
+        let language = |name: &str, key: &str| {
+            custom
+                .as_deref()
+                .and_then(|registry| registry.get(name))
+                .or_else(|| crate::languages::get_language_ignore_case(name))
+                .map(|language| language.name)
+                .ok_or_else(|| {
+                    Error::InvalidConfig(format!("Unknown language '{}' in {}", name, key))
+                })
+        };
+        for spec in file.force_lang_def.unwrap_or_default() {
+            let (pattern, lang) = spec
+                .rsplit_once(':')
+                .ok_or_else(|| invalid("force-lang-def", &spec, "GLOB:LANG"))?;
+            config = config.force_lang_glob(pattern, language(lang, "force-lang-def")?);
+        }
+        for spec in file.map_ext.unwrap_or_default() {
+            let (ext, lang) = spec
+                .split_once('=')
+                .ok_or_else(|| invalid("map-ext", &spec, "EXT=LANG"))?;
+            config = config.map_ext(ext, language(lang, "map-ext")?);
+        }
+        config.unmapped_exts = file.unmap_ext.unwrap_or_default();
+        config.match_dir = file.match_d;
+        config.not_match_dir = file.not_match_d.unwrap_or_default();
+        config.match_file = file.match_f;
+        config.not_match_file = file.not_match_f.unwrap_or_default();
+        config.fullpath = file.fullpath.unwrap_or(false);
+        config.include_content = file.include_content;
+        config.exclude_content = file.exclude_content;
+        config.generated_markers = file.generated_marker.unwrap_or_default();
+        config.include_submodules = file.include_submodules.unwrap_or(false);
+        if let Some(ref vcs) = file.vcs {
+            config.vcs = Some(match vcs.to_ascii_lowercase().as_str() {
+                "auto" => VcsMode::Auto,
+                "git" => VcsMode::Git,
+                "hg" => VcsMode::Hg,
+                "svn" => VcsMode::Svn,
+                "none" => VcsMode::None,
+                _ => return Err(invalid("vcs", vcs, "auto, git, hg, svn or none")),
+            });
+        }
+        config
+            .vendor_patterns
+            .extend(file.vendor_pattern.unwrap_or_default());
+        config.split_tests = file.split_tests.unwrap_or(false);
+        config.exclude_tests = file.exclude_tests.unwrap_or(false);
+        config.exclude_nested_repos = file.exclude_nested_repos.unwrap_or(false);
+        config
+            .test_patterns
+            .extend(file.test_pattern.unwrap_or_default());
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_from_config_file() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("rloc.toml");
+        fs::write(
+            &path,
+            "exclude-dir = [\"generated\"]\ninclude-lang = [\"Rust\"]\nmax-depth = 2\n",
+        )
+        .unwrap();
+
+        let config = AnalyzeConfig::from_config_file(&path).unwrap();
+        assert_eq!(config.paths, vec![temp.path().to_path_buf()]);
+        assert!(config.exclude_dirs.contains(&"generated".to_string()));
+        assert!(config.exclude_dirs.contains(&"node_modules".to_string()));
+        assert_eq!(config.include_langs, vec!["Rust".to_string()]);
+        assert_eq!(config.max_depth, Some(2));
+    }
+
+    #[test]
+    fn test_unknown_key_rejected() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("rloc.toml");
+        fs::write(&path, "not-an-option = true\n").unwrap();
+
+        let result = ConfigFile::load(&path);
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_merge_prefers_later_file() {
+        let base = ConfigFile {
+            format: Some("json".into()),
+            threads: Some(2),
+            ..Default::default()
+        };
+        let project = ConfigFile {
+            format: Some("csv".into()),
+            ..Default::default()
+        };
+
+        let merged = base.merge(project);
+        assert_eq!(merged.format.as_deref(), Some("csv"));
+        assert_eq!(merged.threads, Some(2));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("rloc.toml");
+        fs::write(
+            &path,
+            "profile = \"web\"\n\n[profiles.web]\nextends = \"minimal\"\nexclude-dir = [\"coverage\"]\n",
+        )
+        .unwrap();
+
+        let config = AnalyzeConfig::from_config_file(&path).unwrap();
+        assert_eq!(config.exclude_dirs, [".git", ".svn", ".hg", "coverage"]);
+
+        let user = ConfigFile::load(&path).unwrap();
+        let project = ConfigFile {
+            profiles: Some(HashMap::from([("docs".into(), ProfileConfig::default())])),
+            ..Default::default()
+        };
+        let profiles = user.merge(project).profiles.unwrap();
+        assert!(profiles.contains_key("web") && profiles.contains_key("docs"));
+
+        let aggressive = resolve_profile(&profiles, "Aggressive").unwrap();
+        assert!(aggressive.contains(&"node_modules".to_string()));
+        assert!(aggressive.contains(&".terraform".to_string()));
+        assert!(matches!(
+            resolve_profile(&profiles, "huge"),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_config_file_applies_walker_keys() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("build.cfg"), "x = 1\n").unwrap();
+        fs::write(temp.path().join("notes.txt"), "text\n").unwrap();
+        fs::write(temp.path().join("skip.rs"), "// @generated\nfn a() {}\n").unwrap();
+        let path = temp.path().join("rloc.toml");
+        fs::write(
+            &path,
+            "map-ext = [\"cfg=python\"]\nunmap-ext = [\"toml\"]\nnot-match-f = [\"^notes\"]\n\
+             generated-marker = [\"@generated\"]\nno-generated = true\nvcs = \"none\"\n",
+        )
+        .unwrap();
+
+        let config = AnalyzeConfig::from_config_file(&path).unwrap();
+        assert_eq!(config.force_lang["cfg"], "Python");
+        assert_eq!(config.vcs, Some(VcsMode::None));
+        let analysis = crate::analyze_with_config(config).unwrap();
+        let languages: Vec<_> = analysis.languages.iter().map(|l| &*l.name).collect();
+        assert_eq!(languages, ["Python"]);
+
+        fs::write(&path, "map-ext = [\"cfg=nope\"]\n").unwrap();
+        assert!(matches!(
+            AnalyzeConfig::from_config_file(&path),
+            Err(Error::InvalidConfig(_))
+        ));
+        fs::write(&path, "match-d = \"(\"\n").unwrap();
+        let config = AnalyzeConfig::from_config_file(&path).unwrap();
+        assert!(matches!(
+            crate::analyze_with_config(config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_config_file_applies_force_lang_def() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir(temp.path().join("ci")).unwrap();
+        fs::write(temp.path().join("ci/Jenkinsfile.prod"), "node {}\n").unwrap();
+        let path = temp.path().join("rloc.toml");
+        fs::write(
+            &path,
+            "force-lang-def = [\"ci/Jenkinsfile*:groovy\"]\nunmap-ext = [\"toml\"]\n",
+        )
+        .unwrap();
+
+        let config = AnalyzeConfig::from_config_file(&path).unwrap();
+        assert_eq!(
+            config.force_lang_globs,
+            [("ci/Jenkinsfile*".to_string(), "Groovy".to_string())]
+        );
+        let analysis = crate::analyze_with_config(config).unwrap();
+        let languages: Vec<_> = analysis.languages.iter().map(|l| &*l.name).collect();
+        assert_eq!(languages, ["Groovy"]);
+
+        fs::write(&path, "force-lang-def = [\"ci/*:Nope\"]\n").unwrap();
+        assert!(matches!(
+            AnalyzeConfig::from_config_file(&path),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
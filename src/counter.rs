@@ -1,101 +1,1104 @@
-use crate::languages::Language;
+use crate::cancel::{CancellationToken, is_cancelled};
+use crate::languages::{Heredoc, Language};
+use crate::policy::CountPolicy;
+#[cfg(feature = "fs")]
+use memmap2::Mmap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "fs")]
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::{BufRead, BufReader, Read};
+use std::io::Read;
+#[cfg(feature = "fs")]
+use std::ops::Deref;
+use std::ops::Range;
 use std::path::Path;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FileStats {
     pub path: String,
     pub language: String,
     pub code: u64,
     pub comments: u64,
     pub blanks: u64,
+    pub generated: bool,
+    pub vendored: bool,
+    /// Minified or bundled JavaScript/CSS; see [`is_minified`].
+    pub minified: bool,
+    /// Skipped as binary data; no lines are counted.
+    #[serde(skip)]
+    pub binary: bool,
+    /// Documentation comment lines (`///`, `/** */`, docstrings, ...); included in `comments`.
+    pub doc_comments: u64,
+    /// Statements, when counted (see [`CountConfig::logical_lines`]).
+    pub logical: u64,
+    /// Code lines in test files or inline test modules, when split out with
+    /// [`TestMode::Split`]; not included in `code`.
+    pub test_code: u64,
+    /// File size in bytes.
+    pub bytes: u64,
+    /// Bytes in counted lines, excluding line terminators.
+    #[serde(default)]
+    pub line_bytes: u64,
+    /// Length of the longest line in bytes, excluding the line terminator.
+    pub max_line_length: u64,
+    /// Lines excluded by `rloc:ignore-*` directives (see [`IGNORE_START`]); not included in
+    /// any other count.
+    #[serde(default)]
+    pub ignored: u64,
+    /// Line terminators by kind, when counted (see [`CountConfig::line_endings`]).
+    #[serde(default, skip_serializing_if = "LineEndings::is_empty")]
+    pub line_endings: LineEndings,
+    /// Tabs versus spaces and indent widths, when counted (see [`CountConfig::indent_stats`]).
+    #[serde(default, skip_serializing_if = "IndentStats::is_empty")]
+    pub indent: IndentStats,
+    /// Other languages embedded in this file (R Markdown chunks, notebook cells; see
+    /// [`crate::literate`]), reported under their own languages but not as extra files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embedded: Vec<FileStats>,
+    /// SHA-256 of the file's content as lowercase hex, when recorded (see
+    /// [`CountConfig::content_hash`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
 }
 
 impl FileStats {
     pub fn total(&self) -> u64 {
-        self.code + self.comments + self.blanks
+        self.code + self.test_code + self.comments + self.blanks
+    }
+
+    /// No lines were counted, in this file or embedded in it.
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0 && self.embedded.iter().all(FileStats::is_empty)
+    }
+
+    /// Every line was excluded by ignore directives, e.g. `rloc:ignore-file`. Such files
+    /// only add to `ignored`, not to file counts.
+    pub fn is_ignored(&self) -> bool {
+        self.ignored > 0 && self.is_empty()
+    }
+
+    /// Reclassify all code as test code (for files matched by test path patterns).
+    pub fn mark_as_test(&mut self) {
+        self.test_code += self.code;
+        self.code = 0;
+    }
+
+    /// Average bytes per line, excluding line terminators.
+    pub fn avg_line_length(&self) -> f64 {
+        average(self.line_bytes, self.total())
+    }
+}
+
+/// Line terminators of a file by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineEndings {
+    pub lf: u64,
+    pub crlf: u64,
+    /// Lone `\r`, as written by classic Mac OS.
+    pub cr: u64,
+}
+
+impl LineEndings {
+    /// Count the terminators in `content`.
+    pub fn of(content: &[u8]) -> Self {
+        let mut endings = LineEndings::default();
+        let mut terminators = memchr::memchr2_iter(b'\n', b'\r', content);
+        while let Some(at) = terminators.next() {
+            if content[at] == b'\n' {
+                endings.lf += 1;
+            } else if content.get(at + 1) == Some(&b'\n') {
+                endings.crlf += 1;
+                terminators.next();
+            } else {
+                endings.cr += 1;
+            }
+        }
+        endings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lf == 0 && self.crlf == 0 && self.cr == 0
+    }
+
+    pub fn add(&mut self, other: &LineEndings) {
+        self.lf += other.lf;
+        self.crlf += other.crlf;
+        self.cr += other.cr;
+    }
+
+    /// `"lf"`, `"crlf"` or `"cr"` when only one kind is used, `"mixed"` otherwise; `None`
+    /// without any terminator.
+    pub fn style(&self) -> Option<&'static str> {
+        match (self.lf > 0, self.crlf > 0, self.cr > 0) {
+            (false, false, false) => None,
+            (true, false, false) => Some("lf"),
+            (false, true, false) => Some("crlf"),
+            (false, false, true) => Some("cr"),
+            _ => Some("mixed"),
+        }
+    }
+}
+
+/// Largest indent step, in columns, tallied by [`IndentStats::steps`].
+pub const MAX_INDENT_STEP: usize = 8;
+
+/// Indentation of the lines of a file outside block comments and strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndentStats {
+    /// Lines whose indentation contains a tab.
+    pub tabs: u64,
+    /// Lines indented with spaces only.
+    pub spaces: u64,
+    /// How often the indentation grows by 1 to [`MAX_INDENT_STEP`] spaces from one line to
+    /// the next (index 0 is one space).
+    pub steps: [u64; MAX_INDENT_STEP],
+}
+
+impl IndentStats {
+    pub fn is_empty(&self) -> bool {
+        self.tabs == 0 && self.spaces == 0
+    }
+
+    pub fn add(&mut self, other: &IndentStats) {
+        self.tabs += other.tabs;
+        self.spaces += other.spaces;
+        for (step, other) in self.steps.iter_mut().zip(other.steps) {
+            *step += other;
+        }
+    }
+
+    /// The most common space indent step (ties go to the narrower one); `None` without
+    /// nested space indentation.
+    pub fn width(&self) -> Option<u64> {
+        let (index, &count) = self
+            .steps
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|&(_, count)| count)?;
+        (count > 0).then_some(index as u64 + 1)
+    }
+}
+
+/// Builds [`IndentStats`] line by line.
+#[derive(Debug, Default)]
+struct IndentTracker {
+    stats: IndentStats,
+    /// Spaces before the previous space-indented or unindented line.
+    previous: usize,
+}
+
+impl IndentTracker {
+    fn line(&mut self, line: &[u8]) {
+        let width = line
+            .iter()
+            .take_while(|&&b| b == b' ' || b == b'\t')
+            .count();
+        if line[..width].contains(&b'\t') {
+            self.stats.tabs += 1;
+            return;
+        }
+        self.stats.spaces += u64::from(width > 0);
+        if width > self.previous && width - self.previous <= MAX_INDENT_STEP {
+            self.stats.steps[width - self.previous - 1] += 1;
+        }
+        self.previous = width;
+    }
+}
+
+pub(crate) fn average(bytes: u64, lines: u64) -> f64 {
+    if lines == 0 {
+        0.0
+    } else {
+        bytes as f64 / lines as f64
+    }
+}
+
+/// Markers that identify machine-generated files when found near the top.
+pub const DEFAULT_GENERATED_MARKERS: &[&str] = &[
+    "@generated",
+    "DO NOT EDIT",
+    "Code generated by",
+    "Generated by the protocol buffer compiler",
+    "Autogenerated by Thrift Compiler",
+    "This file was automatically generated",
+];
+
+/// Lines from a directive with this marker up to one with [`IGNORE_END`] are not counted,
+/// only tallied as `ignored`. A directive is a line whose first word, after whitespace and
+/// punctuation such as comment markers, is the marker (`// rloc:ignore-start`).
+pub const IGNORE_START: &str = "rloc:ignore-start";
+
+/// Ends a region opened by [`IGNORE_START`]; without it the region runs to the end of the file.
+pub const IGNORE_END: &str = "rloc:ignore-end";
+
+/// A directive with this marker in the first [`GENERATED_SCAN_LINES`] lines excludes the
+/// whole file from counting.
+pub const IGNORE_FILE: &str = "rloc:ignore-file";
+
+/// Only this many leading lines are searched for generated-code markers.
+pub const GENERATED_SCAN_LINES: usize = 20;
+
+/// JavaScript/CSS files with a line at least this long are treated as minified.
+pub const MINIFIED_LINE_LENGTH: usize = 1000;
+
+/// JavaScript/CSS files averaging more than this many bytes per line are treated as minified.
+pub const MINIFIED_AVG_LINE_LENGTH: f64 = 200.0;
+
+/// Languages checked for minification.
+pub const MINIFIABLE_LANGUAGES: &[&str] = &["JavaScript", "CSS"];
+
+/// Comment prefixes that point a bundle at its source map.
+pub(crate) const SOURCE_MAP_MARKERS: &[&str] = &["//# sourceMappingURL=", "/*# sourceMappingURL="];
+
+/// Bytes sampled from the start of a file for binary detection.
+pub const BINARY_SAMPLE_SIZE: usize = 8192;
+
+/// Files whose sample is more than this fraction NUL bytes are treated as binary.
+pub const BINARY_NULL_RATIO: f64 = 0.1;
+
+/// Lines longer than this many bytes are classified in chunks of this size (see
+/// [`CountConfig::long_line_limit`]).
+pub const LONG_LINE_LIMIT: usize = 1 << 20;
+
+/// How often (in lines) counting checks for cancellation.
+pub const CANCEL_CHECK_LINES: usize = 64 * 1024;
+
+/// How test code is counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TestMode {
+    /// Test code is ordinary code.
+    #[default]
+    Include,
+    /// Test code is reported separately as `test_code`.
+    Split,
+    /// Test code is not counted at all.
+    Exclude,
+}
+
+#[derive(Debug, Clone)]
+pub struct CountConfig {
+    pub detect_generated: bool,
+    pub generated_markers: Vec<Regex>,
+    /// Flag minified JavaScript/CSS (see [`is_minified`]).
+    pub detect_minified: bool,
+    /// Also count logical lines (statements); see [`LogicalLines`].
+    pub logical_lines: bool,
+    /// Treatment of inline test modules (Rust `#[cfg(test)] mod`). Whole test files are
+    /// classified by path in the walker.
+    pub tests: TestMode,
+    /// Find comments with a tree-sitter parse where a grammar is available (requires the
+    /// `tree-sitter` feature); other languages use the line classifier.
+    pub accurate: bool,
+    /// Count Python docstrings as code instead of (doc) comments.
+    pub docstrings_as_code: bool,
+    /// Bytes inspected for binary detection; `0` disables it.
+    pub binary_sample_size: usize,
+    /// Fraction of NUL bytes in the sample above which a file is binary.
+    pub binary_null_ratio: f64,
+    /// Attribute lines of generated C after `#line` directives to the files they name, and
+    /// flag bundles with a source map as generated (see [`crate::origins`]).
+    pub follow_origins: bool,
+    /// Where comment lines are tallied; applied to the counts of every file.
+    pub policy: CountPolicy,
+    /// Only count lines ended by a line terminator, like `wc -l`: a last line without a
+    /// trailing newline is not counted.
+    pub require_final_newline: bool,
+    /// Count each file's line terminators into [`FileStats::line_endings`].
+    pub line_endings: bool,
+    /// Tally tab- and space-indented lines and indent widths into [`FileStats::indent`].
+    pub indent_stats: bool,
+    /// Hash each file's content into [`FileStats::sha256`], from the bytes that are counted.
+    pub content_hash: bool,
+    /// Lines longer than this many bytes (minified bundles, single-line JSON) are decoded
+    /// and classified this many bytes at a time instead of whole; `0` disables chunking.
+    pub long_line_limit: usize,
+    /// Checked every [`CANCEL_CHECK_LINES`] lines; counting stops with `ErrorKind::Interrupted`.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for CountConfig {
+    fn default() -> Self {
+        Self {
+            detect_generated: true,
+            generated_markers: DEFAULT_GENERATED_MARKERS
+                .iter()
+                .map(|m| Regex::new(&regex::escape(m)).expect("valid marker"))
+                .collect(),
+            detect_minified: true,
+            logical_lines: false,
+            tests: TestMode::Include,
+            accurate: false,
+            docstrings_as_code: false,
+            binary_sample_size: BINARY_SAMPLE_SIZE,
+            binary_null_ratio: BINARY_NULL_RATIO,
+            follow_origins: false,
+            policy: CountPolicy::default(),
+            require_final_newline: false,
+            line_endings: false,
+            indent_stats: false,
+            content_hash: false,
+            long_line_limit: LONG_LINE_LIMIT,
+            cancel: None,
+        }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum State {
     Code,
-    BlockComment { depth: u32 },
-    String { delimiter: char },
+    BlockComment {
+        depth: u32,
+        /// Opened with the language's [`alt_block_comment`](Language::alt_block_comment).
+        alt: bool,
+    },
+    String {
+        delimiter: char,
+    },
+    /// Inside a heredoc body, until a line holding only the terminator (stored as its
+    /// [`hash_bytes`] so the state stays `Copy`).
+    Heredoc {
+        terminator: u64,
+    },
 }
 
+#[cfg(feature = "fs")]
 pub fn count_lines(path: &Path, language: &Language) -> std::io::Result<FileStats> {
-    let file = File::open(path)?;
+    count_lines_with_config(path, language, &CountConfig::default())
+}
+
+#[cfg(feature = "fs")]
+pub fn count_lines_with_config(
+    path: &Path,
+    language: &Language,
+    config: &CountConfig,
+) -> std::io::Result<FileStats> {
+    let source = read_source(path)?;
+    count_bytes(path, &source, language, config)
+}
+
+/// Count everything `reader` yields as if it were the contents of `path` (e.g. stdin in
+/// an editor integration). `path` is only used for reporting and file-name heuristics.
+pub fn count_reader(
+    path: &Path,
+    mut reader: impl Read,
+    language: &Language,
+    config: &CountConfig,
+) -> std::io::Result<FileStats> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    count_bytes(path, &content, language, config)
+}
+
+/// Count an in-memory buffer as if it were the contents of `path`.
+pub fn count_bytes(
+    path: &Path,
+    content: &[u8],
+    language: &Language,
+    config: &CountConfig,
+) -> std::io::Result<FileStats> {
+    let sha256 = config.content_hash.then(|| sha256_hex(content));
+    let stats = FileStats {
+        path: path.display().to_string(),
+        language: language.name.to_string(),
+        bytes: content.len() as u64,
+        sha256: sha256.clone(),
+        ..Default::default()
+    };
+
+    if is_binary(content, config) {
+        tracing::trace!(path = %path.display(), "binary content");
+        return Ok(FileStats {
+            binary: true,
+            ..stats
+        });
+    }
 
-    if is_binary(&file)? {
+    if is_ignored_file(content) {
         return Ok(FileStats {
+            ignored: split_lines(counted_content(content, config)).count() as u64,
+            ..stats
+        });
+    }
+
+    let mut stats = match crate::literate::count(path, content, language, config) {
+        Some(stats) => stats,
+        None if config.follow_origins => crate::origins::count(path, content, language, config)
+            .unwrap_or_else(|| classify_source(path, content, language, config)),
+        None => classify_source(path, content, language, config),
+    }?;
+    config.policy.apply(&mut stats);
+    if config.line_endings {
+        stats.line_endings = LineEndings::of(content);
+    }
+    stats.sha256 = sha256;
+    Ok(stats)
+}
+
+/// How a single line is counted (see [`classify_file`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineKind {
+    Code,
+    Comment,
+    /// Documentation comment; counted under `comments` as well.
+    DocComment,
+    Blank,
+    /// Code in a test module, split out with [`TestMode::Split`].
+    TestCode,
+    /// Part of a test module dropped with [`TestMode::Exclude`].
+    Excluded,
+    /// Inside an `rloc:ignore-start` region or a file marked `rloc:ignore-file`.
+    Ignored,
+}
+
+impl LineKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineKind::Code => "code",
+            LineKind::Comment => "comment",
+            LineKind::DocComment => "doc",
+            LineKind::Blank => "blank",
+            LineKind::TestCode => "test",
+            LineKind::Excluded => "excluded",
+            LineKind::Ignored => "ignored",
+        }
+    }
+}
+
+/// `rloc annotate --json`: the classification of every line of one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Annotation {
+    pub schema_version: u32,
+    pub path: String,
+    pub language: String,
+    pub lines: Vec<AnnotatedLine>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AnnotatedLine {
+    /// 1-based line number.
+    pub line: usize,
+    pub kind: LineKind,
+}
+
+impl Annotation {
+    pub fn new(path: &Path, language: &Language, lines: &[(usize, LineKind)]) -> Self {
+        Annotation {
+            schema_version: crate::stats::SCHEMA_VERSION,
             path: path.display().to_string(),
             language: language.name.to_string(),
-            ..Default::default()
-        });
+            lines: lines
+                .iter()
+                .map(|&(line, kind)| AnnotatedLine { line, kind })
+                .collect(),
+        }
     }
+}
 
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+/// Classify each line of the file at `path` as `language`, returning 1-based line numbers
+/// with their kind. Line counts of [`count_lines`] are the tally of these kinds.
+#[cfg(feature = "fs")]
+pub fn classify_file(path: &Path, language: &Language) -> std::io::Result<Vec<(usize, LineKind)>> {
+    classify_file_with_config(path, language, &CountConfig::default())
+}
+
+#[cfg(feature = "fs")]
+pub fn classify_file_with_config(
+    path: &Path,
+    language: &Language,
+    config: &CountConfig,
+) -> std::io::Result<Vec<(usize, LineKind)>> {
+    let source = read_source(path)?;
+    classify_bytes(path, &source, language, config)
+}
+
+/// [`classify_file`] for an in-memory buffer. Binary content is an `InvalidData` error.
+///
+/// Literate formats (Markdown, notebooks) are classified with `language`'s own rules rather
+/// than per embedded chunk.
+pub fn classify_bytes(
+    path: &Path,
+    content: &[u8],
+    language: &Language,
+    config: &CountConfig,
+) -> std::io::Result<Vec<(usize, LineKind)>> {
+    if is_binary(content, config) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "binary file",
+        ));
+    }
+    if is_ignored_file(content) {
+        return Ok((1..=split_lines(counted_content(content, config)).count())
+            .map(|line| (line, LineKind::Ignored))
+            .collect());
+    }
+    let mut lines = Vec::new();
+    classify_lines(path, content, language, config, |kind| {
+        lines.push((
+            lines.len() + 1,
+            config.policy.line_kind(language.name, kind),
+        ))
+    })?;
+    Ok(lines)
+}
 
+/// Count `content` line by line with `language`'s comment rules, without the binary check
+/// and the literate formats of [`count_bytes`].
+pub(crate) fn classify_source(
+    path: &Path,
+    content: &[u8],
+    language: &Language,
+    config: &CountConfig,
+) -> std::io::Result<FileStats> {
+    classify_lines(path, content, language, config, |_| {})
+}
+
+/// [`classify_source`], calling `record` with the kind of each line in order.
+fn classify_lines(
+    path: &Path,
+    content: &[u8],
+    language: &Language,
+    config: &CountConfig,
+    mut record: impl FnMut(LineKind),
+) -> std::io::Result<FileStats> {
     let mut stats = FileStats {
         path: path.display().to_string(),
         language: language.name.to_string(),
+        bytes: content.len() as u64,
         ..Default::default()
     };
+    let has_comments = language.has_comments();
+
+    let check_minified = config.detect_minified && MINIFIABLE_LANGUAGES.contains(&language.name);
+    let mut has_source_map = false;
+
+    let mut state = State::Code;
+    let mut longest_line = 0;
+    let mut logical = config.logical_lines.then(|| LogicalLines::new(language));
+    let doc = DocMarkers::for_language(language.name);
+    // Whether the block comment currently open started with a doc marker.
+    let mut in_doc_block = false;
+
+    let mut test_module =
+        (config.tests != TestMode::Include && language.name == "Rust").then(TestModules::default);
+
+    let mut docstrings =
+        (language.name == "Python").then(|| Docstrings::new(config.docstrings_as_code));
+
+    // Most files have no ignore regions; only those that do are searched line by line.
+    let has_ignore_regions = memchr::memmem::find(content, IGNORE_START.as_bytes()).is_some();
+    let mut ignoring = false;
+
+    let mut indent = config.indent_stats.then(IndentTracker::default);
+
+    #[cfg(feature = "tree-sitter")]
+    let syntax = config
+        .accurate
+        .then(|| crate::syntax::comment_ranges(content, language.name, !config.docstrings_as_code))
+        .flatten();
+    #[cfg(not(feature = "tree-sitter"))]
+    let syntax: Option<Vec<Range<usize>>> = None;
+
+    for (index, line) in split_lines(counted_content(content, config)).enumerate() {
+        if index % CANCEL_CHECK_LINES == 0 && is_cancelled(&config.cancel) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "cancelled",
+            ));
+        }
+
+        longest_line = longest_line.max(line.len());
+
+        if has_ignore_regions && (ignoring || is_directive(line, IGNORE_START)) {
+            ignoring = !is_directive(line, IGNORE_END);
+            stats.ignored += 1;
+            record(LineKind::Ignored);
+            continue;
+        }
+
+        stats.line_bytes += line.len() as u64;
+        let offset = line.as_ptr() as usize - content.as_ptr() as usize;
+
+        // Blank lines are the common case and need no UTF-8 decoding.
+        if line.iter().all(u8::is_ascii_whitespace) {
+            let kind = if let Some(ref comments) = syntax {
+                parsed_line(content, offset..offset + line.len(), comments, &doc)
+            } else if has_comments && matches!(state, State::BlockComment { .. }) {
+                match docstrings {
+                    Some(ref docstrings) => docstrings.continued(),
+                    None => Line::Comment { doc: in_doc_block },
+                }
+            } else {
+                Line::Blank
+            };
+            let in_test = test_module.as_ref().is_some_and(TestModules::active);
+            record(tally(&mut stats, kind, in_test, config.tests));
+            continue;
+        }
 
-    let has_comments = !language.line_comments.is_empty() || language.block_comment_start.is_some();
+        // Continuation lines of comments and strings are aligned, not indented.
+        if let Some(ref mut indent) = indent {
+            if state == State::Code {
+                indent.line(line);
+            }
+        }
 
-    if !has_comments {
-        for line in reader.lines() {
-            let line = match line {
-                Ok(l) => l,
-                Err(_) => continue,
+        let limit = config.long_line_limit;
+        if syntax.is_none() && limit > 0 && line.len() > limit {
+            let head = String::from_utf8_lossy(&line[..char_boundary(line, limit)]);
+            if config.detect_generated && !stats.generated && index < GENERATED_SCAN_LINES {
+                stats.generated = config.generated_markers.iter().any(|m| m.is_match(&head));
+            }
+            let head = head.trim_start();
+            if check_minified && !has_source_map {
+                has_source_map = SOURCE_MAP_MARKERS.iter().any(|m| head.starts_with(m));
+            }
+            if !matches!(state, State::BlockComment { .. }) {
+                in_doc_block = doc.starts_block(head);
+            }
+            let (new_state, line_type) =
+                classify_long_line(line, state, language, limit, logical.as_mut());
+            state = new_state;
+            let kind = match line_type {
+                LineType::Code | LineType::Mixed => Line::Code,
+                LineType::Comment => Line::Comment { doc: in_doc_block },
+                LineType::Blank => Line::Blank,
             };
-            if line.trim().is_empty() {
-                stats.blanks += 1;
+            let in_test = test_module.as_ref().is_some_and(TestModules::active);
+            record(tally(&mut stats, kind, in_test, config.tests));
+            continue;
+        }
+
+        let line = String::from_utf8_lossy(line);
+        if config.detect_generated && !stats.generated && index < GENERATED_SCAN_LINES {
+            stats.generated = config.generated_markers.iter().any(|m| m.is_match(&line));
+        }
+
+        let trimmed = line.trim();
+
+        // Checked before trimming, which loses the column.
+        if state == State::Code && starts_column_comment(&line, language) {
+            let in_test = test_module.as_ref().is_some_and(TestModules::active);
+            record(tally(
+                &mut stats,
+                Line::Comment { doc: false },
+                in_test,
+                config.tests,
+            ));
+            continue;
+        }
+
+        if check_minified && !has_source_map {
+            has_source_map = SOURCE_MAP_MARKERS.iter().any(|m| trimmed.starts_with(m));
+        }
+
+        if let Some(ref mut logical) = logical {
+            if has_comments {
+                logical.line(&split_line(trimmed, state, language).1.code, language);
             } else {
-                stats.code += 1;
+                logical.line(trimmed, language);
             }
         }
-        return Ok(stats);
-    }
 
-    let mut state = State::Code;
+        let in_test = match test_module {
+            Some(ref mut tests) => tests.line(&split_line(trimmed, state, language).1.code),
+            None => false,
+        };
+
+        let kind = if let Some(ref comments) = syntax {
+            // Still advanced so logical lines and test modules see the usual state.
+            state = classify_line(trimmed, state, language).0;
+            parsed_line(content, offset..offset + line.len(), comments, &doc)
+        } else if !has_comments {
+            if trimmed.is_empty() {
+                Line::Blank
+            } else {
+                Line::Code
+            }
+        } else if trimmed.is_empty() {
+            match (&docstrings, state) {
+                (_, State::Code | State::String { .. } | State::Heredoc { .. }) => Line::Blank,
+                (Some(docstrings), _) => docstrings.continued(),
+                (None, _) => Line::Comment { doc: in_doc_block },
+            }
+        } else {
+            let is_doc = if matches!(state, State::BlockComment { .. }) {
+                in_doc_block
+            } else {
+                in_doc_block = doc.starts_block(trimmed);
+                in_doc_block || doc.starts_line(trimmed)
+            };
 
-    for line in reader.lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => continue,
+            let (new_state, line_type) = classify_line(trimmed, state, language);
+            let kind = match line_type {
+                LineType::Code | LineType::Mixed => Line::Code,
+                LineType::Comment => Line::Comment { doc: is_doc },
+                LineType::Blank => Line::Blank,
+            };
+
+            let kind = match docstrings {
+                Some(ref docstrings) if matches!(state, State::BlockComment { .. }) => {
+                    docstrings.continued()
+                }
+                Some(ref mut docstrings) => {
+                    let code = split_line(trimmed, state, language).1.code;
+                    docstrings.line(trimmed, &code, new_state, kind)
+                }
+                None => kind,
+            };
+            state = new_state;
+            kind
         };
+        record(tally(&mut stats, kind, in_test, config.tests));
+    }
 
-        let trimmed = line.trim();
+    stats.max_line_length = longest_line as u64;
+    stats.logical = logical.map_or(0, LogicalLines::finish);
+    stats.indent = indent.map_or_else(IndentStats::default, |indent| indent.stats);
+    stats.minified = check_minified && (has_source_map || is_minified(path, &stats));
 
-        if trimmed.is_empty() {
-            if matches!(state, State::BlockComment { .. }) {
-                stats.comments += 1;
+    Ok(stats)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Line {
+    Code,
+    Comment { doc: bool },
+    Blank,
+}
+
+fn tally(stats: &mut FileStats, line: Line, in_test: bool, tests: TestMode) -> LineKind {
+    match (line, in_test, tests) {
+        (_, true, TestMode::Exclude) => LineKind::Excluded,
+        (Line::Code, true, TestMode::Split) => {
+            stats.test_code += 1;
+            LineKind::TestCode
+        }
+        (Line::Code, ..) => {
+            stats.code += 1;
+            LineKind::Code
+        }
+        (Line::Comment { doc }, ..) => {
+            stats.comments += 1;
+            stats.doc_comments += u64::from(doc);
+            if doc {
+                LineKind::DocComment
             } else {
-                stats.blanks += 1;
+                LineKind::Comment
             }
-            continue;
+        }
+        (Line::Blank, ..) => {
+            stats.blanks += 1;
+            LineKind::Blank
+        }
+    }
+}
+
+/// Classify a line from parsed comment ranges: code if any non-whitespace byte lies outside
+/// a comment, otherwise a comment (blank lines inside a block comment included).
+fn parsed_line(
+    content: &[u8],
+    line: Range<usize>,
+    comments: &[Range<usize>],
+    doc: &DocMarkers,
+) -> Line {
+    let first = comments.partition_point(|c| c.end <= line.start);
+    let overlapping = comments[first..]
+        .iter()
+        .take_while(|c| c.start < line.end.max(line.start + 1));
+    let mut comment = None;
+    let mut covered = line.start;
+    for range in overlapping {
+        if content[covered..range.start.max(covered).min(line.end)]
+            .iter()
+            .any(|b| !b.is_ascii_whitespace())
+        {
+            return Line::Code;
+        }
+        comment.get_or_insert(range);
+        covered = covered.max(range.end.min(line.end));
+    }
+    if content[covered..line.end]
+        .iter()
+        .any(|b| !b.is_ascii_whitespace())
+    {
+        return Line::Code;
+    }
+
+    match comment {
+        Some(range) => {
+            let text = String::from_utf8_lossy(&content[range.clone()]);
+            let text = text.trim_start();
+            Line::Comment {
+                doc: doc.starts_line(text) || doc.starts_block(text),
+            }
+        }
+        None => Line::Blank,
+    }
+}
+
+/// Tracks items marked `#[cfg(test)]` in Rust (usually `mod tests { ... }`) by brace depth.
+///
+/// Fed the code part of each non-blank line; the attribute, the item's first line and
+/// everything up to its closing brace are test code.
+#[derive(Debug, Default)]
+struct TestModules {
+    /// Saw `#[cfg(test)]` and are waiting for the item it applies to.
+    pending: bool,
+    /// Open braces inside the current test item; 0 when outside one.
+    depth: i64,
+}
+
+impl TestModules {
+    fn active(&self) -> bool {
+        self.pending || self.depth > 0
+    }
+
+    /// Whether this line is part of a test item.
+    fn line(&mut self, code: &str) -> bool {
+        let code = code.trim();
+        let braces = |s: &str| {
+            s.chars().fold(0i64, |depth, c| match c {
+                '{' => depth + 1,
+                '}' => depth - 1,
+                _ => depth,
+            })
+        };
+
+        if self.depth > 0 {
+            self.depth += braces(code);
+            return true;
         }
 
-        let (new_state, line_type) = classify_line(trimmed, state, language);
-        state = new_state;
+        // The item may follow the attribute on the same line (`#[cfg(test)] mod tests {`).
+        let code = match code.strip_prefix("#[cfg(test)]") {
+            Some(rest) => {
+                self.pending = true;
+                rest
+            }
+            None if self.pending => code,
+            None => return false,
+        };
+        let code = skip_attributes(code);
+        if code.is_empty() {
+            return true;
+        }
 
-        match line_type {
-            LineType::Code => stats.code += 1,
-            LineType::Comment => stats.comments += 1,
-            LineType::Mixed => {
-                stats.code += 1;
+        // The item the attribute applies to: followed to its closing brace when it has a
+        // body (`mod tests {`, `fn helper() {`), otherwise just this line (`mod tests;`).
+        self.pending = false;
+        self.depth = braces(code).max(0);
+        true
+    }
+}
+
+/// `code` after any leading attributes (`#[allow(unused)] mod tests {` gives `mod tests {`);
+/// empty if an attribute is left unclosed.
+fn skip_attributes(code: &str) -> &str {
+    let mut code = code.trim_start();
+    while let Some(rest) = code.strip_prefix("#[") {
+        let mut depth = 1;
+        let end = rest.find(|c| {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                _ => {}
             }
-            LineType::Blank => stats.blanks += 1,
+            depth == 0
+        });
+        match end {
+            Some(end) => code = rest[end + 1..].trim_start(),
+            None => return "",
         }
     }
+    code
+}
 
-    Ok(stats)
+/// Tells Python docstrings apart from other triple-quoted strings, which the classifier
+/// reads as block comments.
+///
+/// A docstring is the first statement of a module, class or function body. All its lines
+/// are doc comments (code with `as_code`); all lines of other triple-quoted strings are code.
+#[derive(Debug)]
+struct Docstrings {
+    as_code: bool,
+    /// The next statement is in docstring position.
+    expected: bool,
+    /// Bracket depth of an unfinished `def` or `class` header.
+    header: Option<i64>,
+    /// Whether the triple-quoted string still open is a docstring.
+    open: bool,
+}
+
+impl Docstrings {
+    fn new(as_code: bool) -> Self {
+        Self {
+            as_code,
+            expected: true,
+            header: None,
+            open: false,
+        }
+    }
+
+    fn kind(&self, docstring: bool) -> Line {
+        if docstring && !self.as_code {
+            Line::Comment { doc: true }
+        } else {
+            Line::Code
+        }
+    }
+
+    /// Kind of a line inside a triple-quoted string opened on an earlier line.
+    fn continued(&self) -> Line {
+        self.kind(self.open)
+    }
+
+    /// Kind of a line that starts outside any string, given its code part, the state it
+    /// leaves and the classifier's verdict.
+    fn line(&mut self, line: &str, code: &str, after: State, kind: Line) -> Line {
+        if line.starts_with('#') {
+            return kind;
+        }
+
+        let docstring = self.expected && starts_triple_quote(line);
+        self.expected = false;
+
+        let code = code.trim();
+        let keyword = code.strip_prefix("async ").unwrap_or(code).trim_start();
+        if keyword.starts_with("def ") || keyword.starts_with("class ") {
+            self.header = Some(0);
+        }
+        if let Some(depth) = self.header {
+            let depth = code.chars().fold(depth, |depth, c| match c {
+                '(' | '[' | '{' => depth + 1,
+                ')' | ']' | '}' => depth - 1,
+                _ => depth,
+            });
+            if depth > 0 {
+                self.header = Some(depth);
+            } else {
+                self.header = None;
+                self.expected = code.ends_with(':');
+            }
+        }
+
+        if matches!(after, State::BlockComment { .. }) {
+            self.open = docstring;
+        }
+        if docstring {
+            self.kind(true)
+        } else if matches!(kind, Line::Comment { .. }) {
+            Line::Code
+        } else {
+            kind
+        }
+    }
+}
+
+/// `line` starts with a (possibly prefixed, e.g. `r"""` or `b'''`) triple-quoted string.
+fn starts_triple_quote(line: &str) -> bool {
+    let rest = line.trim_start_matches(['r', 'R', 'u', 'U', 'b', 'B', 'f', 'F']);
+    line.len() - rest.len() <= 2 && (rest.starts_with("\"\"\"") || rest.starts_with("'''"))
+}
+
+/// Heuristic for minified or bundled output: a `.min.` file name, a very long line, or a
+/// high average line length. Source-map comments are checked while counting.
+pub fn is_minified(path: &Path, stats: &FileStats) -> bool {
+    let min_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.contains(".min."));
+    min_name
+        || stats.max_line_length >= MINIFIED_LINE_LENGTH as u64
+        || stats.avg_line_length() > MINIFIED_AVG_LINE_LENGTH
+}
+
+/// Files at least this large are memory-mapped instead of read into a buffer.
+#[cfg(feature = "fs")]
+pub const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+/// File contents, either memory-mapped or read into memory.
+#[cfg(feature = "fs")]
+pub enum Source {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+#[cfg(feature = "fs")]
+impl Deref for Source {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Source::Mapped(map) => map,
+            Source::Owned(buf) => buf,
+        }
+    }
+}
+
+/// Load a file for counting, memory-mapping large files and falling back to a plain read
+/// when mapping is not possible (pipes, special files, unsupported filesystems).
+#[cfg(feature = "fs")]
+pub fn read_source(path: &Path) -> std::io::Result<Source> {
+    let mut file = File::open(crate::walker::long_path(path))?;
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    if len >= MMAP_THRESHOLD {
+        // SAFETY: the map is read-only and dropped before this file is counted again.
+        // Concurrent truncation by another process can fault, the same trade-off other
+        // mmap-based search tools make for large inputs.
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(Source::Mapped(map));
+        }
+    }
+
+    let mut buf = Vec::with_capacity(len as usize);
+    file.read_to_end(&mut buf)?;
+    Ok(Source::Owned(buf))
+}
+
+/// Split on `\n`, `\r\n` or a lone `\r`, like `BufRead::lines` but over bytes and with
+/// classic Mac OS line endings.
+pub(crate) fn split_lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut start = 0;
+    let mut terminators = memchr::memchr2_iter(b'\n', b'\r', content);
+
+    std::iter::from_fn(move || {
+        // The `\r` of a `\r\n` is stripped once the `\n` ends the line.
+        let end = loop {
+            match terminators.next() {
+                Some(at) if content[at] == b'\r' && content.get(at + 1) == Some(&b'\n') => {}
+                end => break end,
+            }
+        };
+        let line = match end {
+            Some(end) => {
+                let line = &content[start..end];
+                start = end + 1;
+                line
+            }
+            None if start < content.len() => {
+                let line = &content[start..];
+                start = content.len();
+                line
+            }
+            None => return None,
+        };
+        Some(line.strip_suffix(b"\r").unwrap_or(line))
+    })
+}
+
+/// `content` without an unterminated last line under
+/// [`CountConfig::require_final_newline`].
+fn counted_content<'a>(content: &'a [u8], config: &CountConfig) -> &'a [u8] {
+    if !config.require_final_newline {
+        return content;
+    }
+    memchr::memrchr2(b'\n', b'\r', content).map_or(&content[..0], |end| &content[..=end])
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -106,9 +1109,25 @@ pub enum LineType {
     Blank,
 }
 
-#[allow(unused_assignments)]
 pub fn classify_line(line: &str, initial_state: State, lang: &Language) -> (State, LineType) {
+    let (state, line_type, _) = scan_line(line, initial_state, lang);
+    (state, line_type)
+}
+
+/// [`classify_line`], also telling whether the line ends in a line comment.
+#[allow(unused_assignments)]
+fn scan_line(line: &str, initial_state: State, lang: &Language) -> (State, LineType, bool) {
+    if let State::Heredoc { terminator } = initial_state {
+        let line_type = if line.trim().is_empty() {
+            LineType::Blank
+        } else {
+            LineType::Code
+        };
+        return (heredoc_line(line, terminator), line_type, false);
+    }
+
     let mut state = initial_state;
+    let mut heredoc = None;
     let mut has_code = false;
     let mut has_comment = matches!(state, State::BlockComment { .. });
 
@@ -119,126 +1138,676 @@ pub fn classify_line(line: &str, initial_state: State, lang: &Language) -> (Stat
 
         match state {
             State::Code => {
-                if c.is_whitespace() {
+                if c.is_whitespace() {
+                    continue;
+                }
+
+                if let Some((block_start, alt)) = block_comment_start(remaining, lang) {
+                    has_comment = true;
+                    state = State::BlockComment { depth: 1, alt };
+                    for _ in 0..block_start.chars().count().saturating_sub(1) {
+                        chars.next();
+                    }
+                    continue;
+                }
+
+                if starts_line_comment(line, byte_idx, lang) {
+                    has_comment = true;
+                    return (
+                        end_of_line(State::Code, heredoc, lang),
+                        if has_code {
+                            LineType::Mixed
+                        } else {
+                            LineType::Comment
+                        },
+                        true,
+                    );
+                }
+
+                if heredoc.is_none() {
+                    heredoc = lang
+                        .heredoc
+                        .as_ref()
+                        .and_then(|syntax| heredoc_terminator(remaining, syntax));
+                }
+
+                if c == '"' || c == '\'' {
+                    for &delim in lang.string_delimiters {
+                        if remaining.starts_with(delim) && delim.len() == 1 {
+                            has_code = true;
+                            state = State::String { delimiter: c };
+                            break;
+                        }
+                    }
+                    if matches!(state, State::String { .. }) {
+                        continue;
+                    }
+                }
+
+                if is_multiline_string(c, lang) {
+                    has_code = true;
+                    state = State::String { delimiter: c };
+                    continue;
+                }
+
+                has_code = true;
+            }
+
+            State::BlockComment { depth, alt } => {
+                let (block_start, block_end) = block_comment(lang, alt);
+                if let Some(block_end) = block_end {
+                    if remaining.starts_with(block_end) {
+                        let new_depth = depth - 1;
+                        if new_depth == 0 {
+                            state = State::Code;
+                        } else {
+                            state = State::BlockComment {
+                                depth: new_depth,
+                                alt,
+                            };
+                        }
+                        for _ in 0..block_end.chars().count().saturating_sub(1) {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                }
+
+                if lang.nested_comments {
+                    if let Some(block_start) = block_start {
+                        if remaining.starts_with(block_start) {
+                            state = State::BlockComment {
+                                depth: depth + 1,
+                                alt,
+                            };
+                            for _ in 0..block_start.chars().count().saturating_sub(1) {
+                                chars.next();
+                            }
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            State::String { delimiter } => {
+                if !c.is_whitespace() {
+                    has_code = true;
+                }
+                if c == '\\' {
+                    chars.next();
+                    continue;
+                }
+                if c == delimiter {
+                    state = State::Code;
+                }
+            }
+
+            State::Heredoc { .. } => unreachable!("heredoc bodies are handled per line"),
+        }
+    }
+
+    state = end_of_line(state, heredoc, lang);
+
+    let line_type = match (has_code, has_comment) {
+        (true, true) => LineType::Mixed,
+        (true, false) => LineType::Code,
+        (false, true) => LineType::Comment,
+        (false, false) => LineType::Blank,
+    };
+
+    (state, line_type, false)
+}
+
+/// [`classify_line`] for a line longer than `chunk` bytes, decoded and classified `chunk`
+/// bytes at a time so that no copy of the whole line is made. The state carries over from
+/// one chunk to the next and a line comment ends the line, so only a token split between
+/// two chunks can be misread. `logical` is fed the code of each chunk.
+fn classify_long_line(
+    line: &[u8],
+    mut state: State,
+    lang: &Language,
+    chunk: usize,
+    mut logical: Option<&mut LogicalLines>,
+) -> (State, LineType) {
+    let (mut has_code, mut has_comment) = (false, false);
+    let mut rest = line;
+    while !rest.is_empty() {
+        let (piece, tail) = rest.split_at(char_boundary(rest, chunk));
+        rest = tail;
+        let text = String::from_utf8_lossy(piece);
+        if let Some(ref mut logical) = logical {
+            logical.line(&split_line(&text, state, lang).1.code, lang);
+        }
+        let (next, line_type, line_comment) = scan_line(&text, state, lang);
+        state = next;
+        has_code |= matches!(line_type, LineType::Code | LineType::Mixed);
+        has_comment |= matches!(line_type, LineType::Comment | LineType::Mixed);
+        if line_comment {
+            break;
+        }
+    }
+    let line_type = match (has_code, has_comment) {
+        (true, true) => LineType::Mixed,
+        (true, false) => LineType::Code,
+        (false, true) => LineType::Comment,
+        (false, false) => LineType::Blank,
+    };
+    (state, line_type)
+}
+
+/// The largest length up to `max` (at least 1) at which `bytes` can be split without
+/// cutting a UTF-8 sequence.
+fn char_boundary(bytes: &[u8], max: usize) -> usize {
+    if bytes.len() <= max {
+        return bytes.len();
+    }
+    let mut end = max.max(1);
+    // Back off over at most three continuation bytes.
+    while end > max.saturating_sub(3).max(1) && bytes[end] & 0xC0 == 0x80 {
+        end -= 1;
+    }
+    end
+}
+
+/// Comment markers that introduce documentation in a language.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DocMarkers {
+    pub line: &'static [&'static str],
+    pub block: &'static [&'static str],
+}
+
+impl DocMarkers {
+    pub fn for_language(name: &str) -> Self {
+        let (line, block): (&[&str], &[&str]) = match name {
+            "Rust" | "C" | "C Header" | "C++" | "C++ Header" | "Objective-C" | "Objective-C++"
+            | "C#" | "D" | "Dart" | "Swift" | "Zig" | "Solidity" => {
+                (&["///", "//!"], &["/**", "/*!"])
+            }
+            "Java" | "JavaScript" | "TypeScript" | "JSX" | "TSX" | "Kotlin" | "Scala"
+            | "Groovy" | "PHP" => (&[], &["/**"]),
+            // Triple-quoted blocks are the only block comments Python has: docstrings.
+            "Python" => (&[], &["\"\"\"", "'''"]),
+            "R" => (&["#'"], &[]),
+            "Haskell" => (&["-- |", "-- ^"], &["{-|"]),
+            "Lua" => (&["---"], &[]),
+            _ => (&[], &[]),
+        };
+        Self { line, block }
+    }
+
+    pub(crate) fn starts_line(&self, line: &str) -> bool {
+        self.line.iter().any(|m| starts_doc(line, m))
+    }
+
+    pub(crate) fn starts_block(&self, line: &str) -> bool {
+        self.block.iter().any(|m| starts_doc(line, m))
+    }
+}
+
+/// `line` starts with `marker` and is not a longer run such as `////`, `/***` or `/**/`.
+fn starts_doc(line: &str, marker: &str) -> bool {
+    let Some(rest) = line.strip_prefix(marker) else {
+        return false;
+    };
+    match (marker.chars().last(), rest.chars().next()) {
+        (Some('*'), Some('*' | '/')) => false,
+        (Some(last @ ('/' | '-')), Some(next)) => next != last,
+        _ => true,
+    }
+}
+
+/// Languages whose statements end in `;`.
+pub const SEMICOLON_LANGUAGES: &[&str] = &[
+    "ActionScript",
+    "C",
+    "C Header",
+    "C#",
+    "C++",
+    "C++ Header",
+    "CSS",
+    "D",
+    "Dart",
+    "Java",
+    "JavaScript",
+    "JSX",
+    "Less",
+    "Objective-C",
+    "Objective-C++",
+    "PHP",
+    "Perl",
+    "Rust",
+    "SCSS",
+    "SQL",
+    "Solidity",
+    "TSX",
+    "TypeScript",
+    "Verilog",
+    "SystemVerilog",
+    "Zig",
+];
+
+/// Heuristic statement counter.
+///
+/// For [`SEMICOLON_LANGUAGES`] every `;` outside parentheses ends a statement and every `{`
+/// opens one (function, loop or conditional headers), so a `for (a; b; c) {` header counts
+/// once. For other languages each line is a statement unless it continues onto the next one
+/// (open brackets or a trailing `\`); `;` separates statements on the same line. Comments
+/// and string contents are ignored.
+pub struct LogicalLines {
+    semicolons: bool,
+    depth: u32,
+    pending: bool,
+    count: u64,
+}
+
+impl LogicalLines {
+    pub fn new(language: &Language) -> Self {
+        Self {
+            semicolons: SEMICOLON_LANGUAGES.contains(&language.name),
+            depth: 0,
+            pending: false,
+            count: 0,
+        }
+    }
+
+    /// Feed the code part of one line (comments already removed).
+    pub fn line(&mut self, code: &str, language: &Language) {
+        let mut chars = code.chars();
+        while let Some(c) = chars.next() {
+            if language
+                .string_delimiters
+                .iter()
+                .any(|d| d.len() == 1 && d.starts_with(c))
+            {
+                skip_string(&mut chars, c);
+                self.pending = true;
+                continue;
+            }
+
+            match c {
+                '(' | '[' => self.depth += 1,
+                ')' | ']' => self.depth = self.depth.saturating_sub(1),
+                ';' if self.depth == 0 => {
+                    if self.semicolons || self.pending {
+                        self.count += 1;
+                    }
+                    self.pending = false;
+                    continue;
+                }
+                '{' if self.semicolons => {
+                    self.count += 1;
+                    self.pending = false;
+                    continue;
+                }
+                _ => {}
+            }
+            if !c.is_whitespace() && !matches!(c, '}' | ')' | ']') {
+                self.pending = true;
+            }
+        }
+
+        if !self.semicolons && self.pending && self.depth == 0 && !code.ends_with('\\') {
+            self.count += 1;
+            self.pending = false;
+        }
+    }
+
+    pub fn finish(self) -> u64 {
+        // A statement still open at the end of the file (e.g. unbalanced brackets).
+        self.count + u64::from(!self.semicolons && self.pending)
+    }
+}
+
+/// Advance past the closing `delimiter`, honoring backslash escapes.
+fn skip_string(chars: &mut std::str::Chars, delimiter: char) {
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == delimiter {
+            return;
+        }
+    }
+}
+
+/// The block comment opening at the start of `text`: its start marker and whether it is
+/// the language's alternate pair.
+fn block_comment_start(text: &str, lang: &Language) -> Option<(&'static str, bool)> {
+    let primary = lang.block_comment_start.map(|start| (start, false));
+    let alt = lang.alt_block_comment.map(|(start, _)| (start, true));
+    primary
+        .into_iter()
+        .chain(alt)
+        .find(|(start, _)| text.starts_with(start))
+}
+
+/// The start and end markers of a block comment opened with the primary or alternate pair.
+fn block_comment(lang: &Language, alt: bool) -> (Option<&'static str>, Option<&'static str>) {
+    if alt {
+        lang.alt_block_comment
+            .map_or((None, None), |(start, end)| (Some(start), Some(end)))
+    } else {
+        (lang.block_comment_start, lang.block_comment_end)
+    }
+}
+
+/// The code and comment text of one line.
+#[derive(Debug, Default, PartialEq)]
+pub struct LineSegments {
+    pub code: String,
+    pub comment: String,
+}
+
+/// Split a line into its code and comment parts, following the same rules as
+/// [`classify_line`]: comment markers inside strings are code,
+/// block comments may span lines and nest where the language allows it.
+pub fn split_line(line: &str, initial_state: State, lang: &Language) -> (State, LineSegments) {
+    if let State::Heredoc { terminator } = initial_state {
+        let segments = LineSegments {
+            code: line.to_string(),
+            comment: String::new(),
+        };
+        return (heredoc_line(line, terminator), segments);
+    }
+
+    let mut state = initial_state;
+    let mut heredoc = None;
+    let mut segments = LineSegments::default();
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        match state {
+            State::Code => {
+                if let Some((start, alt)) = block_comment_start(rest, lang) {
+                    state = State::BlockComment { depth: 1, alt };
+                    segments.comment.push_str(start);
+                    rest = &rest[start.len()..];
                     continue;
                 }
 
-                if let Some(block_start) = lang.block_comment_start {
-                    if remaining.starts_with(block_start) {
-                        has_comment = true;
-                        state = State::BlockComment { depth: 1 };
-                        for _ in 0..block_start.chars().count().saturating_sub(1) {
-                            chars.next();
-                        }
-                        continue;
-                    }
+                if starts_line_comment(line, line.len() - rest.len(), lang) {
+                    segments.comment.push_str(rest);
+                    break;
                 }
 
-                for &line_comment in lang.line_comments {
-                    if remaining.starts_with(line_comment) {
-                        has_comment = true;
-                        return (
-                            State::Code,
-                            if has_code {
-                                LineType::Mixed
-                            } else {
-                                LineType::Comment
-                            },
-                        );
-                    }
+                if heredoc.is_none() {
+                    heredoc = lang
+                        .heredoc
+                        .as_ref()
+                        .and_then(|syntax| heredoc_terminator(rest, syntax));
                 }
 
-                if c == '"' || c == '\'' {
-                    for &delim in lang.string_delimiters {
-                        if remaining.starts_with(delim) && delim.len() == 1 {
-                            has_code = true;
-                            state = State::String { delimiter: c };
-                            break;
-                        }
-                    }
-                    if matches!(state, State::String { .. }) {
-                        continue;
-                    }
+                if lang
+                    .string_delimiters
+                    .iter()
+                    .any(|d| d.len() == 1 && d.starts_with(c))
+                    || is_multiline_string(c, lang)
+                {
+                    state = State::String { delimiter: c };
                 }
-
-                has_code = true;
+                segments.code.push(c);
             }
 
-            State::BlockComment { depth } => {
-                if let Some(block_end) = lang.block_comment_end {
-                    if remaining.starts_with(block_end) {
-                        let new_depth = depth - 1;
-                        if new_depth == 0 {
-                            state = State::Code;
-                        } else {
-                            state = State::BlockComment { depth: new_depth };
+            State::BlockComment { depth, alt } => {
+                let (block_start, block_end) = block_comment(lang, alt);
+                if let Some(end) = block_end.filter(|e| rest.starts_with(e)) {
+                    state = if depth == 1 {
+                        State::Code
+                    } else {
+                        State::BlockComment {
+                            depth: depth - 1,
+                            alt,
                         }
-                        for _ in 0..block_end.chars().count().saturating_sub(1) {
-                            chars.next();
-                        }
-                        continue;
-                    }
+                    };
+                    segments.comment.push_str(end);
+                    rest = &rest[end.len()..];
+                    continue;
                 }
 
                 if lang.nested_comments {
-                    if let Some(block_start) = lang.block_comment_start {
-                        if remaining.starts_with(block_start) {
-                            state = State::BlockComment { depth: depth + 1 };
-                            for _ in 0..block_start.chars().count().saturating_sub(1) {
-                                chars.next();
-                            }
-                            continue;
-                        }
+                    if let Some(start) = block_start.filter(|s| rest.starts_with(s)) {
+                        state = State::BlockComment {
+                            depth: depth + 1,
+                            alt,
+                        };
+                        segments.comment.push_str(start);
+                        rest = &rest[start.len()..];
+                        continue;
                     }
                 }
+                segments.comment.push(c);
             }
 
             State::String { delimiter } => {
+                segments.code.push(c);
                 if c == '\\' {
-                    chars.next();
+                    rest = &rest[c.len_utf8()..];
+                    if let Some(escaped) = rest.chars().next() {
+                        segments.code.push(escaped);
+                        rest = &rest[escaped.len_utf8()..];
+                    }
                     continue;
                 }
                 if c == delimiter {
                     state = State::Code;
                 }
             }
+
+            State::Heredoc { .. } => unreachable!("heredoc bodies are handled per line"),
         }
+        rest = &rest[c.len_utf8()..];
     }
 
-    if matches!(state, State::String { .. }) {
-        state = State::Code;
+    (end_of_line(state, heredoc, lang), segments)
+}
+
+/// Splits the lines of one file like [`split_line`], with the counter's rules on top:
+/// fixed-form comment lines are comments, and Python's triple-quoted strings are code
+/// unless they are docstrings.
+pub(crate) struct LineSplitter<'a> {
+    language: &'a Language,
+    state: State,
+    docstrings: Option<Docstrings>,
+}
+
+impl<'a> LineSplitter<'a> {
+    pub(crate) fn new(language: &'a Language) -> Self {
+        Self {
+            language,
+            state: State::Code,
+            docstrings: (language.name == "Python").then(|| Docstrings::new(false)),
+        }
     }
 
-    let line_type = match (has_code, has_comment) {
-        (true, true) => LineType::Mixed,
-        (true, false) => LineType::Code,
-        (false, true) => LineType::Comment,
-        (false, false) => LineType::Blank,
+    /// Whether the next line starts inside a block comment or docstring.
+    pub(crate) fn in_comment(&self) -> bool {
+        matches!(self.state, State::BlockComment { .. })
+            && self.docstrings.as_ref().is_none_or(|d| d.open)
+    }
+
+    /// The code and comment parts of the next line.
+    pub(crate) fn split(&mut self, line: &str) -> LineSegments {
+        let before = self.state;
+        if before == State::Code && starts_column_comment(line, self.language) {
+            return LineSegments {
+                comment: line.to_string(),
+                ..Default::default()
+            };
+        }
+
+        let (after, segments) = split_line(line, before, self.language);
+        self.state = after;
+        let trimmed = line.trim();
+        let Some(ref mut docstrings) = self.docstrings else {
+            return segments;
+        };
+        if trimmed.is_empty() {
+            return segments;
+        }
+
+        let continued = matches!(before, State::BlockComment { .. });
+        let kind = if continued {
+            docstrings.continued()
+        } else if segments.code.trim().is_empty() {
+            docstrings.line(trimmed, &segments.code, after, Line::Comment { doc: false })
+        } else {
+            docstrings.line(trimmed, &segments.code, after, Line::Code)
+        };
+        let in_string = continued
+            || segments.comment.starts_with("\"\"\"")
+            || segments.comment.starts_with("'''");
+        if in_string && matches!(kind, Line::Code) {
+            LineSegments {
+                code: line.to_string(),
+                comment: String::new(),
+            }
+        } else {
+            segments
+        }
+    }
+}
+
+/// A line comment starts at byte `at` of `line`.
+fn starts_line_comment(line: &str, at: usize, lang: &Language) -> bool {
+    let (before, rest) = line.split_at(at);
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    lang.line_comments.iter().any(|m| rest.starts_with(m))
+        || (before.trim().is_empty()
+            && lang.line_start_comments.iter().any(|m| rest.starts_with(m)))
+        || lang.word_comments.iter().any(|m| {
+            rest.get(..m.len())
+                .is_some_and(|s| s.eq_ignore_ascii_case(m))
+                && starts_command(before)
+                && !rest[m.len()..].chars().next().is_some_and(is_word)
+        })
+}
+
+/// Text ending in `before` starts a new command: the start of the line, or after `&`, `|`
+/// or `(` (`echo hi & rem note`, `if x (rem note`).
+fn starts_command(before: &str) -> bool {
+    before
+        .trim_end()
+        .chars()
+        .next_back()
+        .is_none_or(|c| matches!(c, '&' | '|' | '('))
+}
+
+/// `line` (untrimmed) is a fixed-form comment: a column-1 comment marker.
+pub fn starts_column_comment(line: &str, lang: &Language) -> bool {
+    lang.column_comments.iter().any(|m| line.starts_with(m))
+}
+
+/// Whether `line` is an ignore directive with `marker` (see [`IGNORE_START`]).
+fn is_directive(line: &[u8], marker: &str) -> bool {
+    let Some(start) = line
+        .iter()
+        .position(|b| !b.is_ascii_whitespace() && !b.is_ascii_punctuation())
+    else {
+        return false;
     };
+    line[start..]
+        .strip_prefix(marker.as_bytes())
+        .is_some_and(|rest| {
+            rest.first()
+                .is_none_or(|b| !b.is_ascii_alphanumeric() && *b != b'-' && *b != b'_')
+        })
+}
 
-    (state, line_type)
+/// Whether `content` opts out of counting with an [`IGNORE_FILE`] directive.
+fn is_ignored_file(content: &[u8]) -> bool {
+    memchr::memmem::find(content, IGNORE_FILE.as_bytes()).is_some()
+        && split_lines(content)
+            .take(GENERATED_SCAN_LINES)
+            .any(|line| is_directive(line, IGNORE_FILE))
+}
+
+/// The state carried to the next line. Strings end with the line unless the language lets
+/// them span lines; a heredoc opened on the line starts on the next one.
+fn end_of_line(state: State, heredoc: Option<u64>, lang: &Language) -> State {
+    match state {
+        State::String { delimiter } if is_multiline_string(delimiter, lang) => state,
+        State::String { .. } | State::Code => match heredoc {
+            Some(terminator) => State::Heredoc { terminator },
+            None => State::Code,
+        },
+        State::BlockComment { .. } | State::Heredoc { .. } => state,
+    }
+}
+
+fn is_multiline_string(c: char, lang: &Language) -> bool {
+    lang.multiline_strings
+        .iter()
+        .any(|d| d.len() == c.len_utf8() && d.starts_with(c))
 }
 
-fn is_binary(file: &File) -> std::io::Result<bool> {
-    let mut buffer = [0u8; 8192];
-    let mut handle = file.try_clone()?;
-    let bytes_read = handle.read(&mut buffer)?;
+/// The state after a heredoc body line: back to code once the terminator is reached.
+fn heredoc_line(line: &str, terminator: u64) -> State {
+    if hash_bytes(line.trim().as_bytes()) == terminator {
+        State::Code
+    } else {
+        State::Heredoc { terminator }
+    }
+}
+
+/// Hash of the terminator of a heredoc opened at the start of `code`, if one is.
+fn heredoc_terminator(code: &str, syntax: &Heredoc) -> Option<u64> {
+    let opener = syntax.openers.iter().find(|op| code.starts_with(*op))?;
+    let mut rest = &code[opener.len()..];
+    if syntax.spaced {
+        rest = rest.trim_start_matches([' ', '\t']);
+    }
+
+    let word = match rest.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let body = &rest[1..];
+            &body[..body.find(quote)?]
+        }
+        '\\' if syntax.spaced => word_prefix(&rest[1..]),
+        c if c.is_ascii_uppercase() || c == '_' => word_prefix(rest),
+        _ => return None,
+    };
+    (!word.is_empty()).then(|| hash_bytes(word.as_bytes()))
+}
 
-    if bytes_read == 0 {
-        return Ok(false);
+fn word_prefix(s: &str) -> &str {
+    let end = s
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+fn is_binary(content: &[u8], config: &CountConfig) -> bool {
+    let head = &content[..content.len().min(config.binary_sample_size)];
+
+    if head.is_empty() {
+        return false;
     }
 
-    let null_count = buffer[..bytes_read].iter().filter(|&&b| b == 0).count();
-    let binary_threshold = bytes_read / 10;
+    let null_count = memchr::memchr_iter(0, head).count();
+    let binary_threshold = (head.len() as f64 * config.binary_null_ratio) as usize;
 
-    Ok(null_count > binary_threshold.max(1))
+    null_count > binary_threshold.max(1)
 }
 
+#[cfg(feature = "fs")]
 pub fn compute_file_hash(path: &Path) -> std::io::Result<u64> {
-    let content = std::fs::read(path)?;
+    Ok(hash_bytes(&read_source(path)?))
+}
+
+/// Content hash used for duplicate detection.
+pub fn hash_bytes(content: &[u8]) -> u64 {
     let mut hasher = ahash::AHasher::default();
     content.hash(&mut hasher);
-    Ok(hasher.finish())
+    hasher.finish()
+}
+
+/// SHA-256 of `content` as lowercase hex. Unlike [`hash_bytes`], this is stable across
+/// versions and platforms.
+pub fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
 }
 
 #[cfg(test)]
@@ -257,7 +1826,10 @@ mod tests {
             ("/* block */", State::Code, LineType::Comment),
             (
                 "/* start",
-                State::BlockComment { depth: 1 },
+                State::BlockComment {
+                    depth: 1,
+                    alt: false,
+                },
                 LineType::Comment,
             ),
         ];
@@ -269,12 +1841,603 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_generated_detection() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let rust = LANGUAGES.get("Rust").unwrap();
+
+        let generated = temp.path().join("gen.rs");
+        std::fs::write(
+            &generated,
+            "// Code generated by protoc. DO NOT EDIT.\nfn x() {}\n",
+        )
+        .unwrap();
+        assert!(count_lines(&generated, rust).unwrap().generated);
+
+        let handwritten = temp.path().join("main.rs");
+        std::fs::write(&handwritten, "// Hand written\nfn main() {}\n").unwrap();
+        assert!(!count_lines(&handwritten, rust).unwrap().generated);
+
+        let js = LANGUAGES.get("JavaScript").unwrap();
+        let minified = temp.path().join("app.min.js");
+        std::fs::write(
+            &minified,
+            format!("var a={};\n", "1+".repeat(MINIFIED_LINE_LENGTH)),
+        )
+        .unwrap();
+        assert!(count_lines(&minified, js).unwrap().minified);
+
+        let config = CountConfig {
+            detect_generated: false,
+            ..Default::default()
+        };
+        assert!(
+            !count_lines_with_config(&generated, rust, &config)
+                .unwrap()
+                .generated
+        );
+    }
+
+    #[test]
+    fn test_ignore_directives() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let count = |source: &str| {
+            count_bytes(
+                Path::new("a.rs"),
+                source.as_bytes(),
+                rust,
+                &CountConfig::default(),
+            )
+            .unwrap()
+        };
+
+        let source = "fn a() {}\n// rloc:ignore-start\nconst T: [u8; 2] = [\n    1, 2,\n];\n\
+                      // rloc:ignore-end\n\n// done\n";
+        let stats = count(source);
+        assert_eq!(
+            (stats.code, stats.comments, stats.blanks, stats.ignored),
+            (1, 1, 1, 5)
+        );
+        assert!(!stats.is_ignored());
+        let kinds = classify_bytes(
+            Path::new("a.rs"),
+            source.as_bytes(),
+            rust,
+            &CountConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(kinds[1], (2, LineKind::Ignored));
+        assert_eq!(kinds[6], (7, LineKind::Blank));
+
+        // Unterminated regions run to the end; mentions inside code are not directives.
+        let stats = count("let s = \"rloc:ignore-start\";\n/* rloc:ignore-start */\nx();\n");
+        assert_eq!((stats.code, stats.ignored), (1, 2));
+
+        let stats = count("//! rloc:ignore-file\nfn a() {}\n\nfn b() {}\n");
+        assert_eq!((stats.total(), stats.ignored), (0, 4));
+        assert!(stats.is_ignored());
+        assert_eq!(count("fn a() {}\n// rloc:ignore-files\n").ignored, 0);
+    }
+
+    #[test]
+    fn test_binary_detection_config() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let mut content = b"fn main() {}\n".repeat(100);
+        content.extend_from_slice(&[0; 64]);
+
+        let config = CountConfig::default();
+        let stats = count_bytes(Path::new("a.rs"), &content, rust, &config).unwrap();
+        assert!(!stats.binary);
+        assert!(stats.code >= 100);
+
+        let strict = CountConfig {
+            binary_null_ratio: 0.01,
+            ..Default::default()
+        };
+        let stats = count_bytes(Path::new("a.rs"), &content, rust, &strict).unwrap();
+        assert!(stats.binary);
+        assert_eq!(stats.total(), 0);
+
+        let head_only = CountConfig {
+            binary_sample_size: 64,
+            ..strict
+        };
+        assert!(
+            !count_bytes(Path::new("a.rs"), &content, rust, &head_only)
+                .unwrap()
+                .binary
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let config = CountConfig::default();
+        let count = |lang: &str, source: &str| {
+            let lang = LANGUAGES.get(lang).unwrap();
+            count_bytes(Path::new("x"), source.as_bytes(), lang, &config).unwrap()
+        };
+
+        let rust = "//! Crate docs\n/// Item docs\n//// not docs\n// plain\n/**\n * Block docs\n */\n/* plain */\nfn x() {} /// trailing\n";
+        let stats = count("Rust", rust);
+        assert_eq!(stats.comments, 8);
+        assert_eq!(stats.doc_comments, 5);
+
+        let python = "\"\"\"Module.\n\nMore.\n\"\"\"\n# plain\nx = 1\n";
+        let stats = count("Python", python);
+        assert_eq!(stats.doc_comments, 4);
+        assert_eq!(stats.comments, 5);
+
+        let r = "#' Roxygen\n# plain\nf <- 1\n";
+        assert_eq!(count("R", r).doc_comments, 1);
+
+        let js = "/***********/\n/**/\n/** doc */\nlet a = 1;\n";
+        assert_eq!(count("JavaScript", js).doc_comments, 1);
+    }
+
+    #[test]
+    fn test_heredocs_and_template_literals() {
+        let config = CountConfig::default();
+        let count = |lang: &str, source: &str| {
+            let lang = LANGUAGES.get(lang).unwrap();
+            let stats = count_bytes(Path::new("x"), source.as_bytes(), lang, &config).unwrap();
+            (stats.code, stats.comments)
+        };
+
+        let bash = "cat <<EOF\n# not a comment\nEOF\n# comment\ncat << 'SQL' # trailing\n# kept\n  SQL\necho $((1<<2)) # shift\ncat <<<\"x\" # here-string\n";
+        assert_eq!(count("Bash", bash), (8, 1));
+
+        let ruby = "text = <<~EOS\n  # body\nEOS\nlist << item # append\nputs(<<-'X', 1)\n#{x}\n  X\n# real\n";
+        assert_eq!(count("Ruby", ruby), (7, 1));
+
+        let perl = "print <<\"END\";\n# body\nEND\nmy $x = 1 << 3; # shift\n# real\n";
+        assert_eq!(count("Perl", perl), (4, 1));
+
+        let js = "const s = `\n// not a comment\n/* nor this */\n`; // real\nconst t = `${a}`; // one line\n// real\n";
+        assert_eq!(count("JavaScript", js), (5, 1));
+    }
+
+    #[test]
+    fn test_word_and_line_start_comments() {
+        let config = CountConfig::default();
+        let count = |lang: &str, source: &str| {
+            let lang = LANGUAGES.get(lang).unwrap();
+            let stats = count_bytes(Path::new("x"), source.as_bytes(), lang, &config).unwrap();
+            (stats.code, stats.comments)
+        };
+
+        let batch = "REM comment\nremove.exe foo\necho REM not\n:: comment\necho a :: b\nrem\n@REM quiet\nRem. dotted\n";
+        assert_eq!(count("Batch", batch), (3, 5));
+
+        let sql =
+            "INSERT INTO t VALUES ('one\n-- not a comment\n');\n-- real\nSELECT \"a--b\" FROM t;\n";
+        assert_eq!(count("SQL", sql), (4, 1));
+
+        let batch = LANGUAGES.get("Batch").unwrap();
+        assert_eq!(split_line("echo a :: b", State::Code, batch).1.comment, "");
+        assert_eq!(split_line("echo REM not", State::Code, batch).1.comment, "");
+        assert_eq!(split_line("set x=REM", State::Code, batch).1.comment, "");
+        assert_eq!(
+            split_line("dir | rem note", State::Code, batch).1.comment,
+            "rem note"
+        );
+        assert_eq!(
+            split_line("if x==1 (rem note", State::Code, batch)
+                .1
+                .comment,
+            "rem note"
+        );
+        assert_eq!(
+            split_line("echo hi & rem note", State::Code, batch)
+                .1
+                .comment,
+            "rem note"
+        );
+    }
+
+    #[test]
+    fn test_fortran_forms() {
+        let config = CountConfig::default();
+        let count = |lang: &str, source: &str| {
+            let lang = LANGUAGES.get(lang).unwrap();
+            let stats = count_bytes(Path::new("x"), source.as_bytes(), lang, &config).unwrap();
+            (stats.code, stats.comments)
+        };
+
+        let fixed = "C     Fixed-form comment\n*     Another\n      CALL SOLVE(X) ! trailing\n      CONTINUE\n! bang comment\n";
+        assert_eq!(count("Fortran 77", fixed), (2, 3));
+
+        let free = "program main\n  ! comment\n  call solve(x)\ncontains\n  character(len=*) :: c\nend program\n";
+        assert_eq!(count("Fortran", free), (5, 1));
+    }
+
+    #[test]
+    fn test_python_docstrings() {
+        let source = "#!/usr/bin/env python\n\"\"\"Module docs.\n\nMore.\n\"\"\"\nimport os\n\nQUERY = \"\"\"\nSELECT 1\n\"\"\"\n\n@decorator\nclass A(\n    Base,\n):\n    r\"\"\"Class docs.\"\"\"\n\n    async def f(self):\n        # comment\n        \"\"\"Method docs.\"\"\"\n        x = \"\"\"a\"\"\"\n        \"\"\"Not a docstring.\"\"\"\n        return x\n";
+        let python = LANGUAGES.get("Python").unwrap();
+        let count = |config: &CountConfig| {
+            let stats = count_bytes(Path::new("x.py"), source.as_bytes(), python, config).unwrap();
+            (stats.code, stats.comments, stats.doc_comments, stats.blanks)
+        };
+
+        assert_eq!(count(&CountConfig::default()), (12, 8, 6, 3));
+        let as_code = CountConfig {
+            docstrings_as_code: true,
+            ..Default::default()
+        };
+        assert_eq!(count(&as_code), (18, 2, 0, 3));
+    }
+
+    #[test]
+    fn test_python_single_quoted_docstrings() {
+        let source = "'''Module docs.\n\nQuotes \"\"\" stay inside.\n'''\nimport os\n\ndef f():\n    '''Function docs.'''\n    s = '''a\n\"\"\"\nb'''\n    return s\n";
+        let python = LANGUAGES.get("Python").unwrap();
+        let stats = count_bytes(
+            Path::new("x.py"),
+            source.as_bytes(),
+            python,
+            &CountConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            (stats.code, stats.comments, stats.doc_comments, stats.blanks),
+            (6, 5, 5, 1)
+        );
+    }
+
+    #[test]
+    fn test_logical_lines() {
+        let config = CountConfig {
+            logical_lines: true,
+            ..Default::default()
+        };
+        let logical = |lang: &str, source: &str| {
+            let lang = LANGUAGES.get(lang).unwrap();
+            count_bytes(Path::new("x"), source.as_bytes(), lang, &config)
+                .unwrap()
+                .logical
+        };
+
+        let c = "int main() {\n    for (int i = 0; i < 3; i++) {\n        printf(\"a;b\"); // x;\n    }\n    return 0;\n}\n";
+        assert_eq!(logical("C", c), 4);
+
+        let python =
+            "x = 1; y = 2\nresult = call(\n    x,\n    y,\n)\n# a; b\ntotal = x + \\\n    y\n";
+        assert_eq!(logical("Python", python), 4);
+
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let default = count_bytes(
+            Path::new("x"),
+            b"let a = 1;\n",
+            rust,
+            &CountConfig::default(),
+        );
+        assert_eq!(default.unwrap().logical, 0);
+    }
+
+    #[test]
+    fn test_inline_test_modules() {
+        let source = "pub fn add(a: i32) -> i32 {\n    a + 1\n}\n\n#[cfg(test)]\nmod tests {\n    use super::*;\n\n    // check\n    #[test]\n    fn adds() {\n        assert_eq!(add(1), 2);\n    }\n}\n";
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let count = |tests| {
+            let config = CountConfig {
+                tests,
+                ..Default::default()
+            };
+            count_bytes(Path::new("lib.rs"), source.as_bytes(), rust, &config).unwrap()
+        };
+
+        let include = count(TestMode::Include);
+        assert_eq!((include.code, include.test_code), (11, 0));
+
+        let split = count(TestMode::Split);
+        assert_eq!((split.code, split.test_code), (3, 8));
+        assert_eq!((split.comments, split.blanks), (1, 2));
+
+        let exclude = count(TestMode::Exclude);
+        assert_eq!((exclude.code, exclude.test_code), (3, 0));
+        assert_eq!((exclude.comments, exclude.blanks), (0, 1));
+
+        let mut file = include.clone();
+        file.mark_as_test();
+        assert_eq!(
+            (file.code, file.test_code, file.total()),
+            (0, 11, include.total())
+        );
+
+        // Braces on the attribute's own line open the module.
+        let one_line = "#[cfg(test)] mod tests {
+    #[test]
+    fn a() {}
+}
+fn after() {}
+";
+        let one_line_attrs = "#[cfg(test)] #[allow(unused)] mod tests {
+    fn a() {}
+}
+fn after() {}
+";
+        let inline = "#[cfg(test)] mod tests { fn a() {} }
+fn after() {}
+";
+        for (source, test_code) in [(one_line, 4), (one_line_attrs, 3), (inline, 1)] {
+            let config = CountConfig {
+                tests: TestMode::Split,
+                ..Default::default()
+            };
+            let stats = count_bytes(Path::new("lib.rs"), source.as_bytes(), rust, &config).unwrap();
+            assert_eq!((stats.code, stats.test_code), (1, test_code), "{}", source);
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_classify_lines() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let source = "/// Adds one.\nfn add(a: i32) -> i32 {\n\n    a + 1 // sum\n}\n/* a\n\n*/\n#[cfg(test)]\nmod tests {}\n";
+        let config = CountConfig {
+            tests: TestMode::Split,
+            ..Default::default()
+        };
+        let lines = classify_bytes(Path::new("lib.rs"), source.as_bytes(), rust, &config).unwrap();
+        let kinds: Vec<_> = lines.iter().map(|&(_, kind)| kind).collect();
+        use LineKind::*;
+        assert_eq!(
+            kinds,
+            [
+                DocComment, Code, Blank, Code, Code, Comment, Comment, Comment, TestCode, TestCode
+            ]
+        );
+        assert_eq!(lines.last().unwrap().0, 10);
+
+        // The kinds tally up to the counts.
+        let stats = count_bytes(Path::new("lib.rs"), source.as_bytes(), rust, &config).unwrap();
+        let tally = |kind| kinds.iter().filter(|&&k| k == kind).count() as u64;
+        assert_eq!(stats.code, tally(Code));
+        assert_eq!(stats.comments, tally(Comment) + tally(DocComment));
+        assert_eq!(stats.test_code, tally(TestCode));
+
+        let binary = classify_bytes(Path::new("a.rs"), b"\0\0\0\0", rust, &config);
+        assert_eq!(binary.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("main.rs");
+        std::fs::write(&path, "// hi\r\nfn main() {}").unwrap();
+        assert_eq!(
+            classify_file(&path, rust).unwrap(),
+            [(1, Comment), (2, Code)]
+        );
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    #[test]
+    fn test_accurate_counting() {
+        let source = "const help = `\n// usage: run\n/* twice */\n`;\n\n/**\n * Docs.\n\n */\nrun(); // go\n";
+        let js = LANGUAGES.get("JavaScript").unwrap();
+        let count = |accurate| {
+            let config = CountConfig {
+                accurate,
+                ..Default::default()
+            };
+            count_bytes(Path::new("cli.js"), source.as_bytes(), js, &config).unwrap()
+        };
+
+        // The line classifier follows template literals as well.
+        let fast = count(false);
+        assert_eq!((fast.code, fast.comments), (5, 4));
+
+        let accurate = count(true);
+        assert_eq!(
+            (accurate.code, accurate.comments, accurate.blanks),
+            (5, 4, 1)
+        );
+        assert_eq!(accurate.doc_comments, 4);
+    }
+
+    #[test]
+    fn test_minified_detection() {
+        let js = LANGUAGES.get("JavaScript").unwrap();
+        let config = CountConfig::default();
+        let count = |name: &str, content: &str| {
+            count_bytes(Path::new(name), content.as_bytes(), js, &config).unwrap()
+        };
+
+        let source = "function add(a, b) {\n    return a + b;\n}\n";
+        assert!(!count("add.js", source).minified);
+        assert!(count("add.min.js", source).minified);
+
+        let mapped = format!("{}//# sourceMappingURL=add.js.map\n", source);
+        assert!(count("bundle.js", &mapped).minified);
+
+        let dense = format!("{}\n", "a=1;".repeat(60));
+        assert!(count("bundle.js", &dense).minified);
+
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let stats = count_bytes(Path::new("lib.min.rs"), b"fn x() {}\n", rust, &config).unwrap();
+        assert!(!stats.minified);
+    }
+
     #[test]
     fn test_nested_comments() {
         let rust = LANGUAGES.get("Rust").unwrap();
         assert!(rust.nested_comments);
 
         let (state, _) = classify_line("/* outer /* inner */", State::Code, rust);
-        assert_eq!(state, State::BlockComment { depth: 1 });
+        assert_eq!(
+            state,
+            State::BlockComment {
+                depth: 1,
+                alt: false
+            }
+        );
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let lines: Vec<&[u8]> = split_lines(b"a\r\n\nb\nc").collect();
+        assert_eq!(lines, vec![&b"a"[..], b"", b"b", b"c"]);
+        assert_eq!(split_lines(b"a\n").count(), 1);
+        assert_eq!(split_lines(b"").count(), 0);
+        let mac: Vec<&[u8]> = split_lines(b"a\rb\r\rc\r\n").collect();
+        assert_eq!(mac, vec![&b"a"[..], b"b", b"", b"c"]);
+    }
+
+    #[test]
+    fn test_long_lines_chunked() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let whole = CountConfig {
+            long_line_limit: 0,
+            logical_lines: true,
+            ..Default::default()
+        };
+        let chunked = CountConfig {
+            long_line_limit: 16,
+            ..whole.clone()
+        };
+        let count = |source: &str, config: &CountConfig| {
+            let stats = count_bytes(Path::new("a.rs"), source.as_bytes(), rust, config).unwrap();
+            (
+                stats.code,
+                stats.comments,
+                stats.logical,
+                stats.max_line_length,
+            )
+        };
+
+        let sources = [
+            // A line comment in a later chunk covers the rest of the line.
+            "let a = 1; let b = 2; let c = 3; // x /* not a block\nfn f() {}\n",
+            // A block comment opened at the end of a long line continues on the next.
+            "let a = 1; let b = 2; let c = 3; /* open\nstill comment\n*/\n",
+            // Only comments, across several chunks, with multi-byte characters at the cuts.
+            "/* ééééééééééééééééééééééééééééééé */\nfn f() {}\n",
+        ];
+        for source in sources {
+            assert_eq!(count(source, &chunked), count(source, &whole), "{}", source);
+        }
+        assert_eq!(count(sources[1], &chunked), (1, 2, 3, 40));
+
+        assert_eq!(char_boundary("aé".as_bytes(), 2), 1);
+        assert_eq!(char_boundary(b"abc", 5), 3);
+        assert_eq!(char_boundary(b"abc", 0), 1);
+    }
+
+    #[test]
+    fn test_indent_stats() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let config = CountConfig {
+            indent_stats: true,
+            ..Default::default()
+        };
+        let source = "fn a() {\n    if x {\n        y();\n\n    }\n}\n/*\n *  aligned\n */\nfn b() {\n\tc();\n}\n";
+        let stats = count_bytes(Path::new("a.rs"), source.as_bytes(), rust, &config).unwrap();
+        assert_eq!((stats.indent.tabs, stats.indent.spaces), (1, 3));
+        assert_eq!(stats.indent.steps[3], 2);
+        assert_eq!(stats.indent.width(), Some(4));
+        assert!(
+            count_bytes(
+                Path::new("a.rs"),
+                source.as_bytes(),
+                rust,
+                &CountConfig::default()
+            )
+            .unwrap()
+            .indent
+            .is_empty()
+        );
+
+        let mut tie = IndentStats::default();
+        tie.steps[1] = 3;
+        tie.steps[3] = 3;
+        assert_eq!(tie.width(), Some(2));
+        assert_eq!(IndentStats::default().width(), None);
+    }
+
+    #[test]
+    fn test_final_newline_and_line_endings() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let count = |content: &[u8], config: &CountConfig| {
+            count_bytes(Path::new("a.rs"), content, rust, config).unwrap()
+        };
+        let mut config = CountConfig::default();
+        assert_eq!(count(b"fn a() {}\r// b\rfn c() {}", &config).total(), 3);
+        assert!(count(b"fn a() {}", &config).line_endings.is_empty());
+
+        config.require_final_newline = true;
+        config.line_endings = true;
+        let stats = count(b"fn a() {}\r\n// b\nfn c() {}\r", &config);
+        assert_eq!((stats.code, stats.comments), (2, 1));
+        assert_eq!(
+            stats.line_endings,
+            LineEndings {
+                lf: 1,
+                crlf: 1,
+                cr: 1
+            }
+        );
+        assert_eq!(stats.line_endings.style(), Some("mixed"));
+        assert_eq!(count(b"fn a() {}\n// b", &config).total(), 1);
+        assert_eq!(count(b"fn a() {}", &config).total(), 0);
+        assert_eq!(LineEndings::of(b"a\r\nb\r\n").style(), Some("crlf"));
+        assert_eq!(LineEndings::of(b"a").style(), None);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let mut config = CountConfig::default();
+        let count = |config: &CountConfig| {
+            count_bytes(Path::new("a.rs"), b"a", rust, config)
+                .unwrap()
+                .sha256
+        };
+        assert_eq!(count(&config), None);
+        config.content_hash = true;
+        assert_eq!(
+            count(&config).as_deref(),
+            Some("ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb")
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_mapped_and_buffered_counts_match() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let chunk = "// comment\r\nfn f() {}\n\n/* a\n b */\n";
+
+        let small = temp.path().join("small.rs");
+        std::fs::write(&small, chunk).unwrap();
+        let large = temp.path().join("large.rs");
+        let repeats = MMAP_THRESHOLD as usize / chunk.len() + 1;
+        std::fs::write(&large, chunk.repeat(repeats)).unwrap();
+
+        assert!(matches!(read_source(&small).unwrap(), Source::Owned(_)));
+        assert!(matches!(read_source(&large).unwrap(), Source::Mapped(_)));
+
+        let small_stats = count_lines(&small, rust).unwrap();
+        let large_stats = count_lines(&large, rust).unwrap();
+        assert_eq!(
+            (small_stats.code, small_stats.comments, small_stats.blanks),
+            (1, 3, 1)
+        );
+        assert_eq!(large_stats.code, small_stats.code * repeats as u64);
+        assert_eq!(small_stats.bytes, chunk.len() as u64);
+        assert_eq!(small_stats.max_line_length, 10);
+        // Line terminators (one `\r\n`, four `\n`) are not part of any line's length.
+        assert_eq!(small_stats.line_bytes, chunk.len() as u64 - 6);
+        assert!(
+            (small_stats.avg_line_length() - (chunk.len() - 6) as f64 / 5.0).abs() < f64::EPSILON
+        );
+        assert_eq!(
+            large_stats.line_bytes,
+            small_stats.line_bytes * repeats as u64
+        );
+        assert_eq!(large_stats.comments, small_stats.comments * repeats as u64);
+        assert_eq!(large_stats.blanks, small_stats.blanks * repeats as u64);
     }
 }
@@ -1,12 +1,26 @@
-use crate::languages::Language;
+use crate::languages::{Category, Language, detect_language, get_language_ignore_case};
+use globset::{Glob, GlobMatcher};
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct CustomLanguageDef {
+    #[serde(default)]
     pub extensions: Vec<String>,
+    /// Exact file names, e.g. `Jenkinsfile`.
+    #[serde(default)]
+    pub filenames: Vec<String>,
+    /// Globs matched against the file name (or the whole path if they contain `/`).
+    #[serde(default)]
+    pub globs: Vec<String>,
+    /// Directory prefixes, e.g. `ci/pipelines`; every file below them matches.
+    #[serde(default)]
+    pub path_prefixes: Vec<String>,
+    /// Other names accepted by `--include-lang`, `--exclude-lang` and `--force-lang`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
     #[serde(default)]
     pub line_comments: Vec<String>,
     pub block_comment_start: Option<String>,
@@ -21,72 +35,446 @@ fn default_string_delimiters() -> Vec<String> {
     vec!["\"".to_string(), "'".to_string()]
 }
 
-static CUSTOM_LANGUAGES: OnceLock<CustomLanguages> = OnceLock::new();
+impl CustomLanguageDef {
+    pub fn into_language_def(self, name: impl Into<String>) -> LanguageDef {
+        LanguageDef {
+            name: name.into(),
+            extensions: self.extensions,
+            filenames: self.filenames,
+            globs: self.globs,
+            path_prefixes: self.path_prefixes,
+            aliases: self.aliases,
+            line_comments: self.line_comments,
+            block_comment_start: self.block_comment_start,
+            block_comment_end: self.block_comment_end,
+            nested_comments: self.nested_comments,
+            string_delimiters: self.string_delimiters,
+        }
+    }
+}
+
+/// A language definition registered at runtime.
+///
+/// Using the name of a built-in language (e.g. `"Rust"`) replaces its comment syntax;
+/// listed extensions take precedence over the built-in extension map.
+#[derive(Debug, Clone)]
+pub struct LanguageDef {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub filenames: Vec<String>,
+    pub globs: Vec<String>,
+    pub path_prefixes: Vec<String>,
+    pub aliases: Vec<String>,
+    pub line_comments: Vec<String>,
+    pub block_comment_start: Option<String>,
+    pub block_comment_end: Option<String>,
+    pub nested_comments: bool,
+    pub string_delimiters: Vec<String>,
+}
+
+impl LanguageDef {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            extensions: Vec::new(),
+            filenames: Vec::new(),
+            globs: Vec::new(),
+            path_prefixes: Vec::new(),
+            aliases: Vec::new(),
+            line_comments: Vec::new(),
+            block_comment_start: None,
+            block_comment_end: None,
+            nested_comments: false,
+            string_delimiters: default_string_delimiters(),
+        }
+    }
+
+    pub fn extensions<S: Into<String>>(mut self, exts: impl IntoIterator<Item = S>) -> Self {
+        self.extensions = exts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn filenames<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.filenames = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn globs<S: Into<String>>(mut self, globs: impl IntoIterator<Item = S>) -> Self {
+        self.globs = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn path_prefixes<S: Into<String>>(mut self, prefixes: impl IntoIterator<Item = S>) -> Self {
+        self.path_prefixes = prefixes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn aliases<S: Into<String>>(mut self, aliases: impl IntoIterator<Item = S>) -> Self {
+        self.aliases = aliases.into_iter().map(Into::into).collect();
+        self
+    }
 
-pub struct CustomLanguages {
+    /// Check that every glob pattern compiles.
+    pub fn validate(&self) -> Result<(), String> {
+        for pattern in &self.globs {
+            Glob::new(pattern)
+                .map_err(|e| format!("Invalid glob '{}' for {}: {}", pattern, self.name, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn line_comments<S: Into<String>>(mut self, comments: impl IntoIterator<Item = S>) -> Self {
+        self.line_comments = comments.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn block_comments(mut self, start: impl Into<String>, end: impl Into<String>) -> Self {
+        self.block_comment_start = Some(start.into());
+        self.block_comment_end = Some(end.into());
+        self
+    }
+
+    pub fn nested_comments(mut self, nested: bool) -> Self {
+        self.nested_comments = nested;
+        self
+    }
+
+    pub fn string_delimiters<S: Into<String>>(
+        mut self,
+        delims: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.string_delimiters = delims.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn into_language(self) -> &'static Language {
+        // Built-in languages keep their category when overridden.
+        let category = get_language_ignore_case(&self.name)
+            .map(|lang| lang.category)
+            .unwrap_or(Category::Programming);
+
+        Box::leak(Box::new(Language {
+            name: Box::leak(self.name.into_boxed_str()),
+            line_comments: leak_strs(self.line_comments),
+            word_comments: &[],
+            line_start_comments: &[],
+            block_comment_start: self
+                .block_comment_start
+                .map(|s| Box::leak(s.into_boxed_str()) as &'static str),
+            block_comment_end: self
+                .block_comment_end
+                .map(|s| Box::leak(s.into_boxed_str()) as &'static str),
+            alt_block_comment: None,
+            nested_comments: self.nested_comments,
+            string_delimiters: leak_strs(self.string_delimiters),
+            raw_string_start: None,
+            raw_string_end: None,
+            heredoc: None,
+            multiline_strings: &[],
+            column_comments: &[],
+            category,
+        }))
+    }
+}
+
+fn leak_strs(strings: Vec<String>) -> &'static [&'static str] {
+    Box::leak(
+        strings
+            .into_iter()
+            .map(|s| Box::leak(s.into_boxed_str()) as &'static str)
+            .collect::<Vec<_>>()
+            .into_boxed_slice(),
+    )
+}
+
+/// A set of custom language definitions consulted before the built-in tables.
+///
+/// Attach one to an analysis with `AnalyzeConfig::custom_languages` (or
+/// `WalkerConfig::custom_languages`); nothing is shared between analyses.
+#[derive(Debug, Clone, Default)]
+pub struct CustomLanguageRegistry {
     languages: HashMap<String, &'static Language>,
     extensions: HashMap<String, String>,
+    filenames: HashMap<String, String>,
+    globs: Vec<(GlobMatcher, bool, String)>,
+    path_prefixes: Vec<(PathBuf, String)>,
+    aliases: HashMap<String, String>,
 }
 
-impl CustomLanguages {
-    pub fn load(path: &Path) -> Result<(), String> {
+impl CustomLanguageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a language, replacing any earlier definition with the same name.
+    ///
+    /// Globs that fail to compile are ignored; call [`LanguageDef::validate`] first to
+    /// report them.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, def: LanguageDef) -> Self {
+        self.insert(def);
+        self
+    }
+
+    pub fn insert(&mut self, def: LanguageDef) {
+        for ext in &def.extensions {
+            self.extensions
+                .insert(ext.trim_start_matches('.').to_lowercase(), def.name.clone());
+        }
+        for filename in &def.filenames {
+            self.filenames.insert(filename.clone(), def.name.clone());
+        }
+        for pattern in &def.globs {
+            if let Ok(glob) = Glob::new(pattern) {
+                self.globs.push((
+                    glob.compile_matcher(),
+                    pattern.contains('/'),
+                    def.name.clone(),
+                ));
+            }
+        }
+        for prefix in &def.path_prefixes {
+            let prefix: PathBuf = prefix
+                .split(['/', '\\'])
+                .filter(|c| !c.is_empty() && *c != ".")
+                .collect();
+            if !prefix.as_os_str().is_empty() {
+                self.path_prefixes.push((prefix, def.name.clone()));
+            }
+        }
+        for alias in &def.aliases {
+            self.aliases.insert(alias.to_lowercase(), def.name.clone());
+        }
+        let name = def.name.clone();
+        self.languages.insert(name, def.into_language());
+    }
+
+    /// Build a registry from a file mapping language names to definitions.
+    ///
+    /// The format follows the extension: `.json`, `.toml`, otherwise YAML.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
         let content = std::fs::read_to_string(path)
             .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
 
-        let defs: HashMap<String, CustomLanguageDef> = serde_yaml::from_str(&content)
-            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
-
-        let mut languages = HashMap::new();
-        let mut extensions = HashMap::new();
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let defs: HashMap<String, CustomLanguageDef> = match ext.as_deref() {
+            Some("json") => serde_json::from_str(&content).map_err(|e| e.to_string()),
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
 
+        let mut registry = Self::new();
         for (name, def) in defs {
-            for ext in &def.extensions {
-                extensions.insert(ext.to_lowercase(), name.clone());
+            let def = def.into_language_def(name);
+            def.validate()
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            registry.insert(def);
+        }
+        Ok(registry)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty()
+    }
+
+    /// Look up a registered language by name or alias, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&'static Language> {
+        self.languages
+            .get(name)
+            .copied()
+            .or_else(|| {
+                self.languages
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                    .map(|(_, v)| *v)
+            })
+            .or_else(|| {
+                let name = self.aliases.get(&name.to_lowercase())?;
+                self.languages.get(name).copied()
+            })
+    }
+
+    pub fn get_by_extension(&self, ext: &str) -> Option<&'static Language> {
+        let name = self.extensions.get(&ext.to_lowercase())?;
+        self.languages.get(name).copied()
+    }
+
+    /// Language matched by a filename, path prefix or glob rule, in that order.
+    pub fn get_by_path(&self, path: &Path) -> Option<&'static Language> {
+        let filename = path.file_name().and_then(|n| n.to_str());
+
+        if let Some(name) = filename.and_then(|f| self.filenames.get(f)) {
+            return self.languages.get(name).copied();
+        }
+
+        if let Some(dir) = path.parent() {
+            for (prefix, name) in &self.path_prefixes {
+                if contains_dirs(dir, prefix) {
+                    return self.languages.get(name).copied();
+                }
             }
+        }
 
-            let lang = Box::leak(Box::new(Language {
-                name: Box::leak(name.clone().into_boxed_str()),
-                line_comments: Box::leak(
-                    def.line_comments
-                        .into_iter()
-                        .map(|s| Box::leak(s.into_boxed_str()) as &'static str)
-                        .collect::<Vec<_>>()
-                        .into_boxed_slice(),
-                ),
-                block_comment_start: def
-                    .block_comment_start
-                    .map(|s| Box::leak(s.into_boxed_str()) as &'static str),
-                block_comment_end: def
-                    .block_comment_end
-                    .map(|s| Box::leak(s.into_boxed_str()) as &'static str),
-                nested_comments: def.nested_comments,
-                string_delimiters: Box::leak(
-                    def.string_delimiters
-                        .into_iter()
-                        .map(|s| Box::leak(s.into_boxed_str()) as &'static str)
-                        .collect::<Vec<_>>()
-                        .into_boxed_slice(),
-                ),
-                raw_string_start: None,
-                raw_string_end: None,
-            }));
-
-            languages.insert(name, lang as &'static Language);
+        for (matcher, full_path, name) in &self.globs {
+            let matched = if *full_path {
+                matcher.is_match(path)
+            } else {
+                filename.is_some_and(|f| matcher.is_match(f))
+            };
+            if matched {
+                return self.languages.get(name).copied();
+            }
         }
 
-        CUSTOM_LANGUAGES
-            .set(CustomLanguages {
-                languages,
-                extensions,
-            })
-            .map_err(|_| "Custom languages already loaded".to_string())?;
+        None
+    }
 
-        Ok(())
+    /// Detect a file's language: custom filename/path/glob rules and extensions first,
+    /// then the built-in tables, with overridden built-in languages swapped for their
+    /// custom definitions.
+    pub fn detect(&self, path: &Path) -> Option<&'static Language> {
+        if let Some(lang) = self.get_by_path(path) {
+            return Some(lang);
+        }
+
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            if let Some(lang) = self.get_by_extension(ext) {
+                return Some(lang);
+            }
+        }
+
+        let builtin = detect_language(path)?;
+        Some(self.languages.get(builtin.name).copied().unwrap_or(builtin))
+    }
+
+    /// Iterate over registered languages as `(name, language)` pairs.
+    pub fn languages(&self) -> impl Iterator<Item = (&str, &'static Language)> {
+        self.languages.iter().map(|(k, v)| (k.as_str(), *v))
+    }
+
+    /// Iterate over registered extensions as `(extension, language name)` pairs.
+    pub fn extensions(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.extensions
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+static CUSTOM_LANGUAGES: OnceLock<CustomLanguageRegistry> = OnceLock::new();
+
+/// Process-wide custom languages used by the free `detect_language` function.
+///
+/// Prefer passing a [`CustomLanguageRegistry`] through the analysis config.
+pub struct CustomLanguages;
+
+impl CustomLanguages {
+    pub fn load(path: &Path) -> Result<(), String> {
+        Self::install(CustomLanguageRegistry::from_file(path)?)
+    }
+
+    pub fn install(registry: CustomLanguageRegistry) -> Result<(), String> {
+        CUSTOM_LANGUAGES
+            .set(registry)
+            .map_err(|_| "Custom languages already loaded".to_string())
     }
 
     pub fn get_by_extension(ext: &str) -> Option<&'static Language> {
-        let custom = CUSTOM_LANGUAGES.get()?;
-        let lang_name = custom.extensions.get(&ext.to_lowercase())?;
-        custom.languages.get(lang_name).copied()
+        CUSTOM_LANGUAGES.get()?.get_by_extension(ext)
+    }
+}
+
+/// Whether the directories `prefix` names appear, whole and in order, in `dir`: at its
+/// start or below any of its ancestors.
+fn contains_dirs(dir: &Path, prefix: &Path) -> bool {
+    let mut tail = dir.components();
+    loop {
+        if tail.as_path().starts_with(prefix) {
+            return true;
+        }
+        if tail.next().is_none() {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_new_language() {
+        let registry = CustomLanguageRegistry::new().add(
+            LanguageDef::new("MyLang")
+                .extensions(["myl"])
+                .aliases(["ml2"])
+                .line_comments(["%%"]),
+        );
+
+        let lang = registry.detect(Path::new("src/a.MYL")).unwrap();
+        assert_eq!(lang.name, "MyLang");
+        assert_eq!(lang.line_comments, &["%%"]);
+        assert_eq!(registry.get("mylang").unwrap().name, "MyLang");
+        assert_eq!(registry.get("ML2").unwrap().name, "MyLang");
+        assert_eq!(registry.detect(Path::new("a.rs")).unwrap().name, "Rust");
+    }
+
+    #[test]
+    fn test_from_file_formats() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let files = [
+            ("langs.yaml", "MyLang:\n  extensions: [myl]\n"),
+            ("langs.json", r#"{"MyLang": {"extensions": ["myl"]}}"#),
+            ("langs.toml", "[MyLang]\nextensions = [\"myl\"]\n"),
+        ];
+
+        for (name, content) in files {
+            let path = temp.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            let registry = CustomLanguageRegistry::from_file(&path).unwrap();
+            assert_eq!(registry.get_by_extension("myl").unwrap().name, "MyLang");
+        }
+    }
+
+    #[test]
+    fn test_registry_filename_glob_prefix() {
+        let registry = CustomLanguageRegistry::new()
+            .add(LanguageDef::new("Jenkins").filenames(["Jenkinsfile"]))
+            .add(LanguageDef::new("Blade").globs(["*.blade.php"]))
+            .add(LanguageDef::new("Pipeline").path_prefixes(["ci/pipelines"]));
+
+        let detect = |p: &str| registry.detect(Path::new(p)).map(|l| l.name);
+        assert_eq!(detect("repo/Jenkinsfile"), Some("Jenkins"));
+        assert_eq!(detect("views/home.blade.php"), Some("Blade"));
+        assert_eq!(detect("views/home.php"), Some("PHP"));
+        assert_eq!(detect("./ci/pipelines/build"), Some("Pipeline"));
+        assert_eq!(detect("/repo/ci/pipelines/deploy.yml"), Some("Pipeline"));
+        assert_eq!(detect("ci/other.yml"), Some("YAML"));
+        assert_eq!(detect("ci/pipelines2/other.yml"), Some("YAML"));
+        assert_eq!(detect("ci/pipelines"), None);
+        assert!(LanguageDef::new("Bad").globs(["a[b"]).validate().is_err());
+
+        // Components that are not UTF-8 still separate the prefix's directories.
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let path = Path::new(std::ffi::OsStr::from_bytes(b"ci/\xff/pipelines/a.yml"));
+            assert_eq!(registry.detect(path).map(|l| l.name), Some("YAML"));
+        }
+    }
+
+    #[test]
+    fn test_registry_overrides_builtin() {
+        let registry = CustomLanguageRegistry::new()
+            .add(LanguageDef::new("Python").line_comments(["#", "//"]))
+            .add(LanguageDef::new("Templ").extensions([".h"]));
+
+        let python = registry.detect(Path::new("main.py")).unwrap();
+        assert_eq!(python.line_comments, &["#", "//"]);
+        assert_eq!(python.category, Category::Programming);
+        assert_eq!(registry.detect(Path::new("x.h")).unwrap().name, "Templ");
     }
 }
@@ -1,9 +1,23 @@
-use crate::counter::{FileStats, count_lines};
-use crate::walker::{FileEntry, WalkerConfig, walk_files};
-use std::collections::HashMap;
-use std::path::PathBuf;
+//! Comparing two file sets (`--diff`) or two saved analyses.
+//!
+//! Files are matched by their path below each side's root and counted once per distinct
+//! content, in parallel.
 
-#[derive(Debug, Clone, Default)]
+use crate::archive::{extract_archive_root, is_archive};
+use crate::counter::{CountConfig, FileStats, count_bytes, hash_bytes, read_source};
+use crate::stats::Warning;
+use crate::walker::{FileEntry, WalkerConfig, relative_to_root, walk_files};
+use crate::{Analysis, LanguageBreakdown};
+use dashmap::DashMap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct DiffStats {
     pub same: LanguageDiff,
     pub modified: LanguageDiff,
@@ -11,7 +25,7 @@ pub struct DiffStats {
     pub removed: LanguageDiff,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct LanguageDiff {
     pub files: u64,
     pub code: u64,
@@ -27,128 +41,437 @@ impl LanguageDiff {
         self.blanks += stats.blanks;
     }
 
-    #[allow(dead_code)]
     pub fn total(&self) -> u64 {
         self.code + self.comments + self.blanks
     }
+
+    fn merge(&mut self, other: &LanguageDiff) {
+        self.files += other.files;
+        self.code += other.code;
+        self.comments += other.comments;
+        self.blanks += other.blanks;
+    }
+}
+
+/// How a file changed between the two sides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    /// Identical content on both sides.
+    Same,
+    /// Different content, even if the line counts match.
+    Modified,
+    Added,
+    Removed,
+}
+
+impl FileStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileStatus::Same => "same",
+            FileStatus::Modified => "modified",
+            FileStatus::Added => "added",
+            FileStatus::Removed => "removed",
+        }
+    }
+}
+
+/// Line counts of a file on one side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineCounts {
+    pub code: u64,
+    pub comments: u64,
+    pub blanks: u64,
+}
+
+impl From<&FileStats> for LineCounts {
+    fn from(stats: &FileStats) -> Self {
+        Self {
+            code: stats.code,
+            comments: stats.comments,
+            blanks: stats.blanks,
+        }
+    }
+}
+
+/// One file matched between the two sides by its path below the root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: PathBuf,
+    pub language: String,
+    pub status: FileStatus,
+    /// Counts on the old side; `None` for added files.
+    pub old: Option<LineCounts>,
+    /// Counts on the new side; `None` for removed files.
+    pub new: Option<LineCounts>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct DiffResult {
-    pub by_language: HashMap<String, DiffStats>,
+    pub by_language: BTreeMap<String, DiffStats>,
     pub totals: DiffStats,
+    /// Every compared file, sorted by path. Empty for [`diff_analyses`], which has no
+    /// per-file data.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileDiff>,
+    /// Files in either set that could not be read, sorted by path.
+    pub warnings: Vec<Warning>,
+}
+
+/// Directory to walk for one side of a diff: `path` itself, or for an archive the
+/// directory it is extracted to under `scratch`, below any top-level directory shared by
+/// all entries, so `app-1.0.tar.gz` and `app-1.1.tar.gz` compare file by file.
+pub fn diff_root(path: &Path, scratch: &Path) -> io::Result<PathBuf> {
+    if path.is_file() && is_archive(path) {
+        std::fs::create_dir_all(scratch)?;
+        Ok(extract_archive_root(path, scratch)?)
+    } else {
+        Ok(path.to_path_buf())
+    }
 }
 
-pub fn compute_diff(config1: &WalkerConfig, config2: &WalkerConfig, verbose: bool) -> DiffResult {
+/// Counts shared by both sides of a diff: files with identical content (e.g. unchanged
+/// files in two release trees) are counted once per language.
+#[derive(Default)]
+struct DiffCounter {
+    config: CountConfig,
+    counted: DashMap<(u64, &'static str), FileStats>,
+    warnings: Mutex<Vec<Warning>>,
+    done: AtomicU64,
+    total: u64,
+}
+
+impl DiffResult {
+    /// Languages ordered by the code they touch (same + modified + added + removed), most
+    /// first.
+    pub fn languages_by_code(&self) -> Vec<(&str, &DiffStats)> {
+        let mut languages: Vec<_> = self
+            .by_language
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+            .collect();
+        languages.sort_by_key(|(_, stats)| {
+            std::cmp::Reverse(
+                stats.same.code + stats.modified.code + stats.added.code + stats.removed.code,
+            )
+        });
+        languages
+    }
+}
+
+/// Compare the files under `old` with those under `new`, walking both with `config`'s
+/// filters and counting with `count_config`. Archives are extracted to a private scratch
+/// directory that is removed afterwards. `progress` is called with the files counted so far
+/// and the total after each file.
+pub fn diff_paths(
+    old: &[PathBuf],
+    new: &Path,
+    config: &WalkerConfig,
+    count_config: &CountConfig,
+    progress: impl Fn(u64, u64) + Sync,
+) -> io::Result<DiffResult> {
+    let scratch = tempfile::Builder::new().prefix("rloc-diff-").tempdir()?;
+    let mut config1 = config.clone();
+    config1.paths = old
+        .iter()
+        .enumerate()
+        .map(|(i, path)| diff_root(path, &scratch.path().join(format!("old-{}", i))))
+        .collect::<io::Result<_>>()?;
+    let mut config2 = config.clone();
+    config2.paths = vec![diff_root(new, &scratch.path().join("new"))?];
+    Ok(compute_diff(&config1, &config2, count_config, progress))
+}
+
+/// Compare two file sets, counting files in parallel with `count_config`. `progress` is
+/// called with the files counted so far and the total after each file.
+pub fn compute_diff(
+    config1: &WalkerConfig,
+    config2: &WalkerConfig,
+    count_config: &CountConfig,
+    progress: impl Fn(u64, u64) + Sync,
+) -> DiffResult {
     let files1 = walk_files(config1);
     let files2 = walk_files(config2);
 
-    let stats1 = collect_stats(&files1, verbose);
-    let stats2 = collect_stats(&files2, verbose);
+    let counter = DiffCounter {
+        config: count_config.clone(),
+        total: (files1.len() + files2.len()) as u64,
+        ..Default::default()
+    };
+    let stats1 = counter.collect_stats(&files1, &config1.paths, &progress);
+    let stats2 = counter.collect_stats(&files2, &config2.paths, &progress);
 
-    let mut by_language: HashMap<String, DiffStats> = HashMap::new();
+    let mut by_language: BTreeMap<String, DiffStats> = BTreeMap::new();
     let mut totals = DiffStats::default();
+    let mut files = Vec::with_capacity(stats1.len().max(stats2.len()));
 
     // Process files from set 1
-    for (path, (lang, stats)) in &stats1 {
+    for (path, (lang, hash, stats)) in &stats1 {
         let entry = by_language.entry(lang.clone()).or_default();
+        let new = stats2.get(path);
 
-        if let Some((_, stats2)) = stats2.get(path) {
-            if stats.code == stats2.code
-                && stats.comments == stats2.comments
-                && stats.blanks == stats2.blanks
-            {
+        // Compared by content: an edit can leave the line counts as they were.
+        let status = match new {
+            Some((_, new_hash, _)) if new_hash == hash => {
                 entry.same.add(stats);
                 totals.same.add(stats);
-            } else {
+                FileStatus::Same
+            }
+            Some(_) => {
                 entry.modified.add(stats);
                 totals.modified.add(stats);
+                FileStatus::Modified
             }
-        } else {
-            entry.removed.add(stats);
-            totals.removed.add(stats);
-        }
+            None => {
+                entry.removed.add(stats);
+                totals.removed.add(stats);
+                FileStatus::Removed
+            }
+        };
+        files.push(FileDiff {
+            path: path.clone(),
+            language: lang.clone(),
+            status,
+            old: Some(stats.into()),
+            new: new.map(|(_, _, stats2)| LineCounts::from(stats2)),
+        });
     }
 
     // Process files only in set 2 (added)
-    for (path, (lang, stats)) in &stats2 {
+    for (path, (lang, _, stats)) in &stats2 {
         if !stats1.contains_key(path) {
             let entry = by_language.entry(lang.clone()).or_default();
             entry.added.add(stats);
             totals.added.add(stats);
+            files.push(FileDiff {
+                path: path.clone(),
+                language: lang.clone(),
+                status: FileStatus::Added,
+                old: None,
+                new: Some(stats.into()),
+            });
         }
     }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut warnings = counter.warnings.into_inner().unwrap_or_default();
+    warnings.sort_by(|a, b| a.path.cmp(&b.path));
 
     DiffResult {
         by_language,
         totals,
+        files,
+        warnings,
     }
 }
 
-fn collect_stats(files: &[FileEntry], verbose: bool) -> HashMap<PathBuf, (String, FileStats)> {
-    let mut result = HashMap::new();
+impl DiffCounter {
+    /// Language, content hash and stats keyed by path below the root, so both sides line up.
+    fn collect_stats(
+        &self,
+        files: &[FileEntry],
+        roots: &[PathBuf],
+        progress: &(impl Fn(u64, u64) + Sync),
+    ) -> HashMap<PathBuf, (String, u64, FileStats)> {
+        files
+            .par_iter()
+            .filter_map(|entry| {
+                let counted = self.count(entry);
+                progress(self.done.fetch_add(1, Ordering::Relaxed) + 1, self.total);
+                let (hash, stats) = counted.filter(|(_, stats)| !stats.is_empty())?;
+                let relative = relative_to_root(&entry.path, roots);
+                // A file given directly as a root is matched by its name.
+                let relative = if relative == entry.path {
+                    PathBuf::from(relative.file_name().unwrap_or_default())
+                } else {
+                    relative.to_path_buf()
+                };
+                Some((relative, (entry.language.name.to_string(), hash, stats)))
+            })
+            .collect()
+    }
+
+    /// The content hash and stats of `entry`.
+    fn count(&self, entry: &FileEntry) -> Option<(u64, FileStats)> {
+        let source = match read_source(&entry.path) {
+            Ok(source) => source,
+            Err(e) => {
+                self.warn(entry, &e);
+                return None;
+            }
+        };
 
-    for entry in files {
-        match count_lines(&entry.path, entry.language) {
-            Ok(stats) if stats.total() > 0 => {
-                let relative = entry
-                    .path
-                    .file_name()
-                    .map(PathBuf::from)
-                    .unwrap_or_else(|| entry.path.clone());
-                result.insert(relative, (entry.language.name.to_string(), stats));
+        let hash = hash_bytes(&source);
+        let key = (hash, entry.language.name);
+        if let Some(stats) = self.counted.get(&key) {
+            return Some((hash, stats.clone()));
+        }
+        match count_bytes(&entry.path, &source, entry.language, &self.config) {
+            Ok(stats) => {
+                self.counted.insert(key, stats.clone());
+                Some((hash, stats))
             }
-            Err(e) if verbose => {
-                eprintln!("warning: {}: {}", entry.path.display(), e);
+            Err(e) => {
+                self.warn(entry, &e);
+                None
             }
-            _ => {}
         }
     }
 
-    result
-}
-
-pub fn render_diff(result: &DiffResult) {
-    println!();
-    println!(
-        "{:<14} {:>10} {:>10} {:>10} {:>10}",
-        "Language", "Same", "Modified", "Added", "Removed"
-    );
-    println!("{}", "─".repeat(58));
-
-    let mut langs: Vec<_> = result.by_language.iter().collect();
-    langs.sort_by(|a, b| {
-        let total_a = a.1.same.code + a.1.modified.code + a.1.added.code + a.1.removed.code;
-        let total_b = b.1.same.code + b.1.modified.code + b.1.added.code + b.1.removed.code;
-        total_b.cmp(&total_a)
-    });
-
-    for (lang, stats) in langs {
-        println!(
-            "{:<14} {:>10} {:>10} {:>10} {:>10}",
-            lang,
-            format_diff_count(stats.same.code),
-            format_diff_count(stats.modified.code),
-            format_diff_count(stats.added.code),
-            format_diff_count(stats.removed.code),
-        );
+    fn warn(&self, entry: &FileEntry, error: &std::io::Error) {
+        if let Ok(mut warnings) = self.warnings.lock() {
+            warnings.push(Warning::from_io(&entry.path, error));
+        }
+    }
+}
+
+/// Compare two analyses language by language. Without per-file data, lines present on
+/// both sides count as `same` (the smaller of the two counts) and the difference as
+/// `added` or `removed`; `modified` stays empty.
+pub fn diff_analyses(old: &Analysis, new: &Analysis) -> DiffResult {
+    let find = |analysis: &'_ Analysis, name: &str| {
+        analysis
+            .languages
+            .iter()
+            .find(|lang| lang.name == name)
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let mut by_language = BTreeMap::new();
+    let mut totals = DiffStats::default();
+    for name in old
+        .languages
+        .iter()
+        .chain(&new.languages)
+        .map(|l| l.name.as_ref())
+    {
+        if by_language.contains_key(name) {
+            continue;
+        }
+        let stats = compare_breakdowns(&find(old, name), &find(new, name));
+        for (total, part) in [
+            (&mut totals.same, &stats.same),
+            (&mut totals.added, &stats.added),
+            (&mut totals.removed, &stats.removed),
+        ] {
+            total.merge(part);
+        }
+        by_language.insert(name.to_string(), stats);
     }
 
-    println!("{}", "─".repeat(58));
-    println!(
-        "{:<14} {:>10} {:>10} {:>10} {:>10}",
-        "SUM",
-        format_diff_count(result.totals.same.code),
-        format_diff_count(result.totals.modified.code),
-        format_diff_count(result.totals.added.code),
-        format_diff_count(result.totals.removed.code),
-    );
+    DiffResult {
+        by_language,
+        totals,
+        files: Vec::new(),
+        warnings: Vec::new(),
+    }
 }
 
-fn format_diff_count(n: u64) -> String {
-    if n == 0 {
-        "-".to_string()
-    } else {
-        n.to_string()
+fn compare_breakdowns(old: &LanguageBreakdown, new: &LanguageBreakdown) -> DiffStats {
+    let split = |old: u64, new: u64| {
+        (
+            old.min(new),
+            new.saturating_sub(old),
+            old.saturating_sub(new),
+        )
+    };
+    let (same_files, added_files, removed_files) = split(old.files, new.files);
+    let (same_code, added_code, removed_code) = split(old.code, new.code);
+    let (same_comments, added_comments, removed_comments) = split(old.comments, new.comments);
+    let (same_blanks, added_blanks, removed_blanks) = split(old.blanks, new.blanks);
+
+    DiffStats {
+        same: LanguageDiff {
+            files: same_files,
+            code: same_code,
+            comments: same_comments,
+            blanks: same_blanks,
+        },
+        modified: LanguageDiff::default(),
+        added: LanguageDiff {
+            files: added_files,
+            code: added_code,
+            comments: added_comments,
+            blanks: added_blanks,
+        },
+        removed: LanguageDiff {
+            files: removed_files,
+            code: removed_code,
+            comments: removed_comments,
+            blanks: removed_blanks,
+        },
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_compute_diff() {
+        let old = TempDir::new().unwrap();
+        let new = TempDir::new().unwrap();
+        let unchanged = "fn a() {}\n// note\n";
+        for dir in [old.path(), new.path()] {
+            fs::write(dir.join("same.rs"), unchanged).unwrap();
+            fs::write(dir.join("copy.rs"), unchanged).unwrap();
+        }
+        fs::write(old.path().join("edit.rs"), "fn b() {}\n").unwrap();
+        // Same counts, different content.
+        fs::write(old.path().join("reword.rs"), "let a = 1;\n").unwrap();
+        fs::write(new.path().join("reword.rs"), "let b = 2;\n").unwrap();
+        fs::write(new.path().join("edit.rs"), "fn b() {}\nfn c() {}\n").unwrap();
+        fs::write(old.path().join("gone.py"), "x = 1\n").unwrap();
+        fs::write(new.path().join("new.py"), "y = 2\nz = 3\n").unwrap();
+
+        let config = |dir: &TempDir| {
+            let mut config = WalkerConfig::default();
+            config.paths = vec![dir.path().to_path_buf()];
+            config
+        };
+        let counted = AtomicU64::new(0);
+        let count_config = CountConfig::default();
+        let result = compute_diff(
+            &config(&old),
+            &config(&new),
+            &count_config,
+            |done, total| {
+                assert_eq!(total, 10);
+                counted.fetch_max(done, Ordering::Relaxed);
+            },
+        );
+
+        assert_eq!(counted.into_inner(), 10);
+        let rust = &result.by_language["Rust"];
+        assert_eq!((rust.same.files, rust.same.code), (2, 2));
+        assert_eq!((rust.modified.files, rust.modified.code), (2, 2));
+        let python = &result.by_language["Python"];
+        assert_eq!((python.added.code, python.removed.code), (2, 1));
+        assert!(result.warnings.is_empty());
+
+        let files: Vec<_> = result
+            .files
+            .iter()
+            .map(|file| (file.path.to_str().unwrap(), file.status))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("copy.rs", FileStatus::Same),
+                ("edit.rs", FileStatus::Modified),
+                ("gone.py", FileStatus::Removed),
+                ("new.py", FileStatus::Added),
+                ("reword.rs", FileStatus::Modified),
+                ("same.rs", FileStatus::Same),
+            ]
+        );
+        let edit = &result.files[1];
+        assert_eq!(edit.old.map(|old| old.code), Some(1));
+        assert_eq!(edit.new.map(|new| new.code), Some(2));
+        assert_eq!(result.files[3].old, None);
     }
 }
@@ -14,4 +14,25 @@ pub enum Error {
 
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
+
+    #[error("Invalid report: {0}")]
+    InvalidReport(String),
+
+    #[error("Analysis cancelled")]
+    Cancelled,
+
+    #[error("{failed} of {total} budgets exceeded")]
+    BudgetExceeded { failed: usize, total: usize },
+}
+
+impl Error {
+    /// Process exit code the CLI uses for this error: 2 when no source files were found,
+    /// 3 when a budget was exceeded and 1 otherwise.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::NoSourceFiles => 2,
+            Error::BudgetExceeded { .. } => 3,
+            _ => 1,
+        }
+    }
 }
@@ -0,0 +1,676 @@
+//! Step-by-step account of how one file is walked, detected and counted (`rloc explain`).
+//!
+//! [`Explainer::explain`] replays the walker's decisions for a single path (directory
+//! exclusions, ignore rules, path and language filters, size and content filters) and then
+//! the counter's (unreadable, duplicate, binary and empty files), recording each step with
+//! the rule that decided it. The filters themselves are the walker's, so the trace cannot
+//! drift from what a count does.
+
+use crate::counter::{self, CountConfig};
+use crate::custom_langs::CustomLanguageRegistry;
+use crate::languages::{Language, detect_language_by_filename};
+use crate::walker::{self, DetectionRule, FileFilter, VcsMode, WalkerConfig};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder, gitconfig_excludes_path};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// What a step decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The file got past this step.
+    Pass,
+    /// Left out by a filter, like files a count never mentions.
+    Excluded,
+    /// Found but not counted, like the files tallied in [`crate::SkippedFiles`].
+    Skipped,
+}
+
+impl Outcome {
+    pub fn name(self) -> &'static str {
+        match self {
+            Outcome::Pass => "pass",
+            Outcome::Excluded => "excluded",
+            Outcome::Skipped => "skipped",
+        }
+    }
+}
+
+/// One check applied to the file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Step {
+    /// What was checked, e.g. `ignore rules` or `language`.
+    pub check: &'static str,
+    pub outcome: Outcome,
+    /// The rule that decided, or what was found.
+    pub detail: String,
+}
+
+/// The steps for one file, up to and including the first that stopped it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    /// The path as the walk reports it, below its root.
+    pub path: PathBuf,
+    pub steps: Vec<Step>,
+    /// The language the file is counted as, once detection was reached.
+    pub language: Option<&'static str>,
+    pub counted: bool,
+}
+
+impl Explanation {
+    fn pass(&mut self, check: &'static str, detail: impl Into<String>) {
+        self.steps.push(Step {
+            check,
+            outcome: Outcome::Pass,
+            detail: detail.into(),
+        });
+    }
+}
+
+/// A step that stops the file.
+fn stop(check: &'static str, outcome: Outcome, detail: impl Into<String>) -> Step {
+    Step {
+        check,
+        outcome,
+        detail: detail.into(),
+    }
+}
+
+/// Explains files under one walker and count configuration.
+pub struct Explainer<'a> {
+    walker: &'a WalkerConfig,
+    count: &'a CountConfig,
+    exclude_generated: bool,
+    skip_minified: bool,
+}
+
+impl<'a> Explainer<'a> {
+    pub fn new(walker: &'a WalkerConfig, count: &'a CountConfig) -> Self {
+        Self {
+            walker,
+            count,
+            exclude_generated: false,
+            skip_minified: false,
+        }
+    }
+
+    /// Generated files are dropped after counting (`--no-generated`).
+    pub fn exclude_generated(mut self, exclude: bool) -> Self {
+        self.exclude_generated = exclude;
+        self
+    }
+
+    /// Minified files are dropped after counting (`--skip-minified`).
+    pub fn skip_minified(mut self, skip: bool) -> Self {
+        self.skip_minified = skip;
+        self
+    }
+
+    /// Why `path` is or is not counted. The file is looked up below the configured roots,
+    /// so a relative path may be given from the current directory.
+    pub fn explain(&self, path: &Path) -> Explanation {
+        let mut explanation = Explanation {
+            path: path.to_path_buf(),
+            steps: Vec::new(),
+            language: None,
+            counted: false,
+        };
+        match self.trace(path, &mut explanation) {
+            Ok(()) => explanation.counted = true,
+            Err(step) => explanation.steps.push(step),
+        }
+        explanation
+    }
+
+    fn trace(&self, path: &Path, explanation: &mut Explanation) -> Result<(), Step> {
+        let config = self.walker;
+        let (root, relative) = locate(&config.paths, path).ok_or_else(|| {
+            let roots: Vec<_> = config
+                .paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            stop(
+                "root",
+                Outcome::Excluded,
+                format!("not below the counted paths ({})", roots.join(", ")),
+            )
+        })?;
+        let walked = if relative.as_os_str().is_empty() {
+            root.clone()
+        } else {
+            root.join(&relative)
+        };
+        explanation.path = walked.clone();
+        if !walked.is_file() {
+            let detail = if walked.exists() {
+                "not a regular file"
+            } else {
+                "no such file"
+            };
+            return Err(stop("root", Outcome::Excluded, detail));
+        }
+
+        if relative.as_os_str().is_empty() {
+            explanation.pass(
+                "root",
+                "given directly, so directory and ignore rules don't apply",
+            );
+        } else {
+            explanation.pass(
+                "root",
+                format!("{} below {}", relative.display(), root.display()),
+            );
+            self.check_directories(root, &relative, explanation)?;
+            self.check_listing(root, &relative, &walked, explanation)?;
+        }
+
+        let filter = FileFilter::new(config);
+        if let Some(reason) = filter.path_rejection(&walked) {
+            return Err(stop("path filters", Outcome::Excluded, reason));
+        }
+        explanation.pass(
+            "path filters",
+            "no extension, glob or name filter excludes it",
+        );
+
+        let detected = filter.detect_with_rule(&walked);
+        let language = filter
+            .sniff(&walked, || walker::read_modeline_window(&walked))
+            .ok_or_else(|| {
+                stop(
+                    "language",
+                    Outcome::Skipped,
+                    "no language matches the file name, extension or a modeline (unrecognized)",
+                )
+            })?;
+        explanation.language = Some(language.name);
+        explanation.pass("language", self.detection(&walked, language, detected));
+
+        if let Some(reason) = filter.language_rejection(language) {
+            return Err(stop("language filters", Outcome::Excluded, reason));
+        }
+        match filter.classification(&walked) {
+            Ok((vendored, test)) => {
+                let kind = match (vendored, test) {
+                    (true, true) => "vendored test code",
+                    (true, false) => "vendored code",
+                    (false, true) => "test code",
+                    (false, false) => "not vendored or test code",
+                };
+                explanation.pass("language filters", format!("{}, {}", language.name, kind));
+            }
+            Err(reason) => return Err(stop("language filters", Outcome::Excluded, reason)),
+        }
+
+        let bytes = walker::long_path(&walked)
+            .metadata()
+            .map_or(0, |meta| meta.len());
+        match filter.max_bytes {
+            Some(max) if bytes > max => {
+                return Err(stop(
+                    "size",
+                    Outcome::Skipped,
+                    format!(
+                        "{} bytes, over the limit of {} (--max-file-size)",
+                        bytes, max
+                    ),
+                ));
+            }
+            _ => explanation.pass("size", format!("{} bytes", bytes)),
+        }
+
+        if filter.filters_content() {
+            if !filter.matches_file_content(&walked) {
+                return Err(stop(
+                    "content filters",
+                    Outcome::Excluded,
+                    "content fails --include-content or matches --exclude-content",
+                ));
+            }
+            explanation.pass("content filters", "content matches");
+        }
+
+        self.check_counting(&walked, language, explanation)
+    }
+
+    /// Excluded directories, depth, hidden paths and nested repositories: what a walk
+    /// prunes before it reaches the file.
+    fn check_directories(
+        &self,
+        root: &Path,
+        relative: &Path,
+        explanation: &mut Explanation,
+    ) -> Result<(), Step> {
+        let config = self.walker;
+        let parent = relative.parent().unwrap_or(Path::new(""));
+        if let Some(dir) = parent
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .find(|name| config.exclude_dirs.iter().any(|dir| dir == name))
+        {
+            return Err(stop(
+                "directories",
+                Outcome::Excluded,
+                format!("directory {} is excluded (--exclude-dir)", dir),
+            ));
+        }
+        let depth = relative.components().count();
+        if let Some(max) = config.max_depth.filter(|&max| depth > max) {
+            return Err(stop(
+                "directories",
+                Outcome::Excluded,
+                format!("{} levels deep, past --max-depth {}", depth, max),
+            ));
+        }
+        if !config.hidden && vcs_mode(config, root).is_none() {
+            if let Some(name) = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .find(|name| name.starts_with('.'))
+            {
+                return Err(stop(
+                    "directories",
+                    Outcome::Excluded,
+                    format!("{} is hidden (--hidden counts hidden files)", name),
+                ));
+            }
+        }
+        if config.exclude_nested_repos {
+            let mut dir = root.to_path_buf();
+            for component in parent.components() {
+                dir.push(component);
+                if walker::is_repo_root(&dir) {
+                    return Err(stop(
+                        "directories",
+                        Outcome::Excluded,
+                        format!(
+                            "{} is a nested repository (--exclude-nested-repos)",
+                            dir.display()
+                        ),
+                    ));
+                }
+            }
+        }
+        explanation.pass("directories", "no excluded, hidden or too deep directory");
+        Ok(())
+    }
+
+    /// The VCS file list (`--vcs`, `--staged`) or, for a file system walk, the ignore
+    /// files.
+    fn check_listing(
+        &self,
+        root: &Path,
+        relative: &Path,
+        walked: &Path,
+        explanation: &mut Explanation,
+    ) -> Result<(), Step> {
+        let config = self.walker;
+        if let Some(vcs) = vcs_mode(config, root) {
+            let listed = walker::vcs_files(config, vcs, root);
+            let command = match vcs {
+                VcsMode::Git if config.staged => "git diff --cached",
+                VcsMode::Git => "git ls-files",
+                VcsMode::Hg => "hg files",
+                _ => "svn ls",
+            };
+            match listed {
+                Some(files) if files.iter().any(|file| file == relative) => {
+                    explanation.pass("vcs", format!("listed by {}", command));
+                }
+                Some(_) => {
+                    return Err(stop(
+                        "vcs",
+                        Outcome::Excluded,
+                        format!("not listed by {}", command),
+                    ));
+                }
+                None if config.staged => {
+                    return Err(stop("vcs", Outcome::Excluded, "not in a git repository"));
+                }
+                None => explanation.pass(
+                    "vcs",
+                    format!("{} failed, so the file system is walked", command),
+                ),
+            }
+        }
+
+        match self.ignore_match(root, walked) {
+            Some((false, pattern, from)) => Err(stop(
+                "ignore rules",
+                Outcome::Excluded,
+                format!("ignored by {} in {}", pattern, from.display()),
+            )),
+            Some((true, pattern, from)) => {
+                explanation.pass(
+                    "ignore rules",
+                    format!("re-included by {} in {}", pattern, from.display()),
+                );
+                Ok(())
+            }
+            None => {
+                explanation.pass("ignore rules", "no ignore rule matches");
+                Ok(())
+            }
+        }
+    }
+
+    /// The ignore rule deciding `walked` as `(whitelisted, pattern, file)`, in the walk's
+    /// order of precedence: `.ignore` then `.gitignore` from the deepest directory up, then
+    /// `.git/info/exclude`, the global excludes file and `--ignore-file`. A VCS listing has
+    /// applied the git rules already, so only `--ignore-file` is checked then.
+    fn ignore_match(&self, root: &Path, walked: &Path) -> Option<(bool, String, PathBuf)> {
+        let config = self.walker;
+        let path = std::path::absolute(walked).ok()?;
+        let root = std::path::absolute(root).ok()?;
+        let mut sources: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+        if vcs_mode(config, &root).is_none() {
+            let repo = path
+                .ancestors()
+                .skip(1)
+                .find(|dir| walker::is_repo_root(dir));
+            let git = !config.skip_gitignore && repo.is_some();
+            for dir in path.ancestors().skip(1) {
+                sources.push((dir.to_path_buf(), dir.join(".ignore")));
+                if git && repo.is_some_and(|repo| dir.starts_with(repo)) {
+                    sources.push((dir.to_path_buf(), dir.join(".gitignore")));
+                }
+            }
+            if let Some(repo) = repo.filter(|_| git) {
+                if !config.skip_git_exclude {
+                    sources.push((repo.to_path_buf(), repo.join(".git/info/exclude")));
+                }
+                if !config.skip_global_gitignore {
+                    if let Some(global) = gitconfig_excludes_path() {
+                        sources.push((repo.to_path_buf(), global));
+                    }
+                }
+            }
+        }
+        for file in &config.custom_ignore_files {
+            sources.push((root.clone(), file.clone()));
+        }
+
+        sources
+            .into_iter()
+            .filter(|(_, file)| file.is_file())
+            .find_map(|(dir, file)| {
+                let mut builder = GitignoreBuilder::new(&dir);
+                builder.add(&file);
+                let matcher = builder.build().unwrap_or_else(|_| Gitignore::empty());
+                let (whitelisted, glob) = match matcher.matched_path_or_any_parents(&path, false) {
+                    Match::None => return None,
+                    Match::Ignore(glob) => (false, glob),
+                    Match::Whitelist(glob) => (true, glob),
+                };
+                Some((whitelisted, glob.original().to_string(), file))
+            })
+    }
+
+    /// How `language` was chosen for `path`.
+    fn detection(
+        &self,
+        path: &Path,
+        language: &'static Language,
+        detected: Option<(&'static Language, DetectionRule<'_>)>,
+    ) -> String {
+        let name = language.name;
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let Some((detected, rule)) = detected.filter(|(detected, _)| detected.name == name) else {
+            return match detected {
+                Some((detected, _)) => {
+                    format!(
+                        "{}, from a modeline (the name says {})",
+                        name, detected.name
+                    )
+                }
+                None => format!("{}, from a modeline", name),
+            };
+        };
+        match rule {
+            DetectionRule::ForcedGlob(glob) => {
+                format!("{}, forced for {} (--force-lang-def)", name, glob)
+            }
+            DetectionRule::ForcedExtension => {
+                format!("{}, forced for .{} (--force-lang, --map-ext)", name, ext)
+            }
+            DetectionRule::Unmapped => {
+                format!(
+                    "{}, by file name as .{} is unmapped (--unmap-ext)",
+                    name, ext
+                )
+            }
+            DetectionRule::Name if is_custom(self.walker.custom_languages.as_deref(), detected) => {
+                format!("{}, from a custom language definition", name)
+            }
+            DetectionRule::Name => match detect_language_by_filename(path) {
+                Some(by_name) if by_name.name == name => format!(
+                    "{}, by file name {}",
+                    name,
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ),
+                _ => format!("{}, by extension .{}", name, ext),
+            },
+        }
+    }
+
+    /// The counter's decisions: unreadable, duplicate, binary, generated, minified and
+    /// empty files.
+    fn check_counting(
+        &self,
+        path: &Path,
+        language: &'static Language,
+        explanation: &mut Explanation,
+    ) -> Result<(), Step> {
+        let source = walker::read_file(self.walker, path)
+            .map_err(|e| stop("read", Outcome::Skipped, format!("unreadable: {}", e)))?;
+
+        if !self.walker.skip_uniqueness {
+            if let Some(twin) = self.duplicate_of(path, &source) {
+                return Err(stop(
+                    "duplicates",
+                    Outcome::Excluded,
+                    format!(
+                        "same content as {}; only one copy is counted (--skip-uniqueness counts both)",
+                        twin.display()
+                    ),
+                ));
+            }
+            explanation.pass("duplicates", "no other counted file has the same content");
+        }
+
+        let stats = counter::count_bytes(path, &source, language, self.count)
+            .map_err(|e| stop("count", Outcome::Skipped, format!("unreadable: {}", e)))?;
+        if stats.binary {
+            return Err(stop(
+                "count",
+                Outcome::Skipped,
+                format!(
+                    "binary: over {}% NUL bytes in the first {} bytes",
+                    self.count.binary_null_ratio * 100.0,
+                    self.count.binary_sample_size
+                ),
+            ));
+        }
+        if stats.generated && self.exclude_generated {
+            return Err(stop(
+                "count",
+                Outcome::Excluded,
+                "generated code is excluded (--no-generated)",
+            ));
+        }
+        if stats.minified && self.skip_minified {
+            return Err(stop(
+                "count",
+                Outcome::Excluded,
+                "minified code is excluded (--skip-minified)",
+            ));
+        }
+        if stats.is_empty() && !stats.is_ignored() {
+            return Err(stop("count", Outcome::Excluded, "no lines to count"));
+        }
+
+        let mut detail = format!(
+            "{} code, {} comment and {} blank lines",
+            stats.code, stats.comments, stats.blanks
+        );
+        if stats.generated {
+            detail.push_str(", under Generated");
+        } else if stats.minified {
+            detail.push_str(", as minified");
+        }
+        explanation.pass("count", detail);
+        Ok(())
+    }
+
+    /// Another file of the walk with the same content as `path`, if any. Only files of the
+    /// same size are read.
+    fn duplicate_of(&self, path: &Path, content: &[u8]) -> Option<PathBuf> {
+        let hash = counter::hash_bytes(content);
+        let len = content.len() as u64;
+        walker::walk_files(self.walker)
+            .into_iter()
+            .map(|entry| entry.path)
+            .filter(|other| other != path)
+            .filter(|other| {
+                walker::long_path(other)
+                    .metadata()
+                    .is_ok_and(|meta| meta.len() == len)
+            })
+            .find(|other| {
+                counter::read_source(other).is_ok_and(|source| counter::hash_bytes(&source) == hash)
+            })
+    }
+}
+
+/// The root `path` is below, and `path` relative to it (empty if it is the root itself).
+fn locate<'r>(roots: &'r [PathBuf], path: &Path) -> Option<(&'r PathBuf, PathBuf)> {
+    let absolute = std::path::absolute(path).ok()?;
+    roots.iter().find_map(|root| {
+        let relative = absolute
+            .strip_prefix(std::path::absolute(root).ok()?)
+            .ok()?;
+        Some((root, relative.to_path_buf()))
+    })
+}
+
+/// The VCS listing `root`'s files, if the walk uses one.
+fn vcs_mode(config: &WalkerConfig, root: &Path) -> Option<VcsMode> {
+    match config.vcs {
+        _ if config.staged => Some(VcsMode::Git),
+        Some(VcsMode::Auto) => walker::detect_vcs(root),
+        Some(VcsMode::None) | None => None,
+        vcs => vcs,
+    }
+}
+
+fn is_custom(registry: Option<&CustomLanguageRegistry>, language: &'static Language) -> bool {
+    registry
+        .and_then(|registry| registry.get(language.name))
+        .is_some_and(|custom| std::ptr::eq(custom, language))
+        && crate::languages::get_language(language.name)
+            .is_none_or(|builtin| !std::ptr::eq(builtin, language))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn explain(config: &WalkerConfig, path: &Path) -> Explanation {
+        Explainer::new(config, &CountConfig::default()).explain(path)
+    }
+
+    fn last(explanation: &Explanation) -> (&'static str, Outcome) {
+        let step = explanation.steps.last().unwrap();
+        (step.check, step.outcome)
+    }
+
+    #[test]
+    fn test_explain_walk_decisions() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("node_modules/pkg")).unwrap();
+        fs::write(root.join(".gitignore"), "*.gen.rs\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/out.gen.rs"), "fn gen() {}\n").unwrap();
+        fs::write(root.join("node_modules/pkg/index.js"), "let a = 1;\n").unwrap();
+        fs::write(root.join("notes.weird"), "x\n").unwrap();
+        let config = WalkerConfig {
+            paths: vec![root.to_path_buf()],
+            ..Default::default()
+        };
+
+        let counted = explain(&config, &root.join("src/main.rs"));
+        assert!(counted.counted);
+        assert_eq!(counted.language, Some("Rust"));
+        assert!(
+            counted
+                .steps
+                .iter()
+                .all(|step| step.outcome == Outcome::Pass)
+        );
+        assert_eq!(last(&counted), ("count", Outcome::Pass));
+
+        let ignored = explain(&config, &root.join("src/out.gen.rs"));
+        assert!(!ignored.counted);
+        assert_eq!(last(&ignored), ("ignore rules", Outcome::Excluded));
+        assert!(ignored.steps.last().unwrap().detail.contains("*.gen.rs"));
+
+        let vendored = explain(&config, &root.join("node_modules/pkg/index.js"));
+        assert_eq!(last(&vendored), ("directories", Outcome::Excluded));
+
+        let unknown = explain(&config, &root.join("notes.weird"));
+        assert_eq!(last(&unknown), ("language", Outcome::Skipped));
+
+        let outside = explain(&config, Path::new("/definitely/not/below"));
+        assert_eq!(last(&outside), ("root", Outcome::Excluded));
+    }
+
+    #[test]
+    fn test_explain_filters_and_counting() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::write(root.join("a.py"), "x = 1\n").unwrap();
+        fs::write(root.join("b.py"), "x = 1\n").unwrap();
+        fs::write(root.join("c.py"), "").unwrap();
+        fs::write(root.join("d.h"), "int d;\n").unwrap();
+        let config = WalkerConfig {
+            paths: vec![root.to_path_buf()],
+            force_lang_globs: vec![("*.h".to_string(), "C++ Header".to_string())],
+            exclude_langs: vec!["c++ header".to_string()],
+            ..Default::default()
+        };
+
+        let duplicate = explain(&config, &root.join("a.py"));
+        assert_eq!(last(&duplicate), ("duplicates", Outcome::Excluded));
+        assert!(duplicate.steps.last().unwrap().detail.contains("b.py"));
+
+        let empty = explain(&config, &root.join("c.py"));
+        assert_eq!(last(&empty), ("count", Outcome::Excluded));
+
+        let forced = explain(&config, &root.join("d.h"));
+        assert_eq!(forced.language, Some("C++ Header"));
+        assert!(
+            forced
+                .steps
+                .iter()
+                .any(|step| step.detail.contains("forced for *.h"))
+        );
+        assert_eq!(last(&forced), ("language filters", Outcome::Excluded));
+
+        let config = WalkerConfig {
+            skip_uniqueness: true,
+            ..config
+        };
+        assert!(explain(&config, &root.join("a.py")).counted);
+    }
+}
@@ -0,0 +1,171 @@
+//! Git pre-commit hook for `rloc hook`.
+//!
+//! `rloc hook install` writes a pre-commit hook that runs `rloc hook run` with the given
+//! budgets, and `rloc hook run` counts the staged files and checks them. For the pre-commit
+//! framework, [`pre_commit_config`] gives an equivalent local hook entry instead.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Marks a hook written by rloc; only such hooks are replaced without `--force`.
+pub const MARKER: &str = "# Installed by rloc hook install";
+
+/// The pre-commit hook script running `rloc hook run` with `args`.
+pub fn script(args: &[String]) -> String {
+    let args: String = args
+        .iter()
+        .map(|arg| format!(" {}", shell_quote(arg)))
+        .collect();
+    format!("#!/bin/sh\n{}\nexec rloc hook run{}\n", MARKER, args)
+}
+
+/// A `.pre-commit-config.yaml` entry running `rloc hook run` with `args` on every commit.
+pub fn pre_commit_config(args: &[String]) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| serde_json::to_string(arg).unwrap_or_default())
+        .collect();
+    format!(
+        "repos:\n  - repo: local\n    hooks:\n      - id: rloc\n        name: rloc line budgets\n        \
+         entry: rloc hook run\n        args: [{}]\n        language: system\n        \
+         pass_filenames: false\n        always_run: true\n",
+        args.join(", ")
+    )
+}
+
+/// The hooks directory of the repository containing `root`, honouring `core.hooksPath`;
+/// `None` if git is unavailable or `root` is not in a git working copy.
+pub fn hooks_dir(root: &Path) -> Option<PathBuf> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["rev-parse", "--git-path", "hooks"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    let dir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!dir.is_empty()).then(|| root.join(dir))
+}
+
+/// Write `script` as the pre-commit hook of the repository containing `root` and return its
+/// path. An existing hook not written by rloc is only replaced with `force`.
+pub fn install(root: &Path, script: &str, force: bool) -> io::Result<PathBuf> {
+    let dir = hooks_dir(root)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not in a git repository"))?;
+    let path = dir.join("pre-commit");
+    if !force {
+        if let Ok(existing) = fs::read_to_string(&path) {
+            if !existing.contains(MARKER) {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} already exists; use --force to replace it",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+    }
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(path)
+}
+
+/// The content of `path` as of `HEAD`; `None` if it is new, or not in a git working copy.
+pub fn head_content(path: &Path) -> Option<Vec<u8>> {
+    git_show(path, "HEAD:./")
+}
+
+/// The content of `path` as staged in the index; `None` if it is not staged, or not in a
+/// git working copy.
+pub fn staged_content(path: &Path) -> Option<Vec<u8>> {
+    git_show(path, ":./")
+}
+
+/// `git show <rev>./<name>` run from `path`'s directory.
+fn git_show(path: &Path, rev: &str) -> Option<Vec<u8>> {
+    let name = path.file_name()?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut spec = std::ffi::OsString::from(rev);
+    spec.push(name);
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(spec)
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    Some(output.stdout)
+}
+
+/// `arg` quoted for `sh` where needed.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_=.,:/+@%".contains(&b));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn args() -> Vec<String> {
+        ["--max-added", "500", "--max-lang", "C++ Header=0"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    #[test]
+    fn test_script() {
+        assert_eq!(
+            script(&args()),
+            "#!/bin/sh\n# Installed by rloc hook install\n\
+             exec rloc hook run --max-added 500 --max-lang 'C++ Header=0'\n"
+        );
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert!(
+            pre_commit_config(&args())
+                .contains(r#"args: ["--max-added", "500", "--max-lang", "C++ Header=0"]"#)
+        );
+    }
+
+    #[test]
+    fn test_install() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let git_init = Command::new("git").arg("-C").arg(root).arg("init").output();
+        if !git_init.is_ok_and(|out| out.status.success()) {
+            return;
+        }
+        let hook = root.join(".git/hooks/pre-commit");
+        fs::create_dir_all(hook.parent().unwrap()).unwrap();
+        fs::write(&hook, "#!/bin/sh\nmake lint\n").unwrap();
+
+        let error = install(root, &script(&[]), false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&hook).unwrap(), "#!/bin/sh\nmake lint\n");
+
+        install(root, &script(&[]), true).unwrap();
+        // A hook rloc wrote is replaced without --force.
+        let path = install(root, &script(&args()), false).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), script(&args()));
+    }
+}
@@ -1,5 +1,7 @@
 use phf::phf_map;
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::OnceLock;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -42,31 +44,141 @@ pub enum CommentStyle {
     None,
 }
 
+/// Broad grouping of languages, used to separate real code from markup, config and docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Category {
+    Programming,
+    Markup,
+    Data,
+    Documentation,
+    Build,
+    /// Summary buckets that are not languages (`Generated`, `Vendored`).
+    Other,
+}
+
+impl Category {
+    pub const ALL: [Category; 6] = [
+        Category::Programming,
+        Category::Markup,
+        Category::Data,
+        Category::Documentation,
+        Category::Build,
+        Category::Other,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Programming => "Programming",
+            Category::Markup => "Markup",
+            Category::Data => "Data/Config",
+            Category::Documentation => "Documentation",
+            Category::Build => "Build",
+            Category::Other => "Other",
+        }
+    }
+
+    /// Category of a summary row; unknown names (custom languages) count as programming.
+    pub fn of(language: &str) -> Category {
+        if language == crate::stats::GENERATED_LANGUAGE
+            || language == crate::stats::VENDORED_LANGUAGE
+            || language.ends_with(crate::stats::MINIFIED_SUFFIX)
+        {
+            return Category::Other;
+        }
+        LANGUAGES
+            .get(language)
+            .map(|lang| lang.category)
+            .unwrap_or(Category::Programming)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Language {
     pub name: &'static str,
     pub line_comments: &'static [&'static str],
+    /// Line comment markers that only count as a whole word at the start of a command,
+    /// ignoring ASCII case (`REM`).
+    pub word_comments: &'static [&'static str],
+    /// Line comment markers that only count at the start of a line (Batch `::`).
+    pub line_start_comments: &'static [&'static str],
     pub block_comment_start: Option<&'static str>,
     pub block_comment_end: Option<&'static str>,
+    /// A second block comment pair, closed only by its own end marker (Python `'''`).
+    pub alt_block_comment: Option<(&'static str, &'static str)>,
     pub nested_comments: bool,
     pub string_delimiters: &'static [&'static str],
     #[allow(dead_code)]
     pub raw_string_start: Option<&'static str>,
     #[allow(dead_code)]
     pub raw_string_end: Option<&'static str>,
+    /// Heredoc syntax (`<<EOF` ... `EOF`); heredoc bodies are code.
+    pub heredoc: Option<Heredoc>,
+    /// Single-character string delimiters whose strings may span lines (JS template literals).
+    pub multiline_strings: &'static [&'static str],
+    /// Markers that make a line a comment only in the first column (fixed-form Fortran).
+    pub column_comments: &'static [&'static str],
+    pub category: Category,
+}
+
+/// How a language opens heredocs.
+///
+/// A heredoc starts on the line after its opener and runs until a line holding only the
+/// terminator. Unquoted terminators must start with an uppercase letter or `_`, which keeps
+/// shifts and appends (`1 << n`, `list << item`) from being read as heredocs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heredoc {
+    /// Operators that introduce a heredoc, longest first (`<<~`, `<<-`, `<<`).
+    pub openers: &'static [&'static str],
+    /// Whether whitespace may separate the operator from the terminator (`cat << EOF`).
+    pub spaced: bool,
+}
+
+impl Heredoc {
+    const SHELL: Heredoc = Heredoc {
+        openers: &["<<-", "<<"],
+        spaced: true,
+    };
+    const RUBY: Heredoc = Heredoc {
+        openers: &["<<~", "<<-", "<<"],
+        spaced: false,
+    };
+    const PERL: Heredoc = Heredoc {
+        openers: &["<<~", "<<"],
+        spaced: false,
+    };
 }
 
 impl Language {
+    /// Whether the language has any comment syntax.
+    pub fn has_comments(&self) -> bool {
+        !self.line_comments.is_empty()
+            || !self.word_comments.is_empty()
+            || !self.line_start_comments.is_empty()
+            || self.block_comment_start.is_some()
+    }
+
     const fn new(name: &'static str) -> Self {
         Self {
             name,
             line_comments: &[],
+            word_comments: &[],
+            line_start_comments: &[],
             block_comment_start: None,
             block_comment_end: None,
+            alt_block_comment: None,
             nested_comments: false,
             string_delimiters: &["\"", "'"],
             raw_string_start: None,
             raw_string_end: None,
+            heredoc: None,
+            multiline_strings: &[],
+            column_comments: &[],
+            category: Category::Programming,
         }
     }
 
@@ -74,12 +186,19 @@ impl Language {
         Self {
             name,
             line_comments: &["//"],
+            word_comments: &[],
+            line_start_comments: &[],
             block_comment_start: Some("/*"),
             block_comment_end: Some("*/"),
+            alt_block_comment: None,
             nested_comments: false,
             string_delimiters: &["\"", "'"],
             raw_string_start: None,
             raw_string_end: None,
+            heredoc: None,
+            multiline_strings: &[],
+            column_comments: &[],
+            category: Category::Programming,
         }
     }
 
@@ -87,12 +206,19 @@ impl Language {
         Self {
             name,
             line_comments: &["#"],
+            word_comments: &[],
+            line_start_comments: &[],
             block_comment_start: None,
             block_comment_end: None,
+            alt_block_comment: None,
             nested_comments: false,
             string_delimiters: &["\"", "'"],
             raw_string_start: None,
             raw_string_end: None,
+            heredoc: None,
+            multiline_strings: &[],
+            column_comments: &[],
+            category: Category::Programming,
         }
     }
 
@@ -100,12 +226,19 @@ impl Language {
         Self {
             name,
             line_comments: &[],
+            word_comments: &[],
+            line_start_comments: &[],
             block_comment_start: Some("<!--"),
             block_comment_end: Some("-->"),
+            alt_block_comment: None,
             nested_comments: false,
             string_delimiters: &["\"", "'"],
             raw_string_start: None,
             raw_string_end: None,
+            heredoc: None,
+            multiline_strings: &[],
+            column_comments: &[],
+            category: Category::Programming,
         }
     }
 
@@ -125,6 +258,21 @@ impl Language {
         self
     }
 
+    const fn with_category(mut self, category: Category) -> Self {
+        self.category = category;
+        self
+    }
+
+    const fn with_heredocs(mut self, heredoc: Heredoc) -> Self {
+        self.heredoc = Some(heredoc);
+        self
+    }
+
+    const fn with_multiline_strings(mut self, delims: &'static [&'static str]) -> Self {
+        self.multiline_strings = delims;
+        self
+    }
+
     #[allow(dead_code)]
     const fn with_string_delimiters(mut self, delims: &'static [&'static str]) -> Self {
         self.string_delimiters = delims;
@@ -141,38 +289,61 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "C++ Header" => Language::c_style("C++ Header"),
     "Objective-C" => Language::c_style("Objective-C"),
     "Objective-C++" => Language::c_style("Objective-C++"),
+    "Yacc" => Language::c_style("Yacc"),
+    "Lex" => Language::c_style("Lex"),
     "D" => Language::c_style("D").with_nested_comments(),
     "Zig" => Language {
         name: "Zig",
         line_comments: &["//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Odin" => Language::c_style("Odin").with_nested_comments(),
     "V" => Language::c_style("V"),
     "Nim" => Language {
         name: "Nim",
         line_comments: &["#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("#["),
         block_comment_end: Some("]#"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Crystal" => Language {
         name: "Crystal",
         line_comments: &["#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // JVM Languages
@@ -183,12 +354,19 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "Clojure" => Language {
         name: "Clojure",
         line_comments: &[";"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // .NET Languages
@@ -196,44 +374,65 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "F#" => Language {
         name: "F#",
         line_comments: &["//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("(*"),
         block_comment_end: Some("*)"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Visual Basic" => Language {
         name: "Visual Basic",
         line_comments: &["'"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Web Languages
-    "JavaScript" => Language::c_style("JavaScript"),
-    "TypeScript" => Language::c_style("TypeScript"),
-    "JSX" => Language::c_style("JSX"),
-    "TSX" => Language::c_style("TSX"),
+    "JavaScript" => Language::c_style("JavaScript").with_multiline_strings(&["`"]),
+    "TypeScript" => Language::c_style("TypeScript").with_multiline_strings(&["`"]),
+    "JSX" => Language::c_style("JSX").with_multiline_strings(&["`"]),
+    "TSX" => Language::c_style("TSX").with_multiline_strings(&["`"]),
     "CoffeeScript" => Language::shell_style("CoffeeScript").with_block_comments("###", "###"),
-    "HTML" => Language::html_style("HTML"),
+    "HTML" => Language::html_style("HTML").with_category(Category::Markup),
     "CSS" => Language {
         name: "CSS",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("/*"),
         block_comment_end: Some("*/"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Markup,
     },
-    "SCSS" => Language::c_style("SCSS"),
-    "Sass" => Language::c_style("Sass"),
-    "Less" => Language::c_style("Less"),
+    "SCSS" => Language::c_style("SCSS").with_category(Category::Markup),
+    "Sass" => Language::c_style("Sass").with_category(Category::Markup),
+    "Less" => Language::c_style("Less").with_category(Category::Markup),
     "Vue" => Language::html_style("Vue"),
     "Svelte" => Language::html_style("Svelte"),
 
@@ -241,145 +440,255 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "Python" => Language {
         name: "Python",
         line_comments: &["#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("\"\"\""),
         block_comment_end: Some("\"\"\""),
+        alt_block_comment: Some(("'''", "'''")),
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Ruby" => Language {
         name: "Ruby",
         line_comments: &["#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("=begin"),
         block_comment_end: Some("=end"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: Some(Heredoc::RUBY),
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
-    "Perl" => Language::shell_style("Perl").with_block_comments("=pod", "=cut"),
+    "Perl" => Language::shell_style("Perl")
+        .with_block_comments("=pod", "=cut")
+        .with_heredocs(Heredoc::PERL),
     "PHP" => Language::c_style("PHP").with_line_comments(&["//", "#"]),
     "Lua" => Language {
         name: "Lua",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("--[["),
         block_comment_end: Some("]]"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Tcl" => Language::shell_style("Tcl"),
     "Awk" => Language::shell_style("Awk"),
 
     // Shell Languages
-    "Shell" => Language::shell_style("Shell"),
-    "Bash" => Language::shell_style("Bash"),
-    "Zsh" => Language::shell_style("Zsh"),
+    "Shell" => Language::shell_style("Shell").with_heredocs(Heredoc::SHELL),
+    "Bash" => Language::shell_style("Bash").with_heredocs(Heredoc::SHELL),
+    "Zsh" => Language::shell_style("Zsh").with_heredocs(Heredoc::SHELL),
     "Fish" => Language::shell_style("Fish"),
     "PowerShell" => Language {
         name: "PowerShell",
         line_comments: &["#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("<#"),
         block_comment_end: Some("#>"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Batch" => Language {
         name: "Batch",
-        line_comments: &["REM", "rem", "::"],
+        line_comments: &[],
+        word_comments: &["REM", "@REM"],
+        line_start_comments: &["::"],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Functional Languages
+    "Literate Haskell" => Language {
+        name: "Literate Haskell",
+        line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
+        block_comment_start: Some("{-"),
+        block_comment_end: Some("-}"),
+        alt_block_comment: None,
+        nested_comments: true,
+        string_delimiters: &["\""],
+        raw_string_start: None,
+        raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
+    },
     "Haskell" => Language {
         name: "Haskell",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{-"),
         block_comment_end: Some("-}"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "OCaml" => Language {
         name: "OCaml",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("(*"),
         block_comment_end: Some("*)"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Standard ML" => Language {
         name: "Standard ML",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("(*"),
         block_comment_end: Some("*)"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Elm" => Language {
         name: "Elm",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{-"),
         block_comment_end: Some("-}"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Erlang" => Language {
         name: "Erlang",
         line_comments: &["%"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Elixir" => Language::shell_style("Elixir").with_block_comments("@doc \"\"\"", "\"\"\""),
     "Lisp" => Language {
         name: "Lisp",
         line_comments: &[";"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("#|"),
         block_comment_end: Some("|#"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Scheme" => Language {
         name: "Scheme",
         line_comments: &[";"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("#|"),
         block_comment_end: Some("|#"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Racket" => Language {
         name: "Racket",
         line_comments: &[";"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("#|"),
         block_comment_end: Some("|#"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Go and friends
@@ -389,120 +698,186 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "Swift" => Language::c_style("Swift").with_nested_comments(),
 
     // Data/Config Languages
-    "JSON" => Language::new("JSON"),
-    "JSON5" => Language::c_style("JSON5"),
-    "YAML" => Language::shell_style("YAML"),
-    "TOML" => Language::shell_style("TOML"),
-    "XML" => Language::html_style("XML"),
+    "JSON" => Language::new("JSON").with_category(Category::Data),
+    "JSON5" => Language::c_style("JSON5").with_category(Category::Data),
+    "YAML" => Language::shell_style("YAML").with_category(Category::Data),
+    "TOML" => Language::shell_style("TOML").with_category(Category::Data),
+    "XML" => Language::html_style("XML").with_category(Category::Markup),
     "INI" => Language {
         name: "INI",
         line_comments: &[";", "#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Data,
     },
     "Properties" => Language {
         name: "Properties",
         line_comments: &["#", "!"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &[],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Data,
     },
 
     // Query Languages
     "SQL" => Language {
         name: "SQL",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("/*"),
         block_comment_end: Some("*/"),
+        alt_block_comment: None,
         nested_comments: false,
-        string_delimiters: &["'"],
+        // Quoted identifiers ("a--b") are strings too; literals may span lines.
+        string_delimiters: &["'", "\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &["'"],
+        column_comments: &[],
+        category: Category::Programming,
     },
-    "GraphQL" => Language::shell_style("GraphQL"),
+    "GraphQL" => Language::shell_style("GraphQL").with_category(Category::Data),
 
     // Build/Config
-    "Makefile" => Language::shell_style("Makefile"),
-    "CMake" => Language::shell_style("CMake"),
-    "Meson" => Language::shell_style("Meson"),
-    "Dockerfile" => Language::shell_style("Dockerfile"),
-    "Docker Compose" => Language::shell_style("Docker Compose"),
-    "Terraform" => Language::c_style("Terraform").with_line_comments(&["//", "#"]),
-    "HCL" => Language::c_style("HCL").with_line_comments(&["//", "#"]),
-    "Nix" => Language::shell_style("Nix").with_block_comments("/*", "*/"),
-    "Bazel" => Language::shell_style("Bazel"),
-    "Just" => Language::shell_style("Just"),
+    "Makefile" => Language::shell_style("Makefile").with_category(Category::Build),
+    "CMake" => Language::shell_style("CMake").with_category(Category::Build),
+    "Meson" => Language::shell_style("Meson").with_category(Category::Build),
+    "Dockerfile" => Language::shell_style("Dockerfile").with_category(Category::Build),
+    "Docker Compose" => Language::shell_style("Docker Compose").with_category(Category::Build),
+    "Terraform" => Language::c_style("Terraform").with_line_comments(&["//", "#"]).with_category(Category::Data),
+    "HCL" => Language::c_style("HCL").with_line_comments(&["//", "#"]).with_category(Category::Data),
+    "Nix" => Language::shell_style("Nix").with_block_comments("/*", "*/").with_category(Category::Build),
+    "Bazel" => Language::shell_style("Bazel").with_category(Category::Build),
+    "Just" => Language::shell_style("Just").with_category(Category::Build),
 
     // Documentation
-    "Markdown" => Language::html_style("Markdown"),
+    "Markdown" => Language::html_style("Markdown").with_category(Category::Documentation),
+    "R Markdown" => Language::html_style("R Markdown").with_category(Category::Documentation),
+    "Jupyter Notebook" => Language::new("Jupyter Notebook"),
     "reStructuredText" => Language {
         name: "reStructuredText",
         line_comments: &[".."],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &[],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Documentation,
     },
     "AsciiDoc" => Language {
         name: "AsciiDoc",
         line_comments: &["//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("////"),
         block_comment_end: Some("////"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &[],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Documentation,
     },
     "LaTeX" => Language {
         name: "LaTeX",
         line_comments: &["%"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &[],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Documentation,
     },
     "TeX" => Language {
         name: "TeX",
         line_comments: &["%"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &[],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Documentation,
     },
 
     // Assembly
     "Assembly" => Language {
         name: "Assembly",
         line_comments: &[";", "#", "//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("/*"),
         block_comment_end: Some("*/"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "ARM Assembly" => Language {
         name: "ARM Assembly",
         line_comments: &[";", "@", "//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("/*"),
         block_comment_end: Some("*/"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Hardware Description
@@ -511,12 +886,19 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "VHDL" => Language {
         name: "VHDL",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Scientific/Math
@@ -525,74 +907,142 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "MATLAB" => Language {
         name: "MATLAB",
         line_comments: &["%"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("%{"),
         block_comment_end: Some("%}"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["'", "\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Octave" => Language {
         name: "Octave",
         line_comments: &["%", "#"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("%{"),
         block_comment_end: Some("%}"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["'", "\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
+    // Free form (.f90 and later): `!` comments anywhere.
     "Fortran" => Language {
         name: "Fortran",
-        line_comments: &["!", "C", "c", "*"],
+        line_comments: &["!"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["'", "\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
+    },
+    // Fixed form (.f, .f77): `C`, `c` or `*` in column 1 starts a comment.
+    "Fortran 77" => Language {
+        name: "Fortran 77",
+        line_comments: &["!"],
+        word_comments: &[],
+        line_start_comments: &[],
+        block_comment_start: None,
+        block_comment_end: None,
+        alt_block_comment: None,
+        nested_comments: false,
+        string_delimiters: &["'", "\""],
+        raw_string_start: None,
+        raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &["C", "c", "*"],
+        category: Category::Programming,
     },
 
     // Legacy
     "COBOL" => Language {
         name: "COBOL",
         line_comments: &["*"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Pascal" => Language {
         name: "Pascal",
         line_comments: &["//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{"),
         block_comment_end: Some("}"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Delphi" => Language {
         name: "Delphi",
         line_comments: &["//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{"),
         block_comment_end: Some("}"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Ada" => Language {
         name: "Ada",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Mobile
@@ -602,103 +1052,166 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "Prolog" => Language {
         name: "Prolog",
         line_comments: &["%"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("/*"),
         block_comment_end: Some("*/"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Forth" => Language {
         name: "Forth",
         line_comments: &["\\"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("("),
         block_comment_end: Some(")"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "ActionScript" => Language::c_style("ActionScript"),
     "Vim Script" => Language {
         name: "Vim Script",
         line_comments: &["\""],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Emacs Lisp" => Language {
         name: "Emacs Lisp",
         line_comments: &[";"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Wasm
     "WebAssembly" => Language {
         name: "WebAssembly",
         line_comments: &[";;"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("(;"),
         block_comment_end: Some(";)"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
 
     // Protocol/Schema
-    "Protocol Buffers" => Language::c_style("Protocol Buffers"),
-    "Thrift" => Language::c_style("Thrift"),
-    "Cap'n Proto" => Language::shell_style("Cap'n Proto"),
-    "FlatBuffers" => Language::c_style("FlatBuffers"),
+    "Protocol Buffers" => Language::c_style("Protocol Buffers").with_category(Category::Data),
+    "Thrift" => Language::c_style("Thrift").with_category(Category::Data),
+    "Cap'n Proto" => Language::shell_style("Cap'n Proto").with_category(Category::Data),
+    "FlatBuffers" => Language::c_style("FlatBuffers").with_category(Category::Data),
 
     // Templating
     "Jinja2" => Language {
         name: "Jinja2",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{#"),
         block_comment_end: Some("#}"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Markup,
     },
     "Handlebars" => Language {
         name: "Handlebars",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{{!--"),
         block_comment_end: Some("--}}"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Markup,
     },
     "EJS" => Language {
         name: "EJS",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("<%#"),
         block_comment_end: Some("%>"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Markup,
     },
     "ERB" => Language {
         name: "ERB",
         line_comments: &[],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("<%#"),
         block_comment_end: Some("%>"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\"", "'"],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Markup,
     },
 
     // Solidity / Smart Contracts
@@ -706,19 +1219,26 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "Vyper" => Language::shell_style("Vyper").with_block_comments("\"\"\"", "\"\"\""),
 
     // Modern config
-    "Jsonnet" => Language::c_style("Jsonnet"),
+    "Jsonnet" => Language::c_style("Jsonnet").with_category(Category::Data),
     "Dhall" => Language {
         name: "Dhall",
         line_comments: &["--"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("{-"),
         block_comment_end: Some("-}"),
+        alt_block_comment: None,
         nested_comments: true,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Data,
     },
-    "CUE" => Language::c_style("CUE"),
-    "KDL" => Language::c_style("KDL"),
+    "CUE" => Language::c_style("CUE").with_category(Category::Data),
+    "KDL" => Language::c_style("KDL").with_category(Category::Data),
 
     // Gleam
     "Gleam" => Language::c_style("Gleam"),
@@ -733,63 +1253,84 @@ pub static LANGUAGES: phf::Map<&'static str, Language> = phf_map! {
     "Move" => Language::c_style("Move"),
 
     // Windows/Visual Studio
-    "Windows Resource" => Language::c_style("Windows Resource"),
-    "MSBuild" => Language::html_style("MSBuild"),
-    "Visual Studio Solution" => Language::shell_style("Visual Studio Solution"),
-    "XSD" => Language::html_style("XSD"),
+    "Windows Resource" => Language::c_style("Windows Resource").with_category(Category::Data),
+    "MSBuild" => Language::html_style("MSBuild").with_category(Category::Build),
+    "Visual Studio Solution" => Language::shell_style("Visual Studio Solution").with_category(Category::Build),
+    "XSD" => Language::html_style("XSD").with_category(Category::Markup),
     "Windows Module Definition" => Language {
         name: "Windows Module Definition",
         line_comments: &[";"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Data,
     },
     "C# Generated" => Language::c_style("C# Generated"),
     "InstallShield" => Language {
         name: "InstallShield",
         line_comments: &["//"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: Some("/*"),
         block_comment_end: Some("*/"),
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Build,
     },
     "Civet" => Language::c_style("Civet"),
 
     // Org Mode
-    "Org" => Language::shell_style("Org"),
+    "Org" => Language::shell_style("Org").with_category(Category::Documentation),
 
     // Infrastructure & DevOps
     "Aria" => Language::shell_style("Aria"),
-    "AXAML" => Language::html_style("AXAML"),
-    "Bicep" => Language::c_style("Bicep"),
-    "BitBake" => Language::shell_style("BitBake"),
+    "AXAML" => Language::html_style("AXAML").with_category(Category::Markup),
+    "Bicep" => Language::c_style("Bicep").with_category(Category::Data),
+    "BitBake" => Language::shell_style("BitBake").with_category(Category::Build),
     "Clarity" => Language {
         name: "Clarity",
         line_comments: &[";;"],
+        word_comments: &[],
+        line_start_comments: &[],
         block_comment_start: None,
         block_comment_end: None,
+        alt_block_comment: None,
         nested_comments: false,
         string_delimiters: &["\""],
         raw_string_start: None,
         raw_string_end: None,
+        heredoc: None,
+        multiline_strings: &[],
+        column_comments: &[],
+        category: Category::Programming,
     },
     "Magik" => Language::shell_style("Magik"),
     "Rego" => Language::shell_style("Rego"),
-    "USS" => Language::c_style("USS"),
-    "UXML" => Language::html_style("UXML"),
-    "VSCode Workspace" => Language::new("VSCode Workspace"),
-    "Yarn" => Language::shell_style("Yarn"),
+    "USS" => Language::c_style("USS").with_category(Category::Markup),
+    "UXML" => Language::html_style("UXML").with_category(Category::Markup),
+    "VSCode Workspace" => Language::new("VSCode Workspace").with_category(Category::Data),
+    "Yarn" => Language::shell_style("Yarn").with_category(Category::Data),
 
     // Plain Text
-    "Text" => Language::new("Text"),
+    "Text" => Language::new("Text").with_category(Category::Documentation),
 
     // SVG
-    "SVG" => Language::html_style("SVG"),
+    "SVG" => Language::html_style("SVG").with_category(Category::Markup),
 };
 
 pub static EXTENSION_MAP: phf::Map<&'static str, &'static str> = phf_map! {
@@ -814,6 +1355,14 @@ pub static EXTENSION_MAP: phf::Map<&'static str, &'static str> = phf_map! {
     "m" => "Objective-C",
     "mm" => "Objective-C++",
 
+    // Parser and scanner generators
+    "y" => "Yacc",
+    "yy" => "Yacc",
+    "ypp" => "Yacc",
+    "l" => "Lex",
+    "ll" => "Lex",
+    "lex" => "Lex",
+
     // D
     "d" => "D",
     "di" => "D",
@@ -945,7 +1494,7 @@ pub static EXTENSION_MAP: phf::Map<&'static str, &'static str> = phf_map! {
 
     // Haskell
     "hs" => "Haskell",
-    "lhs" => "Haskell",
+    "lhs" => "Literate Haskell",
 
     // OCaml
     "ml" => "OCaml",
@@ -1046,13 +1595,20 @@ pub static EXTENSION_MAP: phf::Map<&'static str, &'static str> = phf_map! {
     // Scientific
     "r" => "R",
     "R" => "R",
+    "rmd" => "R Markdown",
+    "Rmd" => "R Markdown",
+    "ipynb" => "Jupyter Notebook",
     "jl" => "Julia",
     // Note: .m conflicts with Objective-C, MATLAB files typically use .mat or are detected by content
     "mat" => "MATLAB",
-    "f" => "Fortran",
-    "for" => "Fortran",
-    "f77" => "Fortran",
+    "f" => "Fortran 77",
+    "for" => "Fortran 77",
+    "ftn" => "Fortran 77",
+    "f77" => "Fortran 77",
+    "F" => "Fortran 77",
+    "FOR" => "Fortran 77",
     "f90" => "Fortran",
+    "F90" => "Fortran",
     "f95" => "Fortran",
     "f03" => "Fortran",
     "f08" => "Fortran",
@@ -1230,16 +1786,8 @@ pub fn detect_language(path: &Path) -> Option<&'static Language> {
         }
     }
 
-    if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-        if let Some(&lang_name) = FILENAME_MAP.get(filename) {
-            return LANGUAGES.get(lang_name);
-        }
-
-        // C# Generated files (.g.cs, .designer.cs)
-        let lower = filename.to_lowercase();
-        if lower.ends_with(".g.cs") || lower.ends_with(".designer.cs") {
-            return LANGUAGES.get("C# Generated");
-        }
+    if let Some(lang) = detect_language_by_filename(path) {
+        return Some(lang);
     }
 
     if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
@@ -1251,18 +1799,49 @@ pub fn detect_language(path: &Path) -> Option<&'static Language> {
     None
 }
 
-#[allow(dead_code)]
+/// Detect a language from a file name such as `main.rs` or `Makefile`, for callers
+/// without a file system path.
+pub fn detect_language_from_name(name: &str) -> Option<&'static Language> {
+    detect_language(Path::new(name))
+}
+
+/// Detect a language from the file name alone (`Makefile`, `*.designer.cs`, ...),
+/// ignoring the extension map.
+pub fn detect_language_by_filename(path: &Path) -> Option<&'static Language> {
+    let filename = path.file_name().and_then(|n| n.to_str())?;
+
+    if let Some(&lang_name) = FILENAME_MAP.get(filename) {
+        return LANGUAGES.get(lang_name);
+    }
+
+    // C# Generated files (.g.cs, .designer.cs)
+    let lower = filename.to_lowercase();
+    if lower.ends_with(".g.cs") || lower.ends_with(".designer.cs") {
+        return LANGUAGES.get("C# Generated");
+    }
+
+    None
+}
+
 pub fn get_language(name: &str) -> Option<&'static Language> {
     LANGUAGES.get(name)
 }
 
+/// Look up a language by name or alias (`cpp`, `golang`, `js`), ignoring case.
 pub fn get_language_ignore_case(name: &str) -> Option<&'static Language> {
-    LANGUAGES.get(name).or_else(|| {
-        LANGUAGES
-            .entries()
-            .find(|(k, _)| k.eq_ignore_ascii_case(name))
-            .map(|(_, v)| v)
-    })
+    LANGUAGES
+        .get(name)
+        .or_else(|| {
+            LANGUAGES
+                .entries()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v)
+        })
+        .or_else(|| {
+            ALIASES
+                .get(name.to_lowercase().as_str())
+                .and_then(|name| LANGUAGES.get(name))
+        })
 }
 
 pub fn list_languages() -> impl Iterator<Item = (&'static str, &'static Language)> {
@@ -1273,11 +1852,241 @@ pub fn list_extensions() -> impl Iterator<Item = (&'static str, &'static str)> {
     EXTENSION_MAP.entries().map(|(k, v)| (*k, *v))
 }
 
+/// Alternative names accepted by [`find_language_by_alias`], keyed by lowercase alias.
+pub static ALIASES: phf::Map<&'static str, &'static str> = phf_map! {
+    "rs" => "Rust",
+    "cpp" => "C++",
+    "cxx" => "C++",
+    "cplusplus" => "C++",
+    "objc" => "Objective-C",
+    "objective c" => "Objective-C",
+    "objcpp" => "Objective-C++",
+    "golang" => "Go",
+    "js" => "JavaScript",
+    "node" => "JavaScript",
+    "ts" => "TypeScript",
+    "py" => "Python",
+    "python3" => "Python",
+    "rb" => "Ruby",
+    "sh" => "Shell",
+    "shell script" => "Shell",
+    "pwsh" => "PowerShell",
+    "ps1" => "PowerShell",
+    "bat" => "Batch",
+    "cmd" => "Batch",
+    "csharp" => "C#",
+    "cs" => "C#",
+    "fsharp" => "F#",
+    "vb" => "Visual Basic",
+    "vbnet" => "Visual Basic",
+    "kt" => "Kotlin",
+    "hs" => "Haskell",
+    "ml" => "OCaml",
+    "ex" => "Elixir",
+    "erl" => "Erlang",
+    "elisp" => "Emacs Lisp",
+    "emacs-lisp" => "Emacs Lisp",
+    "vim" => "Vim Script",
+    "viml" => "Vim Script",
+    "asm" => "Assembly",
+    "make" => "Makefile",
+    "docker" => "Dockerfile",
+    "tf" => "Terraform",
+    "proto" => "Protocol Buffers",
+    "protobuf" => "Protocol Buffers",
+    "md" => "Markdown",
+    "rst" => "reStructuredText",
+    "adoc" => "AsciiDoc",
+    "tex" => "TeX",
+    "yml" => "YAML",
+    "sol" => "Solidity",
+    "wasm" => "WebAssembly",
+    "wat" => "WebAssembly",
+};
+
+impl Language {
+    /// Extensions mapped to this built-in language, sorted.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        static INDEX: OnceLock<HashMap<&'static str, Vec<&'static str>>> = OnceLock::new();
+        let index = INDEX.get_or_init(|| invert(&EXTENSION_MAP));
+        index.get(self.name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Exact file names (e.g. `Makefile`) detected as this built-in language, sorted.
+    pub fn filenames(&self) -> &'static [&'static str] {
+        static INDEX: OnceLock<HashMap<&'static str, Vec<&'static str>>> = OnceLock::new();
+        let index = INDEX.get_or_init(|| invert(&FILENAME_MAP));
+        index.get(self.name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Aliases accepted by [`find_language_by_alias`], sorted.
+    pub fn aliases(&self) -> &'static [&'static str] {
+        static INDEX: OnceLock<HashMap<&'static str, Vec<&'static str>>> = OnceLock::new();
+        let index = INDEX.get_or_init(|| invert(&ALIASES));
+        index.get(self.name).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// Group the keys of a `key => language name` map by language name.
+fn invert(
+    map: &'static phf::Map<&'static str, &'static str>,
+) -> HashMap<&'static str, Vec<&'static str>> {
+    let mut index: HashMap<&'static str, Vec<&'static str>> = HashMap::new();
+    for (key, name) in map.entries() {
+        index.entry(*name).or_default().push(*key);
+    }
+    for keys in index.values_mut() {
+        keys.sort_unstable();
+    }
+    index
+}
+
+/// Built-in languages claiming the extension `ext` (case-insensitive, leading dot optional).
+pub fn languages_for_extension(ext: &str) -> &'static [&'static Language] {
+    static INDEX: OnceLock<HashMap<&'static str, Vec<&'static Language>>> = OnceLock::new();
+    let index = INDEX.get_or_init(|| {
+        let mut index: HashMap<&'static str, Vec<&'static Language>> = HashMap::new();
+        for (ext, name) in EXTENSION_MAP.entries() {
+            if let Some(lang) = LANGUAGES.get(name) {
+                index.entry(*ext).or_default().push(lang);
+            }
+        }
+        index
+    });
+
+    let ext = ext.trim_start_matches('.').to_lowercase();
+    index.get(ext.as_str()).map(Vec::as_slice).unwrap_or(&[])
+}
+
+/// Look up a language by name, alias (`c++`, `golang`, `py`) or extension, ignoring case.
+pub fn find_language_by_alias(alias: &str) -> Option<&'static Language> {
+    let alias = alias.trim();
+    get_language_ignore_case(alias).or_else(|| languages_for_extension(alias).first().copied())
+}
+
+/// Lines at the start and end of a file searched for a Vim modeline (Vim's `modelines` default).
+pub const MODELINE_LINES: usize = 5;
+
+/// Emacs and Vim mode names that are not also language names, aliases or extensions.
+static MODELINE_NAMES: phf::Map<&'static str, &'static str> = phf_map! {
+    "c++" => "C++",
+    "cperl" => "Perl",
+    "js2" => "JavaScript",
+    "rjsx" => "JSX",
+    "coffee" => "CoffeeScript",
+    "shell-script" => "Shell",
+    "makefile-gmake" => "Makefile",
+    "makefile-bsdmake" => "Makefile",
+    "dosbatch" => "Batch",
+    "dosini" => "INI",
+    "conf" => "INI",
+    "plaintex" => "TeX",
+    "tuareg" => "OCaml",
+    "caml" => "OCaml",
+    "sml" => "Standard ML",
+    "lisp-interaction" => "Emacs Lisp",
+    "nxml" => "XML",
+};
+
+/// Detect a language from an Emacs (`-*- mode: python -*-`, `-*- C++ -*-`) or Vim
+/// (`vim: set ft=sh:`) modeline. The Emacs form is looked for on the first line, or the
+/// second after a `#!` line; the Vim form in the first and last [`MODELINE_LINES`] lines.
+pub fn detect_language_by_modeline(content: &[u8]) -> Option<&'static Language> {
+    let lines: Vec<&[u8]> = content.split(|&b| b == b'\n').collect();
+    let emacs = lines
+        .iter()
+        .take(if lines[0].starts_with(b"#!") { 2 } else { 1 })
+        .find_map(|line| emacs_mode(&String::from_utf8_lossy(line)));
+    let vim = || {
+        let tail = lines
+            .len()
+            .saturating_sub(MODELINE_LINES)
+            .max(MODELINE_LINES);
+        lines
+            .iter()
+            .take(MODELINE_LINES)
+            .chain(lines.iter().skip(tail))
+            .find_map(|line| vim_filetype(&String::from_utf8_lossy(line)))
+    };
+    let mode = emacs.or_else(vim)?.to_lowercase();
+    MODELINE_NAMES
+        .get(mode.as_str())
+        .and_then(|name| LANGUAGES.get(name))
+        .or_else(|| find_language_by_alias(&mode))
+}
+
+/// The mode of an Emacs `-*- ... -*-` line: the `mode:` variable, or the whole text when it
+/// sets no variables.
+fn emacs_mode(line: &str) -> Option<String> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (vars, _) = rest.split_once("-*-")?;
+    if !vars.contains(':') {
+        let mode = vars.trim();
+        return (!mode.is_empty()).then(|| mode.to_string());
+    }
+    vars.split(';').find_map(|var| {
+        let (key, value) = var.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("mode")
+            .then(|| value.trim().to_string())
+    })
+}
+
+/// The `ft=`, `filetype=`, `syn=` or `syntax=` option of a Vim modeline (`vim: ft=sh`,
+/// `vi: set filetype=python :`).
+fn vim_filetype(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "Vim:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(i, _)| *i == 0 || line[..*i].ends_with(char::is_whitespace))
+            .map(|(i, _)| i + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key, "ft" | "filetype" | "syn" | "syntax")
+                .then(|| value.to_string())
+                .filter(|value| !value.is_empty())
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::Path;
 
+    #[test]
+    fn test_detect_language_by_modeline() {
+        let cases = [
+            (
+                "#!/bin/sh\n# -*- mode: python; coding: utf-8 -*-\nx = 1\n",
+                Some("Python"),
+            ),
+            ("// -*- C++ -*-\n#include <vector>\n", Some("C++")),
+            (";; -*- Mode: Emacs-Lisp -*-\n", Some("Emacs Lisp")),
+            ("x\n\n\n\n\n\n\n# vim: set ft=sh ts=4:\n", Some("Shell")),
+            ("# vi: filetype=ruby\nputs 1\n", Some("Ruby")),
+            ("/* vim:syntax=c */\nint x;\n", Some("C")),
+            // Not on the first line, or not a known mode.
+            ("x = 1\n# -*- python -*-\n", None),
+            ("-*- mode: no-such-mode -*-\n", None),
+            ("see envim: ft=sh\n", None),
+            ("", None),
+        ];
+        for (content, expected) in cases {
+            assert_eq!(
+                detect_language_by_modeline(content.as_bytes()).map(|l| l.name),
+                expected,
+                "{:?}",
+                content
+            );
+        }
+
+        // Only the first and last lines are searched for Vim modelines.
+        let middle = format!("{}# vim: ft=ruby\n{}", "x\n".repeat(6), "x\n".repeat(6));
+        assert!(detect_language_by_modeline(middle.as_bytes()).is_none());
+    }
+
     #[test]
     fn test_detect_language_by_extension() {
         let cases = [
@@ -1298,6 +2107,8 @@ mod tests {
             ("test.css", "CSS"),
             ("test.html", "HTML"),
             ("test.json", "JSON"),
+            ("test.f", "Fortran 77"),
+            ("test.f90", "Fortran"),
             ("test.yaml", "YAML"),
             ("test.yml", "YAML"),
             ("test.toml", "TOML"),
@@ -1306,6 +2117,8 @@ mod tests {
             ("test.swift", "Swift"),
             ("test.kt", "Kotlin"),
             ("test.cs", "C#"),
+            ("parse.y", "Yacc"),
+            ("scan.l", "Lex"),
         ];
 
         for (filename, expected_lang) in cases {
@@ -1439,4 +2252,40 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_language_metadata() {
+        let rust = get_language("Rust").unwrap();
+        assert_eq!(rust.extensions(), &["rs"]);
+        assert!(rust.aliases().contains(&"rs"));
+        assert_eq!(rust.category, Category::Programming);
+
+        let make = get_language("Makefile").unwrap();
+        assert!(make.filenames().contains(&"GNUmakefile"));
+        assert_eq!(make.category, Category::Build);
+
+        assert_eq!(languages_for_extension(".HPP")[0].name, "C++ Header");
+        assert!(languages_for_extension("nope").is_empty());
+    }
+
+    #[test]
+    fn test_find_language_by_alias() {
+        let cases = [
+            ("c++", "C++"),
+            ("cpp", "C++"),
+            ("golang", "Go"),
+            ("Py", "Python"),
+            ("javascript", "JavaScript"),
+            ("tsx", "TSX"),
+            ("yml", "YAML"),
+        ];
+        for (alias, expected) in cases {
+            assert_eq!(find_language_by_alias(alias).unwrap().name, expected);
+        }
+        assert!(find_language_by_alias("not-a-language").is_none());
+
+        for (alias, name) in ALIASES.entries() {
+            assert!(LANGUAGES.contains_key(name), "Alias {} -> {}", alias, name);
+        }
+    }
 }
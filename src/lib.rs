@@ -33,55 +33,158 @@
 //! ```
 
 // Internal modules - exposed publicly for CLI binary
+#[cfg(feature = "fs")]
 pub mod archive;
+pub mod badge;
+#[cfg(feature = "fs")]
+pub mod bench;
+pub mod budget;
+#[cfg(feature = "fs")]
+pub mod cache;
+pub mod cancel;
+pub mod churn;
+#[cfg(feature = "fs")]
+pub mod config;
 pub mod counter;
 pub mod custom_langs;
+#[cfg(feature = "fs")]
+pub mod diff;
+#[cfg(feature = "fs")]
+pub mod explain;
+pub mod hook;
 mod languages;
+pub mod literate;
+pub mod origins;
+pub mod policy;
+pub mod project;
+pub mod report;
+pub mod snapshot;
 pub mod stats;
+pub mod strip;
+pub mod trend;
+#[cfg(feature = "fs")]
 pub mod walker;
 
 #[cfg(feature = "cli")]
 pub mod cli;
 #[cfg(feature = "cli")]
-pub mod diff;
-#[cfg(feature = "cli")]
 pub mod output;
 #[cfg(feature = "cli")]
-pub mod strip;
+pub mod rpc;
+#[cfg(feature = "cli")]
+pub mod serve;
+#[cfg(feature = "tree-sitter")]
+pub mod syntax;
 
+#[cfg(feature = "fs")]
 use dashmap::DashSet;
-use rayon::prelude::*;
+use std::borrow::Cow;
+#[cfg(feature = "fs")]
+use std::hash::{Hash, Hasher};
 use std::path::Path;
 
-pub use languages::{LANGUAGES, Language, detect_language, list_extensions, list_languages};
+pub use languages::{
+    Category, Heredoc, LANGUAGES, Language, detect_language, detect_language_by_modeline,
+    detect_language_from_name, find_language_by_alias, get_language, languages_for_extension,
+    list_extensions, list_languages,
+};
 
 mod error;
+pub use cancel::CancellationToken;
 pub use error::Error;
+pub use stats::{SkipReason, SkippedFiles, Warning};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LanguageBreakdown {
-    pub name: &'static str,
+    /// Borrowed for built-in languages; owned for custom ones and names read back with
+    /// `serde`, so unknown names are never leaked.
+    pub name: Cow<'static, str>,
     pub files: u64,
     pub code: u64,
     pub comments: u64,
     pub blanks: u64,
+    /// Documentation comment lines; a subset of `comments`.
+    pub doc_comments: u64,
+    /// Statements; only counted with [`AnalyzeConfig::logical_lines`].
+    pub logical: u64,
+    /// Test code; only split out of `code` with [`AnalyzeConfig::split_tests`].
+    pub test_code: u64,
+    /// Lines excluded by `rloc:ignore-*` directives (see [`counter::IGNORE_START`]); not
+    /// part of [`total_lines`](Self::total_lines).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ignored: u64,
 }
 
 impl LanguageBreakdown {
+    pub fn total_lines(&self) -> u64 {
+        self.code + self.test_code + self.comments + self.blanks
+    }
+}
+
+/// Line counts for one language category (see [`Analysis::by_category`]).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CategoryBreakdown {
+    pub category: Category,
+    pub files: u64,
+    pub code: u64,
+    pub comments: u64,
+    pub blanks: u64,
+}
+
+impl CategoryBreakdown {
     pub fn total_lines(&self) -> u64 {
         self.code + self.comments + self.blanks
     }
 }
 
+/// Line counts for one file extension (see [`Analysis::by_extension`]).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtensionBreakdown {
+    /// Lowercased, without the dot; `"(none)"` for files without one.
+    pub extension: String,
+    pub files: u64,
+    pub code: u64,
+    pub comments: u64,
+    pub blanks: u64,
+    pub test_code: u64,
+}
+
+impl ExtensionBreakdown {
+    pub fn total_lines(&self) -> u64 {
+        self.code + self.test_code + self.comments + self.blanks
+    }
+}
+
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Analysis {
     pub languages: Vec<LanguageBreakdown>,
     pub total_files: u64,
     pub total_code: u64,
     pub total_comments: u64,
     pub total_blanks: u64,
+    pub total_doc_comments: u64,
+    pub total_logical: u64,
+    pub total_test_code: u64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub total_ignored: u64,
+    /// Files found during the walk that were not counted, by reason.
+    pub skipped: SkippedFiles,
+    /// Files no language matched, in walk order.
+    pub unrecognized_files: Vec<std::path::PathBuf>,
+    /// Files that were found but could not be read, sorted by path.
+    pub warnings: Vec<Warning>,
+    /// Per-file counts, sorted by path; empty for [`analyze_fast`].
+    pub files: Vec<counter::FileStats>,
+    /// The analysis stopped early at [`AnalyzeConfig::max_files`] or
+    /// [`AnalyzeConfig::timeout`], so the counts cover only part of the input.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub truncated: bool,
 }
 
 impl Analysis {
@@ -90,31 +193,249 @@ impl Analysis {
     }
 
     pub fn total_lines(&self) -> u64 {
-        self.total_code + self.total_comments + self.total_blanks
+        self.total_code + self.total_test_code + self.total_comments + self.total_blanks
+    }
+
+    /// Languages rolled up into categories (programming, markup, data/config, ...),
+    /// sorted by lines of code.
+    pub fn by_category(&self) -> Vec<CategoryBreakdown> {
+        let mut categories: Vec<CategoryBreakdown> = Vec::new();
+
+        for lang in &self.languages {
+            let category = Category::of(&lang.name);
+            let index = match categories.iter().position(|c| c.category == category) {
+                Some(index) => index,
+                None => {
+                    categories.push(CategoryBreakdown {
+                        category,
+                        files: 0,
+                        code: 0,
+                        comments: 0,
+                        blanks: 0,
+                    });
+                    categories.len() - 1
+                }
+            };
+            let entry = &mut categories[index];
+            entry.files += lang.files;
+            entry.code += lang.code;
+            entry.comments += lang.comments;
+            entry.blanks += lang.blanks;
+        }
+
+        categories.sort_by_key(|c| std::cmp::Reverse(c.code));
+        categories
+    }
+
+    /// Counted files grouped by extension instead of language, sorted by lines of code.
+    /// Empty for [`analyze_fast`], which keeps no per-file counts.
+    pub fn by_extension(&self) -> Vec<ExtensionBreakdown> {
+        stats::by_extension(&self.files)
+            .into_iter()
+            .map(|row| ExtensionBreakdown {
+                extension: row.name,
+                files: row.files,
+                code: row.code,
+                comments: row.comments,
+                blanks: row.blanks,
+                test_code: row.test_code,
+            })
+            .collect()
+    }
+
+    /// The `n` files with the most lines of code, largest first; ties are broken by path.
+    pub fn largest_files(&self, n: usize) -> Vec<&counter::FileStats> {
+        let mut files: Vec<&counter::FileStats> = self.files.iter().collect();
+        files.sort_by(|a, b| stats::largest_first(a, b));
+        files.truncate(n);
+        files
+    }
+
+    /// Extensions of [`Analysis::unrecognized_files`] with their file counts, most common
+    /// first. Files without an extension are counted under `"(none)"`.
+    pub fn unrecognized_extensions(&self) -> Vec<(String, u64)> {
+        stats::extension_histogram(&self.unrecognized_files)
+    }
+
+    /// shields.io endpoint badge for the total lines of code.
+    pub fn loc_badge(&self) -> badge::Badge {
+        badge::Badge::lines_of_code(self.total_code)
+    }
+
+    /// shields.io endpoint badge for the top language, if any were found.
+    pub fn top_language_badge(&self) -> Option<badge::Badge> {
+        self.top_language()
+            .map(|top| badge::Badge::top_language(&top.name, top.code, self.total_code))
     }
 }
 
+/// Count `text` as a source file in `language`, without touching the file system (e.g.
+/// code pasted into a web playground).
+///
+/// # Example
+///
+/// ```
+/// let rust = rloc::detect_language_from_name("main.rs").unwrap();
+/// let stats = rloc::count_str("// entry point\nfn main() {}\n", rust).unwrap();
+/// assert_eq!((stats.code, stats.comments), (1, 1));
+/// ```
+pub fn count_str(text: &str, language: &Language) -> Result<counter::FileStats> {
+    Ok(counter::count_bytes(
+        Path::new(""),
+        text.as_bytes(),
+        language,
+        &counter::CountConfig::default(),
+    )?)
+}
+
+/// The comments in the file at `path`, in source order, with their lines and whether they
+/// are documentation. The language is detected from the file name; see
+/// [`strip::comment_spans`] to pass it explicitly.
+///
+/// # Example
+///
+/// ```no_run
+/// for comment in rloc::extract_comments("src/lib.rs").unwrap() {
+///     println!("{}-{}: {}", comment.start_line, comment.end_line, comment.text);
+/// }
+/// ```
+#[cfg(feature = "fs")]
+pub fn extract_comments(path: impl AsRef<Path>) -> Result<Vec<strip::CommentSpan>> {
+    let path = path.as_ref();
+    let language = detect_language(path).ok_or(Error::NoSourceFiles)?;
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    Ok(strip::comment_spans(reader, language)?)
+}
+
+/// Everything an analysis can be configured with. With the `serde` feature it can be
+/// stored and loaded; the custom language registry, cancellation token and cache are
+/// runtime handles and are skipped. Missing fields take their `Default` value, except the
+/// excluded directories and vendored and test patterns, which get those of [`Self::new`].
+#[cfg(feature = "fs")]
 #[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct AnalyzeConfig {
     pub paths: Vec<std::path::PathBuf>,
+    #[cfg_attr(feature = "serde", serde(default = "default_exclude_dirs"))]
     pub exclude_dirs: Vec<String>,
     pub exclude_exts: Vec<String>,
     pub exclude_langs: Vec<String>,
     pub include_exts: Vec<String>,
     pub include_langs: Vec<String>,
+    pub include_globs: Vec<String>,
+    pub exclude_globs: Vec<String>,
+    /// Languages forced by extension (lowercase, without the dot), like `--map-ext`.
+    pub force_lang: std::collections::HashMap<String, String>,
+    /// Languages forced by path glob, checked before the extension, like `--force-lang-def`.
+    pub force_lang_globs: Vec<(String, String)>,
+    /// Extensions left unrecognized, like `--unmap-ext`.
+    pub unmapped_exts: Vec<String>,
+    /// Only walk directories whose name (or path, with `fullpath`) matches this regex.
+    pub match_dir: Option<String>,
+    /// Skip directories matching any of these regexes.
+    pub not_match_dir: Vec<String>,
+    /// Only count files whose name (or path, with `fullpath`) matches this regex.
+    pub match_file: Option<String>,
+    /// Skip files matching any of these regexes.
+    pub not_match_file: Vec<String>,
+    /// Match the directory and file regexes against full paths instead of names.
+    pub fullpath: bool,
+    /// Only count files whose content matches this regex.
+    pub include_content: Option<String>,
+    /// Skip files whose content matches this regex.
+    pub exclude_content: Option<String>,
+    /// List files from version control instead of walking the file system.
+    pub vcs: Option<walker::VcsMode>,
+    /// Include files in git submodules when listing files with git.
+    pub include_submodules: bool,
+    /// Regexes marking generated files, in addition to the built-in markers.
+    pub generated_markers: Vec<String>,
     pub follow_symlinks: bool,
+    pub one_file_system: bool,
     pub hidden: bool,
+    /// Let Emacs/Vim modelines override the language detected from the file name.
+    pub modelines: bool,
     pub max_depth: Option<usize>,
     pub skip_gitignore: bool,
+    pub skip_global_gitignore: bool,
+    pub skip_git_exclude: bool,
+    /// Extra files of gitignore patterns, matched relative to each analyzed path.
+    pub custom_ignore_files: Vec<std::path::PathBuf>,
+    pub skip_uniqueness: bool,
     pub max_file_size: Option<u64>,
     pub threads: Option<usize>,
+    /// Stop after counting this many files; the result is marked truncated.
+    pub max_files: Option<usize>,
+    /// Stop once the analysis has run this long; the result is marked truncated.
+    pub timeout: Option<std::time::Duration>,
+    pub exclude_generated: bool,
+    pub skip_minified: bool,
+    pub logical_lines: bool,
+    pub accurate: bool,
+    pub docstrings_as_code: bool,
+    /// Attribute generated C to the files its `#line` directives name.
+    pub follow_origins: bool,
+    /// Languages whose comment lines count as code.
+    pub comments_as_code: Vec<String>,
+    /// Count comment lines as blank (non-code) lines.
+    pub ignore_comments: bool,
+    /// Don't count a last line without a trailing newline.
+    pub require_final_newline: bool,
+    /// Count line terminators by kind into [`counter::FileStats::line_endings`].
+    pub line_endings: bool,
+    /// Tally indentation into [`counter::FileStats::indent`].
+    pub indent_stats: bool,
+    /// Hash file contents into [`counter::FileStats::sha256`].
+    pub content_hash: bool,
+    /// Bytes inspected for binary detection (default [`counter::BINARY_SAMPLE_SIZE`]).
+    pub binary_sample_size: Option<usize>,
+    /// NUL-byte fraction above which a file is binary (default [`counter::BINARY_NULL_RATIO`]).
+    pub binary_null_ratio: Option<f64>,
+    /// Length above which lines are classified in chunks (default [`counter::LONG_LINE_LIMIT`]).
+    pub long_line_limit: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default = "default_vendor_patterns"))]
+    pub vendor_patterns: Vec<String>,
+    pub exclude_vendored: bool,
+    #[cfg_attr(feature = "serde", serde(default = "default_test_patterns"))]
+    pub test_patterns: Vec<String>,
+    pub split_tests: bool,
+    pub exclude_tests: bool,
+    pub exclude_nested_repos: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub custom_languages: Option<std::sync::Arc<custom_langs::CustomLanguageRegistry>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cancel: Option<CancellationToken>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cache: Option<cache::FileCache>,
+}
+
+#[cfg(all(feature = "fs", feature = "serde"))]
+fn default_exclude_dirs() -> Vec<String> {
+    walker::WalkerConfig::default().exclude_dirs
+}
+
+#[cfg(all(feature = "fs", feature = "serde"))]
+fn default_vendor_patterns() -> Vec<String> {
+    walker::WalkerConfig::default().vendor_patterns
+}
+
+#[cfg(all(feature = "fs", feature = "serde"))]
+fn default_test_patterns() -> Vec<String> {
+    walker::WalkerConfig::default().test_patterns
 }
 
+#[cfg(feature = "fs")]
 impl AnalyzeConfig {
     pub fn new(path: impl AsRef<Path>) -> Self {
         Self {
             paths: vec![path.as_ref().to_path_buf()],
             exclude_dirs: walker::WalkerConfig::default().exclude_dirs,
+            vendor_patterns: walker::WalkerConfig::default().vendor_patterns,
+            test_patterns: walker::WalkerConfig::default().test_patterns,
             ..Default::default()
         }
     }
@@ -129,6 +450,24 @@ impl AnalyzeConfig {
         self
     }
 
+    /// Replace the excluded directories with those of a built-in profile.
+    pub fn exclude_profile(mut self, profile: walker::ExcludeProfile) -> Self {
+        self.exclude_dirs = profile.exclude_dirs();
+        self
+    }
+
+    /// Skip files with these extensions (without the dot, case-insensitive).
+    pub fn exclude_exts(mut self, exts: Vec<String>) -> Self {
+        self.exclude_exts = exts;
+        self
+    }
+
+    /// Only count files with these extensions (without the dot, case-insensitive).
+    pub fn include_exts(mut self, exts: Vec<String>) -> Self {
+        self.include_exts = exts;
+        self
+    }
+
     pub fn include_langs(mut self, langs: Vec<String>) -> Self {
         self.include_langs = langs;
         self
@@ -139,6 +478,157 @@ impl AnalyzeConfig {
         self
     }
 
+    /// Only count files whose path below the root matches one of these globs.
+    pub fn include_globs(mut self, globs: Vec<String>) -> Self {
+        self.include_globs = globs;
+        self
+    }
+
+    /// Skip files whose path below the root matches one of these globs.
+    pub fn exclude_globs(mut self, globs: Vec<String>) -> Self {
+        self.exclude_globs = globs;
+        self
+    }
+
+    /// Count files with extension `ext` as `language`.
+    pub fn map_ext(mut self, ext: &str, language: &str) -> Self {
+        self.force_lang.insert(
+            ext.trim_start_matches('.').to_lowercase(),
+            language.to_string(),
+        );
+        self
+    }
+
+    /// Count files whose path below the root matches `pattern` (a glob) as `language`.
+    pub fn force_lang_glob(mut self, pattern: &str, language: &str) -> Self {
+        self.force_lang_globs
+            .push((pattern.to_string(), language.to_string()));
+        self
+    }
+
+    /// Leave files with these extensions unrecognized.
+    pub fn unmap_exts(mut self, exts: Vec<String>) -> Self {
+        self.unmapped_exts = exts;
+        self
+    }
+
+    /// Only walk directories matching `pattern` (a regex).
+    pub fn match_dir(mut self, pattern: &str) -> Self {
+        self.match_dir = Some(pattern.to_string());
+        self
+    }
+
+    /// Skip directories matching any of these regexes.
+    pub fn not_match_dirs(mut self, patterns: Vec<String>) -> Self {
+        self.not_match_dir = patterns;
+        self
+    }
+
+    /// Only count files matching `pattern` (a regex).
+    pub fn match_file(mut self, pattern: &str) -> Self {
+        self.match_file = Some(pattern.to_string());
+        self
+    }
+
+    /// Skip files matching any of these regexes.
+    pub fn not_match_files(mut self, patterns: Vec<String>) -> Self {
+        self.not_match_file = patterns;
+        self
+    }
+
+    /// Match the directory and file regexes against full paths instead of names.
+    pub fn fullpath(mut self, enabled: bool) -> Self {
+        self.fullpath = enabled;
+        self
+    }
+
+    /// Only count files whose content matches `pattern` (a regex).
+    pub fn include_content(mut self, pattern: &str) -> Self {
+        self.include_content = Some(pattern.to_string());
+        self
+    }
+
+    /// Skip files whose content matches `pattern` (a regex).
+    pub fn exclude_content(mut self, pattern: &str) -> Self {
+        self.exclude_content = Some(pattern.to_string());
+        self
+    }
+
+    /// List files from version control instead of walking the file system.
+    pub fn vcs(mut self, vcs: walker::VcsMode) -> Self {
+        self.vcs = Some(vcs);
+        self
+    }
+
+    /// Include files in git submodules when listing files with git.
+    pub fn include_submodules(mut self, include: bool) -> Self {
+        self.include_submodules = include;
+        self
+    }
+
+    /// Regexes marking generated files, in addition to the built-in markers.
+    pub fn generated_markers(mut self, patterns: Vec<String>) -> Self {
+        self.generated_markers = patterns;
+        self
+    }
+
+    /// Follow symbolic links. Directories reached more than once (e.g. through a symlink
+    /// loop) are walked only the first time.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Don't descend into directories on another file system than the root.
+    pub fn one_file_system(mut self, enabled: bool) -> Self {
+        self.one_file_system = enabled;
+        self
+    }
+
+    /// Also walk hidden files and directories.
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Let Emacs/Vim modelines override the language detected from the file name. Files
+    /// whose name matches no language are checked for a modeline either way.
+    pub fn modelines(mut self, enabled: bool) -> Self {
+        self.modelines = enabled;
+        self
+    }
+
+    /// Count files ignored by `.gitignore`, `.git/info/exclude` and the global git excludes.
+    pub fn skip_gitignore(mut self, skip: bool) -> Self {
+        self.skip_gitignore = skip;
+        self
+    }
+
+    /// Don't apply the global git excludes file (`core.excludesFile`).
+    pub fn skip_global_gitignore(mut self, skip: bool) -> Self {
+        self.skip_global_gitignore = skip;
+        self
+    }
+
+    /// Don't apply the repository's `.git/info/exclude`.
+    pub fn skip_git_exclude(mut self, skip: bool) -> Self {
+        self.skip_git_exclude = skip;
+        self
+    }
+
+    /// Also skip files matching the gitignore patterns in these files. Patterns are
+    /// anchored at each analyzed path, not the current directory.
+    pub fn custom_ignore_files(mut self, files: Vec<std::path::PathBuf>) -> Self {
+        self.custom_ignore_files = files;
+        self
+    }
+
+    /// Skip files larger than `megabytes` MiB.
+    pub fn max_file_size(mut self, megabytes: u64) -> Self {
+        self.max_file_size = Some(megabytes);
+        self
+    }
+
     pub fn max_depth(mut self, depth: usize) -> Self {
         self.max_depth = Some(depth);
         self
@@ -148,6 +638,248 @@ impl AnalyzeConfig {
         self.threads = Some(threads);
         self
     }
+
+    /// Count at most `max` files, returning partial results flagged as
+    /// [`Analysis::truncated`] when there are more.
+    pub fn max_files(mut self, max: usize) -> Self {
+        self.max_files = Some(max);
+        self
+    }
+
+    /// Stop after `timeout`, returning partial results flagged as [`Analysis::truncated`].
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Count files with identical content separately instead of only once.
+    pub fn skip_uniqueness(mut self, skip: bool) -> Self {
+        self.skip_uniqueness = skip;
+        self
+    }
+
+    /// Drop generated files instead of reporting them under "Generated".
+    pub fn exclude_generated(mut self, exclude: bool) -> Self {
+        self.exclude_generated = exclude;
+        self
+    }
+
+    /// Drop minified JavaScript/CSS instead of reporting it under "<Language> (minified)".
+    pub fn skip_minified(mut self, skip: bool) -> Self {
+        self.skip_minified = skip;
+        self
+    }
+
+    /// Also count logical lines (statements), reported as `logical`. This costs an extra
+    /// pass over each line.
+    pub fn logical_lines(mut self, enabled: bool) -> Self {
+        self.logical_lines = enabled;
+        self
+    }
+
+    /// Find comments with a tree-sitter parse for languages with a bundled grammar, so
+    /// comment tokens inside strings, template literals and heredocs are not miscounted.
+    /// Only has an effect with the `tree-sitter` feature.
+    pub fn accurate(mut self, enabled: bool) -> Self {
+        self.accurate = enabled;
+        self
+    }
+
+    /// Count Python docstrings as code instead of doc comments. Either way, other
+    /// triple-quoted strings are code.
+    pub fn docstrings_as_code(mut self, as_code: bool) -> Self {
+        self.docstrings_as_code = as_code;
+        self
+    }
+
+    /// Report lines of generated C that follow a `#line` directive under the file and
+    /// language it names, and flag JavaScript/CSS with a source map as generated.
+    pub fn follow_origins(mut self, enabled: bool) -> Self {
+        self.follow_origins = enabled;
+        self
+    }
+
+    /// Count the comment lines of these languages as code, e.g. SQL whose header comments
+    /// drive migrations. Aliases such as `cpp` are accepted.
+    pub fn comments_as_code(mut self, langs: Vec<String>) -> Self {
+        self.comments_as_code = langs;
+        self
+    }
+
+    /// Count comment lines (of languages not in
+    /// [`comments_as_code`](Self::comments_as_code)) as blank lines, so only code and
+    /// non-code lines are reported.
+    pub fn ignore_comments(mut self, enabled: bool) -> Self {
+        self.ignore_comments = enabled;
+        self
+    }
+
+    /// Only count lines ended by a newline, as `wc -l` does. By default a last line without
+    /// a trailing newline counts like any other.
+    pub fn require_final_newline(mut self, enabled: bool) -> Self {
+        self.require_final_newline = enabled;
+        self
+    }
+
+    /// Count each file's LF, CRLF and CR line endings, reported per file in
+    /// [`Analysis::files`].
+    pub fn line_endings(mut self, enabled: bool) -> Self {
+        self.line_endings = enabled;
+        self
+    }
+
+    /// Count tab- and space-indented lines and space indent steps per file, reported in
+    /// [`Analysis::files`]; [`IndentStats::width`](counter::IndentStats::width) gives the
+    /// usual indent width.
+    pub fn indent_stats(mut self, enabled: bool) -> Self {
+        self.indent_stats = enabled;
+        self
+    }
+
+    /// Record a SHA-256 of each file's content in [`Analysis::files`], as needed by
+    /// [`snapshot::Snapshot::new`].
+    pub fn content_hash(mut self, enabled: bool) -> Self {
+        self.content_hash = enabled;
+        self
+    }
+
+    /// Tune binary detection: inspect the first `sample_size` bytes (`0` disables it) and
+    /// treat files with more than `null_ratio` NUL bytes as binary.
+    pub fn binary_detection(mut self, sample_size: usize, null_ratio: f64) -> Self {
+        self.binary_sample_size = Some(sample_size);
+        self.binary_null_ratio = Some(null_ratio);
+        self
+    }
+
+    /// Classify lines longer than `limit` bytes in chunks of that size, so that huge
+    /// single-line files (minified bundles, JSON dumps) are counted without decoding the
+    /// whole line at once; `0` classifies every line whole.
+    pub fn long_line_limit(mut self, limit: usize) -> Self {
+        self.long_line_limit = Some(limit);
+        self
+    }
+
+    /// Drop vendored files instead of reporting them under "Vendored".
+    pub fn exclude_vendored(mut self, exclude: bool) -> Self {
+        self.exclude_vendored = exclude;
+        self
+    }
+
+    /// Token that aborts the analysis with [`Error::Cancelled`] once cancelled.
+    pub fn cancel_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Reuse the counts of files unchanged since an earlier analysis with the same cache.
+    pub fn cache(mut self, cache: cache::FileCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Custom language definitions used by this analysis only.
+    pub fn custom_languages(mut self, registry: custom_langs::CustomLanguageRegistry) -> Self {
+        self.custom_languages = Some(std::sync::Arc::new(registry));
+        self
+    }
+
+    /// Path globs classifying files as vendored (replaces the defaults).
+    pub fn vendor_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.vendor_patterns = patterns;
+        self
+    }
+
+    /// Report test code (test files and Rust `#[cfg(test)]` modules) as `test_code`
+    /// instead of `code`.
+    pub fn split_tests(mut self, split: bool) -> Self {
+        self.split_tests = split;
+        self
+    }
+
+    /// Drop test code (test files and Rust `#[cfg(test)]` modules) entirely.
+    pub fn exclude_tests(mut self, exclude: bool) -> Self {
+        self.exclude_tests = exclude;
+        self
+    }
+
+    /// Skip git repositories and submodules nested below the analyzed paths.
+    pub fn exclude_nested_repos(mut self, exclude: bool) -> Self {
+        self.exclude_nested_repos = exclude;
+        self
+    }
+
+    /// Path globs classifying files as tests (replaces the defaults).
+    pub fn test_patterns(mut self, patterns: Vec<String>) -> Self {
+        self.test_patterns = patterns;
+        self
+    }
+
+    /// Finish the builder, rejecting configurations [`AnalyzeConfig::validate`] refuses.
+    pub fn build(self) -> Result<Self> {
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Check for invalid globs and contradictory options: a language or extension both
+    /// included and excluded, tests both split out and excluded, or a binary NUL ratio
+    /// outside `0..=1`. The analysis functions run this before walking.
+    pub fn validate(&self) -> Result<()> {
+        for pattern in self
+            .include_globs
+            .iter()
+            .chain(&self.exclude_globs)
+            .chain(&self.test_patterns)
+            .chain(self.force_lang_globs.iter().map(|(pattern, _)| pattern))
+        {
+            globset::Glob::new(pattern)
+                .map_err(|e| Error::InvalidConfig(format!("Invalid glob '{}': {}", pattern, e)))?;
+        }
+
+        let custom = self.custom_languages.as_deref();
+        let excluded = walker::canonical_language_names(custom, &self.exclude_langs);
+        let included = walker::canonical_language_names(custom, &self.include_langs);
+        if let Some(index) = included.iter().position(|lang| excluded.contains(lang)) {
+            return Err(Error::InvalidConfig(format!(
+                "Language '{}' is both included and excluded",
+                self.include_langs[index]
+            )));
+        }
+
+        if let Some(ext) = self.include_exts.iter().find(|ext| {
+            self.exclude_exts
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(ext))
+        }) {
+            return Err(Error::InvalidConfig(format!(
+                "Extension '{}' is both included and excluded",
+                ext
+            )));
+        }
+
+        if let Some(file) = self.custom_ignore_files.iter().find(|file| !file.is_file()) {
+            return Err(Error::InvalidConfig(format!(
+                "Ignore file '{}' not found",
+                file.display()
+            )));
+        }
+
+        if self.split_tests && self.exclude_tests {
+            return Err(Error::InvalidConfig(
+                "Test code cannot be both split out and excluded".to_string(),
+            ));
+        }
+
+        if let Some(ratio) = self.binary_null_ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(Error::InvalidConfig(format!(
+                    "Binary NUL ratio {} is not between 0 and 1",
+                    ratio
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Get the top (most code) language in a directory.
@@ -163,6 +895,7 @@ impl AnalyzeConfig {
 /// let top = rloc::top_language(Path::new(".")).unwrap();
 /// println!("{}: {} lines of code", top.name, top.code);
 /// ```
+#[cfg(feature = "fs")]
 pub fn top_language(path: impl AsRef<Path>) -> Result<LanguageBreakdown> {
     let analysis = analyze(path)?;
     analysis.top_language().cloned().ok_or(Error::NoSourceFiles)
@@ -181,6 +914,7 @@ pub fn top_language(path: impl AsRef<Path>) -> Result<LanguageBreakdown> {
 /// let top = rloc::top_language_fast(Path::new(".")).unwrap();
 /// println!("{}: {} files", top.name, top.files);
 /// ```
+#[cfg(feature = "fs")]
 pub fn top_language_fast(path: impl AsRef<Path>) -> Result<LanguageBreakdown> {
     let analysis = analyze_fast(path)?;
     analysis.top_language().cloned().ok_or(Error::NoSourceFiles)
@@ -190,6 +924,7 @@ pub fn top_language_fast(path: impl AsRef<Path>) -> Result<LanguageBreakdown> {
 ///
 /// This reads file contents to accurately count lines of code, comments,
 /// and blank lines.
+#[cfg(feature = "fs")]
 pub fn analyze(path: impl AsRef<Path>) -> Result<Analysis> {
     let config = AnalyzeConfig::new(path);
     analyze_with_config(config)
@@ -199,57 +934,363 @@ pub fn analyze(path: impl AsRef<Path>) -> Result<Analysis> {
 ///
 /// This is useful when you only need to know the language distribution
 /// by file count, not by lines of code.
+#[cfg(feature = "fs")]
 pub fn analyze_fast(path: impl AsRef<Path>) -> Result<Analysis> {
     let config = AnalyzeConfig::new(path);
     analyze_fast_with_config(config)
 }
 
 /// Analyze with custom configuration.
+///
+/// When `threads` is set, the analysis runs in its own rayon pool of that size; the global
+/// pool is left untouched.
+#[cfg(feature = "fs")]
 pub fn analyze_with_config(config: AnalyzeConfig) -> Result<Analysis> {
-    if let Some(threads) = config.threads {
-        if threads > 0 {
-            rayon::ThreadPoolBuilder::new()
+    config.validate()?;
+    in_pool(&config, || run_analysis(&config))
+}
+
+/// The files an analysis with `config` would count, sorted by path, without counting them.
+///
+/// Each entry carries the language it was detected as, so this shows why a file is missing
+/// from the counts. Files are only read where detection needs it (content filters, shebangs
+/// and modelines), so empty, binary and duplicate files are still listed.
+#[cfg(feature = "fs")]
+pub fn list_files(config: AnalyzeConfig) -> Result<Vec<walker::FileEntry>> {
+    config.validate()?;
+    let walker_config = config_to_walker(&config)?;
+    let mut files = walker::walk_files(&walker_config);
+    check_cancelled(&config)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Compare the files under `old` with those under `new` (directories, files or archives),
+/// filtered by `config`; its `paths` are ignored.
+///
+/// Files are matched by their path below each root. An archive whose entries share one
+/// top-level directory is compared from inside it, so two release tarballs line up.
+#[cfg(feature = "fs")]
+pub fn diff(
+    old: impl AsRef<Path>,
+    new: impl AsRef<Path>,
+    config: AnalyzeConfig,
+) -> Result<diff::DiffResult> {
+    config.validate()?;
+    let walker_config = config_to_walker(&config)?;
+    let count_config = config_to_count(&config)?;
+    let (old, new) = (old.as_ref().to_path_buf(), new.as_ref());
+    let result = in_pool(&config, || {
+        Ok(diff::diff_paths(
+            &[old],
+            new,
+            &walker_config,
+            &count_config,
+            |_, _| {},
+        )?)
+    })?;
+    if result.by_language.is_empty() {
+        return Err(Error::NoSourceFiles);
+    }
+    Ok(result)
+}
+
+/// Compare two analyses, e.g. a saved one with a fresh run, language by language. See
+/// [`diff::diff_analyses`].
+#[cfg(feature = "fs")]
+pub fn diff_analyses(old: &Analysis, new: &Analysis) -> diff::DiffResult {
+    diff::diff_analyses(old, new)
+}
+
+/// Run `f` in a rayon pool of `config.threads` threads, or the global pool if unset.
+#[cfg(feature = "fs")]
+fn in_pool<T: Send>(config: &AnalyzeConfig, f: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+    match config.threads.filter(|&threads| threads > 0) {
+        Some(threads) => {
+            let pool = rayon::ThreadPoolBuilder::new()
                 .num_threads(threads)
-                .build_global()
-                .ok();
+                .build()
+                .map_err(|e| Error::InvalidConfig(format!("Failed to build thread pool: {}", e)))?;
+            pool.install(f)
         }
+        None => f(),
     }
+}
 
-    let walker_config = config_to_walker(&config);
-    let files = walker::walk_files(&walker_config);
+/// [`analyze_with_config`] walking and counting with `walker_config` and `count_config`
+/// instead of the settings derived from `config`, which still supplies the cache, limits,
+/// cancellation and the generated and minified file filters.
+#[cfg(feature = "cli")]
+pub(crate) fn analyze_with_settings(
+    config: AnalyzeConfig,
+    walker_config: walker::WalkerConfig,
+    count_config: counter::CountConfig,
+) -> Result<Analysis> {
+    config.validate()?;
+    in_pool(&config, || {
+        run_analysis_with(&config, walker_config, count_config)
+    })
+}
 
-    if files.is_empty() {
-        return Err(Error::NoSourceFiles);
+#[cfg(feature = "fs")]
+fn config_to_count(config: &AnalyzeConfig) -> Result<counter::CountConfig> {
+    let mut defaults = counter::CountConfig::default();
+    for pattern in &config.generated_markers {
+        defaults
+            .generated_markers
+            .push(config_regex(pattern, "generated marker")?);
     }
+    Ok(counter::CountConfig {
+        binary_sample_size: config
+            .binary_sample_size
+            .unwrap_or(defaults.binary_sample_size),
+        long_line_limit: config.long_line_limit.unwrap_or(defaults.long_line_limit),
+        binary_null_ratio: config
+            .binary_null_ratio
+            .unwrap_or(defaults.binary_null_ratio),
+        tests: if config.exclude_tests {
+            counter::TestMode::Exclude
+        } else if config.split_tests {
+            counter::TestMode::Split
+        } else {
+            counter::TestMode::Include
+        },
+        logical_lines: config.logical_lines,
+        accurate: config.accurate,
+        docstrings_as_code: config.docstrings_as_code,
+        follow_origins: config.follow_origins,
+        policy: policy::CountPolicy {
+            ignore_comments: config.ignore_comments,
+            ..Default::default()
+        }
+        .with_comments_as_code(&config.comments_as_code),
+        require_final_newline: config.require_final_newline,
+        line_endings: config.line_endings,
+        indent_stats: config.indent_stats,
+        content_hash: config.content_hash,
+        ..defaults
+    })
+}
+
+#[cfg(feature = "fs")]
+fn run_analysis(config: &AnalyzeConfig) -> Result<Analysis> {
+    run_analysis_with(config, config_to_walker(config)?, config_to_count(config)?)
+}
 
+#[cfg(feature = "fs")]
+fn run_analysis_with(
+    config: &AnalyzeConfig,
+    walker_config: walker::WalkerConfig,
+    count_config: counter::CountConfig,
+) -> Result<Analysis> {
+    let _span = tracing::info_span!("analyze").entered();
+    let limiter = cancel::Limiter::new(config.max_files, config.timeout, config.cancel.as_ref());
+    let walker_config = walker::WalkerConfig {
+        cancel: Some(limiter.walk_token()),
+        ..walker_config
+    };
+    let count_config = counter::CountConfig {
+        cancel: Some(limiter.count_token()),
+        ..count_config
+    };
     let seen_hashes: DashSet<u64> = DashSet::new();
+    let cache_key = config
+        .cache
+        .as_ref()
+        .map_or(0, |_| count_settings(&count_config));
+    let mut skipped = SkippedFiles::default();
+    let mut unrecognized_files = Vec::new();
+    let warnings = std::sync::Mutex::new(Vec::new());
+    let warn = |path: &Path, error: &std::io::Error| {
+        if let Ok(mut warnings) = warnings.lock() {
+            warnings.push(Warning::from_io(path, error));
+        }
+    };
 
-    let file_stats: Vec<_> = files
-        .into_par_iter()
-        .filter_map(|entry| {
-            if let Ok(hash) = counter::compute_file_hash(&entry.path) {
-                if !seen_hashes.insert(hash) {
+    // Files are counted while the walk is still in progress.
+    let (discovered, results) = limiter.run(|| {
+        walker::walk_files_parallel(
+            &walker_config,
+            |_| {},
+            |path, reason| {
+                skipped.record(reason);
+                if reason == SkipReason::Unrecognized {
+                    unrecognized_files.push(path);
+                }
+            },
+            |entry| {
+                if cancel::is_cancelled(&walker_config.cancel) || !limiter.admit() {
                     return None;
                 }
-            }
 
-            match counter::count_lines(&entry.path, entry.language) {
-                Ok(stats) if stats.total() > 0 => Some(stats),
-                _ => None,
-            }
-        })
-        .collect();
+                let settings = cache_settings(cache_key, entry.language);
+                let cached = config
+                    .cache
+                    .as_ref()
+                    .and_then(|cache| cache.get(&entry.path, settings));
+                let counted = match cached {
+                    Some((hash, stats)) => {
+                        if !walker_config.skip_uniqueness && !seen_hashes.insert(hash) {
+                            return None;
+                        }
+                        Ok(stats)
+                    }
+                    None => {
+                        // Read once: the same buffer is hashed for dedup and then counted.
+                        let source = match walker::read_file(&walker_config, &entry.path) {
+                            Ok(source) => source,
+                            Err(e) => {
+                                warn(&entry.path, &e);
+                                return Some(Err(SkipReason::Unreadable));
+                            }
+                        };
+                        let hash = counter::hash_bytes(&source);
+                        if !walker_config.skip_uniqueness && !seen_hashes.insert(hash) {
+                            return None;
+                        }
+                        let counted = counter::count_bytes(
+                            &entry.path,
+                            &source,
+                            entry.language,
+                            &count_config,
+                        );
+                        if let (Some(cache), Ok(stats)) = (&config.cache, &counted) {
+                            cache.insert(&entry.path, settings, hash, stats);
+                        }
+                        counted
+                    }
+                };
 
-    let summary = stats::Summary::from_file_stats(file_stats);
-    Ok(summary_to_analysis(&summary))
+                match counted {
+                    Ok(stats) if stats.binary => Some(Err(SkipReason::Binary)),
+                    Ok(stats) if stats.generated && config.exclude_generated => None,
+                    Ok(stats) if stats.minified && config.skip_minified => None,
+                    Ok(mut stats) if !stats.is_empty() || stats.is_ignored() => {
+                        if entry.test && count_config.tests == counter::TestMode::Split {
+                            stats.mark_as_test();
+                        }
+                        Some(Ok(counter::FileStats {
+                            vendored: entry.vendored,
+                            ..stats
+                        }))
+                    }
+                    Ok(_) => None,
+                    // Interrupted mid-file by the timeout.
+                    Err(e)
+                        if e.kind() == std::io::ErrorKind::Interrupted && limiter.truncated() =>
+                    {
+                        None
+                    }
+                    Err(e) => {
+                        warn(&entry.path, &e);
+                        Some(Err(SkipReason::Unreadable))
+                    }
+                }
+            },
+        )
+    });
+
+    check_cancelled(config)?;
+    let truncated = limiter.truncated();
+    if discovered == 0 && !truncated {
+        return Err(Error::NoSourceFiles);
+    }
+
+    let mut file_stats = Vec::with_capacity(results.len());
+    for result in results {
+        match result {
+            Ok(stats) => file_stats.push(stats),
+            Err(reason) => skipped.record(reason),
+        }
+    }
+
+    let summary = stats::Summary::from_file_stats(file_stats)
+        .with_skipped(skipped)
+        .with_warnings(warnings.into_inner().unwrap_or_default());
+    Ok(Analysis {
+        unrecognized_files,
+        truncated,
+        ..summary_to_analysis(summary)
+    })
+}
+
+/// Fingerprint of the settings that change how a file is counted, so cached counts are only
+/// reused by analyses that would count the file the same way.
+#[cfg(feature = "fs")]
+fn count_settings(count_config: &counter::CountConfig) -> u64 {
+    let mut hasher = std::hash::DefaultHasher::new();
+    count_config.detect_generated.hash(&mut hasher);
+    for marker in &count_config.generated_markers {
+        marker.as_str().hash(&mut hasher);
+    }
+    count_config.detect_minified.hash(&mut hasher);
+    count_config.logical_lines.hash(&mut hasher);
+    format!("{:?}", count_config.tests).hash(&mut hasher);
+    count_config.accurate.hash(&mut hasher);
+    count_config.docstrings_as_code.hash(&mut hasher);
+    count_config.follow_origins.hash(&mut hasher);
+    count_config.policy.hash(&mut hasher);
+    count_config.require_final_newline.hash(&mut hasher);
+    count_config.line_endings.hash(&mut hasher);
+    count_config.indent_stats.hash(&mut hasher);
+    count_config.content_hash.hash(&mut hasher);
+    count_config.binary_sample_size.hash(&mut hasher);
+    count_config.long_line_limit.hash(&mut hasher);
+    count_config.binary_null_ratio.to_bits().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// [`count_settings`] combined with the language a file is counted as. Languages are
+/// `'static`, so their address tells custom definitions of the same name apart.
+#[cfg(feature = "fs")]
+fn cache_settings(count_settings: u64, language: &'static Language) -> u64 {
+    let mut hasher = std::hash::DefaultHasher::new();
+    count_settings.hash(&mut hasher);
+    std::ptr::from_ref(language).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(feature = "fs")]
+fn check_cancelled(config: &AnalyzeConfig) -> Result<()> {
+    if cancel::is_cancelled(&config.cancel) {
+        Err(Error::Cancelled)
+    } else {
+        Ok(())
+    }
 }
 
 /// Fast analysis with custom configuration (extension-only, no file reads).
+#[cfg(feature = "fs")]
 pub fn analyze_fast_with_config(config: AnalyzeConfig) -> Result<Analysis> {
-    let walker_config = config_to_walker(&config);
-    let files = walker::walk_files(&walker_config);
+    config.validate()?;
+    let limiter = cancel::Limiter::new(config.max_files, config.timeout, config.cancel.as_ref());
+    let walker_config = walker::WalkerConfig {
+        cancel: Some(limiter.walk_token()),
+        ..config_to_walker(&config)?
+    };
+    let mut files = Vec::new();
+    let mut skipped = SkippedFiles::default();
+    let mut unrecognized_files = Vec::new();
+    limiter.run(|| {
+        walker::walk_files_reporting(
+            &walker_config,
+            |entry| {
+                if limiter.admit() {
+                    files.push(entry);
+                }
+            },
+            |path, reason| {
+                skipped.record(reason);
+                if reason == SkipReason::Unrecognized {
+                    unrecognized_files.push(path);
+                }
+            },
+        )
+    });
 
-    if files.is_empty() {
+    check_cancelled(&config)?;
+    let truncated = limiter.truncated();
+    if files.is_empty() && !truncated {
         return Err(Error::NoSourceFiles);
     }
 
@@ -263,11 +1304,15 @@ pub fn analyze_fast_with_config(config: AnalyzeConfig) -> Result<Analysis> {
     let mut languages: Vec<_> = by_language
         .into_iter()
         .map(|(name, files)| LanguageBreakdown {
-            name,
+            name: Cow::Borrowed(name),
             files,
             code: 0,
             comments: 0,
             blanks: 0,
+            doc_comments: 0,
+            logical: 0,
+            test_code: 0,
+            ignored: 0,
         })
         .collect();
 
@@ -281,11 +1326,54 @@ pub fn analyze_fast_with_config(config: AnalyzeConfig) -> Result<Analysis> {
         total_code: 0,
         total_comments: 0,
         total_blanks: 0,
+        total_doc_comments: 0,
+        total_logical: 0,
+        total_test_code: 0,
+        total_ignored: 0,
+        skipped,
+        unrecognized_files,
+        warnings: Vec::new(),
+        files: Vec::new(),
+        truncated,
     })
 }
 
-fn config_to_walker(config: &AnalyzeConfig) -> walker::WalkerConfig {
-    walker::WalkerConfig {
+/// Analyze several roots separately, e.g. the subprojects of a monorepo.
+///
+/// Each result is keyed by the first path of its config. A root without source files
+/// yields an empty [`Analysis`] rather than an error.
+#[cfg(feature = "fs")]
+pub fn analyze_many(
+    configs: impl IntoIterator<Item = AnalyzeConfig>,
+) -> Result<Vec<(std::path::PathBuf, Analysis)>> {
+    configs
+        .into_iter()
+        .map(|config| {
+            let path = config.paths.first().cloned().unwrap_or_else(|| ".".into());
+            match analyze_with_config(config) {
+                Ok(analysis) => Ok((path, analysis)),
+                Err(Error::NoSourceFiles) => Ok((path, Analysis::default())),
+                Err(e) => Err(e),
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "fs")]
+fn config_to_walker(config: &AnalyzeConfig) -> Result<walker::WalkerConfig> {
+    let regex = |pattern: &Option<String>, option| {
+        pattern
+            .as_deref()
+            .map(|pattern| config_regex(pattern, option))
+            .transpose()
+    };
+    let regexes = |patterns: &[String], option| {
+        patterns
+            .iter()
+            .map(|pattern| config_regex(pattern, option))
+            .collect::<Result<Vec<_>>>()
+    };
+    Ok(walker::WalkerConfig {
         paths: if config.paths.is_empty() {
             vec![std::path::PathBuf::from(".")]
         } else {
@@ -296,48 +1384,115 @@ fn config_to_walker(config: &AnalyzeConfig) -> walker::WalkerConfig {
         exclude_langs: config.exclude_langs.clone(),
         include_exts: config.include_exts.clone(),
         include_langs: config.include_langs.clone(),
+        include_globs: config.include_globs.clone(),
+        exclude_globs: config.exclude_globs.clone(),
+        force_lang: config.force_lang.clone(),
+        force_lang_globs: config.force_lang_globs.clone(),
+        unmapped_exts: config.unmapped_exts.clone(),
+        match_dir: regex(&config.match_dir, "directory match")?,
+        not_match_dir: regexes(&config.not_match_dir, "directory exclusion")?,
+        match_file: regex(&config.match_file, "file match")?,
+        not_match_file: regexes(&config.not_match_file, "file exclusion")?,
+        fullpath: config.fullpath,
+        include_content: regex(&config.include_content, "content inclusion")?,
+        exclude_content: regex(&config.exclude_content, "content exclusion")?,
+        vcs: config.vcs,
+        include_submodules: config.include_submodules,
         follow_symlinks: config.follow_symlinks,
+        one_file_system: config.one_file_system,
         hidden: config.hidden,
+        modelines: config.modelines,
         max_depth: config.max_depth,
         skip_gitignore: config.skip_gitignore,
+        skip_global_gitignore: config.skip_global_gitignore,
+        skip_git_exclude: config.skip_git_exclude,
+        custom_ignore_files: config.custom_ignore_files.clone(),
+        skip_uniqueness: config.skip_uniqueness,
         max_file_size: config.max_file_size,
+        vendor_patterns: config.vendor_patterns.clone(),
+        exclude_vendored: config.exclude_vendored,
+        test_patterns: config.test_patterns.clone(),
+        exclude_tests: config.exclude_tests,
+        exclude_nested_repos: config.exclude_nested_repos,
+        custom_languages: config.custom_languages.clone(),
+        cancel: config.cancel.clone(),
         ..Default::default()
-    }
+    })
+}
+
+/// Compile a regex from `config`, naming the option it came from in errors.
+#[cfg(feature = "fs")]
+fn config_regex(pattern: &str, option: &str) -> Result<regex::Regex> {
+    regex::Regex::new(pattern)
+        .map_err(|e| Error::InvalidConfig(format!("Invalid {} regex '{}': {}", option, pattern, e)))
 }
 
-fn summary_to_analysis(summary: &stats::Summary) -> Analysis {
+/// `name` borrowed from the built-in language of that name, or an owned copy for custom
+/// languages.
+#[cfg(feature = "fs")]
+fn language_name(name: &str) -> Cow<'static, str> {
+    languages::LANGUAGES.get(name).map_or_else(
+        || Cow::Owned(name.to_string()),
+        |lang| Cow::Borrowed(lang.name),
+    )
+}
+
+/// A path from the raw bytes of a list file or git's NUL-separated output.
+#[cfg(unix)]
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> std::path::PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    std::path::PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// A path from the raw bytes of a list file or git's NUL-separated output.
+#[cfg(not(unix))]
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> std::path::PathBuf {
+    std::path::PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(feature = "fs")]
+fn summary_to_analysis(summary: stats::Summary) -> Analysis {
     Analysis {
         languages: summary
             .languages
             .iter()
             .map(|l| LanguageBreakdown {
-                name: languages::LANGUAGES
-                    .get(&l.name)
-                    .map(|lang| lang.name)
-                    .unwrap_or_else(|| {
-                        // For custom languages, we need to leak the string to get 'static
-                        // This is acceptable since language names are bounded and reused
-                        Box::leak(l.name.clone().into_boxed_str())
-                    }),
+                name: language_name(&l.name),
                 files: l.files,
                 code: l.code,
                 comments: l.comments,
                 blanks: l.blanks,
+                doc_comments: l.doc_comments,
+                logical: l.logical,
+                test_code: l.test_code,
+                ignored: l.ignored,
             })
             .collect(),
         total_files: summary.total_files,
         total_code: summary.total_code,
         total_comments: summary.total_comments,
         total_blanks: summary.total_blanks,
+        total_doc_comments: summary.total_doc_comments,
+        total_logical: summary.total_logical,
+        total_test_code: summary.total_test_code,
+        total_ignored: summary.total_ignored,
+        skipped: summary.skipped,
+        unrecognized_files: Vec::new(),
+        warnings: summary.warnings,
+        files: summary.file_stats,
+        truncated: summary.truncated,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "fs")]
     use std::fs;
+    #[cfg(feature = "fs")]
     use tempfile::TempDir;
 
+    #[cfg(feature = "fs")]
     #[test]
     fn test_analyze_simple() {
         let temp = TempDir::new().unwrap();
@@ -360,6 +1515,398 @@ mod tests {
         assert_eq!(top.name, "Rust");
     }
 
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_diff_and_diff_analyses() {
+        let old = TempDir::new().unwrap();
+        let new = TempDir::new().unwrap();
+        fs::write(old.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(new.path().join("main.rs"), "fn main() {}\nfn a() {}\n").unwrap();
+        fs::write(new.path().join("app.py"), "x = 1\n").unwrap();
+
+        let result = diff(old.path(), new.path(), AnalyzeConfig::default()).unwrap();
+        assert_eq!(result.by_language["Rust"].modified.code, 1);
+        assert_eq!(result.totals.added.code, 1);
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["by_language"]["Python"]["added"]["files"], 1);
+
+        // Counting settings apply to both sides.
+        let config = AnalyzeConfig {
+            ignore_comments: true,
+            ..AnalyzeConfig::default()
+        };
+        fs::write(new.path().join("app.py"), "# note\nx = 1\n").unwrap();
+        let result = diff(old.path(), new.path(), config).unwrap();
+        let added = &result.by_language["Python"].added;
+        assert_eq!((added.code, added.comments), (1, 0));
+
+        let result = diff_analyses(&analyze(old.path()).unwrap(), &analyze(new.path()).unwrap());
+        let rust = &result.by_language["Rust"];
+        assert_eq!(
+            (rust.same.code, rust.added.code, rust.removed.code),
+            (1, 1, 0)
+        );
+        assert_eq!(result.totals.added.files, 1);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_analysis_by_category() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\nfn x() {}\n").unwrap();
+        fs::write(temp.path().join("config.toml"), "a = 1\n").unwrap();
+
+        let analysis = analyze(temp.path()).unwrap();
+        let categories = analysis.by_category();
+        assert_eq!(categories.len(), 2);
+        assert_eq!(categories[0].category, Category::Programming);
+        assert_eq!(categories[0].code, 2);
+        assert_eq!(categories[1].category, Category::Data);
+        assert_eq!(categories[1].files, 1);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_analysis_by_extension() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("a.h"), "int a;\n").unwrap();
+        fs::create_dir(temp.path().join("inc")).unwrap();
+        fs::write(temp.path().join("inc/b.h"), "int b;\nint c;\n").unwrap();
+        fs::write(temp.path().join("main.c"), "// x\nint main;\n").unwrap();
+
+        let analysis = analyze(temp.path()).unwrap();
+        let rows: Vec<_> = analysis
+            .by_extension()
+            .into_iter()
+            .map(|e| (e.total_lines(), e.extension, e.files, e.code))
+            .collect();
+        assert_eq!(
+            rows,
+            [(3, "h".to_string(), 2, 3), (2, "c".to_string(), 1, 1)]
+        );
+        assert!(analyze_fast(temp.path()).unwrap().by_extension().is_empty());
+    }
+
+    #[cfg(all(feature = "fs", feature = "serde"))]
+    #[test]
+    fn test_analysis_serde_round_trip() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "// entry\nfn main() {}\n").unwrap();
+        fs::write(temp.path().join("notes.xyz"), "?\n").unwrap();
+
+        let analysis = analyze(temp.path()).unwrap();
+        let json = serde_json::to_string(&analysis).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["languages"][0]["name"], "Rust");
+        assert_eq!(value["total_code"], 1);
+        assert_eq!(value["files"][0]["comments"], 1);
+
+        let back: Analysis = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.languages[0].name, "Rust");
+        let custom: LanguageBreakdown = serde_json::from_value(serde_json::json!({
+            "name": "Made Up", "files": 1, "code": 1, "comments": 0, "blanks": 0,
+            "doc_comments": 0, "logical": 0, "test_code": 0,
+        }))
+        .unwrap();
+        assert!(matches!(custom.name, Cow::Owned(ref name) if name == "Made Up"));
+        assert_eq!(back.total_comments, 1);
+        assert_eq!(back.unrecognized_files, analysis.unrecognized_files);
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+
+        let categories = serde_json::to_value(back.by_category()).unwrap();
+        assert_eq!(categories[0]["category"], "programming");
+    }
+
+    #[cfg(all(feature = "fs", feature = "serde"))]
+    #[test]
+    fn test_analyze_config_serde_round_trip() {
+        let config = AnalyzeConfig::new("src")
+            .include_langs(vec!["Rust".into()])
+            .max_depth(2)
+            .cancel_token(CancellationToken::new());
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("cancel"));
+
+        let back: AnalyzeConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.paths, config.paths);
+        assert_eq!(back.include_langs, ["Rust"]);
+        assert_eq!(back.max_depth, Some(2));
+        assert_eq!(back.exclude_dirs, config.exclude_dirs);
+        assert!(back.cancel.is_none());
+
+        // Missing fields take their defaults.
+        let partial: AnalyzeConfig = serde_json::from_str(r#"{"hidden": true}"#).unwrap();
+        assert!(partial.hidden);
+        assert!(partial.paths.is_empty());
+        assert_eq!(partial.exclude_dirs, config.exclude_dirs);
+        assert_eq!(partial.vendor_patterns, config.vendor_patterns);
+        assert_eq!(partial.test_patterns, config.test_patterns);
+        assert!(!partial.exclude_dirs.is_empty());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_largest_files() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("small.rs"), "fn a() {}\n").unwrap();
+        fs::write(
+            temp.path().join("big.rs"),
+            "fn a() {}\nfn b() {}\nfn c() {}\n",
+        )
+        .unwrap();
+        fs::write(temp.path().join("mid.py"), "a = 1\nb = 2\n").unwrap();
+
+        let analysis = analyze(temp.path()).unwrap();
+        let largest: Vec<_> = analysis
+            .largest_files(2)
+            .iter()
+            .map(|f| Path::new(&f.path).file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(largest, ["big.rs", "mid.py"]);
+        assert_eq!(analysis.largest_files(10).len(), 3);
+        assert!(
+            analyze_fast(temp.path())
+                .unwrap()
+                .largest_files(1)
+                .is_empty()
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_shared_cache() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("main.rs");
+        fs::write(&path, "fn a() {}\n").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+
+        let cache = cache::FileCache::new();
+        let config = AnalyzeConfig::new(temp.path()).cache(cache.clone());
+        assert_eq!(analyze_with_config(config.clone()).unwrap().total_code, 1);
+        assert_eq!(cache.len(), 1);
+
+        // Same size and modification time: the cached counts are reused.
+        fs::write(&path, "// a() {}\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_eq!(analyze_with_config(config.clone()).unwrap().total_code, 1);
+
+        // Different counting settings miss the cache.
+        let logical = config.clone().logical_lines(true);
+        assert_eq!(analyze_with_config(logical).unwrap().total_comments, 1);
+
+        fs::write(&path, "fn a() {}\nfn b() {}\n").unwrap();
+        assert_eq!(analyze_with_config(config).unwrap().total_code, 2);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_builder_validation() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(temp.path().join("big.py"), "x = 1\n".repeat(1000)).unwrap();
+
+        let config = AnalyzeConfig::new(temp.path())
+            .include_exts(vec!["rs".into(), "py".into()])
+            .exclude_exts(vec!["txt".into()])
+            .hidden(true)
+            .skip_gitignore(true)
+            .max_file_size(1)
+            .build()
+            .unwrap();
+        assert_eq!(analyze_with_config(config).unwrap().total_files, 2);
+
+        let conflicting = [
+            AnalyzeConfig::new(".")
+                .include_langs(vec!["cpp".into()])
+                .exclude_langs(vec!["C++".into()]),
+            AnalyzeConfig::new(".")
+                .include_exts(vec!["rs".into()])
+                .exclude_exts(vec!["RS".into()]),
+            AnalyzeConfig::new(".")
+                .split_tests(true)
+                .exclude_tests(true),
+            AnalyzeConfig::new(".").binary_detection(1024, 1.5),
+            AnalyzeConfig::new(".").exclude_globs(vec!["[".into()]),
+            AnalyzeConfig::new(".").custom_ignore_files(vec![temp.path().join("missing")]),
+        ];
+        for config in conflicting {
+            assert!(matches!(
+                config.clone().build(),
+                Err(Error::InvalidConfig(_))
+            ));
+            assert!(matches!(
+                analyze_with_config(config),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+
+        let error = AnalyzeConfig::new(".")
+            .include_langs(vec!["Rust".into(), "cpp".into()])
+            .exclude_langs(vec!["c++".into()])
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid configuration: Language 'cpp' is both included and excluded"
+        );
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_analyze_many() {
+        let temp = TempDir::new().unwrap();
+        let app = temp.path().join("app");
+        let docs = temp.path().join("docs");
+        fs::create_dir_all(&app).unwrap();
+        fs::create_dir_all(&docs).unwrap();
+        fs::write(app.join("main.rs"), "fn main() {}\n").unwrap();
+
+        let results = analyze_many([AnalyzeConfig::new(&app), AnalyzeConfig::new(&docs)]).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, app);
+        assert_eq!(results[0].1.top_language().unwrap().name, "Rust");
+        assert_eq!(results[1].0, docs);
+        assert_eq!(results[1].1.total_files, 0);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_threads_apply_per_call() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        for threads in [1, 3] {
+            let config = AnalyzeConfig::new(temp.path()).threads(threads);
+            let analysis = analyze_with_config(config).unwrap();
+            assert_eq!(analysis.total_files, 1);
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let observed = pool.install(|| {
+            analyze_with_config(AnalyzeConfig::new(temp.path()).threads(1)).unwrap();
+            rayon::current_num_threads()
+        });
+        assert_eq!(observed, 2);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_cancelled_analysis() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let config = AnalyzeConfig::new(temp.path()).cancel_token(token.clone());
+        assert!(matches!(analyze_with_config(config), Err(Error::Cancelled)));
+
+        let config = AnalyzeConfig::new(temp.path()).cancel_token(token);
+        assert!(matches!(
+            analyze_fast_with_config(config),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_limits_truncate() {
+        let temp = TempDir::new().unwrap();
+        for i in 0..5 {
+            fs::write(
+                temp.path().join(format!("f{}.rs", i)),
+                format!("fn f{}() {{}}\n", i),
+            )
+            .unwrap();
+        }
+
+        let analysis = analyze_with_config(AnalyzeConfig::new(temp.path()).max_files(2)).unwrap();
+        assert!(analysis.truncated);
+        assert_eq!(analysis.total_files, 2);
+        assert_eq!(analysis.files.len(), 2);
+
+        let config = AnalyzeConfig::new(temp.path()).max_files(3);
+        let analysis = analyze_fast_with_config(config).unwrap();
+        assert!(analysis.truncated);
+        assert_eq!(analysis.total_files, 3);
+
+        let analysis = analyze_with_config(AnalyzeConfig::new(temp.path()).max_files(5)).unwrap();
+        assert!(!analysis.truncated);
+        assert_eq!(analysis.total_files, 5);
+
+        let config = AnalyzeConfig::new(temp.path()).timeout(std::time::Duration::ZERO);
+        assert!(analyze_with_config(config).unwrap().truncated);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_skip_uniqueness() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("a.rs"), "fn a() {}\n").unwrap();
+        fs::write(temp.path().join("b.rs"), "fn a() {}\n").unwrap();
+
+        let analysis = analyze(temp.path()).unwrap();
+        assert_eq!(analysis.total_files, 1);
+
+        let config = AnalyzeConfig::new(temp.path()).skip_uniqueness(true);
+        let analysis = analyze_with_config(config).unwrap();
+        assert_eq!(analysis.total_files, 2);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_logical_lines() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.c"), "int a = 1; int b = 2;\n").unwrap();
+
+        assert_eq!(analyze(temp.path()).unwrap().total_logical, 0);
+
+        let config = AnalyzeConfig::new(temp.path()).logical_lines(true);
+        let analysis = analyze_with_config(config).unwrap();
+        assert_eq!(analysis.total_logical, 2);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_skipped_files() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(temp.path().join("blob.rs"), [0u8; 64]).unwrap();
+        fs::write(temp.path().join("notes.unknownext"), "text\n").unwrap();
+
+        let analysis = analyze(temp.path()).unwrap();
+        assert_eq!(analysis.total_files, 1);
+        assert_eq!(
+            analysis.skipped,
+            SkippedFiles {
+                binary: 1,
+                unrecognized: 1,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            analysis.unrecognized_files,
+            vec![temp.path().join("notes.unknownext")]
+        );
+        assert_eq!(
+            analysis.unrecognized_extensions(),
+            vec![("unknownext".to_string(), 1)]
+        );
+
+        let config = AnalyzeConfig::new(temp.path()).binary_detection(0, 0.1);
+        let analysis = analyze_with_config(config).unwrap();
+        assert_eq!(analysis.skipped.binary, 0);
+    }
+
+    #[cfg(feature = "fs")]
     #[test]
     fn test_analyze_fast() {
         let temp = TempDir::new().unwrap();
@@ -375,6 +1922,26 @@ mod tests {
         assert_eq!(top.files, 2);
     }
 
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_list_files() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}").unwrap();
+        fs::write(temp.path().join("empty.py"), "").unwrap();
+        fs::write(temp.path().join("notes.weird"), "x").unwrap();
+
+        let files = list_files(AnalyzeConfig::new(temp.path())).unwrap();
+        let listed: Vec<_> = files
+            .iter()
+            .map(|f| (f.path.file_name().unwrap().to_owned(), f.language.name))
+            .collect();
+        assert_eq!(
+            listed,
+            [("empty.py".into(), "Python"), ("main.rs".into(), "Rust")]
+        );
+    }
+
+    #[cfg(feature = "fs")]
     #[test]
     fn test_top_language() {
         let temp = TempDir::new().unwrap();
@@ -400,6 +1967,50 @@ mod tests {
         assert!(detect_language(Path::new("test.unknown")).is_none());
     }
 
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_extract_comments() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("app.py");
+        fs::write(
+            &path,
+            "\"\"\"Module docs.\n\nMore.\n\"\"\"\n# setup\nx = 1\n",
+        )
+        .unwrap();
+
+        let comments = extract_comments(&path).unwrap();
+        assert_eq!(comments.len(), 2);
+        assert_eq!((comments[0].start_line, comments[0].end_line), (1, 4));
+        assert!(comments[0].doc);
+        assert_eq!(comments[1].text, "# setup");
+        assert!(!comments[1].doc);
+
+        let unknown = temp.path().join("notes.unknown");
+        fs::write(&unknown, "# x\n").unwrap();
+        assert!(matches!(
+            extract_comments(&unknown),
+            Err(Error::NoSourceFiles)
+        ));
+    }
+
+    #[test]
+    fn test_count_str() {
+        let python = detect_language_from_name("script.py").unwrap();
+        let stats = count_str("# setup\n\nx = 1\ny = 2\n", python).unwrap();
+        assert_eq!((stats.code, stats.comments, stats.blanks), (2, 1, 1));
+        assert_eq!(stats.language, "Python");
+
+        assert_eq!(
+            detect_language_from_name("Makefile").unwrap().name,
+            "Makefile"
+        );
+        assert!(detect_language_from_name("notes.unknown").is_none());
+
+        let stats = count_str("\0\0\0\0binary", python).unwrap();
+        assert!(stats.binary);
+    }
+
+    #[cfg(feature = "fs")]
     #[test]
     fn test_no_source_files() {
         let temp = TempDir::new().unwrap();
@@ -0,0 +1,424 @@
+//! Literate and mixed-language formats: literate Haskell (`.lhs`), R Markdown (`.Rmd`),
+//! Jupyter notebooks (`.ipynb`) and PHP templates.
+//!
+//! Literate Haskell stays one language: Bird-style `>` lines and `\begin{code}` blocks are
+//! counted with Haskell's rules and the prose around them counts as comments.
+//!
+//! R Markdown documents and notebooks mix languages. Their content is reported as
+//! [`FileStats::embedded`] stats: chunks and code cells under their engine or kernel language,
+//! prose and markdown cells as Markdown. Lines that belong to the format itself (chunk fences,
+//! YAML front matter, raw notebook cells) stay with the file.
+//!
+//! PHP files count the `<?php ... ?>` regions as PHP and report the HTML around them as
+//! embedded HTML. Blank lines and lines mixing both count as PHP.
+
+use crate::counter::{self, CountConfig, FileStats};
+use crate::languages::{Language, find_language_by_alias, get_language};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// Kernel language assumed for notebooks whose metadata does not name one.
+const DEFAULT_KERNEL: &str = "Python";
+
+/// Count `content` when `language` is a literate format, `None` otherwise.
+pub(crate) fn count(
+    path: &Path,
+    content: &[u8],
+    language: &Language,
+    config: &CountConfig,
+) -> Option<io::Result<FileStats>> {
+    let text = String::from_utf8_lossy(content);
+    let stats = match language.name {
+        "Literate Haskell" => literate_haskell(path, &text, config),
+        "R Markdown" => r_markdown(path, &text, config),
+        "Jupyter Notebook" => notebook(path, &text, config),
+        "PHP" => php(path, &text, language, config),
+        _ => return None,
+    };
+    Some(stats.map(|mut stats| {
+        stats.path = path.display().to_string();
+        stats.language = language.name.to_string();
+        stats.max_line_length = text.lines().map(str::len).max().unwrap_or(0) as u64;
+        stats.bytes = (content.len() as u64)
+            .saturating_sub(stats.embedded.iter().map(|child| child.bytes).sum());
+        stats.line_bytes = text
+            .lines()
+            .map(|line| line.len() as u64)
+            .sum::<u64>()
+            .saturating_sub(stats.embedded.iter().map(|child| child.line_bytes).sum());
+        stats
+    }))
+}
+
+fn literate_haskell(path: &Path, text: &str, config: &CountConfig) -> io::Result<FileStats> {
+    let mut code = String::new();
+    let mut prose = Document::default();
+    let mut in_block = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if in_block {
+            if trimmed.starts_with("\\end{code}") {
+                in_block = false;
+                prose.line(line);
+            } else {
+                push_line(&mut code, line);
+            }
+        } else if trimmed.starts_with("\\begin{code}") {
+            in_block = true;
+            prose.line(line);
+        } else if let Some(bird) = line.strip_prefix('>') {
+            push_line(&mut code, bird);
+        } else {
+            prose.line(line);
+        }
+    }
+
+    let haskell = get_language("Haskell").expect("Haskell is a built-in language");
+    let mut stats = counter::classify_source(path, code.as_bytes(), haskell, config)?;
+    // Everything outside the code is commentary.
+    stats.comments += prose.code;
+    stats.blanks += prose.blanks;
+    Ok(stats)
+}
+
+fn r_markdown(path: &Path, text: &str, config: &CountConfig) -> io::Result<FileStats> {
+    let mut document = Document::default();
+    let mut sources = Sources::default();
+    let mut lines = text.lines().peekable();
+
+    if lines.peek().is_some_and(|line| line.trim_end() == "---") {
+        document.line(lines.next().unwrap_or_default());
+        for line in lines.by_ref() {
+            document.line(line);
+            if matches!(line.trim_end(), "---" | "...") {
+                break;
+            }
+        }
+    }
+
+    // Engine of the chunk being read, and whether the reader is in a plain Markdown fence.
+    let mut chunk: Option<Option<&'static Language>> = None;
+    let mut in_fence = false;
+    for line in lines {
+        let trimmed = line.trim_start();
+        match chunk {
+            Some(_) if trimmed.starts_with("```") => {
+                document.line(line);
+                chunk = None;
+            }
+            Some(Some(engine)) => sources.push(engine, line),
+            Some(None) => document.line(line),
+            None if !in_fence && trimmed.starts_with("```{") => {
+                document.line(line);
+                chunk = Some(chunk_engine(&trimmed[4..]));
+            }
+            None => {
+                if trimmed.starts_with("```") {
+                    in_fence = !in_fence;
+                }
+                sources.push(markdown(), line);
+            }
+        }
+    }
+
+    document.finish(sources.count(path, config)?)
+}
+
+/// The language of a chunk header such as `{r setup, echo=FALSE}` or `{python}`.
+fn chunk_engine(header: &str) -> Option<&'static Language> {
+    let engine = header
+        .split(|c: char| c == '}' || c == ',' || c.is_whitespace())
+        .next()?;
+    find_language_by_alias(engine)
+}
+
+fn notebook(path: &Path, text: &str, config: &CountConfig) -> io::Result<FileStats> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let notebook: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| invalid(format!("invalid notebook: {}", e)))?;
+
+    let metadata = &notebook["metadata"];
+    let kernel = metadata["kernelspec"]["language"]
+        .as_str()
+        .or_else(|| metadata["language_info"]["name"].as_str())
+        .and_then(find_language_by_alias)
+        .or_else(|| get_language(DEFAULT_KERNEL));
+
+    let cells = notebook["cells"]
+        .as_array()
+        .ok_or_else(|| invalid("invalid notebook: missing 'cells'".to_string()))?;
+
+    let mut document = Document::default();
+    let mut sources = Sources::default();
+    for cell in cells {
+        let source = match &cell["source"] {
+            serde_json::Value::String(source) => source.clone(),
+            serde_json::Value::Array(lines) => lines.iter().filter_map(|l| l.as_str()).collect(),
+            _ => String::new(),
+        };
+        let language = match cell["cell_type"].as_str() {
+            Some("code") => kernel,
+            Some("markdown") => Some(markdown()),
+            _ => None,
+        };
+        for line in source.lines() {
+            match language {
+                Some(language) => sources.push(language, line),
+                None => document.line(line),
+            }
+        }
+    }
+
+    document.finish(sources.count(path, config)?)
+}
+
+/// Where a PHP file scan is at the end of a line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Php {
+    Html,
+    Code,
+    String(char),
+    BlockComment,
+}
+
+fn php(
+    path: &Path,
+    text: &str,
+    language: &Language,
+    config: &CountConfig,
+) -> io::Result<FileStats> {
+    let mut code = String::new();
+    let mut html = String::new();
+    let mut mode = Php::Html;
+
+    for (index, line) in text.lines().enumerate() {
+        if index == 0 && line.starts_with("#!") {
+            push_line(&mut code, line);
+            continue;
+        }
+
+        let mut php_part = String::new();
+        let mut html_part = String::new();
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            let mut len = c.len_utf8();
+            match mode {
+                Php::Html => match php_open_tag(rest) {
+                    Some(tag) => {
+                        len = tag;
+                        mode = Php::Code;
+                    }
+                    None => html_part.push(c),
+                },
+                Php::Code if rest.starts_with("?>") => {
+                    len = 2;
+                    mode = Php::Html;
+                }
+                // A line comment ends at the line end or at `?>`.
+                Php::Code if rest.starts_with("//") || c == '#' => {
+                    len = rest.find("?>").unwrap_or(rest.len());
+                }
+                Php::Code if rest.starts_with("/*") => {
+                    len = 2;
+                    mode = Php::BlockComment;
+                }
+                Php::Code if matches!(c, '\'' | '"' | '`') => mode = Php::String(c),
+                Php::Code => {}
+                Php::String(_) if c == '\\' => {
+                    len += rest[len..].chars().next().map_or(0, char::len_utf8);
+                }
+                Php::String(quote) if c == quote => mode = Php::Code,
+                Php::String(_) => {}
+                Php::BlockComment if rest.starts_with("*/") => {
+                    len = 2;
+                    mode = Php::Code;
+                }
+                Php::BlockComment => {}
+            }
+            if mode != Php::Html || len > c.len_utf8() {
+                php_part.push_str(&rest[..len]);
+            }
+            rest = &rest[len..];
+        }
+
+        if php_part.trim().is_empty() && !html_part.trim().is_empty() {
+            push_line(&mut html, &html_part);
+        } else {
+            push_line(&mut code, &php_part);
+        }
+    }
+
+    let mut stats = counter::classify_source(path, code.as_bytes(), language, config)?;
+    if !html.is_empty() {
+        let html_language = get_language("HTML").expect("HTML is a built-in language");
+        stats.embedded = vec![counter::classify_source(
+            path,
+            html.as_bytes(),
+            html_language,
+            config,
+        )?];
+    }
+    Ok(stats)
+}
+
+/// Length of the PHP open tag (`<?php`, `<?=`, `<?`) starting `text`, if any. `<?xml` is
+/// not one.
+fn php_open_tag(text: &str) -> Option<usize> {
+    let rest = text.strip_prefix("<?")?;
+    if rest
+        .get(..3)
+        .is_some_and(|tag| tag.eq_ignore_ascii_case("php"))
+    {
+        Some(5)
+    } else if rest.starts_with('=') {
+        Some(3)
+    } else if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn markdown() -> &'static Language {
+    get_language("Markdown").expect("Markdown is a built-in language")
+}
+
+fn push_line(buffer: &mut String, line: &str) {
+    buffer.push_str(line);
+    buffer.push('\n');
+}
+
+/// Lines belonging to the format itself; every non-blank one is code.
+#[derive(Debug, Default)]
+struct Document {
+    code: u64,
+    blanks: u64,
+}
+
+impl Document {
+    fn line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            self.blanks += 1;
+        } else {
+            self.code += 1;
+        }
+    }
+
+    fn finish(self, embedded: Vec<FileStats>) -> io::Result<FileStats> {
+        Ok(FileStats {
+            code: self.code,
+            blanks: self.blanks,
+            embedded,
+            ..Default::default()
+        })
+    }
+}
+
+/// Embedded source collected per language, in a stable order.
+#[derive(Debug, Default)]
+struct Sources {
+    by_language: BTreeMap<&'static str, (&'static Language, String)>,
+}
+
+impl Sources {
+    fn push(&mut self, language: &'static Language, line: &str) {
+        let (_, buffer) = self
+            .by_language
+            .entry(language.name)
+            .or_insert_with(|| (language, String::new()));
+        push_line(buffer, line);
+    }
+
+    fn count(self, path: &Path, config: &CountConfig) -> io::Result<Vec<FileStats>> {
+        let mut embedded = Vec::new();
+        for (language, source) in self.by_language.into_values() {
+            let stats = counter::classify_source(path, source.as_bytes(), language, config)?;
+            if stats.total() > 0 {
+                embedded.push(stats);
+            }
+        }
+        Ok(embedded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_as(language: &str, source: &str) -> FileStats {
+        let language = get_language(language).unwrap();
+        count(
+            Path::new("doc"),
+            source.as_bytes(),
+            language,
+            &CountConfig::default(),
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    fn lines(stats: &FileStats) -> (&str, u64, u64, u64) {
+        (&stats.language, stats.code, stats.comments, stats.blanks)
+    }
+
+    #[test]
+    fn test_literate_haskell() {
+        let bird = "Prose about main.\n\n> main :: IO ()\n> main = putStrLn \"hi\" -- greet\n>\n\\begin{code}\n{- block -}\nf = 1\n\\end{code}\n";
+        let stats = count_as("Literate Haskell", bird);
+        assert_eq!(lines(&stats), ("Literate Haskell", 3, 4, 2));
+        assert!(stats.embedded.is_empty());
+    }
+
+    #[test]
+    fn test_r_markdown() {
+        let rmd = "---\ntitle: Report\n---\n\n# Results\n\n```{r setup, echo=FALSE}\nx <- 1 # one\n# note\n```\n\n```{python}\nprint(1)\n```\n\n```\nnot {r} code\n```\n";
+        let stats = count_as("R Markdown", rmd);
+        assert_eq!(lines(&stats), ("R Markdown", 7, 0, 0));
+        let embedded: Vec<_> = stats.embedded.iter().map(lines).collect();
+        assert_eq!(
+            embedded,
+            [("Markdown", 4, 0, 4), ("Python", 1, 0, 0), ("R", 1, 1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_notebook() {
+        let ipynb = r##"{
+            "metadata": {"kernelspec": {"language": "python"}},
+            "cells": [
+                {"cell_type": "markdown", "source": ["# Title\n", "\n", "Text"]},
+                {"cell_type": "code", "source": ["import os\n", "# comment\n", "os.getcwd()"]},
+                {"cell_type": "raw", "source": "raw text"}
+            ]
+        }"##;
+        let stats = count_as("Jupyter Notebook", ipynb);
+        assert_eq!(lines(&stats), ("Jupyter Notebook", 1, 0, 0));
+        let embedded: Vec<_> = stats.embedded.iter().map(lines).collect();
+        assert_eq!(embedded, [("Markdown", 2, 0, 1), ("Python", 2, 1, 0)]);
+
+        let error = count(
+            Path::new("bad.ipynb"),
+            b"{",
+            get_language("Jupyter Notebook").unwrap(),
+            &CountConfig::default(),
+        )
+        .unwrap()
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_php_template() {
+        let php = "<html>\n<!-- page -->\n<?php\n// greet\n$name = \"?>\"; # not closed\n?>\n\n<p><?= $name ?></p>\n<?xml version=\"1.0\"?>\n";
+        let stats = count_as("PHP", php);
+        assert_eq!(lines(&stats), ("PHP", 4, 1, 1));
+        let embedded: Vec<_> = stats.embedded.iter().map(lines).collect();
+        assert_eq!(embedded, [("HTML", 2, 1, 0)]);
+
+        let plain = count_as("PHP", "<?php\necho 1; // one\n");
+        assert_eq!(lines(&plain), ("PHP", 2, 0, 0));
+        assert!(plain.embedded.is_empty());
+    }
+}
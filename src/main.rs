@@ -1,53 +1,195 @@
-use clap::Parser;
+use clap::CommandFactory;
 use dashmap::DashSet;
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
-use rayon::prelude::*;
-use rloc::cli::Cli;
+use indicatif::{ProgressBar, ProgressStyle};
+use rloc::cli::{Cli, Command, HookCommand, LogLevel, SnapshotCommand, TrendCommand};
+use rloc::counter::{CountConfig, FileStats, TestMode};
 use rloc::diff;
 use rloc::output::{self, OutputFormat, render};
+use rloc::stats::{JsonOutput, ReportDelta, SkipReason, SkippedFiles, Warning};
 use rloc::strip::{self, StripMode};
+use rloc::walker::FileEntry;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::sync::Mutex;
 use std::time::Instant;
 
 fn main() -> ExitCode {
-    match run() {
+    // Usage errors exit with 1 like any other error; 2 means "no source files".
+    let cli = match Cli::try_parse_args_from(std::env::args_os()) {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    init_logging(cli.log_level);
+
+    match run(cli) {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
             eprintln!("error: {}", e);
-            ExitCode::FAILURE
+            let code = e
+                .downcast_ref::<rloc::Error>()
+                .map_or(1, rloc::Error::exit_code);
+            ExitCode::from(code)
         }
     }
 }
 
-fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+/// Send `tracing` events to stderr at `--log-level`, or as filtered by `RLOC_LOG`
+/// (`debug`, `rloc::walker=trace`, ...). Without either, nothing is logged.
+fn init_logging(level: Option<LogLevel>) {
+    use clap::ValueEnum;
+    use tracing_subscriber::EnvFilter;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    let filter = match level {
+        Some(level) => {
+            let name = level
+                .to_possible_value()
+                .map(|value| value.get_name().to_string());
+            EnvFilter::new(name.unwrap_or_default())
+        }
+        None => match std::env::var("RLOC_LOG") {
+            Ok(directives) if !directives.trim().is_empty() => {
+                match EnvFilter::try_new(&directives) {
+                    Ok(filter) => filter,
+                    Err(e) => {
+                        eprintln!("warning: invalid RLOC_LOG '{}': {}", directives, e);
+                        return;
+                    }
+                }
+            }
+            _ => return,
+        },
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        // Closing a span logs its duration, timing each phase.
+        .with_span_events(FmtSpan::CLOSE)
+        .init();
+}
+
+fn run(mut cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    // Generated from the argument definitions alone, so a broken config file can't stop them.
+    match cli.command {
+        Some(Command::Completions(ref completions)) => {
+            let mut command = Cli::command();
+            // `generate` panics on write errors, so the script is written out here instead.
+            let mut script = Vec::new();
+            clap_complete::generate(completions.shell, &mut command, "rloc", &mut script);
+            let mut out = io::stdout().lock();
+            ignore_broken_pipe(out.write_all(&script).and_then(|()| out.flush()))?;
+            return Ok(());
+        }
+        Some(Command::Manpage(ref manpage)) => return write_manpage(manpage),
+        _ => {}
+    }
+    match cli.command {
+        Some(Command::Check(ref check)) if !check.paths.is_empty() => {
+            cli.paths = check.paths.clone();
+        }
+        Some(Command::Trend(TrendCommand::Record(ref record))) if !record.paths.is_empty() => {
+            cli.paths = record.paths.clone();
+        }
+        Some(Command::Snapshot(ref snapshot)) if !snapshot.args().paths.is_empty() => {
+            cli.paths = snapshot.args().paths.clone();
+        }
+        Some(Command::Hook(HookCommand::Run(_))) => cli.staged = true,
+        Some(Command::Explain(ref explain)) if !explain.paths.is_empty() => {
+            cli.paths = explain.paths.clone();
+        }
+        Some(Command::Bench(ref bench)) if !bench.paths.is_empty() => {
+            cli.paths = bench.paths.clone();
+        }
+        _ => {}
+    }
+    cli.load_config_file()?;
+    cli.load_custom_languages()?;
+    cli.check_output_options()?;
+
+    if cli.stdio_rpc {
+        let session = rloc::rpc::RpcSession::new(cli.to_walker_config()?, cli.to_count_config()?);
+        session.run(io::stdin().lock(), io::stdout())?;
+        return Ok(());
+    }
+
+    match cli.command {
+        Some(Command::Trend(TrendCommand::Show(ref show))) => return show_trend(&cli, show),
+        Some(Command::Serve(ref serve)) => return run_serve(&cli, serve),
+        Some(Command::Annotate(ref annotate)) => return annotate_file(&cli, annotate),
+        Some(Command::Explain(ref explain)) => return explain_file(&cli, explain),
+        Some(Command::Bench(ref bench)) => return run_bench(&cli, bench),
+        Some(Command::Diff(ref diff)) => {
+            return run_diff(&cli, vec![diff.old.clone()], &diff.new);
+        }
+        Some(Command::Hook(HookCommand::Install(ref install))) => {
+            return install_hook(&cli, install);
+        }
+        _ => {}
+    }
 
     if cli.show_lang {
-        rloc::cli::show_languages();
+        rloc::cli::show_languages(cli.custom_languages.as_deref());
         return Ok(());
     }
 
     if cli.show_ext {
-        rloc::cli::show_extensions();
+        rloc::cli::show_extensions(cli.custom_languages.as_deref());
         return Ok(());
     }
 
-    if let Some(ref path) = cli.read_lang_def {
-        rloc::custom_langs::CustomLanguages::load(path)?;
+    if cli.show_unrecognized {
+        let walker_config = cli.to_walker_config()?;
+        let mut unrecognized = Vec::new();
+        rloc::walker::walk_files_reporting(
+            &walker_config,
+            |_| {},
+            |path, reason| {
+                if reason == SkipReason::Unrecognized {
+                    unrecognized.push(path);
+                }
+            },
+        );
+        rloc::cli::show_unrecognized(&unrecognized);
+        return Ok(());
+    }
+
+    if cli.list_only {
+        return list_files(&cli);
     }
 
     if !cli.sum_reports.is_empty() {
         return sum_reports(&cli);
     }
 
+    if !cli.diff_report.is_empty() {
+        return diff_reports(&cli);
+    }
+
     if cli.strip_comments.is_some() || cli.strip_code.is_some() {
         return run_strip(&cli);
     }
 
-    if let Some(ref diff_path) = cli.diff {
-        return run_diff(&cli, diff_path);
+    if let Some((old, new)) = cli.diff_sides() {
+        return run_diff(&cli, old, &new);
+    }
+
+    if cli.reads_tar_stdin()? {
+        return run_tar_stdin(&cli);
+    }
+
+    if cli.reads_stdin() {
+        return run_stdin(&cli);
     }
 
     if cli.threads > 0 {
@@ -57,8 +199,14 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
             .ok();
     }
 
+    let limiter = rloc::cancel::Limiter::new(cli.max_files, cli.timeout, None);
     let mut walker_config = cli.to_walker_config()?;
+    walker_config.cancel = Some(limiter.walk_token());
     let output_config = cli.to_output_config();
+    let count_config = rloc::counter::CountConfig {
+        cancel: Some(limiter.count_token()),
+        ..cli.to_count_config()?
+    };
 
     let start = Instant::now();
 
@@ -71,8 +219,9 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
             if path.is_file() && rloc::archive::is_archive(path) {
                 let archive_dest = temp.join(path.file_stem().unwrap_or_default());
                 std::fs::create_dir_all(&archive_dest)?;
-                if rloc::archive::extract_archive(path, &archive_dest).is_ok() {
-                    extra_paths.push(archive_dest);
+                match rloc::archive::extract_archive(path, &archive_dest) {
+                    Ok(_) => extra_paths.push(archive_dest),
+                    Err(e) => eprintln!("warning: {}: {}", path.display(), e),
                 }
             }
         }
@@ -83,85 +232,208 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
-    let files = rloc::walker::walk_files(&walker_config);
-
-    if files.is_empty() {
-        if !cli.quiet {
-            eprintln!("No source files found.");
-        }
-        return Ok(());
-    }
-
-    let file_count = files.len();
     let skip_uniqueness = walker_config.skip_uniqueness;
     let seen_hashes: DashSet<u64> = DashSet::new();
 
-    let progress = if cli.quiet || output_config.format != OutputFormat::Table {
+    let progress = if cli.quiet
+        || output_config.format != OutputFormat::Table
+        || output_config.template.is_some()
+    {
         ProgressBar::hidden()
     } else {
-        let pb = ProgressBar::new(file_count as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({per_sec})")
-                .unwrap()
-                .progress_chars("=>-"),
-        );
-        pb
+        // The length grows as the walk discovers files, so it is a running estimate.
+        new_progress_bar()
     };
 
-    let file_stats: Vec<_> = files
-        .into_par_iter()
-        .progress_with(progress.clone())
-        .filter_map(|entry| {
-            if !skip_uniqueness {
-                if let Ok(hash) = rloc::counter::compute_file_hash(&entry.path) {
-                    if !seen_hashes.insert(hash) {
-                        return None;
+    // NDJSON is streamed: each file is written as soon as it has been counted.
+    let stream: Option<Mutex<Box<dyn Write + Send>>> =
+        if output_config.format == OutputFormat::Ndjson && output_config.template.is_none() {
+            let writer: Box<dyn Write + Send> = match cli.output_path() {
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout()),
+            };
+            Some(Mutex::new(writer))
+        } else {
+            None
+        };
+
+    // The first failed NDJSON write; counting stops once the output is gone.
+    let stream_error: Mutex<Option<io::Error>> = Mutex::new(None);
+
+    let mut skipped = SkippedFiles::default();
+    let warnings = Mutex::new(Vec::new());
+    let warn = |path: &Path, error: &io::Error| {
+        if let Ok(mut warnings) = warnings.lock() {
+            warnings.push(Warning::from_io(path, error));
+        }
+    };
+    let (discovered, results) = limiter.run(|| {
+        rloc::walker::walk_files_parallel(
+            &walker_config,
+            |_| progress.inc_length(1),
+            |_, reason| skipped.record(reason),
+            |entry| {
+                progress.inc(1);
+                if !limiter.admit() || stream_error.lock().is_ok_and(|error| error.is_some()) {
+                    return None;
+                }
+
+                let source = match rloc::walker::read_file(&walker_config, &entry.path) {
+                    Ok(source) => source,
+                    Err(e) => {
+                        warn(&entry.path, &e);
+                        return Some(Err(SkipReason::Unreadable));
                     }
+                };
+                if !skip_uniqueness && !seen_hashes.insert(rloc::counter::hash_bytes(&source)) {
+                    return None;
                 }
-            }
 
-            match rloc::counter::count_lines(&entry.path, entry.language) {
-                Ok(stats) if stats.total() > 0 => Some(stats),
-                Ok(_) => None,
-                Err(e) => {
-                    if cli.verbose > 0 {
-                        eprintln!("warning: {}: {}", entry.path.display(), e);
+                match rloc::counter::count_bytes(
+                    &entry.path,
+                    &source,
+                    entry.language,
+                    &count_config,
+                ) {
+                    Ok(stats) => {
+                        let kept = keep_counted(&cli, &count_config, &entry, stats);
+                        if let (Some(stream), Some(Ok(stats))) = (&stream, &kept) {
+                            if let Ok(mut writer) = stream.lock() {
+                                if let Err(e) = output::write_ndjson_line(stats, &mut *writer) {
+                                    if let Ok(mut error) = stream_error.lock() {
+                                        error.get_or_insert(e);
+                                    }
+                                }
+                            }
+                        }
+                        kept
+                    }
+                    // Interrupted mid-file by `--timeout`.
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted && limiter.truncated() => None,
+                    Err(e) => {
+                        warn(&entry.path, &e);
+                        Some(Err(SkipReason::Unreadable))
                     }
-                    None
                 }
-            }
-        })
-        .collect();
+            },
+        )
+    });
+    let truncated = limiter.truncated();
+    if let Some(e) = stream_error.into_inner().map_err(|e| e.to_string())? {
+        progress.finish_and_clear();
+        return Ok(ignore_broken_pipe(Err(e))?);
+    }
+
+    if discovered == 0 && !truncated && cli.command.is_none() {
+        progress.finish_and_clear();
+        if cli.fail_on_empty {
+            return Err(rloc::Error::NoSourceFiles.into());
+        }
+        if !cli.quiet {
+            eprintln!("No source files found.");
+        }
+        return Ok(());
+    }
 
     progress.finish_and_clear();
 
+    let mut file_stats = Vec::with_capacity(results.len());
+    for result in results {
+        match result {
+            Ok(stats) => file_stats.push(stats),
+            Err(reason) => skipped.record(reason),
+        }
+    }
+
     let elapsed = start.elapsed();
-    let summary = rloc::stats::Summary::from_file_stats(file_stats).with_elapsed(elapsed);
+    let summary = rloc::stats::Summary::from_file_stats(file_stats)
+        .with_skipped(skipped)
+        .with_warnings(warnings.into_inner().unwrap_or_default())
+        .with_truncated(truncated)
+        .with_elapsed(elapsed);
 
-    if let Some(output_path) = cli.output_path() {
-        let file = File::create(output_path)?;
-        let mut writer = BufWriter::new(file);
-        render_to_writer(&summary, &output_config, &mut writer)?;
-        writer.flush()?;
-    } else {
-        render(&summary, &output_config)?;
+    if cli.verbose > 0 {
+        for warning in &summary.warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    if truncated && !cli.quiet {
+        eprintln!("warning: stopped early at --max-files or --timeout; results are partial");
     }
 
     if let Some(temp) = temp_dir {
         let _ = std::fs::remove_dir_all(temp);
     }
 
+    if cli.fail_on_empty && summary.total_files == 0 {
+        return Err(rloc::Error::NoSourceFiles.into());
+    }
+
+    match cli.command {
+        Some(Command::Check(ref check)) => return run_check(check, &summary),
+        Some(Command::Trend(TrendCommand::Record(ref record))) => {
+            return record_trend(&cli, record, &walker_config.paths, &summary);
+        }
+        Some(Command::Snapshot(ref snapshot)) => return run_snapshot(&cli, snapshot, &summary),
+        Some(Command::Hook(HookCommand::Run(ref budget))) => {
+            return run_hook(&cli, budget, &walker_config, &summary);
+        }
+        _ => {}
+    }
+
+    if let Some(stream) = stream {
+        let mut writer = stream.into_inner().map_err(|e| e.to_string())?;
+        ignore_broken_pipe(writer.flush())?;
+    } else if cli.churn {
+        write_hotspots(&cli, &walker_config.paths, &summary)?;
+    } else if cli.by_project {
+        write_projects(&cli, &walker_config.paths, &summary)?;
+    } else if cli.group_by_path || cli.nested_repos {
+        let mut roots = walker_config.paths.clone();
+        if cli.nested_repos {
+            let files = summary.file_stats.iter().map(|file| Path::new(&file.path));
+            roots.extend(rloc::walker::nested_repos(files, &walker_config.paths));
+        }
+        write_groups(&cli, &roots, &summary)?;
+    } else {
+        write_summary(&cli, &summary)?;
+    }
+
     Ok(())
 }
 
+/// `result`, with a closed pipe (`rloc ... | head`) treated as success: the reader has all
+/// the output it wants.
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Progress bar for counting files; callers set or grow its length.
+fn new_progress_bar() -> ProgressBar {
+    let pb = ProgressBar::new(0);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({per_sec})",
+            )
+            .unwrap()
+            .progress_chars("=>-"),
+    );
+    pb
+}
+
 fn render_to_writer(
     summary: &rloc::stats::Summary,
     config: &output::OutputConfig,
     out: &mut impl Write,
 ) -> io::Result<()> {
     match config.format {
-        OutputFormat::Table => {
+        OutputFormat::Table if config.template.is_none() => {
+            let summary = output::prepare_summary(summary, config);
+            let label = output::row_label(config);
             if !config.hide_rate {
                 if let Some(elapsed) = summary.elapsed {
                     writeln!(out)?;
@@ -178,10 +450,15 @@ fn render_to_writer(
                     }
                     writeln!(out)?;
                 }
+                output::write_skipped(&summary, out)?;
             }
 
             writeln!(out)?;
-            writeln!(out, "Language       Files    Blank  Comment     Code")?;
+            writeln!(
+                out,
+                "{:<14} {:>5} {:>8} {:>8} {:>8}",
+                label, "Files", "Blank", "Comment", "Code"
+            )?;
             writeln!(out, "─────────────────────────────────────────────────")?;
 
             for lang in &summary.languages {
@@ -204,150 +481,552 @@ fn render_to_writer(
             )?;
             Ok(())
         }
-        OutputFormat::Json => {
-            let output = rloc::stats::JsonOutput::from(summary);
-            let json = serde_json::to_string_pretty(&output).map_err(io::Error::other)?;
-            writeln!(out, "{}", json)
-        }
-        OutputFormat::Csv => {
-            let mut writer = csv::Writer::from_writer(out);
-            writer.write_record(["Language", "Files", "Blank", "Comment", "Code"])?;
-            for lang in &summary.languages {
-                writer.write_record([
-                    &lang.name,
-                    &lang.files.to_string(),
-                    &lang.blanks.to_string(),
-                    &lang.comments.to_string(),
-                    &lang.code.to_string(),
-                ])?;
+        _ => output::render_to(summary, config, out),
+    }
+}
+
+/// What becomes of a counted file: a skip if it is binary, `None` if the options leave it
+/// out (generated, minified or empty), otherwise its stats marked as vendored or test code
+/// per `entry`.
+fn keep_counted(
+    cli: &Cli,
+    count_config: &CountConfig,
+    entry: &FileEntry,
+    mut stats: FileStats,
+) -> Option<Result<FileStats, SkipReason>> {
+    if stats.binary {
+        return Some(Err(SkipReason::Binary));
+    }
+    if (stats.generated && cli.no_generated)
+        || (stats.minified && cli.skip_minified)
+        || (stats.is_empty() && !stats.is_ignored())
+    {
+        return None;
+    }
+    if entry.test && count_config.tests == TestMode::Split {
+        stats.mark_as_test();
+    }
+    Some(Ok(FileStats {
+        vendored: entry.vendored,
+        ..stats
+    }))
+}
+
+/// Count the files of a tar archive read from stdin (`git archive HEAD | rloc --stdin-format
+/// tar`) without writing them to disk.
+fn run_tar_stdin(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let limiter = rloc::cancel::Limiter::new(cli.max_files, cli.timeout, None);
+    let walker_config = cli.to_walker_config()?;
+    let count_config = CountConfig {
+        cancel: Some(limiter.count_token()),
+        ..cli.to_count_config()?
+    };
+    let filter = rloc::walker::EntryFilter::new(&walker_config);
+    let walk_token = limiter.walk_token();
+    let limits = rloc::archive::ExtractLimits {
+        max_file_bytes: walker_config.max_file_size.map(|mb| mb * 1024 * 1024),
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let mut seen_hashes = HashSet::new();
+    let mut skipped = SkippedFiles::default();
+    let mut warnings = Vec::new();
+    let mut file_stats = Vec::new();
+    limiter.run(|| {
+        rloc::archive::read_tar_stream(io::stdin().lock(), &limits, |path, content| {
+            if walk_token.is_cancelled() {
+                return ControlFlow::Break(());
             }
-            writer.write_record([
-                "SUM",
-                &summary.total_files.to_string(),
-                &summary.total_blanks.to_string(),
-                &summary.total_comments.to_string(),
-                &summary.total_code.to_string(),
-            ])?;
-            writer.flush()?;
-            Ok(())
+            let entry = match filter.check(path.to_path_buf(), &content) {
+                Some(Ok(entry)) => entry,
+                Some(Err(reason)) => {
+                    skipped.record(reason);
+                    return ControlFlow::Continue(());
+                }
+                None => return ControlFlow::Continue(()),
+            };
+            if !limiter.admit() {
+                return ControlFlow::Break(());
+            }
+            if !walker_config.skip_uniqueness
+                && !seen_hashes.insert(rloc::counter::hash_bytes(&content))
+            {
+                return ControlFlow::Continue(());
+            }
+
+            match rloc::counter::count_bytes(&entry.path, &content, entry.language, &count_config) {
+                Ok(stats) => match keep_counted(cli, &count_config, &entry, stats) {
+                    Some(Ok(stats)) => file_stats.push(stats),
+                    Some(Err(reason)) => skipped.record(reason),
+                    None => {}
+                },
+                // Interrupted mid-file by `--timeout`.
+                Err(e) if e.kind() == io::ErrorKind::Interrupted && limiter.truncated() => {
+                    return ControlFlow::Break(());
+                }
+                Err(e) => {
+                    warnings.push(Warning::from_io(&entry.path, &e));
+                    skipped.record(SkipReason::Unreadable);
+                }
+            }
+            ControlFlow::Continue(())
+        })
+    })?;
+
+    let truncated = limiter.truncated();
+    if cli.fail_on_empty && file_stats.is_empty() && !truncated {
+        return Err(rloc::Error::NoSourceFiles.into());
+    }
+    let summary = rloc::stats::Summary::from_file_stats(file_stats)
+        .with_skipped(skipped)
+        .with_warnings(warnings)
+        .with_truncated(truncated)
+        .with_elapsed(start.elapsed());
+
+    if cli.verbose > 0 {
+        for warning in &summary.warnings {
+            eprintln!("warning: {}", warning);
         }
-        OutputFormat::Yaml => {
-            let output = rloc::stats::JsonOutput::from(summary);
-            let yaml = serde_yaml::to_string(&output).map_err(io::Error::other)?;
-            write!(out, "{}", yaml)
+    }
+    if truncated && !cli.quiet {
+        eprintln!("warning: stopped early at --max-files or --timeout; results are partial");
+    }
+    write_summary(cli, &summary)
+}
+
+/// Count a single file read from stdin (`rloc - --stdin-name NAME`).
+fn run_stdin(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let language = cli.stdin_language()?;
+    let count_config = cli.to_count_config()?;
+    let name = cli.stdin_name.as_deref().unwrap_or(Path::new("-"));
+
+    let start = Instant::now();
+    let stats = rloc::counter::count_reader(name, io::stdin().lock(), language, &count_config)?;
+    let files = if !stats.is_empty() || stats.is_ignored() {
+        vec![stats]
+    } else if cli.fail_on_empty {
+        return Err(rloc::Error::NoSourceFiles.into());
+    } else {
+        vec![]
+    };
+    let summary = rloc::stats::Summary::from_file_stats(files).with_elapsed(start.elapsed());
+    write_summary(cli, &summary)
+}
+
+/// Read a saved JSON report (rloc or cloc format); `-` reads stdin.
+fn read_report(path: &Path) -> Result<JsonOutput, String> {
+    let content = if path == Path::new("-") {
+        io::read_to_string(io::stdin()).map_err(|e| format!("Failed to read stdin: {}", e))?
+    } else {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+    };
+    rloc::report::Report::from_json(&content)
+        .map(JsonOutput::from)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+fn sum_reports(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let reports = cli
+        .sum_reports
+        .iter()
+        .map(|path| read_report(path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let combined = JsonOutput::sum_reports(reports);
+    write_summary(cli, &rloc::stats::Summary::from(&combined))
+}
+
+fn run_check(
+    check: &rloc::cli::CheckArgs,
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let baseline = match check.fail_on_growth {
+        Some(ref path) => Some(rloc::stats::Summary::from(&read_report(path)?)),
+        None => None,
+    };
+    let results = check.to_budget(baseline).check(summary);
+    for result in &results {
+        println!("{}", result);
+    }
+
+    rloc::budget::ensure_passed(&results)?;
+    Ok(())
+}
+
+/// Write the pre-commit hook (`rloc hook install`), or print the pre-commit framework entry.
+fn install_hook(
+    cli: &Cli,
+    install: &rloc::cli::HookInstallArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let args = install.budget.to_args();
+    if install.pre_commit_config {
+        print!("{}", rloc::hook::pre_commit_config(&args));
+        return Ok(());
+    }
+    let path = rloc::hook::install(Path::new("."), &rloc::hook::script(&args), install.force)?;
+    if !cli.quiet {
+        eprintln!("Installed {}", path.display());
+    }
+    Ok(())
+}
+
+/// Check the staged files, as in the index, against the hook's budgets (`rloc hook run`). For
+/// `--max-added` the same files are counted again as of `HEAD`; files new in this commit count
+/// as empty.
+fn run_hook(
+    cli: &Cli,
+    budget: &rloc::cli::HookBudgetArgs,
+    walker_config: &rloc::walker::WalkerConfig,
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let head = match budget.max_added {
+        Some(_) => {
+            let count_config = cli.to_count_config()?;
+            let files = summary
+                .file_stats
+                .iter()
+                .filter_map(|file| {
+                    let path = Path::new(&file.path);
+                    let content = rloc::hook::head_content(path)?;
+                    let language = rloc::walker::detect_path_language(walker_config, path)?;
+                    rloc::counter::count_bytes(path, &content, language, &count_config).ok()
+                })
+                .collect();
+            Some(rloc::stats::Summary::from_file_stats(files))
         }
-        OutputFormat::Markdown => {
-            writeln!(out, "| Language | Files | Blank | Comment | Code |")?;
-            writeln!(out, "|----------|------:|------:|--------:|-----:|")?;
-            for lang in &summary.languages {
-                writeln!(
-                    out,
-                    "| {} | {} | {} | {} | {} |",
-                    lang.name, lang.files, lang.blanks, lang.comments, lang.code
-                )?;
-            }
-            writeln!(
-                out,
-                "| **SUM** | **{}** | **{}** | **{}** | **{}** |",
-                summary.total_files,
-                summary.total_blanks,
-                summary.total_comments,
-                summary.total_code
-            )
+        None => None,
+    };
+
+    let results = budget.to_budget(head).check(summary);
+    for result in &results {
+        println!("{}", result);
+    }
+    rloc::budget::ensure_passed(&results)?;
+    Ok(())
+}
+
+/// Append this run to the trend store (`rloc trend record`).
+fn record_trend(
+    cli: &Cli,
+    record: &rloc::cli::TrendRecordArgs,
+    roots: &[std::path::PathBuf],
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let commit = roots
+        .first()
+        .and_then(|root| rloc::trend::head_commit(root));
+    let entry = rloc::trend::TrendEntry::new(summary, commit);
+    rloc::trend::append(&record.store, &entry)
+        .map_err(|e| format!("{}: {}", record.store.display(), e))?;
+    if !cli.quiet {
+        eprintln!(
+            "Recorded {} lines of code in {} files to {}",
+            summary.total_code,
+            summary.total_files,
+            record.store.display()
+        );
+    }
+    Ok(())
+}
+
+/// Save a snapshot of `summary` (`rloc snapshot save`), or render what changed since one
+/// (`rloc snapshot compare`), to `--out` or stdout.
+fn run_snapshot(
+    cli: &Cli,
+    command: &SnapshotCommand,
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = &command.args().file;
+    let current = rloc::snapshot::Snapshot::new(summary);
+    if let SnapshotCommand::Save(_) = command {
+        current
+            .save(file)
+            .map_err(|e| format!("{}: {}", file.display(), e))?;
+        if !cli.quiet {
+            eprintln!(
+                "Saved {} files with {} lines of code to {}",
+                current.files.len(),
+                summary.total_code,
+                file.display()
+            );
         }
-        OutputFormat::Sql => {
-            writeln!(out, "CREATE TABLE t (")?;
-            writeln!(out, "    Language TEXT,")?;
-            writeln!(out, "    nFiles INTEGER,")?;
-            writeln!(out, "    nBlank INTEGER,")?;
-            writeln!(out, "    nComment INTEGER,")?;
-            writeln!(out, "    nCode INTEGER")?;
-            writeln!(out, ");")?;
-            writeln!(out)?;
+        return Ok(());
+    }
 
-            for lang in &summary.languages {
-                writeln!(
-                    out,
-                    "INSERT INTO t VALUES ('{}', {}, {}, {}, {});",
-                    lang.name.replace('\'', "''"),
-                    lang.files,
-                    lang.blanks,
-                    lang.comments,
-                    lang.code
-                )?;
-            }
+    let saved =
+        rloc::snapshot::Snapshot::load(file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let diff = saved.compare(&current);
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_snapshot_diff_to(&diff, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_snapshot_diff_to(&diff, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
 
-            writeln!(
-                out,
-                "INSERT INTO t VALUES ('SUM', {}, {}, {}, {});",
-                summary.total_files,
-                summary.total_blanks,
-                summary.total_comments,
-                summary.total_code
-            )
+/// Render the recorded runs (`rloc trend show`), to `--out` or stdout.
+fn show_trend(
+    cli: &Cli,
+    show: &rloc::cli::TrendShowArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries =
+        rloc::trend::load(&show.store).map_err(|e| format!("{}: {}", show.store.display(), e))?;
+    if entries.is_empty() {
+        return Err(format!("{}: no runs recorded yet", show.store.display()).into());
+    }
+    if let Some(last) = show.last {
+        entries.drain(..entries.len().saturating_sub(last));
+    }
+
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_trend_to(&entries, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_trend_to(&entries, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Print one file with the classification of each line (`rloc annotate`).
+fn annotate_file(
+    cli: &Cli,
+    annotate: &rloc::cli::AnnotateArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = &annotate.file;
+    let language = cli.file_language(path)?;
+    let content = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let lines = rloc::counter::classify_bytes(path, &content, language, &cli.to_count_config()?)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_annotation_to(
+            path,
+            language,
+            &content,
+            &lines,
+            &output_config,
+            &mut writer,
+        )?;
+        writer.flush()?;
+    } else {
+        let mut out = io::stdout().lock();
+        output::render_annotation_to(path, language, &content, &lines, &output_config, &mut out)?;
+    }
+    Ok(())
+}
+
+/// Print the walker's and counter's decisions for one file (`rloc explain`).
+fn explain_file(
+    cli: &Cli,
+    explain: &rloc::cli::ExplainArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let walker_config = cli.to_walker_config()?;
+    let count_config = cli.to_count_config()?;
+    let explanation = rloc::explain::Explainer::new(&walker_config, &count_config)
+        .exclude_generated(cli.no_generated)
+        .skip_minified(cli.skip_minified)
+        .explain(&explain.file);
+
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_explanation_to(&explanation, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_explanation_to(&explanation, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Print the man page, or write pages for rloc and every subcommand into `--dir`
+/// (`rloc manpage`).
+fn write_manpage(manpage: &rloc::cli::ManpageArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let command = Cli::command();
+    match manpage.dir {
+        Some(ref dir) => {
+            std::fs::create_dir_all(dir)?;
+            clap_mangen::generate_to(command, dir)?;
         }
-        OutputFormat::Xml => {
-            writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
-            writeln!(out, "<results>")?;
-
-            if let Some(elapsed) = summary.elapsed {
-                writeln!(out, "  <header>")?;
-                writeln!(out, "    <n_files>{}</n_files>", summary.total_files)?;
-                writeln!(out, "    <n_lines>{}</n_lines>", summary.total_lines())?;
-                writeln!(
-                    out,
-                    "    <elapsed_seconds>{:.3}</elapsed_seconds>",
-                    elapsed.as_secs_f64()
-                )?;
-                writeln!(out, "  </header>")?;
-            }
+        None => {
+            let mut out = io::stdout().lock();
+            ignore_broken_pipe(
+                clap_mangen::Man::new(command)
+                    .render(&mut out)
+                    .and_then(|()| out.flush()),
+            )?
+        }
+    }
+    Ok(())
+}
 
-            writeln!(out, "  <languages>")?;
-            for lang in &summary.languages {
-                let escaped_name = lang
-                    .name
-                    .replace('&', "&amp;")
-                    .replace('<', "&lt;")
-                    .replace('>', "&gt;")
-                    .replace('"', "&quot;");
-                writeln!(out, "    <language name=\"{}\">", escaped_name)?;
-                writeln!(out, "      <files>{}</files>", lang.files)?;
-                writeln!(out, "      <blank>{}</blank>", lang.blanks)?;
-                writeln!(out, "      <comment>{}</comment>", lang.comments)?;
-                writeln!(out, "      <code>{}</code>", lang.code)?;
-                writeln!(out, "    </language>")?;
-            }
-            writeln!(out, "  </languages>")?;
+/// Time repeated scans phase by phase for each thread count (`rloc bench`).
+fn run_bench(cli: &Cli, bench: &rloc::cli::BenchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let walker_config = cli.to_walker_config()?;
+    let count_config = cli.to_count_config()?;
+    let threads = if bench.compare_threads.is_empty() && cli.threads > 0 {
+        vec![cli.threads]
+    } else {
+        bench.compare_threads.clone()
+    };
+    let report = rloc::bench::Bench::new(&walker_config, &count_config)
+        .runs(bench.runs)
+        .warmup(bench.warmup)
+        .threads(threads)
+        .run()?;
 
-            writeln!(out, "  <total>")?;
-            writeln!(out, "    <files>{}</files>", summary.total_files)?;
-            writeln!(out, "    <blank>{}</blank>", summary.total_blanks)?;
-            writeln!(out, "    <comment>{}</comment>", summary.total_comments)?;
-            writeln!(out, "    <code>{}</code>", summary.total_code)?;
-            writeln!(out, "  </total>")?;
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_bench_to(&report, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_bench_to(&report, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Answer analysis requests over HTTP until the process is stopped (`rloc serve`).
+fn run_serve(cli: &Cli, serve: &rloc::cli::ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let server =
+        rloc::serve::Server::bind(&serve.listen).map_err(|e| format!("{}: {}", serve.listen, e))?;
+    if !cli.quiet {
+        eprintln!("Listening on http://{}", server.local_addr()?);
+    }
+    server.run()?;
+    Ok(())
+}
+
+fn diff_reports(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let [old, new] = cli.diff_report.as_slice() else {
+        return Err("--diff-report takes exactly two reports".into());
+    };
+    let old = rloc::stats::Summary::from(&read_report(old)?);
+    let new = rloc::stats::Summary::from(&read_report(new)?);
+    let delta = ReportDelta::between(&old, &new);
+
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_delta_to(&delta, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_delta_to(&delta, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
 
-            writeln!(out, "</results>")
+/// Render files ranked by size times git commits (`--churn`), to `--out` or stdout.
+fn write_hotspots(
+    cli: &Cli,
+    roots: &[std::path::PathBuf],
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let since = cli.since.as_deref();
+    let mut commits = std::collections::HashMap::new();
+    for root in roots {
+        match rloc::churn::commit_counts(root, since) {
+            Some(counts) => commits.extend(counts),
+            None if !cli.quiet => {
+                eprintln!("warning: {}: no git history found", root.display())
+            }
+            None => {}
         }
     }
+    let hotspots = rloc::churn::hotspots(&summary.file_stats, &commits);
+
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_hotspots_to(&hotspots, since, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_hotspots_to(&hotspots, since, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
 }
 
-fn sum_reports(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    use rloc::stats::JsonOutput;
+/// Print the files the walk accepts with their languages, without counting them
+/// (`--list-only`), to `--out` or stdout.
+fn list_files(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files = rloc::walker::walk_files(&cli.to_walker_config()?);
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_file_list_to(&files, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_file_list_to(&files, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
 
-    let mut reports = Vec::new();
+/// Render languages broken down by project (`--by-project`), to `--out` or stdout.
+fn write_projects(
+    cli: &Cli,
+    roots: &[std::path::PathBuf],
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut resolver = rloc::project::ProjectResolver::new(roots);
+    let nested = summary.by_project(|file| resolver.project_name(Path::new(&file.path)));
 
-    for path in &cli.sum_reports {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
-        let report: JsonOutput = serde_json::from_str(&content)
-            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
-        reports.push(report);
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_projects_to(summary, &nested, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_projects_to(summary, &nested, &output_config, &mut io::stdout().lock())?;
     }
+    Ok(())
+}
 
-    let combined = JsonOutput::sum_reports(reports);
-    let json = serde_json::to_string_pretty(&combined)?;
-    println!("{}", json);
+/// Render one report per root (`--group-by-path`), to `--out` or stdout.
+fn write_groups(
+    cli: &Cli,
+    roots: &[std::path::PathBuf],
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let groups: Vec<_> = summary
+        .group_by_root(roots)
+        .into_iter()
+        .map(|(root, group)| (root.display().to_string(), group))
+        .collect();
 
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_groups_to(&groups, summary, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_groups_to(&groups, summary, &output_config, &mut io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Render `summary` with the configured output format, to `--out` or stdout.
+fn write_summary(
+    cli: &Cli,
+    summary: &rloc::stats::Summary,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        render_to_writer(summary, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        render(summary, &output_config)?;
+    }
     Ok(())
 }
 
@@ -363,23 +1042,46 @@ fn run_strip(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
         return Err("No strip mode specified".into());
     };
 
+    if cli.stdout {
+        let [entry] = files.as_slice() else {
+            return Err(format!("--stdout needs exactly one file, found {}", files.len()).into());
+        };
+        let reader = io::BufReader::new(File::open(&entry.path)?);
+        let mut out = io::stdout().lock();
+        strip::strip_to_writer(reader, entry.language, mode, &mut out)?;
+        return Ok(());
+    }
+
     let mut processed = 0;
     let mut errors = 0;
 
     for entry in files {
-        match strip::strip_file(
-            &entry.path,
-            entry.language,
-            match mode {
-                StripMode::Comments => StripMode::Comments,
-                StripMode::Code => StripMode::Code,
-            },
-            ext,
-        ) {
-            Ok(()) => {
+        let output_path = match cli.strip_output_dir {
+            Some(ref dir) => {
+                let relative = rloc::walker::relative_to_root(&entry.path, &walker_config.paths);
+                // A file given directly as a root has no relative path of its own.
+                let relative = if relative == entry.path {
+                    Path::new(relative.file_name().unwrap_or_default())
+                } else {
+                    relative
+                };
+                dir.join(relative)
+            }
+            None => entry.path.with_extension(ext),
+        };
+
+        let result = if cli.in_place {
+            strip::strip_file_in_place(&entry.path, entry.language, mode, Some(&cli.backup_ext))
+        } else {
+            strip::strip_file_to(&entry.path, entry.language, mode, &output_path).map(|()| true)
+        };
+
+        match result {
+            Ok(changed) => {
                 processed += 1;
                 if cli.verbose > 0 {
-                    eprintln!("Stripped: {}", entry.path.display());
+                    let action = if changed { "Stripped" } else { "Unchanged" };
+                    eprintln!("{}: {}", action, entry.path.display());
                 }
             }
             Err(e) => {
@@ -398,13 +1100,33 @@ fn run_strip(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn run_diff(cli: &Cli, diff_path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
-    let config1 = cli.to_walker_config()?;
-    let mut config2 = config1.clone();
-    config2.paths = vec![diff_path.to_path_buf()];
+fn run_diff(cli: &Cli, old: Vec<PathBuf>, new: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let config = cli.to_walker_config()?;
+    let count_config = cli.to_count_config()?;
+    let progress = if cli.quiet {
+        ProgressBar::hidden()
+    } else {
+        new_progress_bar()
+    };
+    let result = diff::diff_paths(&old, new, &config, &count_config, |done, total| {
+        progress.set_length(total);
+        progress.set_position(done);
+    })?;
+    progress.finish_and_clear();
 
-    let result = diff::compute_diff(&config1, &config2, cli.verbose > 0);
-    diff::render_diff(&result);
+    if cli.verbose > 0 {
+        for warning in &result.warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    let output_config = cli.to_output_config();
+    if let Some(output_path) = cli.output_path() {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        output::render_diff_to(&result, &output_config, &mut writer)?;
+        writer.flush()?;
+    } else {
+        output::render_diff_to(&result, &output_config, &mut io::stdout().lock())?;
+    }
 
     Ok(())
 }
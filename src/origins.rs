@@ -0,0 +1,204 @@
+//! Attribution of generated output to the sources it came from (`--follow-origins`).
+//!
+//! Parser and scanner generators (Bison, Flex, re2c, ...) copy user code into the C they
+//! write and mark it with `#line N "file"` directives (or `# N "file"` line markers in
+//! preprocessor output). Lines after a directive naming a file of a known language are
+//! reported as [`FileStats::embedded`] stats under that file and its language. They are
+//! counted with the generated file's own comment rules, since the text is copied verbatim. A
+//! directive naming the generated file itself, or a file of unknown language, hands the
+//! following lines back to the generated file. When no line can be attributed, the file is
+//! flagged as generated instead.
+//!
+//! JavaScript and CSS that point at a source map (`//# sourceMappingURL=`) are flagged as
+//! generated; their mappings are not resolved.
+
+use crate::counter::{self, CountConfig, FileStats, SOURCE_MAP_MARKERS};
+use crate::languages::{Language, detect_language};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// Languages whose preprocessor understands `#line`.
+const LINE_DIRECTIVE_LANGUAGES: &[&str] = &[
+    "C",
+    "C Header",
+    "C++",
+    "C++ Header",
+    "Objective-C",
+    "Objective-C++",
+    "C#",
+];
+
+/// Count `content` when it carries origin hints, `None` otherwise.
+pub(crate) fn count(
+    path: &Path,
+    content: &[u8],
+    language: &Language,
+    config: &CountConfig,
+) -> Option<io::Result<FileStats>> {
+    let text = String::from_utf8_lossy(content);
+    if counter::MINIFIABLE_LANGUAGES.contains(&language.name) {
+        let mapped = text.lines().any(|line| {
+            let trimmed = line.trim_start();
+            SOURCE_MAP_MARKERS.iter().any(|m| trimmed.starts_with(m))
+        });
+        return mapped.then(|| {
+            counter::classify_source(path, content, language, config).map(|stats| FileStats {
+                generated: true,
+                ..stats
+            })
+        });
+    }
+    if !LINE_DIRECTIVE_LANGUAGES.contains(&language.name) {
+        return None;
+    }
+
+    let own_name = path.file_name();
+    let mut own = String::new();
+    let mut origins: BTreeMap<String, (&'static Language, String)> = BTreeMap::new();
+    let mut current: Option<String> = None;
+    let mut directives = false;
+
+    for line in text.lines() {
+        if let Some(file) = line_directive(line) {
+            directives = true;
+            // Pseudo-files like `<stdin>` and `<built-in>` are never resolvable.
+            current = detect_language(Path::new(file))
+                .filter(|_| !file.starts_with('<') && Path::new(file).file_name() != own_name)
+                .map(|origin| {
+                    origins
+                        .entry(file.to_string())
+                        .or_insert_with(|| (origin, String::new()));
+                    file.to_string()
+                });
+            push_line(&mut own, line);
+            continue;
+        }
+        match current.as_ref().and_then(|file| origins.get_mut(file)) {
+            Some((_, buffer)) => push_line(buffer, line),
+            None => push_line(&mut own, line),
+        }
+    }
+    if !directives {
+        return None;
+    }
+
+    let stats =
+        counter::classify_source(path, own.as_bytes(), language, config).and_then(|mut stats| {
+            for (file, (origin, source)) in origins {
+                let child = counter::classify_source(path, source.as_bytes(), language, config)?;
+                if child.total() > 0 {
+                    stats.embedded.push(FileStats {
+                        path: file,
+                        language: origin.name.to_string(),
+                        ..child
+                    });
+                }
+            }
+            stats.generated |= stats.embedded.is_empty();
+            stats.bytes = (content.len() as u64)
+                .saturating_sub(stats.embedded.iter().map(|child| child.bytes).sum());
+            stats.line_bytes = text
+                .lines()
+                .map(|line| line.len() as u64)
+                .sum::<u64>()
+                .saturating_sub(stats.embedded.iter().map(|child| child.line_bytes).sum());
+            stats.max_line_length = text.lines().map(str::len).max().unwrap_or(0) as u64;
+            Ok(stats)
+        });
+    Some(stats)
+}
+
+/// The file named by a `#line N "file"` directive or a `# N "file"` line marker.
+fn line_directive(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("line").unwrap_or(rest).trim_start();
+    let number = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if number == 0 {
+        return None;
+    }
+    let file = rest[number..].trim_start().strip_prefix('"')?;
+    let file = &file[..file.find('"')?];
+    (!file.is_empty()).then_some(file)
+}
+
+fn push_line(buffer: &mut String, line: &str) {
+    buffer.push_str(line);
+    buffer.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::get_language;
+
+    fn count_as(language: &str, name: &str, source: &str) -> FileStats {
+        let language = get_language(language).unwrap();
+        count(
+            Path::new(name),
+            source.as_bytes(),
+            language,
+            &CountConfig::default(),
+        )
+        .expect("origin hints")
+        .unwrap()
+    }
+
+    #[test]
+    fn test_line_directive() {
+        assert_eq!(line_directive("#line 12 \"parse.y\""), Some("parse.y"));
+        assert_eq!(line_directive("  #  line 3 \"a/b.l\" "), Some("a/b.l"));
+        assert_eq!(line_directive("# 1 \"src/x.c\" 2"), Some("src/x.c"));
+        assert_eq!(line_directive("# 1 \"<built-in>\""), Some("<built-in>"));
+        assert_eq!(line_directive("#line 40"), None);
+        assert_eq!(line_directive("#lineage 4 \"x.y\""), None);
+        assert_eq!(line_directive("#include \"x.h\""), None);
+    }
+
+    #[test]
+    fn test_line_directives_attributed() {
+        let stats = count_as(
+            "C",
+            "parse.c",
+            "/* generated */\n\
+             int state;\n\
+             #line 10 \"grammar/parse.y\"\n\
+             int action(void) { return 1; }\n\
+             // user comment\n\
+             #line 20 \"parse.c\"\n\
+             int table[2];\n\
+             #line 30 \"notes.unknown\"\n\
+             int more;\n",
+        );
+        assert_eq!((stats.code, stats.comments, stats.generated), (6, 1, false));
+        let embedded: Vec<_> = stats
+            .embedded
+            .iter()
+            .map(|e| (e.path.as_str(), e.language.as_str(), e.code, e.comments))
+            .collect();
+        assert_eq!(embedded, [("grammar/parse.y", "Yacc", 1, 1)]);
+
+        let unresolved = count_as("C", "out.c", "#line 1 \"<stdin>\"\nint x;\n");
+        assert!(unresolved.generated && unresolved.embedded.is_empty());
+        assert!(
+            count(
+                Path::new("plain.c"),
+                b"int x;\n",
+                get_language("C").unwrap(),
+                &CountConfig::default(),
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_source_map_flagged() {
+        let stats = count_as(
+            "JavaScript",
+            "app.js",
+            "let a = 1;\n//# sourceMappingURL=app.js.map\n",
+        );
+        assert!(stats.generated);
+        assert_eq!(stats.code, 1);
+    }
+}
@@ -1,35 +1,71 @@
-use crate::stats::{JsonOutput, LanguageStats, Summary};
+use crate::Language;
+use crate::badge::{Badge, format_count};
+use crate::bench::BenchReport;
+use crate::churn::{Hotspot, HotspotReport};
+use crate::counter::{self, Annotation, FileStats, LineKind};
+use crate::diff::{DiffResult, LineCounts};
+use crate::explain::Explanation;
+use crate::snapshot::SnapshotDiff;
+use crate::stats::{
+    JsonFileByLangOutput, JsonFileOutput, JsonGroupedOutput, JsonOutput, LanguageDelta,
+    LanguageStats, ReportDelta, Summary, percent_of,
+};
+use crate::trend::{self, TrendEntry, TrendReport};
+use crate::walker::FileEntry;
 use comfy_table::{
     Attribute, Cell, Color, ContentArrangement, Table, presets::UTF8_FULL_CONDENSED,
 };
+use std::borrow::Cow;
 use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Fold languages that fall below the configured cutoffs into a single "Other" entry.
+///
+/// A language is kept when it has at least `summary_cutoff` files, at least
+/// `cutoff_percent` of the total code, and ranks within the `top` languages by code.
+/// Kept languages stay in their given order, with "Other" last.
+fn fold_languages(languages: &[LanguageStats], config: &OutputConfig) -> Vec<LanguageStats> {
+    let total_code: u64 = languages.iter().map(|l| l.code).sum();
+
+    let mut ranked: Vec<usize> = (0..languages.len()).collect();
+    ranked.sort_by_key(|&i| std::cmp::Reverse(languages[i].code));
+
+    let mut keep = vec![false; languages.len()];
+    let mut kept = 0;
+    for i in ranked {
+        let lang = &languages[i];
+        let above_files = config
+            .summary_cutoff
+            .is_none_or(|cutoff| lang.files as usize >= cutoff);
+        let above_percent = config.cutoff_percent.is_none_or(|pct| {
+            total_code > 0 && (lang.code as f64 / total_code as f64) * 100.0 >= pct
+        });
+        let within_top = config.top.is_none_or(|n| kept < n);
 
-fn apply_summary_cutoff(languages: &[LanguageStats], cutoff: usize) -> Vec<LanguageStats> {
-    let mut kept: Vec<LanguageStats> = Vec::new();
+        if above_files && above_percent && within_top {
+            keep[i] = true;
+            kept += 1;
+        }
+    }
+
+    let mut folded: Vec<LanguageStats> = Vec::new();
     let mut other = LanguageStats {
         name: "Other".to_string(),
-        files: 0,
-        code: 0,
-        comments: 0,
-        blanks: 0,
+        ..Default::default()
     };
-
-    for lang in languages {
-        if lang.files as usize >= cutoff {
-            kept.push(lang.clone());
+    for (lang, keep) in languages.iter().zip(keep) {
+        if keep {
+            folded.push(lang.clone());
         } else {
-            other.files += lang.files;
-            other.code += lang.code;
-            other.comments += lang.comments;
-            other.blanks += lang.blanks;
+            other.merge(lang);
         }
     }
 
     if other.files > 0 {
-        kept.push(other);
+        folded.push(other);
     }
 
-    kept
+    folded
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -42,11 +78,17 @@ pub enum OutputFormat {
     Markdown,
     Sql,
     Xml,
+    Badge,
+    Ndjson,
 }
 
 #[derive(Debug, Clone)]
 pub struct OutputConfig {
     pub format: OutputFormat,
+    /// Style table cells with ANSI colors and bold text.
+    pub color: bool,
+    /// Color of the code column when `color` is set; `None` leaves it unstyled.
+    pub code_color: Option<Color>,
     pub by_file: bool,
     pub by_file_by_lang: bool,
     pub hide_rate: bool,
@@ -55,12 +97,83 @@ pub struct OutputConfig {
     pub csv_delimiter: u8,
     pub by_percent: bool,
     pub summary_cutoff: Option<usize>,
+    pub top: Option<usize>,
+    pub cutoff_percent: Option<f64>,
+    pub template: Option<PathBuf>,
+    pub columns: Vec<Column>,
+    pub by_category: bool,
+    /// Group rows by file extension instead of language (`--by-ext`).
+    pub by_extension: bool,
+    /// `(from, into)` language rows folded together before any other grouping
+    /// (`--merge-lang`, `--group-headers`); per-file rows are not renamed.
+    pub merge_languages: Vec<(String, String)>,
+    /// Append the `Logical` column (statements counted with `--logical-lines`).
+    pub show_logical_column: bool,
+    /// Append the `Tests` column (test code split out with `--split-tests`).
+    pub show_tests_column: bool,
+    /// Append the `LF`, `CRLF` and `CR` columns (terminators counted with `--line-endings`).
+    pub show_line_ending_columns: bool,
+    /// Append the `Tabs`, `Spaces` and `Indent` columns (counted with `--indent-stats`).
+    pub show_indent_columns: bool,
+    /// Add `total`, `bytes` and `avg_line_len` to JSON and YAML language entries.
+    pub extended_json: bool,
+    /// Append comment ratio, blank ratio and lines per file to language rows (`--ratios`).
+    pub show_ratios: bool,
+    /// List the N largest files, overall and per language, after the table (`--show-largest`).
+    pub show_largest: Option<usize>,
+    /// Break each language row down by file extension: a table after the summary, and
+    /// `extensions` in JSON and YAML (`-v`). Ignored when rows are not languages.
+    pub show_extensions: bool,
+}
+
+impl OutputConfig {
+    /// Columns to render, in order, including the `--show-total` column.
+    pub fn effective_columns(&self) -> Vec<Column> {
+        let mut columns = if self.columns.is_empty() {
+            Column::DEFAULT.to_vec()
+        } else {
+            self.columns.clone()
+        };
+        if self.show_logical_column && !columns.contains(&Column::Logical) {
+            columns.push(Column::Logical);
+        }
+        if self.show_tests_column && !columns.contains(&Column::Tests) {
+            columns.push(Column::Tests);
+        }
+        let line_endings = self
+            .show_line_ending_columns
+            .then_some(Column::LINE_ENDINGS);
+        let indent = self.show_indent_columns.then_some(Column::INDENT);
+        for column in line_endings.into_iter().chain(indent).flatten() {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+        if self.show_total_column && !columns.contains(&Column::Total) {
+            columns.push(Column::Total);
+        }
+        columns
+    }
+
+    fn file_columns(&self) -> Vec<Column> {
+        let mut columns: Vec<Column> = self
+            .effective_columns()
+            .into_iter()
+            .filter(|c| *c != Column::Files)
+            .collect();
+        if self.columns.is_empty() {
+            columns.extend(Column::FILE_METADATA);
+        }
+        columns
+    }
 }
 
 impl Default for OutputConfig {
     fn default() -> Self {
         Self {
             format: OutputFormat::Table,
+            color: false,
+            code_color: Some(Color::Green),
             by_file: false,
             by_file_by_lang: false,
             hide_rate: false,
@@ -69,6 +182,156 @@ impl Default for OutputConfig {
             csv_delimiter: b',',
             by_percent: false,
             summary_cutoff: None,
+            top: None,
+            cutoff_percent: None,
+            template: None,
+            columns: Column::DEFAULT.to_vec(),
+            by_category: false,
+            by_extension: false,
+            merge_languages: Vec::new(),
+            show_logical_column: false,
+            show_tests_column: false,
+            show_line_ending_columns: false,
+            show_indent_columns: false,
+            extended_json: false,
+            show_ratios: false,
+            show_largest: None,
+            show_extensions: false,
+        }
+    }
+}
+
+/// A numeric column in table, CSV and Markdown output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Files,
+    Blanks,
+    Comments,
+    Code,
+    Total,
+    DocComments,
+    Logical,
+    Tests,
+    Ignored,
+    Bytes,
+    AvgLineLength,
+    MaxLineLength,
+    Lf,
+    Crlf,
+    Cr,
+    Tabs,
+    Spaces,
+    IndentWidth,
+}
+
+impl Column {
+    pub const DEFAULT: [Column; 4] = [
+        Column::Files,
+        Column::Blanks,
+        Column::Comments,
+        Column::Code,
+    ];
+
+    /// Line terminator counts, appended with `--line-endings`.
+    pub const LINE_ENDINGS: [Column; 3] = [Column::Lf, Column::Crlf, Column::Cr];
+
+    /// Indentation columns, appended with `--indent-stats`.
+    pub const INDENT: [Column; 3] = [Column::Tabs, Column::Spaces, Column::IndentWidth];
+
+    /// Extra columns shown by default in per-file output.
+    pub const FILE_METADATA: [Column; 3] =
+        [Column::Bytes, Column::AvgLineLength, Column::MaxLineLength];
+
+    pub fn header(self) -> &'static str {
+        match self {
+            Column::Files => "Files",
+            Column::Blanks => "Blank",
+            Column::Comments => "Comment",
+            Column::Code => "Code",
+            Column::Total => "Total",
+            Column::DocComments => "Doc",
+            Column::Logical => "Logical",
+            Column::Tests => "Tests",
+            Column::Ignored => "Ignored",
+            Column::Bytes => "Bytes",
+            Column::AvgLineLength => "Avg Line",
+            Column::MaxLineLength => "Max Line",
+            Column::Lf => "LF",
+            Column::Crlf => "CRLF",
+            Column::Cr => "CR",
+            Column::Tabs => "Tabs",
+            Column::Spaces => "Spaces",
+            Column::IndentWidth => "Indent",
+        }
+    }
+
+    fn language_value(self, lang: &LanguageStats) -> u64 {
+        match self {
+            Column::Files => lang.files,
+            Column::Blanks => lang.blanks,
+            Column::Comments => lang.comments,
+            Column::Code => lang.code,
+            Column::Total => lang.total(),
+            Column::DocComments => lang.doc_comments,
+            Column::Logical => lang.logical,
+            Column::Tests => lang.test_code,
+            Column::Ignored => lang.ignored,
+            Column::Bytes => lang.bytes,
+            Column::AvgLineLength => lang.avg_line_length().round() as u64,
+            Column::MaxLineLength => lang.max_line_length,
+            Column::Lf => lang.line_endings.lf,
+            Column::Crlf => lang.line_endings.crlf,
+            Column::Cr => lang.line_endings.cr,
+            Column::Tabs => lang.indent.tabs,
+            Column::Spaces => lang.indent.spaces,
+            Column::IndentWidth => lang.indent.width().unwrap_or(0),
+        }
+    }
+
+    /// Per-file value; `None` for columns that only make sense for aggregates.
+    fn file_value(self, file: &FileStats) -> Option<u64> {
+        match self {
+            Column::Files => None,
+            Column::Blanks => Some(file.blanks),
+            Column::Comments => Some(file.comments),
+            Column::Code => Some(file.code),
+            Column::Total => Some(file.total()),
+            Column::DocComments => Some(file.doc_comments),
+            Column::Logical => Some(file.logical),
+            Column::Tests => Some(file.test_code),
+            Column::Ignored => Some(file.ignored),
+            Column::Bytes => Some(file.bytes),
+            Column::AvgLineLength => Some(file.avg_line_length().round() as u64),
+            Column::MaxLineLength => Some(file.max_line_length),
+            Column::Lf => Some(file.line_endings.lf),
+            Column::Crlf => Some(file.line_endings.crlf),
+            Column::Cr => Some(file.line_endings.cr),
+            Column::Tabs => Some(file.indent.tabs),
+            Column::Spaces => Some(file.indent.spaces),
+            Column::IndentWidth => Some(file.indent.width().unwrap_or(0)),
+        }
+    }
+
+    fn summary_value(self, summary: &Summary) -> u64 {
+        match self {
+            Column::Files => summary.total_files,
+            Column::Blanks => summary.total_blanks,
+            Column::Comments => summary.total_comments,
+            Column::Code => summary.total_code,
+            Column::Total => summary.total_lines(),
+            Column::DocComments => summary.total_doc_comments,
+            Column::Logical => summary.total_logical,
+            Column::Tests => summary.total_test_code,
+            Column::Ignored => summary.total_ignored,
+            Column::Bytes => summary.total_bytes,
+            Column::AvgLineLength => summary.avg_line_length().round() as u64,
+            Column::MaxLineLength => summary.max_line_length,
+            Column::Lf => summary.line_endings.lf,
+            Column::Crlf => summary.line_endings.crlf,
+            Column::Cr => summary.line_endings.cr,
+            Column::Tabs => summary.indent.tabs,
+            Column::Spaces => summary.indent.spaces,
+            Column::IndentWidth => summary.indent.width().unwrap_or(0),
         }
     }
 }
@@ -84,18 +347,135 @@ pub enum SortBy {
     Total,
 }
 
+impl SortBy {
+    /// Order of two language rows: by name, or descending by the chosen count with ties
+    /// broken by name, so every output format lists languages the same way on every run.
+    pub fn compare(self, a: &LanguageStats, b: &LanguageStats) -> std::cmp::Ordering {
+        let key = |l: &LanguageStats| match self {
+            SortBy::Language => 0,
+            SortBy::Files => l.files,
+            SortBy::Code => l.code,
+            SortBy::Comments => l.comments,
+            SortBy::Blanks => l.blanks,
+            SortBy::Total => l.total(),
+        };
+        key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name))
+    }
+}
+
 pub fn render(summary: &Summary, config: &OutputConfig) -> io::Result<()> {
     let mut stdout = io::stdout().lock();
+    render_to(summary, config, &mut stdout)
+}
+
+pub fn render_to(summary: &Summary, config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
+    let summary = prepare_summary(summary, config);
+    let summary = summary.as_ref();
+
+    if let Some(ref template) = config.template {
+        return render_template(summary, config, template, out);
+    }
 
     match config.format {
-        OutputFormat::Table => render_table(summary, config, &mut stdout),
-        OutputFormat::Json => render_json(summary, config, &mut stdout),
-        OutputFormat::Csv => render_csv(summary, config, &mut stdout),
-        OutputFormat::Yaml => render_yaml(summary, config, &mut stdout),
-        OutputFormat::Markdown => render_markdown(summary, config, &mut stdout),
-        OutputFormat::Sql => render_sql(summary, config, &mut stdout),
-        OutputFormat::Xml => render_xml(summary, config, &mut stdout),
+        OutputFormat::Table => render_table(summary, config, out),
+        OutputFormat::Json => render_json(summary, config, out),
+        OutputFormat::Csv => render_csv(summary, config, out),
+        OutputFormat::Yaml => render_yaml(summary, config, out),
+        OutputFormat::Markdown => render_markdown(summary, config, out),
+        OutputFormat::Sql => render_sql(summary, config, out),
+        OutputFormat::Xml => render_xml(summary, config, out),
+        OutputFormat::Badge => render_badge(summary, config, out),
+        OutputFormat::Ndjson => render_ndjson(summary, config, out),
+    }
+}
+
+/// Apply the language-row transformations (`--merge-lang`, `--by-category` or `--by-ext`,
+/// then folding) shared by all formats.
+pub fn prepare_summary<'a>(summary: &'a Summary, config: &OutputConfig) -> Cow<'a, Summary> {
+    let mut summary = Cow::Borrowed(summary);
+
+    if !config.merge_languages.is_empty() {
+        let merged = summary.merge_languages(&config.merge_languages);
+        summary.to_mut().languages = merged;
+    }
+
+    if config.by_category {
+        let categories = summary.by_category();
+        summary.to_mut().languages = categories;
+    } else if config.by_extension {
+        let extensions = summary.by_extension();
+        summary.to_mut().languages = extensions;
+    }
+
+    let order = |a: &LanguageStats, b: &LanguageStats| config.sort_by.compare(a, b);
+    if !summary.languages.is_sorted_by(|a, b| order(a, b).is_le()) {
+        summary.to_mut().languages.sort_by(order);
+    }
+
+    // After sorting, so "Other" stays the last row.
+    if config.summary_cutoff.is_some() || config.top.is_some() || config.cutoff_percent.is_some() {
+        let folded = fold_languages(&summary.languages, config);
+        summary.to_mut().languages = folded;
     }
+
+    summary
+}
+
+/// A table in the shared style. Without `config.color` it is never styled, even on a
+/// terminal, but still wraps to the terminal width.
+fn new_table(config: &OutputConfig) -> Table {
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL_CONDENSED)
+        .set_content_arrangement(ContentArrangement::Dynamic);
+    if config.color {
+        table.enforce_styling();
+    } else {
+        if let Some(width) = table.width() {
+            table.set_width(width);
+        }
+        table.force_no_tty();
+    }
+    table
+}
+
+/// `cell` in the configured code column color.
+fn code_cell(cell: Cell, config: &OutputConfig) -> Cell {
+    match config.code_color {
+        Some(color) => cell.fg(color),
+        None => cell,
+    }
+}
+
+pub fn row_label(config: &OutputConfig) -> &'static str {
+    if config.by_category {
+        "Category"
+    } else if config.by_extension {
+        "Extension"
+    } else {
+        "Language"
+    }
+}
+
+/// Lines noting files that were found but not counted and lines left out by ignore
+/// directives, if any.
+pub fn write_skipped(summary: &Summary, out: &mut impl Write) -> io::Result<()> {
+    if summary.skipped.total() > 0 {
+        writeln!(
+            out,
+            "{} files skipped ({})",
+            summary.skipped.total(),
+            summary.skipped
+        )?;
+    }
+    if summary.total_ignored > 0 {
+        writeln!(
+            out,
+            "{} lines ignored (rloc:ignore directives)",
+            summary.total_ignored
+        )?;
+    }
+    Ok(())
 }
 
 fn render_table(summary: &Summary, config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
@@ -114,118 +494,135 @@ fn render_table(summary: &Summary, config: &OutputConfig, out: &mut impl Write)
             }
             writeln!(out)?;
         }
+        write_skipped(summary, out)?;
     }
 
-    if config.by_file || config.by_file_by_lang {
+    if config.by_file_by_lang {
+        render_by_file_by_lang_table(summary, config, out)?;
+    } else if config.by_file {
         render_by_file_table(summary, config, out)?;
-    }
-
-    if !config.by_file || config.by_file_by_lang {
+    } else {
         render_language_table(summary, config, out)?;
     }
 
+    if let Some(n) = config.show_largest.filter(|&n| n > 0) {
+        render_largest_tables(summary, n, config, out)?;
+    }
+    if extensions_shown(config) && !config.by_file && !config.by_file_by_lang {
+        render_extension_table(summary, config, out)?;
+    }
     Ok(())
 }
 
-fn render_language_table(
+/// Whether rows get an extension breakdown: with `-v`, unless they are categories or
+/// extensions already.
+fn extensions_shown(config: &OutputConfig) -> bool {
+    config.show_extensions && !config.by_category && !config.by_extension
+}
+
+/// Each language row followed by its files per extension (`-v`).
+fn render_extension_table(
     summary: &Summary,
     config: &OutputConfig,
     out: &mut impl Write,
 ) -> io::Result<()> {
-    let mut table = Table::new();
-    table
-        .load_preset(UTF8_FULL_CONDENSED)
-        .set_content_arrangement(ContentArrangement::Dynamic);
+    let columns = config.effective_columns();
+    let values = |row: &LanguageStats| -> Vec<Cell> {
+        columns
+            .iter()
+            .map(|c| Cell::new(c.language_value(row)))
+            .collect()
+    };
 
-    let mut headers = vec![
-        Cell::new("Language").add_attribute(Attribute::Bold),
-        Cell::new("Files").add_attribute(Attribute::Bold),
-        Cell::new("Blank").add_attribute(Attribute::Bold),
-        Cell::new("Comment").add_attribute(Attribute::Bold),
-        Cell::new("Code").add_attribute(Attribute::Bold),
-    ];
+    let mut table = new_table(config);
+    let mut headers = vec![Cell::new("Language / Extension").add_attribute(Attribute::Bold)];
+    headers.extend(
+        columns
+            .iter()
+            .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold)),
+    );
+    table.set_header(headers);
 
-    if config.show_total_column {
-        headers.push(Cell::new("Total").add_attribute(Attribute::Bold));
+    for (lang, extensions) in summary.extensions_by_language(&config.merge_languages) {
+        if extensions.is_empty() {
+            continue;
+        }
+        let mut row = vec![Cell::new(&lang.name).add_attribute(Attribute::Bold)];
+        row.extend(
+            values(&lang)
+                .into_iter()
+                .map(|cell| cell.add_attribute(Attribute::Bold)),
+        );
+        table.add_row(row);
+        for extension in &extensions {
+            let mut row = vec![Cell::new(format!("  {}", extension.name))];
+            row.extend(values(extension));
+            table.add_row(row);
+        }
     }
 
-    table.set_header(headers);
+    writeln!(out)?;
+    writeln!(out, "{}", table)
+}
 
-    let mut languages = if let Some(cutoff) = config.summary_cutoff {
-        apply_summary_cutoff(&summary.languages, cutoff)
-    } else {
-        summary.languages.clone()
-    };
-    match config.sort_by {
-        SortBy::Language => languages.sort_by(|a, b| a.name.cmp(&b.name)),
-        SortBy::Files => languages.sort_by(|a, b| b.files.cmp(&a.files)),
-        SortBy::Code => languages.sort_by(|a, b| b.code.cmp(&a.code)),
-        SortBy::Comments => languages.sort_by(|a, b| b.comments.cmp(&a.comments)),
-        SortBy::Blanks => languages.sort_by(|a, b| b.blanks.cmp(&a.blanks)),
-        SortBy::Total => languages.sort_by_key(|l| std::cmp::Reverse(l.total())),
-    }
-
-    for lang in &languages {
-        let mut row = if config.by_percent {
-            vec![
-                Cell::new(&lang.name),
-                Cell::new(format_percent(lang.files, summary.total_files)),
-                Cell::new(format_percent(lang.blanks, summary.total_blanks)),
-                Cell::new(format_percent(lang.comments, summary.total_comments)),
-                Cell::new(format_percent(lang.code, summary.total_code)).fg(Color::Green),
-            ]
-        } else {
-            vec![
-                Cell::new(&lang.name),
-                Cell::new(lang.files),
-                Cell::new(lang.blanks),
-                Cell::new(lang.comments),
-                Cell::new(lang.code).fg(Color::Green),
-            ]
-        };
+fn render_language_table(
+    summary: &Summary,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut table = new_table(config);
 
-        if config.show_total_column {
-            if config.by_percent {
-                row.push(Cell::new(format_percent(
-                    lang.total(),
-                    summary.total_lines(),
-                )));
+    let columns = config.effective_columns();
+
+    let mut headers = vec![Cell::new(row_label(config)).add_attribute(Attribute::Bold)];
+    headers.extend(
+        columns
+            .iter()
+            .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold)),
+    );
+    if config.show_ratios {
+        headers.extend(RATIO_HEADERS.map(|h| Cell::new(h).add_attribute(Attribute::Bold)));
+    }
+    table.set_header(headers);
+
+    for lang in &summary.languages {
+        let mut row = vec![Cell::new(&lang.name)];
+        for &column in &columns {
+            let value = column.language_value(lang);
+            let cell = if config.by_percent {
+                Cell::new(format_percent(value, column.summary_value(summary)))
             } else {
-                row.push(Cell::new(lang.total()));
-            }
+                Cell::new(value)
+            };
+            row.push(if column == Column::Code {
+                code_cell(cell, config)
+            } else {
+                cell
+            });
+        }
+        if config.show_ratios {
+            row.extend(language_ratios(lang).map(Cell::new));
         }
-
         table.add_row(row);
     }
 
-    let mut sum_row = if config.by_percent {
-        vec![
-            Cell::new("SUM").add_attribute(Attribute::Bold),
-            Cell::new("100.00%").add_attribute(Attribute::Bold),
-            Cell::new("100.00%").add_attribute(Attribute::Bold),
-            Cell::new("100.00%").add_attribute(Attribute::Bold),
+    let mut sum_row = vec![Cell::new("SUM").add_attribute(Attribute::Bold)];
+    for &column in &columns {
+        let cell = if config.by_percent {
             Cell::new("100.00%")
-                .add_attribute(Attribute::Bold)
-                .fg(Color::Green),
-        ]
-    } else {
-        vec![
-            Cell::new("SUM").add_attribute(Attribute::Bold),
-            Cell::new(summary.total_files).add_attribute(Attribute::Bold),
-            Cell::new(summary.total_blanks).add_attribute(Attribute::Bold),
-            Cell::new(summary.total_comments).add_attribute(Attribute::Bold),
-            Cell::new(summary.total_code)
-                .add_attribute(Attribute::Bold)
-                .fg(Color::Green),
-        ]
-    };
-
-    if config.show_total_column {
-        if config.by_percent {
-            sum_row.push(Cell::new("100.00%").add_attribute(Attribute::Bold));
         } else {
-            sum_row.push(Cell::new(summary.total_lines()).add_attribute(Attribute::Bold));
+            Cell::new(column.summary_value(summary))
         }
+        .add_attribute(Attribute::Bold);
+        sum_row.push(if column == Column::Code {
+            code_cell(cell, config)
+        } else {
+            cell
+        });
+    }
+    if config.show_ratios {
+        sum_row
+            .extend(summary_ratios(summary).map(|v| Cell::new(v).add_attribute(Attribute::Bold)));
     }
 
     table.add_row(sum_row);
@@ -238,44 +635,174 @@ fn render_language_table(
 
 fn render_by_file_table(
     summary: &Summary,
-    _config: &OutputConfig,
+    config: &OutputConfig,
     out: &mut impl Write,
 ) -> io::Result<()> {
-    let mut table = Table::new();
-    table
-        .load_preset(UTF8_FULL_CONDENSED)
-        .set_content_arrangement(ContentArrangement::Dynamic);
+    let mut table = new_table(config);
+
+    let columns = config.file_columns();
 
-    table.set_header(vec![
+    let mut headers = vec![
         Cell::new("File").add_attribute(Attribute::Bold),
         Cell::new("Language").add_attribute(Attribute::Bold),
-        Cell::new("Blank").add_attribute(Attribute::Bold),
-        Cell::new("Comment").add_attribute(Attribute::Bold),
-        Cell::new("Code").add_attribute(Attribute::Bold),
-    ]);
+    ];
+    headers.extend(
+        columns
+            .iter()
+            .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold)),
+    );
+    table.set_header(headers);
 
     let mut files = summary.file_stats.clone();
     files.sort_by(|a, b| b.code.cmp(&a.code));
 
     for file in &files {
-        table.add_row(vec![
-            Cell::new(&file.path),
-            Cell::new(&file.language),
-            Cell::new(file.blanks),
-            Cell::new(file.comments),
-            Cell::new(file.code).fg(Color::Green),
-        ]);
+        let mut row = vec![Cell::new(&file.path), Cell::new(&file.language)];
+        for &column in &columns {
+            let cell = Cell::new(column.file_value(file).unwrap_or_default());
+            row.push(if column == Column::Code {
+                code_cell(cell, config)
+            } else {
+                cell
+            });
+        }
+        table.add_row(row);
+    }
+
+    writeln!(out)?;
+    writeln!(out, "{}", table)?;
+
+    Ok(())
+}
+
+/// The `n` largest files by code, first overall and then per language (`--show-largest`).
+fn render_largest_tables(
+    summary: &Summary,
+    n: usize,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let columns = config.file_columns();
+    let file_row = |label: String, file: &FileStats| {
+        let mut row = vec![Cell::new(label)];
+        row.extend(columns.iter().map(|&column| {
+            let cell = Cell::new(column.file_value(file).unwrap_or_default());
+            if column == Column::Code {
+                code_cell(cell, config)
+            } else {
+                cell
+            }
+        }));
+        row
+    };
+    let headers = |first: &[&str]| {
+        let mut headers: Vec<Cell> = first
+            .iter()
+            .map(|h| Cell::new(h).add_attribute(Attribute::Bold))
+            .collect();
+        headers.extend(
+            columns
+                .iter()
+                .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold)),
+        );
+        headers
+    };
+
+    let mut overall = new_table(config);
+    overall.set_header(headers(&["Largest Files", "Language"]));
+    for file in summary.largest_files(n) {
+        let mut row = file_row(file.path.clone(), file);
+        row.insert(1, Cell::new(&file.language));
+        overall.add_row(row);
+    }
+
+    let mut per_language = new_table(config);
+    per_language.set_header(headers(&["Largest Files by Language"]));
+    for (lang, mut files) in summary.files_by_language() {
+        files.truncate(n);
+        per_language.add_row(vec![Cell::new(&lang.name).add_attribute(Attribute::Bold)]);
+        for file in files {
+            per_language.add_row(file_row(format!("  {}", file.path), file));
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "{}", overall)?;
+    writeln!(out)?;
+    writeln!(out, "{}", per_language)?;
+
+    Ok(())
+}
+
+/// Files grouped under a heading row per language, each group closed by its own SUM row,
+/// followed by the grand SUM (`--by-file-by-lang`).
+fn render_by_file_by_lang_table(
+    summary: &Summary,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let mut table = new_table(config);
+
+    let columns = config.file_columns();
+    let code_colored = |column: Column, cell: Cell| {
+        if column == Column::Code {
+            code_cell(cell, config)
+        } else {
+            cell
+        }
+    };
+
+    let mut headers = vec![Cell::new("Language / File").add_attribute(Attribute::Bold)];
+    headers.extend(
+        columns
+            .iter()
+            .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold)),
+    );
+    table.set_header(headers);
+
+    for (lang, files) in summary.files_by_language() {
+        table.add_row(vec![Cell::new(&lang.name).add_attribute(Attribute::Bold)]);
+        for file in files {
+            let mut row = vec![Cell::new(format!("  {}", file.path))];
+            row.extend(columns.iter().map(|&column| {
+                code_colored(
+                    column,
+                    Cell::new(column.file_value(file).unwrap_or_default()),
+                )
+            }));
+            table.add_row(row);
+        }
+        let mut sum_row =
+            vec![Cell::new(format!("SUM: {}", lang.name)).add_attribute(Attribute::Bold)];
+        sum_row.extend(columns.iter().map(|&column| {
+            let cell = Cell::new(column.language_value(&lang)).add_attribute(Attribute::Bold);
+            code_colored(column, cell)
+        }));
+        table.add_row(sum_row);
     }
 
+    let mut sum_row = vec![Cell::new("SUM").add_attribute(Attribute::Bold)];
+    sum_row.extend(columns.iter().map(|&column| {
+        let cell = Cell::new(column.summary_value(summary)).add_attribute(Attribute::Bold);
+        code_colored(column, cell)
+    }));
+    table.add_row(sum_row);
+
     writeln!(out)?;
     writeln!(out, "{}", table)?;
 
     Ok(())
 }
 
-fn render_json(summary: &Summary, _config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
-    let output = JsonOutput::from(summary);
-    let json = serde_json::to_string_pretty(&output).map_err(io::Error::other)?;
+fn render_json(summary: &Summary, config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
+    let json = if config.by_file_by_lang {
+        serde_json::to_string_pretty(&JsonFileByLangOutput::from(summary))
+    } else if config.by_file {
+        serde_json::to_string_pretty(&JsonFileOutput::from(summary))
+    } else {
+        serde_json::to_string_pretty(&json_output(summary, config))
+    }
+    .map_err(io::Error::other)?;
     writeln!(out, "{}", json)?;
     Ok(())
 }
@@ -286,47 +813,135 @@ fn render_csv(summary: &Summary, config: &OutputConfig, out: &mut impl Write) ->
         .from_writer(out);
 
     if config.by_file {
-        writer.write_record(["File", "Language", "Blank", "Comment", "Code"])?;
+        let columns = config.file_columns();
+        let mut header = vec!["File", "Language"];
+        header.extend(columns.iter().map(|c| c.header()));
+        writer.write_record(&header)?;
         for file in &summary.file_stats {
-            writer.write_record([
-                &file.path,
-                &file.language,
-                &file.blanks.to_string(),
-                &file.comments.to_string(),
-                &file.code.to_string(),
-            ])?;
+            let mut record = vec![file.path.clone(), file.language.clone()];
+            record.extend(
+                columns
+                    .iter()
+                    .map(|c| c.file_value(file).unwrap_or_default().to_string()),
+            );
+            writer.write_record(&record)?;
         }
     } else {
-        let languages = if let Some(cutoff) = config.summary_cutoff {
-            apply_summary_cutoff(&summary.languages, cutoff)
-        } else {
-            summary.languages.clone()
-        };
-        writer.write_record(["Language", "Files", "Blank", "Comment", "Code"])?;
-        for lang in &languages {
-            writer.write_record([
-                &lang.name,
-                &lang.files.to_string(),
-                &lang.blanks.to_string(),
-                &lang.comments.to_string(),
-                &lang.code.to_string(),
-            ])?;
-        }
-        writer.write_record([
-            "SUM",
-            &summary.total_files.to_string(),
-            &summary.total_blanks.to_string(),
-            &summary.total_comments.to_string(),
-            &summary.total_code.to_string(),
-        ])?;
+        let languages = &summary.languages;
+        let columns = config.effective_columns();
+        let mut header = vec![row_label(config)];
+        header.extend(columns.iter().map(|c| c.header()));
+        if config.by_percent {
+            header.extend(PERCENT_HEADERS);
+        }
+        if config.show_ratios {
+            header.extend(RATIO_HEADERS);
+        }
+        writer.write_record(&header)?;
+        for lang in languages {
+            let mut record = vec![lang.name.clone()];
+            record.extend(columns.iter().map(|c| c.language_value(lang).to_string()));
+            if config.by_percent {
+                record.extend(percent_values(lang.files, lang.code, summary));
+            }
+            if config.show_ratios {
+                record.extend(language_ratios(lang));
+            }
+            writer.write_record(&record)?;
+        }
+        let mut record = vec!["SUM".to_string()];
+        record.extend(columns.iter().map(|c| c.summary_value(summary).to_string()));
+        if config.by_percent {
+            record.extend(percent_values(
+                summary.total_files,
+                summary.total_code,
+                summary,
+            ));
+        }
+        if config.show_ratios {
+            record.extend(summary_ratios(summary));
+        }
+        writer.write_record(&record)?;
     }
 
     writer.flush()?;
     Ok(())
 }
 
-fn render_yaml(summary: &Summary, _config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
-    let output = JsonOutput::from(summary);
+/// Write a single file's stats as one NDJSON line.
+pub fn write_ndjson_line(stats: &FileStats, out: &mut impl Write) -> io::Result<()> {
+    let json = serde_json::to_string(stats).map_err(io::Error::other)?;
+    writeln!(out, "{}", json)
+}
+
+fn render_ndjson(
+    summary: &Summary,
+    _config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for file in &summary.file_stats {
+        write_ndjson_line(file, out)?;
+    }
+    Ok(())
+}
+
+/// The per-language report for JSON and YAML, with the extended fields if configured.
+fn json_output(summary: &Summary, config: &OutputConfig) -> JsonOutput {
+    let output = if config.extended_json {
+        JsonOutput::extended(summary)
+    } else {
+        JsonOutput::from(summary)
+    };
+    let output = if config.show_ratios {
+        output.with_ratios(summary)
+    } else {
+        output
+    };
+    let output = if extensions_shown(config) {
+        output.with_extensions(summary, &config.merge_languages)
+    } else {
+        output
+    };
+    if config.by_percent {
+        output.with_percentages()
+    } else {
+        output
+    }
+}
+
+/// `Files %` and `Code %` values for CSV and Markdown rows (`--by-percent`).
+fn percent_values(files: u64, code: u64, summary: &Summary) -> [String; 2] {
+    [
+        format!("{:.2}", percent_of(files, summary.total_files)),
+        format!("{:.2}", percent_of(code, summary.total_code)),
+    ]
+}
+
+const PERCENT_HEADERS: [&str; 2] = ["Files %", "Code %"];
+
+const RATIO_HEADERS: [&str; 3] = ["Comment Ratio", "Blank Ratio", "Lines/File"];
+
+/// Comment ratio, blank ratio and lines per file of one language (`--ratios`).
+fn language_ratios(lang: &LanguageStats) -> [String; 3] {
+    [
+        lang.comment_ratio(),
+        lang.blank_ratio(),
+        lang.avg_file_lines(),
+    ]
+    .map(|v| format!("{:.2}", v))
+}
+
+fn summary_ratios(summary: &Summary) -> [String; 3] {
+    [
+        summary.comment_ratio(),
+        summary.blank_ratio(),
+        summary.avg_file_lines(),
+    ]
+    .map(|v| format!("{:.2}", v))
+}
+
+fn render_yaml(summary: &Summary, config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
+    let output = json_output(summary, config);
     let yaml = serde_yaml::to_string(&output).map_err(io::Error::other)?;
     write!(out, "{}", yaml)?;
     Ok(())
@@ -350,50 +965,114 @@ fn render_markdown(
         }
     }
 
-    if config.by_file {
-        writeln!(out, "| File | Language | Blank | Comment | Code |")?;
-        writeln!(out, "|------|----------|------:|--------:|-----:|")?;
-        for file in &summary.file_stats {
-            writeln!(
-                out,
-                "| {} | {} | {} | {} | {} |",
-                file.path, file.language, file.blanks, file.comments, file.code
-            )?;
-        }
-    } else {
-        let languages = if let Some(cutoff) = config.summary_cutoff {
-            apply_summary_cutoff(&summary.languages, cutoff)
-        } else {
-            summary.languages.clone()
-        };
-        let mut headers = vec!["Language", "Files", "Blank", "Comment", "Code"];
-        let mut alignments = vec![":---", "---:", "---:", "---:", "---:"];
-
-        if config.show_total_column {
-            headers.push("Total");
+    if config.by_file_by_lang {
+        let columns = config.file_columns();
+        let mut headers = vec!["Language / File"];
+        let mut alignments = vec![":---"];
+        for column in &columns {
+            headers.push(column.header());
             alignments.push("---:");
         }
 
         writeln!(out, "| {} |", headers.join(" | "))?;
         writeln!(out, "| {} |", alignments.join(" | "))?;
-
-        for lang in &languages {
-            let mut row = format!(
-                "| {} | {} | {} | {} | {}",
-                lang.name, lang.files, lang.blanks, lang.comments, lang.code
-            );
-            if config.show_total_column {
-                row.push_str(&format!(" | {}", lang.total()));
+        for (lang, files) in summary.files_by_language() {
+            writeln!(out, "| **{}** |{}", lang.name, " |".repeat(columns.len()))?;
+            for file in files {
+                let mut row = format!("| &nbsp;&nbsp;{}", file.path);
+                for column in &columns {
+                    row.push_str(&format!(
+                        " | {}",
+                        column.file_value(file).unwrap_or_default()
+                    ));
+                }
+                writeln!(out, "{} |", row)?;
+            }
+            let mut sum_row = format!("| **SUM: {}**", lang.name);
+            for column in &columns {
+                sum_row.push_str(&format!(" | **{}**", column.language_value(&lang)));
+            }
+            writeln!(out, "{} |", sum_row)?;
+        }
+
+        let mut sum_row = "| **SUM**".to_string();
+        for column in &columns {
+            sum_row.push_str(&format!(" | **{}**", column.summary_value(summary)));
+        }
+        writeln!(out, "{} |", sum_row)?;
+    } else if config.by_file {
+        let columns = config.file_columns();
+        let mut headers = vec!["File", "Language"];
+        let mut alignments = vec!["------", "----------"];
+        for column in &columns {
+            headers.push(column.header());
+            alignments.push("---:");
+        }
+
+        writeln!(out, "| {} |", headers.join(" | "))?;
+        writeln!(out, "|{}|", alignments.join("|"))?;
+        for file in &summary.file_stats {
+            let mut row = format!("| {} | {}", file.path, file.language);
+            for column in &columns {
+                row.push_str(&format!(
+                    " | {}",
+                    column.file_value(file).unwrap_or_default()
+                ));
             }
             writeln!(out, "{} |", row)?;
         }
+    } else {
+        let languages = &summary.languages;
+        let columns = config.effective_columns();
+        let mut headers = vec![row_label(config)];
+        let mut alignments = vec![":---"];
+        for column in &columns {
+            headers.push(column.header());
+            alignments.push("---:");
+        }
+        if config.by_percent {
+            headers.extend(PERCENT_HEADERS);
+            alignments.extend(["---:"; 2]);
+        }
+        if config.show_ratios {
+            headers.extend(RATIO_HEADERS);
+            alignments.extend(["---:"; 3]);
+        }
 
-        let mut sum_row = format!(
-            "| **SUM** | **{}** | **{}** | **{}** | **{}**",
-            summary.total_files, summary.total_blanks, summary.total_comments, summary.total_code
-        );
-        if config.show_total_column {
-            sum_row.push_str(&format!(" | **{}**", summary.total_lines()));
+        writeln!(out, "| {} |", headers.join(" | "))?;
+        writeln!(out, "| {} |", alignments.join(" | "))?;
+
+        for lang in languages {
+            let mut row = format!("| {}", lang.name);
+            for column in &columns {
+                row.push_str(&format!(" | {}", column.language_value(lang)));
+            }
+            if config.by_percent {
+                for value in percent_values(lang.files, lang.code, summary) {
+                    row.push_str(&format!(" | {}", value));
+                }
+            }
+            if config.show_ratios {
+                for value in language_ratios(lang) {
+                    row.push_str(&format!(" | {}", value));
+                }
+            }
+            writeln!(out, "{} |", row)?;
+        }
+
+        let mut sum_row = "| **SUM**".to_string();
+        for column in &columns {
+            sum_row.push_str(&format!(" | **{}**", column.summary_value(summary)));
+        }
+        if config.by_percent {
+            for value in percent_values(summary.total_files, summary.total_code, summary) {
+                sum_row.push_str(&format!(" | **{}**", value));
+            }
+        }
+        if config.show_ratios {
+            for value in summary_ratios(summary) {
+                sum_row.push_str(&format!(" | **{}**", value));
+            }
         }
         writeln!(out, "{} |", sum_row)?;
     }
@@ -425,11 +1104,7 @@ fn render_sql(summary: &Summary, config: &OutputConfig, out: &mut impl Write) ->
             )?;
         }
     } else {
-        let languages = if let Some(cutoff) = config.summary_cutoff {
-            apply_summary_cutoff(&summary.languages, cutoff)
-        } else {
-            summary.languages.clone()
-        };
+        let languages = &summary.languages;
         writeln!(out, "CREATE TABLE t (")?;
         writeln!(out, "    Language TEXT,")?;
         writeln!(out, "    nFiles INTEGER,")?;
@@ -439,7 +1114,7 @@ fn render_sql(summary: &Summary, config: &OutputConfig, out: &mut impl Write) ->
         writeln!(out, ");")?;
         writeln!(out)?;
 
-        for lang in &languages {
+        for lang in languages {
             writeln!(
                 out,
                 "INSERT INTO t VALUES ('{}', {}, {}, {}, {});",
@@ -494,13 +1169,9 @@ fn render_xml(summary: &Summary, config: &OutputConfig, out: &mut impl Write) ->
         }
         writeln!(out, "  </files>")?;
     } else {
-        let languages = if let Some(cutoff) = config.summary_cutoff {
-            apply_summary_cutoff(&summary.languages, cutoff)
-        } else {
-            summary.languages.clone()
-        };
+        let languages = &summary.languages;
         writeln!(out, "  <languages>")?;
-        for lang in &languages {
+        for lang in languages {
             writeln!(out, "    <language name=\"{}\">", escape_xml(&lang.name))?;
             writeln!(out, "      <files>{}</files>", lang.files)?;
             writeln!(out, "      <blank>{}</blank>", lang.blanks)?;
@@ -522,6 +1193,1011 @@ fn render_xml(summary: &Summary, config: &OutputConfig, out: &mut impl Write) ->
     Ok(())
 }
 
+fn render_badge(summary: &Summary, _config: &OutputConfig, out: &mut impl Write) -> io::Result<()> {
+    let badge = Badge::lines_of_code(summary.total_code);
+    let json = serde_json::to_string(&badge).map_err(io::Error::other)?;
+    writeln!(out, "{}", json)?;
+    Ok(())
+}
+
+/// Render the summary through a user-provided Handlebars template.
+///
+/// The template sees `languages`, `files`, `total`, `elapsed_seconds`,
+/// `files_per_second`, `lines_per_second` and `version`.
+fn render_template(
+    summary: &Summary,
+    _config: &OutputConfig,
+    template: &std::path::Path,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let source = std::fs::read_to_string(template).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read template {}: {}", template.display(), e),
+        )
+    })?;
+
+    let mut handlebars = handlebars::Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars
+        .register_template_string("output", source)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    let languages = &summary.languages;
+
+    let context = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "languages": languages
+            .iter()
+            .map(|l| serde_json::json!({
+                "name": l.name,
+                "files": l.files,
+                "code": l.code,
+                "comments": l.comments,
+                "blanks": l.blanks,
+                "total": l.total(),
+            }))
+            .collect::<Vec<_>>(),
+        "files": summary.file_stats,
+        "total": {
+            "files": summary.total_files,
+            "code": summary.total_code,
+            "comments": summary.total_comments,
+            "blanks": summary.total_blanks,
+            "lines": summary.total_lines(),
+        },
+        "elapsed_seconds": summary.elapsed.map(|d| d.as_secs_f64()),
+        "files_per_second": summary.files_per_second(),
+        "lines_per_second": summary.lines_per_second(),
+    });
+
+    handlebars
+        .render_to_write("output", &context, out)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Render languages with their per-project rows nested below them (`--by-project`).
+///
+/// Supported for table, Markdown, CSV, JSON and YAML output.
+pub fn render_projects_to(
+    summary: &Summary,
+    nested: &[(LanguageStats, Vec<LanguageStats>)],
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let columns = config.effective_columns();
+    let values = |row: &LanguageStats| -> Vec<String> {
+        columns
+            .iter()
+            .map(|c| c.language_value(row).to_string())
+            .collect()
+    };
+    let totals: Vec<String> = columns
+        .iter()
+        .map(|c| c.summary_value(summary).to_string())
+        .collect();
+
+    match config.format {
+        OutputFormat::Table => {
+            let mut table = new_table(config);
+            let mut headers = vec![Cell::new("Language / Project").add_attribute(Attribute::Bold)];
+            headers.extend(
+                columns
+                    .iter()
+                    .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold)),
+            );
+            table.set_header(headers);
+
+            for (lang, projects) in nested {
+                let mut row = vec![Cell::new(&lang.name).add_attribute(Attribute::Bold)];
+                row.extend(
+                    values(lang)
+                        .into_iter()
+                        .map(|v| Cell::new(v).add_attribute(Attribute::Bold)),
+                );
+                table.add_row(row);
+                for project in projects {
+                    let mut row = vec![Cell::new(format!("  {}", project.name))];
+                    row.extend(values(project).into_iter().map(Cell::new));
+                    table.add_row(row);
+                }
+            }
+            let mut sum_row = vec![Cell::new("SUM").add_attribute(Attribute::Bold)];
+            sum_row.extend(
+                totals
+                    .into_iter()
+                    .map(|v| Cell::new(v).add_attribute(Attribute::Bold)),
+            );
+            table.add_row(sum_row);
+
+            writeln!(out)?;
+            writeln!(out, "{}", table)
+        }
+        OutputFormat::Markdown => {
+            let mut headers = vec!["Language / Project"];
+            headers.extend(columns.iter().map(|c| c.header()));
+            writeln!(out, "| {} |", headers.join(" | "))?;
+            writeln!(out, "| :--- |{}", " ---: |".repeat(columns.len()))?;
+            for (lang, projects) in nested {
+                writeln!(out, "| **{}** | {} |", lang.name, values(lang).join(" | "))?;
+                for project in projects {
+                    writeln!(
+                        out,
+                        "| &nbsp;&nbsp;{} | {} |",
+                        project.name,
+                        values(project).join(" | ")
+                    )?;
+                }
+            }
+            writeln!(out, "| **SUM** | **{}** |", totals.join("** | **"))
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(config.csv_delimiter)
+                .from_writer(out);
+            let mut header = vec!["Language", "Project"];
+            header.extend(columns.iter().map(|c| c.header()));
+            writer.write_record(&header)?;
+            for (lang, projects) in nested {
+                for project in projects {
+                    let mut record = vec![lang.name.clone(), project.name.clone()];
+                    record.extend(values(project));
+                    writer.write_record(&record)?;
+                }
+            }
+            let mut record = vec!["SUM".to_string(), String::new()];
+            record.extend(totals);
+            writer.write_record(&record)?;
+            writer.flush()?;
+            Ok(())
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let counts = |row: &LanguageStats| {
+                serde_json::json!({
+                    "nFiles": row.files,
+                    "blank": row.blanks,
+                    "comment": row.comments,
+                    "code": row.code,
+                })
+            };
+            let mut map = serde_json::Map::new();
+            for (lang, projects) in nested {
+                let mut entry = counts(lang);
+                entry["projects"] = projects
+                    .iter()
+                    .map(|p| (p.name.clone(), counts(p)))
+                    .collect::<serde_json::Map<_, _>>()
+                    .into();
+                map.insert(lang.name.clone(), entry);
+            }
+            map.insert(
+                "SUM".to_string(),
+                serde_json::to_value(&JsonOutput::from(summary).sum).map_err(io::Error::other)?,
+            );
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(&map).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)
+            } else {
+                let yaml = serde_yaml::to_string(&map).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--by-project supports table, md, csv, json and yaml output",
+        )),
+    }
+}
+
+/// Render one report per analyzed root followed by the grand total (`--group-by-path`).
+///
+/// Supported for table, Markdown, CSV, JSON and YAML output.
+pub fn render_groups_to(
+    groups: &[(String, Summary)],
+    total: &Summary,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Table | OutputFormat::Markdown => {
+            let heading = |path: &str| match config.format {
+                OutputFormat::Markdown => format!("### {}\n", path),
+                _ => format!("{}:", path),
+            };
+            for (path, summary) in groups {
+                writeln!(out)?;
+                writeln!(out, "{}", heading(path))?;
+                render_to(summary, config, out)?;
+            }
+            writeln!(out)?;
+            writeln!(out, "{}", heading("Total"))?;
+            render_to(total, config, out)
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(config.csv_delimiter)
+                .from_writer(out);
+            let columns = config.effective_columns();
+            let mut header = vec!["Path", row_label(config)];
+            header.extend(columns.iter().map(|c| c.header()));
+            writer.write_record(&header)?;
+
+            let total_group = ("Total".to_string(), total.clone());
+            for (path, summary) in groups.iter().chain(std::iter::once(&total_group)) {
+                let summary = prepare_summary(summary, config);
+                for lang in &summary.languages {
+                    let mut record = vec![path.clone(), lang.name.clone()];
+                    record.extend(columns.iter().map(|c| c.language_value(lang).to_string()));
+                    writer.write_record(&record)?;
+                }
+                let mut record = vec![path.clone(), "SUM".to_string()];
+                record.extend(
+                    columns
+                        .iter()
+                        .map(|c| c.summary_value(&summary).to_string()),
+                );
+                writer.write_record(&record)?;
+            }
+            writer.flush()?;
+            Ok(())
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let prepared: Vec<(String, Summary)> = groups
+                .iter()
+                .map(|(path, summary)| {
+                    (path.clone(), prepare_summary(summary, config).into_owned())
+                })
+                .collect();
+            let output = JsonGroupedOutput::new(&prepared, &prepare_summary(total, config));
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(&output).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)
+            } else {
+                let yaml = serde_yaml::to_string(&output).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--group-by-path supports table, md, csv, json and yaml output",
+        )),
+    }
+}
+
+/// Render the steps deciding whether a file is counted (`rloc explain`): JSON with
+/// `--json`, otherwise one line per step and the verdict.
+pub fn render_explanation_to(
+    explanation: &Explanation,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    if config.format == OutputFormat::Json {
+        let json = serde_json::to_string_pretty(explanation).map_err(io::Error::other)?;
+        return writeln!(out, "{}", json);
+    }
+
+    writeln!(out, "{}", explanation.path.display())?;
+    let width = explanation
+        .steps
+        .iter()
+        .map(|step| step.check.len())
+        .max()
+        .unwrap_or(0);
+    for step in &explanation.steps {
+        writeln!(
+            out,
+            "  {:<8}  {:<width$}  {}",
+            step.outcome.name(),
+            step.check,
+            step.detail
+        )?;
+    }
+    match (explanation.counted, explanation.language) {
+        (true, Some(language)) => writeln!(out, "Counted as {}", language),
+        _ => writeln!(out, "Not counted"),
+    }
+}
+
+/// Render the files that would be counted with their detected languages (`--list-only`):
+/// one object per file in a JSON array or NDJSON lines, otherwise `path<TAB>language` lines.
+pub fn render_file_list_to(
+    files: &[FileEntry],
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let entry = |file: &FileEntry| {
+        serde_json::json!({
+            "path": file.path.display().to_string(),
+            "language": file.language.name,
+        })
+    };
+    match config.format {
+        OutputFormat::Json => {
+            let entries: Vec<_> = files.iter().map(entry).collect();
+            let json = serde_json::to_string_pretty(&entries).map_err(io::Error::other)?;
+            writeln!(out, "{}", json)
+        }
+        OutputFormat::Ndjson => {
+            for file in files {
+                writeln!(out, "{}", entry(file))?;
+            }
+            Ok(())
+        }
+        _ => {
+            for file in files {
+                writeln!(out, "{}\t{}", file.path.display(), file.language.name)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Render files ranked by `code * commits` (`--churn`).
+pub fn render_hotspots_to(
+    hotspots: &[Hotspot],
+    since: Option<&str>,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Table => {
+            let mut table = new_table(config);
+            table.set_header(
+                ["Rank", "File", "Language", "Code", "Commits", "Score"]
+                    .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+            );
+            for (rank, hotspot) in hotspots.iter().enumerate() {
+                table.add_row(vec![
+                    Cell::new(rank + 1),
+                    Cell::new(&hotspot.path),
+                    Cell::new(&hotspot.language),
+                    code_cell(Cell::new(hotspot.code), config),
+                    Cell::new(hotspot.commits),
+                    Cell::new(hotspot.score),
+                ]);
+            }
+            writeln!(out)?;
+            writeln!(out, "{}", table)
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let report = HotspotReport::new(hotspots, since);
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)
+            } else {
+                let yaml = serde_yaml::to_string(&report).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--churn supports table, json and yaml output",
+        )),
+    }
+}
+
+/// Print the median phase times and throughput for each thread count (`rloc bench`).
+pub fn render_bench_to(
+    report: &BenchReport,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Table => {
+            let runs = report.results.first().map_or(0, |result| result.runs.len());
+            writeln!(
+                out,
+                "{} files, {} bytes, {} lines; median of {} runs",
+                report.files, report.bytes, report.lines, runs
+            )?;
+            let mut table = new_table(config);
+            table.set_header(
+                [
+                    "Threads",
+                    "Walk",
+                    "Read",
+                    "Classify",
+                    "Aggregate",
+                    "Total",
+                    "MB/s",
+                    "Lines/s",
+                ]
+                .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+            );
+            let secs = |secs: f64| Cell::new(format!("{:.3}s", secs));
+            for result in &report.results {
+                let median = &result.median;
+                table.add_row(vec![
+                    Cell::new(result.threads),
+                    secs(median.walk),
+                    secs(median.read),
+                    secs(median.classify),
+                    secs(median.aggregate),
+                    secs(median.total()),
+                    Cell::new(format!("{:.1}", result.bytes_per_sec / 1_000_000.0)),
+                    Cell::new(format!("{:.0}", result.lines_per_sec)),
+                ]);
+            }
+            writeln!(out)?;
+            writeln!(out, "{}", table)?;
+            if let Some(fastest) = report.fastest() {
+                writeln!(out, "Fastest with --threads {}", fastest.threads)?;
+            }
+            Ok(())
+        }
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(report).map_err(io::Error::other)?;
+            writeln!(out, "{}", json)
+        }
+        OutputFormat::Yaml => {
+            let yaml = serde_yaml::to_string(report).map_err(io::Error::other)?;
+            write!(out, "{}", yaml)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "bench supports table, json and yaml output",
+        )),
+    }
+}
+
+/// Print a file with a gutter holding each line's number and how it is counted
+/// (`rloc annotate`).
+pub fn render_annotation_to(
+    path: &Path,
+    language: &Language,
+    content: &[u8],
+    lines: &[(usize, LineKind)],
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Table => {
+            let width = lines.last().map_or(1, |&(line, _)| line.to_string().len());
+            for (&(line, kind), source) in lines.iter().zip(counter::split_lines(content)) {
+                let source = String::from_utf8_lossy(source);
+                let gutter = format!("{:>width$} {:<8} |", line, kind.name());
+                if source.is_empty() {
+                    writeln!(out, "{}", gutter)?;
+                } else {
+                    writeln!(out, "{} {}", gutter, source)?;
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let annotation = Annotation::new(path, language, lines);
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(&annotation).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)
+            } else {
+                let yaml = serde_yaml::to_string(&annotation).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "annotate supports table, json and yaml output",
+        )),
+    }
+}
+
+/// Render recorded runs with their code deltas, then a sparkline per language
+/// (`rloc trend show`).
+pub fn render_trend_to(
+    entries: &[TrendEntry],
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let points = trend::points(entries);
+    match config.format {
+        OutputFormat::Table => {
+            let mut runs = new_table(config);
+            runs.set_header(
+                ["Date (UTC)", "Commit", "Files", "Code", "Change"]
+                    .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+            );
+            for (index, point) in points.iter().enumerate() {
+                let commit = point.commit.as_deref().unwrap_or_default();
+                runs.add_row(vec![
+                    Cell::new(trend::format_timestamp(point.timestamp)),
+                    Cell::new(&commit[..commit.len().min(10)]),
+                    Cell::new(point.files),
+                    code_cell(Cell::new(point.code), config),
+                    Cell::new(if index == 0 {
+                        String::new()
+                    } else {
+                        format_signed(point.code_delta)
+                    }),
+                ]);
+            }
+
+            // Languages of the latest run first, then any that have since disappeared.
+            let summaries: Vec<Summary> = entries.iter().map(TrendEntry::summary).collect();
+            let mut names: Vec<&str> = Vec::new();
+            for summary in summaries.iter().rev() {
+                for lang in &summary.languages {
+                    if !names.contains(&lang.name.as_str()) {
+                        names.push(&lang.name);
+                    }
+                }
+            }
+            let mut trends = new_table(config);
+            trends.set_header(
+                ["Language", "Trend", "First", "Latest", "Change"]
+                    .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+            );
+            let mut add_trend = |name: &str, values: Vec<u64>| {
+                let first = values.first().copied().unwrap_or_default();
+                let latest = values.last().copied().unwrap_or_default();
+                trends.add_row(vec![
+                    Cell::new(name),
+                    Cell::new(trend::sparkline(&values)),
+                    Cell::new(first),
+                    Cell::new(latest),
+                    Cell::new(format_signed(latest as i64 - first as i64)),
+                ]);
+            };
+            for name in names {
+                let values = summaries
+                    .iter()
+                    .map(|s| {
+                        s.languages
+                            .iter()
+                            .find(|l| l.name == name)
+                            .map_or(0, |l| l.code)
+                    })
+                    .collect();
+                add_trend(name, values);
+            }
+            add_trend("SUM", points.iter().map(|p| p.code).collect());
+
+            writeln!(out)?;
+            writeln!(out, "{}", runs)?;
+            writeln!(out)?;
+            writeln!(out, "{}", trends)
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let report = TrendReport::new(entries);
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)
+            } else {
+                let yaml = serde_yaml::to_string(&report).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)
+            }
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "trend show supports table, json and yaml output",
+        )),
+    }
+}
+
+/// Render the files that changed since a snapshot (`rloc snapshot compare`).
+pub fn render_snapshot_diff_to(
+    diff: &SnapshotDiff,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Table => {
+            let mut table = new_table(config);
+            table.set_header(
+                ["File", "Status", "Language", "Before", "After", "Change"]
+                    .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+            );
+            for file in &diff.files {
+                table.add_row(vec![
+                    Cell::new(&file.path),
+                    Cell::new(file.change.as_str()),
+                    Cell::new(&file.language),
+                    Cell::new(file.code_before),
+                    code_cell(Cell::new(file.code_after), config),
+                    Cell::new(format_signed(file.code_delta)),
+                ]);
+            }
+
+            writeln!(out)?;
+            if !diff.files.is_empty() {
+                writeln!(out, "{}", table)?;
+            }
+            writeln!(
+                out,
+                "{} added, {} removed, {} changed, {} unchanged; code {}, comment {}, blank {}",
+                diff.added,
+                diff.removed,
+                diff.changed,
+                diff.unchanged,
+                format_signed(diff.code_delta),
+                format_signed(diff.comment_delta),
+                format_signed(diff.blank_delta)
+            )
+        }
+        OutputFormat::Json => {
+            let json = serde_json::to_string_pretty(diff).map_err(io::Error::other)?;
+            writeln!(out, "{}", json)
+        }
+        OutputFormat::Yaml => {
+            let yaml = serde_yaml::to_string(diff).map_err(io::Error::other)?;
+            write!(out, "{}", yaml)
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "snapshot compare supports table, json and yaml output",
+        )),
+    }
+}
+
+/// Render the code lines same, modified, added and removed per language (`rloc diff`,
+/// `--diff`), followed by each file's status with `--by-file`.
+pub fn render_diff_to(
+    result: &DiffResult,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let languages = result.languages_by_code();
+    match config.format {
+        OutputFormat::Table => {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{:<14} {:>10} {:>10} {:>10} {:>10}",
+                "Language", "Same", "Modified", "Added", "Removed"
+            )?;
+            writeln!(out, "{}", "─".repeat(58))?;
+
+            for (lang, stats) in languages {
+                writeln!(
+                    out,
+                    "{:<14} {:>10} {:>10} {:>10} {:>10}",
+                    lang,
+                    format_diff_count(stats.same.code),
+                    format_diff_count(stats.modified.code),
+                    format_diff_count(stats.added.code),
+                    format_diff_count(stats.removed.code),
+                )?;
+            }
+
+            writeln!(out, "{}", "─".repeat(58))?;
+            writeln!(
+                out,
+                "{:<14} {:>10} {:>10} {:>10} {:>10}",
+                "SUM",
+                format_diff_count(result.totals.same.code),
+                format_diff_count(result.totals.modified.code),
+                format_diff_count(result.totals.added.code),
+                format_diff_count(result.totals.removed.code),
+            )?;
+
+            if config.by_file {
+                let width = result
+                    .files
+                    .iter()
+                    .map(|file| file.path.display().to_string().chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max("File".len());
+                writeln!(out)?;
+                writeln!(
+                    out,
+                    "{:<width$}  {:<14} {:<9} {:>10} {:>10}",
+                    "File", "Language", "Status", "Old code", "New code"
+                )?;
+                writeln!(out, "{}", "─".repeat(width + 48))?;
+                for file in &result.files {
+                    writeln!(
+                        out,
+                        "{:<width$}  {:<14} {:<9} {:>10} {:>10}",
+                        file.path.display().to_string(),
+                        file.language,
+                        file.status.name(),
+                        file.old.map_or("-".to_string(), |old| old.code.to_string()),
+                        file.new.map_or("-".to_string(), |new| new.code.to_string()),
+                    )?;
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let without_files;
+            let result = if config.by_file {
+                result
+            } else {
+                without_files = DiffResult {
+                    files: Vec::new(),
+                    ..result.clone()
+                };
+                &without_files
+            };
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(result).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)
+            } else {
+                let yaml = serde_yaml::to_string(result).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(config.csv_delimiter)
+                .from_writer(out);
+            if config.by_file {
+                writer.write_record([
+                    "File",
+                    "Language",
+                    "Status",
+                    "Old blank",
+                    "Old comment",
+                    "Old code",
+                    "New blank",
+                    "New comment",
+                    "New code",
+                ])?;
+                let counts = |counts: Option<LineCounts>| {
+                    counts.map_or([String::new(), String::new(), String::new()], |c| {
+                        [
+                            c.blanks.to_string(),
+                            c.comments.to_string(),
+                            c.code.to_string(),
+                        ]
+                    })
+                };
+                for file in &result.files {
+                    let mut record = vec![
+                        file.path.display().to_string(),
+                        file.language.clone(),
+                        file.status.name().to_string(),
+                    ];
+                    record.extend(counts(file.old));
+                    record.extend(counts(file.new));
+                    writer.write_record(record)?;
+                }
+            } else {
+                writer.write_record(["Language", "Same", "Modified", "Added", "Removed"])?;
+                let rows = languages
+                    .into_iter()
+                    .chain(std::iter::once(("SUM", &result.totals)));
+                for (lang, stats) in rows {
+                    writer.write_record([
+                        lang.to_string(),
+                        stats.same.code.to_string(),
+                        stats.modified.code.to_string(),
+                        stats.added.code.to_string(),
+                        stats.removed.code.to_string(),
+                    ])?;
+                }
+            }
+            writer.flush()
+        }
+        OutputFormat::Markdown => {
+            writeln!(out, "| Language | Same | Modified | Added | Removed |")?;
+            writeln!(out, "| :--- | ---: | ---: | ---: | ---: |")?;
+            for (lang, stats) in languages {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} |",
+                    lang,
+                    stats.same.code,
+                    stats.modified.code,
+                    stats.added.code,
+                    stats.removed.code
+                )?;
+            }
+            let totals = &result.totals;
+            writeln!(
+                out,
+                "| **SUM** | **{}** | **{}** | **{}** | **{}** |",
+                totals.same.code, totals.modified.code, totals.added.code, totals.removed.code
+            )?;
+            if config.by_file {
+                writeln!(out)?;
+                writeln!(out, "| File | Language | Status | Old code | New code |")?;
+                writeln!(out, "| :--- | :--- | :--- | ---: | ---: |")?;
+                for file in &result.files {
+                    writeln!(
+                        out,
+                        "| {} | {} | {} | {} | {} |",
+                        file.path.display(),
+                        file.language,
+                        file.status.name(),
+                        file.old.map_or("-".to_string(), |old| old.code.to_string()),
+                        file.new.map_or("-".to_string(), |new| new.code.to_string()),
+                    )?;
+                }
+            }
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "diff supports table, json, yaml, csv and md output",
+        )),
+    }
+}
+
+fn format_diff_count(n: u64) -> String {
+    if n == 0 {
+        "-".to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+/// Render the per-language changes between two reports (`--diff-report`).
+pub fn render_delta_to(
+    delta: &ReportDelta,
+    config: &OutputConfig,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let rows = || delta.languages.iter().chain(std::iter::once(&delta.total));
+
+    match config.format {
+        OutputFormat::Table => {
+            let mut table = new_table(config);
+            table.set_header(
+                ["Language", "Files", "Blank", "Comment", "Code"]
+                    .map(|h| Cell::new(h).add_attribute(Attribute::Bold)),
+            );
+            for lang in rows() {
+                let mut row = vec![
+                    Cell::new(&lang.name),
+                    delta_cell(lang.files),
+                    delta_cell(lang.blanks),
+                    delta_cell(lang.comments),
+                    delta_cell(lang.code),
+                ];
+                if std::ptr::eq(lang, &delta.total) {
+                    row = row
+                        .into_iter()
+                        .map(|c| c.add_attribute(Attribute::Bold))
+                        .collect();
+                }
+                table.add_row(row);
+            }
+            writeln!(out)?;
+            writeln!(out, "{}", table)?;
+        }
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let map: serde_json::Map<String, serde_json::Value> = rows()
+                .map(|lang| {
+                    let value = serde_json::json!({
+                        "nFiles": lang.files,
+                        "blank": lang.blanks,
+                        "comment": lang.comments,
+                        "code": lang.code,
+                    });
+                    (lang.name.clone(), value)
+                })
+                .collect();
+            if config.format == OutputFormat::Json {
+                let json = serde_json::to_string_pretty(&map).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)?;
+            } else {
+                let yaml = serde_yaml::to_string(&map).map_err(io::Error::other)?;
+                write!(out, "{}", yaml)?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(config.csv_delimiter)
+                .from_writer(out);
+            writer.write_record(["Language", "Files", "Blank", "Comment", "Code"])?;
+            for lang in rows() {
+                writer.write_record([
+                    lang.name.clone(),
+                    lang.files.to_string(),
+                    lang.blanks.to_string(),
+                    lang.comments.to_string(),
+                    lang.code.to_string(),
+                ])?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Markdown => {
+            writeln!(out, "| Language | Files | Blank | Comment | Code |")?;
+            writeln!(out, "| :--- | ---: | ---: | ---: | ---: |")?;
+            for lang in &delta.languages {
+                writeln!(
+                    out,
+                    "| {} | {} | {} | {} | {} |",
+                    lang.name,
+                    format_signed(lang.files),
+                    format_signed(lang.blanks),
+                    format_signed(lang.comments),
+                    format_signed(lang.code)
+                )?;
+            }
+            let total = &delta.total;
+            writeln!(
+                out,
+                "| **SUM** | **{}** | **{}** | **{}** | **{}** |",
+                format_signed(total.files),
+                format_signed(total.blanks),
+                format_signed(total.comments),
+                format_signed(total.code)
+            )?;
+        }
+        OutputFormat::Sql => {
+            writeln!(out, "CREATE TABLE delta (")?;
+            writeln!(out, "    Language TEXT,")?;
+            writeln!(out, "    nFiles INTEGER,")?;
+            writeln!(out, "    nBlank INTEGER,")?;
+            writeln!(out, "    nComment INTEGER,")?;
+            writeln!(out, "    nCode INTEGER")?;
+            writeln!(out, ");")?;
+            writeln!(out)?;
+            for lang in rows() {
+                writeln!(
+                    out,
+                    "INSERT INTO delta VALUES ('{}', {}, {}, {}, {});",
+                    lang.name.replace('\'', "''"),
+                    lang.files,
+                    lang.blanks,
+                    lang.comments,
+                    lang.code
+                )?;
+            }
+        }
+        OutputFormat::Xml => {
+            let write_counts = |out: &mut dyn Write, lang: &LanguageDelta| -> io::Result<()> {
+                writeln!(out, "      <files>{}</files>", lang.files)?;
+                writeln!(out, "      <blank>{}</blank>", lang.blanks)?;
+                writeln!(out, "      <comment>{}</comment>", lang.comments)?;
+                writeln!(out, "      <code>{}</code>", lang.code)
+            };
+            writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(out, "<delta>")?;
+            writeln!(out, "  <languages>")?;
+            for lang in &delta.languages {
+                writeln!(out, "    <language name=\"{}\">", escape_xml(&lang.name))?;
+                write_counts(out, lang)?;
+                writeln!(out, "    </language>")?;
+            }
+            writeln!(out, "  </languages>")?;
+            writeln!(out, "  <total>")?;
+            write_counts(out, &delta.total)?;
+            writeln!(out, "  </total>")?;
+            writeln!(out, "</delta>")?;
+        }
+        OutputFormat::Badge => {
+            let magnitude = format_count(delta.total.code.unsigned_abs());
+            let message = match delta.total.code.signum() {
+                1 => format!("+{}", magnitude),
+                -1 => format!("-{}", magnitude),
+                _ => magnitude,
+            };
+            let badge = Badge::new("code change", message);
+            let json = serde_json::to_string(&badge).map_err(io::Error::other)?;
+            writeln!(out, "{}", json)?;
+        }
+        OutputFormat::Ndjson => {
+            for lang in rows() {
+                let json = serde_json::to_string(lang).map_err(io::Error::other)?;
+                writeln!(out, "{}", json)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A table cell for a change: green when it grew, red when it shrank.
+fn delta_cell(value: i64) -> Cell {
+    let cell = Cell::new(format_signed(value));
+    match value.signum() {
+        1 => cell.fg(Color::Green),
+        -1 => cell.fg(Color::Red),
+        _ => cell,
+    }
+}
+
+fn format_signed(value: i64) -> String {
+    if value > 0 {
+        format!("+{}", value)
+    } else {
+        value.to_string()
+    }
+}
+
 fn escape_xml(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -550,9 +2226,39 @@ mod tests {
             code: 100,
             comments: 20,
             blanks: 10,
+            ..Default::default()
         }])
     }
 
+    #[test]
+    fn test_delta_output() {
+        let old = sample_summary();
+        let mut new = sample_summary();
+        new.languages[0].code = 90;
+        new.total_code = 90;
+        let delta = ReportDelta::between(&old, &new);
+
+        let mut out = Vec::new();
+        let config = OutputConfig {
+            format: OutputFormat::Markdown,
+            ..Default::default()
+        };
+        render_delta_to(&delta, &config, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        assert!(output.contains("| Rust | 0 | 0 | 0 | -10 |"));
+        assert!(output.contains("| **SUM** | **0** | **0** | **0** | **-10** |"));
+
+        let mut out = Vec::new();
+        let config = OutputConfig {
+            color: true,
+            ..Default::default()
+        };
+        render_delta_to(&delta, &config, &mut out).unwrap();
+        let output = String::from_utf8(out).unwrap();
+        // Red, not the green of the code column.
+        assert!(output.contains("\x1b[38;5;9m -10"));
+    }
+
     #[test]
     fn test_json_output() {
         let summary = sample_summary();
@@ -563,6 +2269,154 @@ mod tests {
         assert!(json.get("SUM").is_some());
     }
 
+    #[test]
+    fn test_extended_json_output() {
+        let summary = Summary::from_file_stats(vec![FileStats {
+            path: "main.rs".into(),
+            language: "Rust".into(),
+            code: 3,
+            blanks: 1,
+            bytes: 64,
+            line_bytes: 60,
+            ..Default::default()
+        }]);
+
+        let mut output = Vec::new();
+        render_json(&summary, &OutputConfig::default(), &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert!(json["Rust"].get("total").is_none());
+        assert!(json["SUM"].get("bytes").is_none());
+
+        let config = OutputConfig {
+            extended_json: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        render_json(&summary, &config, &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["Rust"]["total"], 4);
+        assert_eq!(json["Rust"]["bytes"], 64);
+        assert_eq!(json["Rust"]["avg_line_len"], 15.0);
+        assert_eq!(json["SUM"]["total"], 4);
+        assert_eq!(json["SUM"]["bytes"], 64);
+    }
+
+    #[test]
+    fn test_json_by_file_output() {
+        let summary = Summary::from_file_stats(vec![FileStats {
+            path: "main.rs".into(),
+            language: "Rust".into(),
+            code: 3,
+            comments: 1,
+            bytes: 64,
+            line_bytes: 60,
+            max_line_length: 30,
+            ..Default::default()
+        }]);
+        let config = OutputConfig {
+            by_file: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        render_json(&summary, &config, &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["main.rs"]["language"], "Rust");
+        assert_eq!(json["main.rs"]["bytes"], 64);
+        assert_eq!(json["main.rs"]["avg_line_length"], 15.0);
+        assert_eq!(json["main.rs"]["max_line_length"], 30);
+        assert_eq!(json["SUM"]["code"], 3);
+    }
+
+    fn two_language_summary() -> Summary {
+        let file = |path: &str, language: &str, code| FileStats {
+            path: path.into(),
+            language: language.into(),
+            code,
+            blanks: 1,
+            ..Default::default()
+        };
+        Summary::from_file_stats(vec![
+            file("a.rs", "Rust", 10),
+            file("run.sh", "Shell", 4),
+            file("b.rs", "Rust", 30),
+        ])
+    }
+
+    #[test]
+    fn test_by_file_by_lang_markdown() {
+        let config = OutputConfig {
+            format: OutputFormat::Markdown,
+            by_file_by_lang: true,
+            columns: vec![Column::Blanks, Column::Code],
+            hide_rate: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        render_to(&two_language_summary(), &config, &mut output).unwrap();
+        let lines: Vec<_> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "| Language / File | Blank | Code |",
+                "| :--- | ---: | ---: |",
+                "| **Rust** | | |",
+                "| &nbsp;&nbsp;b.rs | 1 | 30 |",
+                "| &nbsp;&nbsp;a.rs | 1 | 10 |",
+                "| **SUM: Rust** | **2** | **40** |",
+                "| **Shell** | | |",
+                "| &nbsp;&nbsp;run.sh | 1 | 4 |",
+                "| **SUM: Shell** | **1** | **4** |",
+                "| **SUM** | **3** | **44** |",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_by_file_by_lang_json() {
+        let config = OutputConfig {
+            by_file_by_lang: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        render_json(&two_language_summary(), &config, &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["Rust"]["files"]["b.rs"]["code"], 30);
+        assert_eq!(json["Rust"]["SUM"]["nFiles"], 2);
+        assert_eq!(json["Rust"]["SUM"]["code"], 40);
+        assert_eq!(json["Shell"]["SUM"]["code"], 4);
+        assert_eq!(json["SUM"]["code"], 44);
+    }
+
+    #[test]
+    fn test_table_color_control() {
+        let render = |config: OutputConfig| {
+            let mut output = Vec::new();
+            render_to(&sample_summary(), &config, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let plain = render(OutputConfig::default());
+        assert!(!plain.contains('\x1b'));
+
+        let green = render(OutputConfig {
+            color: true,
+            ..Default::default()
+        });
+        assert!(green.contains('\x1b'));
+
+        let cyan = render(OutputConfig {
+            color: true,
+            code_color: Some(Color::Cyan),
+            ..Default::default()
+        });
+        assert!(cyan.contains('\x1b'));
+        assert_ne!(cyan, green);
+    }
+
     #[test]
     fn test_csv_output() {
         let summary = sample_summary();
@@ -573,4 +2427,303 @@ mod tests {
         assert!(csv.contains("Rust"));
         assert!(csv.contains("SUM"));
     }
+
+    #[test]
+    fn test_template_output() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let template = dir.path().join("report.hbs");
+        std::fs::write(
+            &template,
+            "{{#each languages}}{{name}}={{code}};{{/each}}total={{total.code}}",
+        )
+        .unwrap();
+
+        let summary = sample_summary();
+        let config = OutputConfig {
+            template: Some(template),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        render_to(&summary, &config, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Rust=100;total=100");
+    }
+
+    #[test]
+    fn test_ndjson_output() {
+        let summary = sample_summary();
+        let mut output = Vec::new();
+        render_ndjson(&summary, &OutputConfig::default(), &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["path"], "main.rs");
+        assert_eq!(json["language"], "Rust");
+        assert_eq!(json["code"], 100);
+    }
+
+    #[test]
+    fn test_percent_fields_in_machine_formats() {
+        let config = |format| OutputConfig {
+            format,
+            by_percent: true,
+            hide_rate: true,
+            ..Default::default()
+        };
+        let render = |config: &OutputConfig| {
+            let mut output = Vec::new();
+            render_to(&two_language_summary(), config, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&config(OutputFormat::Json))).unwrap();
+        assert_eq!(json["Rust"]["files_pct"], 66.67);
+        assert_eq!(json["Rust"]["code_pct"], 90.91);
+        assert_eq!(json["Shell"]["code_pct"], 9.09);
+
+        let yaml = render(&config(OutputFormat::Yaml));
+        assert!(yaml.contains("code_pct: 90.91"));
+
+        let csv = render(&config(OutputFormat::Csv));
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Language,Files,Blank,Comment,Code,Files %,Code %")
+        );
+        assert_eq!(lines.next(), Some("Rust,2,2,0,40,66.67,90.91"));
+        assert_eq!(lines.last(), Some("SUM,3,3,0,44,100.00,100.00"));
+
+        let markdown = render(&config(OutputFormat::Markdown));
+        assert!(markdown.contains("| Rust | 2 | 2 | 0 | 40 | 66.67 | 90.91 |"));
+    }
+
+    #[test]
+    fn test_output_is_byte_stable() {
+        let file = |path: &str, language: &str| FileStats {
+            path: path.into(),
+            language: language.into(),
+            code: 5,
+            ..Default::default()
+        };
+        let files = vec![
+            file("a.py", "Python"),
+            file("b.go", "Go"),
+            file("c.rs", "Rust"),
+            file("d.c", "C"),
+        ];
+        let mut reversed = files.clone();
+        reversed.reverse();
+
+        for format in [
+            OutputFormat::Json,
+            OutputFormat::Yaml,
+            OutputFormat::Csv,
+            OutputFormat::Markdown,
+        ] {
+            for by_file in [false, true] {
+                let config = OutputConfig {
+                    format,
+                    by_file,
+                    ..Default::default()
+                };
+                let render = |files: &[FileStats]| {
+                    let mut output = Vec::new();
+                    render_to(
+                        &Summary::from_file_stats(files.to_vec()),
+                        &config,
+                        &mut output,
+                    )
+                    .unwrap();
+                    output
+                };
+                assert_eq!(render(&files), render(&reversed), "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_order_follows_sort() {
+        let summary = Summary::from_file_stats(vec![
+            FileStats {
+                path: "a.adb".into(),
+                language: "Ada".into(),
+                code: 1,
+                blanks: 9,
+                ..Default::default()
+            },
+            FileStats {
+                path: "z.zig".into(),
+                language: "Zig".into(),
+                code: 50,
+                ..Default::default()
+            },
+        ]);
+        let ada_first = |sort_by| {
+            let config = OutputConfig {
+                format: OutputFormat::Json,
+                sort_by,
+                ..Default::default()
+            };
+            let mut output = Vec::new();
+            render_to(&summary, &config, &mut output).unwrap();
+            let json = String::from_utf8(output).unwrap();
+            let position = |key: &str| json.find(&format!("\"{key}\"")).unwrap();
+            position("Ada") < position("Zig")
+        };
+
+        assert!(!ada_first(SortBy::Code));
+        assert!(ada_first(SortBy::Language));
+        assert!(ada_first(SortBy::Blanks));
+    }
+
+    #[test]
+    fn test_ratios() {
+        let summary = Summary::from_file_stats(vec![
+            FileStats {
+                path: "a.rs".into(),
+                language: "Rust".into(),
+                code: 30,
+                comments: 6,
+                blanks: 4,
+                ..Default::default()
+            },
+            FileStats {
+                path: "b.rs".into(),
+                language: "Rust".into(),
+                code: 10,
+                comments: 2,
+                blanks: 8,
+                ..Default::default()
+            },
+        ]);
+        let rust = &summary.languages[0];
+        assert_eq!(rust.comment_ratio(), 0.2);
+        assert_eq!(rust.blank_ratio(), 0.2);
+        assert_eq!(rust.avg_file_lines(), 30.0);
+
+        let config = |format| OutputConfig {
+            format,
+            show_ratios: true,
+            hide_rate: true,
+            ..Default::default()
+        };
+        let render = |config: &OutputConfig| {
+            let mut output = Vec::new();
+            render_to(&summary, config, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let csv = render(&config(OutputFormat::Csv));
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("Language,Files,Blank,Comment,Code,Comment Ratio,Blank Ratio,Lines/File")
+        );
+        assert_eq!(lines.next(), Some("Rust,2,12,8,40,0.20,0.20,30.00"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&config(OutputFormat::Json))).unwrap();
+        assert_eq!(json["Rust"]["comment_ratio"], 0.2);
+        assert_eq!(json["SUM"]["avg_file_lines"], 30.0);
+
+        let table = render(&config(OutputFormat::Table));
+        assert!(table.contains("Comment Ratio"));
+        assert!(table.contains("30.00"));
+    }
+
+    #[test]
+    fn test_csv_custom_columns() {
+        let summary = sample_summary();
+        let config = OutputConfig {
+            columns: vec![Column::Code, Column::Files],
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        render_csv(&summary, &config, &mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("Language,Code,Files"));
+        assert_eq!(lines.next(), Some("Rust,100,1"));
+        assert_eq!(lines.next(), Some("SUM,100,1"));
+    }
+
+    #[test]
+    fn test_fold_languages_top_and_percent() {
+        let lang = |name: &str, code| LanguageStats {
+            name: name.into(),
+            files: 1,
+            code,
+            ..Default::default()
+        };
+        let languages = vec![lang("Rust", 70), lang("Python", 25), lang("Shell", 5)];
+
+        let top = OutputConfig {
+            top: Some(1),
+            ..Default::default()
+        };
+        let folded = fold_languages(&languages, &top);
+        assert_eq!(folded.len(), 2);
+        assert_eq!(folded[0].name, "Rust");
+        assert_eq!(folded[1].name, "Other");
+        assert_eq!(folded[1].code, 30);
+        assert_eq!(folded[1].files, 2);
+
+        let percent = OutputConfig {
+            cutoff_percent: Some(10.0),
+            ..Default::default()
+        };
+        let folded = fold_languages(&languages, &percent);
+        let names: Vec<_> = folded.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Rust", "Python", "Other"]);
+        assert_eq!(folded[2].code, 5);
+    }
+
+    #[test]
+    fn test_folded_rows_keep_the_sort_order() {
+        let lang = |name: &str, files, code| LanguageStats {
+            name: name.into(),
+            files,
+            code,
+            ..Default::default()
+        };
+        let summary = Summary {
+            languages: vec![
+                lang("Rust", 1, 70),
+                lang("Python", 3, 25),
+                lang("Shell", 2, 5),
+                lang("C", 1, 1),
+            ],
+            ..Default::default()
+        };
+        let names = |config: &OutputConfig| -> Vec<String> {
+            let prepared = prepare_summary(&summary, config);
+            prepared.languages.iter().map(|l| l.name.clone()).collect()
+        };
+
+        let by_name = OutputConfig {
+            top: Some(2),
+            sort_by: SortBy::Language,
+            ..Default::default()
+        };
+        assert_eq!(names(&by_name), ["Python", "Rust", "Other"]);
+
+        let by_files = OutputConfig {
+            top: Some(3),
+            sort_by: SortBy::Files,
+            ..Default::default()
+        };
+        assert_eq!(names(&by_files), ["Python", "Shell", "Rust", "Other"]);
+    }
+
+    #[test]
+    fn test_badge_output() {
+        let summary = sample_summary();
+        let mut output = Vec::new();
+        render_badge(&summary, &OutputConfig::default(), &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(json["schemaVersion"], 1);
+        assert_eq!(json["label"], "lines of code");
+        assert_eq!(json["message"], "100");
+    }
 }
@@ -0,0 +1,125 @@
+//! Counting policy: where classified lines are tallied, applied to each file's counts before
+//! they are aggregated.
+//!
+//! The counter decides what a line is; a [`CountPolicy`] decides which bucket it is reported
+//! in. Comments of the languages listed in `comments_as_code` count as code, for languages
+//! whose comments carry meaning (SQL migration headers, license-heavy files). With
+//! `ignore_comments`, all other comment lines are folded into `blanks`, so files report only
+//! code and non-code lines.
+
+use crate::counter::{FileStats, LineKind};
+use crate::languages::get_language_ignore_case;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CountPolicy {
+    /// Languages whose comment lines count as code, matched ignoring case. Use
+    /// [`with_comments_as_code`](Self::with_comments_as_code) to resolve aliases (`cpp`).
+    pub comments_as_code: Vec<String>,
+    /// Count comment lines as blank (non-code) lines.
+    pub ignore_comments: bool,
+}
+
+impl CountPolicy {
+    /// Count the comments of `languages` as code. Aliases resolve to their language; unknown
+    /// names are kept as given, so custom languages match too.
+    pub fn with_comments_as_code(mut self, languages: &[String]) -> Self {
+        self.comments_as_code = languages
+            .iter()
+            .map(|name| match get_language_ignore_case(name) {
+                Some(language) => language.name.to_string(),
+                None => name.clone(),
+            })
+            .collect();
+        self
+    }
+
+    /// Whether counts are reported as classified.
+    pub fn is_default(&self) -> bool {
+        self.comments_as_code.is_empty() && !self.ignore_comments
+    }
+
+    /// Move `stats`' comment lines, and those of its embedded languages, to the bucket the
+    /// policy puts them in.
+    pub fn apply(&self, stats: &mut FileStats) {
+        if let Some(kind) = self.comment_kind(&stats.language) {
+            let comments = std::mem::take(&mut stats.comments);
+            stats.doc_comments = 0;
+            match kind {
+                LineKind::Code => stats.code += comments,
+                _ => stats.blanks += comments,
+            }
+        }
+        for child in &mut stats.embedded {
+            self.apply(child);
+        }
+    }
+
+    /// [`apply`](Self::apply) for a single line of `language`.
+    pub fn line_kind(&self, language: &str, kind: LineKind) -> LineKind {
+        match kind {
+            LineKind::Comment | LineKind::DocComment => self.comment_kind(language).unwrap_or(kind),
+            _ => kind,
+        }
+    }
+
+    /// What comments of `language` are reported as, if not as comments.
+    fn comment_kind(&self, language: &str) -> Option<LineKind> {
+        if self
+            .comments_as_code
+            .iter()
+            .any(|lang| lang.eq_ignore_ascii_case(language))
+        {
+            Some(LineKind::Code)
+        } else if self.ignore_comments {
+            Some(LineKind::Blank)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(language: &str) -> FileStats {
+        FileStats {
+            language: language.to_string(),
+            code: 10,
+            comments: 4,
+            doc_comments: 1,
+            blanks: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = CountPolicy::default().with_comments_as_code(&["sql".into(), "cpp".into()]);
+        assert_eq!(policy.comments_as_code, ["SQL", "C++"]);
+
+        let mut sql = stats("SQL");
+        sql.embedded.push(stats("Rust"));
+        policy.apply(&mut sql);
+        assert_eq!(
+            (sql.code, sql.comments, sql.doc_comments, sql.blanks),
+            (14, 0, 0, 2)
+        );
+        assert_eq!(sql.embedded[0].comments, 4);
+
+        let policy = CountPolicy {
+            ignore_comments: true,
+            ..policy
+        };
+        let mut rust = stats("Rust");
+        policy.apply(&mut rust);
+        assert_eq!((rust.code, rust.comments, rust.blanks), (10, 0, 6));
+        assert_eq!(
+            policy.line_kind("Rust", LineKind::DocComment),
+            LineKind::Blank
+        );
+        assert_eq!(policy.line_kind("C++", LineKind::Comment), LineKind::Code);
+        assert_eq!(policy.line_kind("Rust", LineKind::Code), LineKind::Code);
+        assert!(!policy.is_default() && CountPolicy::default().is_default());
+    }
+}
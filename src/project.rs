@@ -0,0 +1,204 @@
+//! Project boundaries for `--by-project`.
+//!
+//! A directory is a project when it holds a manifest naming one: a Cargo package, an npm
+//! package, a Go module or a Maven module. Workspace members are found through their own
+//! manifests, so a file belongs to the innermost project directory above it. Cargo
+//! workspace roots without a `[package]` are not projects themselves.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Label used for files outside every detected project.
+pub const NO_PROJECT: &str = "(no project)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    Cargo,
+    Npm,
+    Go,
+    Maven,
+}
+
+impl ProjectKind {
+    /// Manifest file names, in the order they are consulted within one directory.
+    pub const MANIFESTS: [(&'static str, ProjectKind); 4] = [
+        ("Cargo.toml", ProjectKind::Cargo),
+        ("package.json", ProjectKind::Npm),
+        ("go.mod", ProjectKind::Go),
+        ("pom.xml", ProjectKind::Maven),
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub name: String,
+    pub root: PathBuf,
+    pub kind: ProjectKind,
+}
+
+impl Project {
+    /// The project whose manifest lives directly in `dir`, if any.
+    pub fn detect(dir: &Path) -> Option<Project> {
+        ProjectKind::MANIFESTS.iter().find_map(|&(file, kind)| {
+            let content = std::fs::read_to_string(dir.join(file)).ok()?;
+            let name = match kind {
+                ProjectKind::Cargo => cargo_package_name(&content)?,
+                ProjectKind::Npm => npm_package_name(&content, dir)?,
+                ProjectKind::Go => go_module_name(&content)?,
+                ProjectKind::Maven => maven_artifact_id(&content)?,
+            };
+            Some(Project {
+                name,
+                root: dir.to_path_buf(),
+                kind,
+            })
+        })
+    }
+}
+
+fn cargo_package_name(manifest: &str) -> Option<String> {
+    let value: toml::Value = toml::from_str(manifest).ok()?;
+    Some(value.get("package")?.get("name")?.as_str()?.to_string())
+}
+
+/// Unnamed packages (e.g. private workspace roots) are named after their directory.
+fn npm_package_name(manifest: &str, dir: &Path) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(manifest).ok()?;
+    match value.get("name").and_then(|n| n.as_str()) {
+        Some(name) => Some(name.to_string()),
+        None => Some(dir.file_name()?.to_string_lossy().into_owned()),
+    }
+}
+
+fn go_module_name(manifest: &str) -> Option<String> {
+    manifest.lines().find_map(|line| {
+        let path = line.trim().strip_prefix("module")?;
+        path.starts_with(char::is_whitespace)
+            .then(|| path.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The module's own `<artifactId>`, ignoring the one inside `<parent>`.
+fn maven_artifact_id(pom: &str) -> Option<String> {
+    let without_parent = match (pom.find("<parent>"), pom.find("</parent>")) {
+        (Some(start), Some(end)) if start < end => {
+            format!("{}{}", &pom[..start], &pom[end + "</parent>".len()..])
+        }
+        _ => pom.to_string(),
+    };
+    let start = without_parent.find("<artifactId>")? + "<artifactId>".len();
+    let end = start + without_parent[start..].find("</artifactId>")?;
+    Some(without_parent[start..end].trim().to_string())
+}
+
+/// Maps files to the innermost project containing them, caching manifest lookups per
+/// directory. The search never climbs above the analyzed roots.
+#[derive(Debug, Default)]
+pub struct ProjectResolver {
+    roots: Vec<PathBuf>,
+    dirs: HashMap<PathBuf, Option<Project>>,
+}
+
+impl ProjectResolver {
+    pub fn new(roots: &[PathBuf]) -> Self {
+        Self {
+            roots: roots.to_vec(),
+            dirs: HashMap::new(),
+        }
+    }
+
+    pub fn project_for(&mut self, file: &Path) -> Option<Project> {
+        for dir in file.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            let project = self
+                .dirs
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| Project::detect(dir));
+            if let Some(project) = project {
+                return Some(project.clone());
+            }
+            if self.roots.iter().any(|root| root == dir) {
+                break;
+            }
+        }
+        None
+    }
+
+    /// Project name for `file`, or [`NO_PROJECT`].
+    pub fn project_name(&mut self, file: &Path) -> String {
+        self.project_for(file)
+            .map_or_else(|| NO_PROJECT.to_string(), |project| project.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_names() {
+        assert_eq!(
+            cargo_package_name("[package]\nname = \"core\"\n").as_deref(),
+            Some("core")
+        );
+        assert_eq!(cargo_package_name("[workspace]\nmembers = [\"a\"]\n"), None);
+        assert_eq!(
+            npm_package_name(r#"{"name": "@acme/web"}"#, Path::new("x")).as_deref(),
+            Some("@acme/web")
+        );
+        assert_eq!(
+            npm_package_name(r#"{"private": true}"#, Path::new("repo")).as_deref(),
+            Some("repo")
+        );
+        assert_eq!(
+            go_module_name("// comment\nmodule example.com/tool\n\ngo 1.22\n").as_deref(),
+            Some("example.com/tool")
+        );
+        assert_eq!(
+            maven_artifact_id(
+                "<project><parent><artifactId>parent</artifactId></parent>\
+                 <artifactId>service</artifactId></project>"
+            )
+            .as_deref(),
+            Some("service")
+        );
+    }
+
+    #[test]
+    fn test_resolver_finds_innermost_project() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("crates/core/src")).unwrap();
+        fs::create_dir_all(root.join("web/src")).unwrap();
+        fs::create_dir_all(root.join("scripts")).unwrap();
+        fs::write(
+            root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("crates/core/Cargo.toml"),
+            "[package]\nname = \"core\"\n",
+        )
+        .unwrap();
+        fs::write(root.join("web/package.json"), r#"{"name": "web"}"#).unwrap();
+
+        let mut resolver = ProjectResolver::new(&[root.to_path_buf()]);
+        let core = resolver
+            .project_for(&root.join("crates/core/src/lib.rs"))
+            .unwrap();
+        assert_eq!(
+            (core.name.as_str(), core.kind),
+            ("core", ProjectKind::Cargo)
+        );
+        assert_eq!(resolver.project_name(&root.join("web/src/app.ts")), "web");
+        assert_eq!(
+            resolver.project_name(&root.join("scripts/build.sh")),
+            NO_PROJECT
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Loading saved JSON reports.
+//!
+//! [`Report::from_json`] validates a report against the published schema
+//! ([`Report::SCHEMA`]) and upgrades older layouts to the current one:
+//!
+//! - reports without `schema_version` (older rloc releases and cloc) are accepted as version 0
+//! - per-file reports (`--by-file`) are rolled up into per-language totals
+//! - a missing `SUM` entry is recomputed from the languages
+
+use crate::stats::{
+    JsonFileStats, JsonHeader, JsonLanguageStats, JsonOutput, SCHEMA_VERSION, Summary, Warning,
+};
+use crate::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// A saved report, normalized to the current schema version.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The version the report was written with (0 if it predates versioning).
+    pub schema_version: u32,
+    pub header: Option<JsonHeader>,
+    pub languages: BTreeMap<String, JsonLanguageStats>,
+    pub sum: JsonLanguageStats,
+    /// Files the run that wrote the report could not read.
+    pub warnings: Vec<Warning>,
+    /// The run stopped early at a file or time limit.
+    pub truncated: bool,
+}
+
+impl Report {
+    /// JSON Schema describing the current report layout.
+    pub const SCHEMA: &'static str = include_str!("../schema/report.v1.json");
+
+    /// Parse and validate a report written by rloc (any version) or cloc.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(json).map_err(invalid)?;
+        let Value::Object(mut entries) = value else {
+            return Err(Error::InvalidReport(
+                "expected a JSON object at the top level".to_string(),
+            ));
+        };
+
+        let schema_version = match entries.remove("schema_version") {
+            None => 0,
+            Some(version) => {
+                let version = version
+                    .as_u64()
+                    .ok_or_else(|| invalid("schema_version must be a non-negative integer"))?;
+                if version > SCHEMA_VERSION as u64 {
+                    return Err(Error::InvalidReport(format!(
+                        "unsupported schema_version {} (this rloc reads up to {})",
+                        version, SCHEMA_VERSION
+                    )));
+                }
+                version as u32
+            }
+        };
+        let header = entries
+            .remove("header")
+            .map(|header| parse_entry::<JsonHeader>("header", header))
+            .transpose()?;
+        let sum = entries
+            .remove("SUM")
+            .map(|sum| parse_entry::<JsonLanguageStats>("SUM", sum))
+            .transpose()?;
+        let warnings = entries
+            .remove("warnings")
+            .map(|warnings| parse_entry::<Vec<Warning>>("warnings", warnings))
+            .transpose()?
+            .unwrap_or_default();
+        let truncated = entries
+            .remove("truncated")
+            .map(|truncated| parse_entry::<bool>("truncated", truncated))
+            .transpose()?
+            .unwrap_or(false);
+        if sum.is_none() && schema_version > 0 {
+            return Err(invalid("missing SUM entry"));
+        }
+
+        let languages = collect_languages(entries)?;
+        let sum = sum.unwrap_or_else(|| {
+            languages
+                .values()
+                .fold(JsonLanguageStats::default(), |mut sum, lang| {
+                    sum.n_files += lang.n_files;
+                    sum.blank += lang.blank;
+                    sum.comment += lang.comment;
+                    sum.code += lang.code;
+                    sum.logical += lang.logical;
+                    sum.tests += lang.tests;
+                    sum.ignored += lang.ignored;
+                    sum
+                })
+        });
+
+        Ok(Report {
+            schema_version,
+            header,
+            languages,
+            sum,
+            warnings,
+            truncated,
+        })
+    }
+
+    /// Rebuild language totals as a [`Summary`]. Per-file data is not kept.
+    pub fn to_summary(&self) -> Summary {
+        Summary::from(&JsonOutput::from(self.clone()))
+    }
+}
+
+impl From<Report> for JsonOutput {
+    fn from(report: Report) -> Self {
+        JsonOutput {
+            schema_version: SCHEMA_VERSION,
+            header: report.header,
+            languages: report.languages.into_iter().collect(),
+            sum: report.sum,
+            warnings: report.warnings,
+            truncated: report.truncated,
+        }
+    }
+}
+
+/// Language entries are kept as-is; per-file entries (those naming a `language`) are summed.
+fn collect_languages(entries: Map<String, Value>) -> Result<BTreeMap<String, JsonLanguageStats>> {
+    let mut languages: BTreeMap<String, JsonLanguageStats> = BTreeMap::new();
+    for (name, entry) in entries {
+        if entry.get("language").is_some() {
+            let file: JsonFileStats = parse_entry(&name, entry)?;
+            let lang = languages.entry(file.language).or_default();
+            lang.n_files += 1;
+            lang.blank += file.blank;
+            lang.comment += file.comment;
+            lang.code += file.code;
+            lang.ignored += file.ignored;
+        } else {
+            let stats: JsonLanguageStats = parse_entry(&name, entry)?;
+            let lang = languages.entry(name).or_default();
+            lang.n_files += stats.n_files;
+            lang.blank += stats.blank;
+            lang.comment += stats.comment;
+            lang.code += stats.code;
+            lang.logical += stats.logical;
+            lang.tests += stats.tests;
+            lang.ignored += stats.ignored;
+        }
+    }
+    Ok(languages)
+}
+
+fn parse_entry<T: DeserializeOwned>(name: &str, value: Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|e| invalid(format!("entry '{}': {}", name, e)))
+}
+
+fn invalid(message: impl ToString) -> Error {
+    Error::InvalidReport(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::FileStats;
+
+    #[test]
+    fn test_round_trip() {
+        let summary = Summary::from_file_stats(vec![FileStats {
+            path: "main.rs".into(),
+            language: "Rust".into(),
+            code: 10,
+            comments: 2,
+            ..Default::default()
+        }])
+        .with_warnings(vec![Warning {
+            path: "locked.rs".into(),
+            kind: "permission denied".into(),
+            message: "Permission denied (os error 13)".into(),
+        }]);
+        let json = serde_json::to_string(&JsonOutput::from(&summary)).unwrap();
+        assert!(json.contains("\"schema_version\":1"));
+        assert!(json.contains("\"warnings\":[{\"path\":\"locked.rs\""));
+
+        let report = Report::from_json(&json).unwrap();
+        assert_eq!(report.schema_version, SCHEMA_VERSION);
+        assert_eq!(report.languages["Rust"].code, 10);
+        assert_eq!(report.sum.comment, 2);
+        assert_eq!(report.to_summary().total_code, 10);
+        assert_eq!(report.warnings, summary.warnings);
+    }
+
+    #[test]
+    fn test_legacy_reports() {
+        let cloc = r#"{"header": {"cloc_version": "2.00"},
+            "Rust": {"nFiles": 1, "blank": 0, "comment": 0, "code": 12},
+            "SUM": {"nFiles": 1, "blank": 0, "comment": 0, "code": 12}}"#;
+        let report = Report::from_json(cloc).unwrap();
+        assert_eq!(report.schema_version, 0);
+        assert_eq!(report.header.unwrap().cloc_version, "2.00");
+
+        let by_file = r#"{"a.rs": {"language": "Rust", "blank": 1, "comment": 0, "code": 5},
+            "b.rs": {"language": "Rust", "blank": 0, "comment": 2, "code": 7}}"#;
+        let report = Report::from_json(by_file).unwrap();
+        let rust = &report.languages["Rust"];
+        assert_eq!((rust.n_files, rust.code, rust.comment), (2, 12, 2));
+        assert_eq!(report.sum.code, 12);
+    }
+
+    #[test]
+    fn test_invalid_reports() {
+        let err = |json: &str| Report::from_json(json).unwrap_err().to_string();
+
+        assert!(err("[]").contains("top level"));
+        assert!(
+            err(r#"{"schema_version": 99, "SUM": {}}"#).contains("unsupported schema_version 99")
+        );
+        assert!(err(r#"{"schema_version": 1}"#).contains("missing SUM"));
+        assert!(
+            err(r#"{"Rust": {"nFiles": 1, "blank": 0, "comment": 0, "code": 1, "cod": 2}}"#)
+                .contains("entry 'Rust'")
+        );
+        assert!(err(r#"{"Rust": {"nFiles": 1}}"#).contains("missing field"));
+    }
+
+    #[test]
+    fn test_schema_is_valid_json() {
+        let schema: Value = serde_json::from_str(Report::SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+    }
+}
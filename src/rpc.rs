@@ -0,0 +1,382 @@
+//! `rloc --stdio-rpc`: JSON-RPC 2.0 over stdin/stdout for editor extensions.
+//!
+//! Messages are newline-delimited JSON objects, one per line. Methods:
+//!
+//! - `analyze` `{path, options}`: the report `rloc --json` prints; `options` as for
+//!   `POST /analyze` in [`crate::serve`]. Runs in the background and can be cancelled.
+//! - `analyzeBuffer` `{text, path?, language?}`: counts for unsaved editor contents, as a
+//!   `--by-file` entry. The language is looked up by name or alias, else detected from `path`.
+//! - `detectLanguage` `{path}`: `{"language": name}`, or `null` for unknown files.
+//! - `cancel` `{id}`: abort the running `analyze` request `id`, which then fails with
+//!   [`REQUEST_CANCELLED`].
+//!
+//! Every method honours the command line's filters and counting and language options
+//! (`--exclude-dir`, `--force-lang`, `--docstrings-as-code`, ...); the `options` of
+//! `analyze` add to them. All `analyze` requests share one [`FileCache`]. The session ends
+//! at end of input, once running requests have answered. A line that is not valid JSON,
+//! UTF-8 included, is answered with [`PARSE_ERROR`], and a request that panics with
+//! [`INTERNAL_ERROR`].
+
+use crate::cache::FileCache;
+use crate::counter::{self, CountConfig};
+use crate::languages::get_language_ignore_case;
+use crate::serve::{self, AnalyzeRequest};
+use crate::walker::{self, WalkerConfig};
+use crate::{CancellationToken, Error};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+/// The analysis failed, e.g. because the path holds no source files.
+pub const ANALYSIS_FAILED: i64 = -32000;
+/// Same code as LSP's `RequestCancelled`.
+pub const REQUEST_CANCELLED: i64 = -32800;
+
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BufferParams {
+    text: String,
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DetectParams {
+    path: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CancelParams {
+    id: Value,
+}
+
+type RpcResult = std::result::Result<Value, (i64, String)>;
+
+/// One editor session; see the module docs for the protocol.
+#[derive(Debug, Clone)]
+pub struct RpcSession {
+    walker_config: Arc<WalkerConfig>,
+    count_config: Arc<CountConfig>,
+    cache: FileCache,
+    /// Cancellation tokens of running `analyze` requests, keyed by their serialized id.
+    running: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl RpcSession {
+    pub fn new(walker_config: WalkerConfig, count_config: CountConfig) -> Self {
+        RpcSession {
+            walker_config: Arc::new(walker_config),
+            count_config: Arc::new(count_config),
+            cache: FileCache::new(),
+            running: Arc::default(),
+        }
+    }
+
+    /// Answer the requests read from `input` on `output` until `input` ends.
+    pub fn run(
+        &self,
+        mut input: impl BufRead,
+        output: impl Write + Send + 'static,
+    ) -> io::Result<()> {
+        let output = Arc::new(Mutex::new(output));
+        let mut workers: Vec<JoinHandle<io::Result<()>>> = Vec::new();
+        let mut line = Vec::new();
+
+        loop {
+            line.clear();
+            if input.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let (finished, running) = workers.into_iter().partition(|w| w.is_finished());
+            workers = running;
+            for worker in finished {
+                join(worker)?;
+            }
+
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let message: Message = match serde_json::from_slice::<Value>(&line) {
+                Err(e) => {
+                    send(&output, &Value::Null, Err((PARSE_ERROR, e.to_string())))?;
+                    continue;
+                }
+                Ok(value) => match serde_json::from_value(value) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        send(&output, &Value::Null, Err((INVALID_REQUEST, e.to_string())))?;
+                        continue;
+                    }
+                },
+            };
+
+            if message.method == "analyze" {
+                let Some(id) = message.id else { continue };
+                let token = CancellationToken::new();
+                self.running
+                    .lock()
+                    .unwrap()
+                    .insert(id.to_string(), token.clone());
+                let (session, output) = (self.clone(), Arc::clone(&output));
+                workers.push(thread::spawn(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        session.analyze(message.params, token)
+                    }))
+                    .unwrap_or_else(|_| Err((INTERNAL_ERROR, "the analysis panicked".to_string())));
+                    session.running.lock().unwrap().remove(&id.to_string());
+                    send(&output, &id, result)
+                }));
+                continue;
+            }
+
+            let result = self.dispatch(&message.method, message.params);
+            if let Some(id) = message.id {
+                send(&output, &id, result)?;
+            }
+        }
+
+        for worker in workers {
+            join(worker)?;
+        }
+        Ok(())
+    }
+
+    fn dispatch(&self, method: &str, params: Value) -> RpcResult {
+        match method {
+            "analyzeBuffer" => self.analyze_buffer(parse_params(params)?),
+            "detectLanguage" => {
+                let params: DetectParams = parse_params(params)?;
+                let language = walker::detect_path_language(&self.walker_config, &params.path);
+                Ok(json!({ "language": language.map(|lang| lang.name) }))
+            }
+            "cancel" => {
+                let params: CancelParams = parse_params(params)?;
+                if let Some(token) = self.running.lock().unwrap().get(&params.id.to_string()) {
+                    token.cancel();
+                }
+                Ok(Value::Null)
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        }
+    }
+
+    fn analyze(&self, params: Value, token: CancellationToken) -> RpcResult {
+        let request: AnalyzeRequest = parse_params(params)?;
+        let config = request.to_config(&self.cache).cancel_token(token);
+        let (walker_config, count_config) =
+            request.apply_to(&self.walker_config, &self.count_config);
+        match crate::analyze_with_settings(config, walker_config, count_config) {
+            Ok(analysis) => to_value(&serve::report(analysis)),
+            Err(e @ Error::Cancelled) => Err((REQUEST_CANCELLED, e.to_string())),
+            Err(e @ Error::InvalidConfig(_)) => Err((INVALID_PARAMS, e.to_string())),
+            Err(e) => Err((ANALYSIS_FAILED, e.to_string())),
+        }
+    }
+
+    fn analyze_buffer(&self, params: BufferParams) -> RpcResult {
+        let path = params.path.unwrap_or_default();
+        let language = match params.language {
+            Some(name) => self
+                .walker_config
+                .custom_languages
+                .as_deref()
+                .and_then(|registry| registry.get(&name))
+                .or_else(|| get_language_ignore_case(&name))
+                .ok_or_else(|| (INVALID_PARAMS, format!("unknown language '{}'", name)))?,
+            None => walker::detect_path_language(&self.walker_config, &path).ok_or_else(|| {
+                (
+                    INVALID_PARAMS,
+                    "no language given and none detected from the path".to_string(),
+                )
+            })?,
+        };
+        let stats =
+            counter::count_bytes(&path, params.text.as_bytes(), language, &self.count_config)
+                .map_err(|e| (ANALYSIS_FAILED, e.to_string()))?;
+        to_value(&stats)
+    }
+}
+
+/// Wait for an `analyze` worker; its panics are answered in the worker itself, so one here
+/// means the response could not be written.
+fn join(worker: JoinHandle<io::Result<()>>) -> io::Result<()> {
+    worker
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("writing a response panicked")))
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+fn to_value(value: &impl serde::Serialize) -> RpcResult {
+    serde_json::to_value(value).map_err(|e| (ANALYSIS_FAILED, e.to_string()))
+}
+
+/// Write one response line; responses of concurrent requests never interleave.
+fn send(output: &Mutex<impl Write>, id: &Value, result: RpcResult) -> io::Result<()> {
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    };
+    let mut output = output.lock().unwrap();
+    writeln!(output, "{}", response)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// A `Write` whose contents stay readable after the session took ownership.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(requests: &[Value]) -> Vec<Value> {
+        let input: String = requests.iter().map(|r| format!("{}\n", r)).collect();
+        let output = SharedBuffer::default();
+        let session = RpcSession::new(WalkerConfig::default(), CountConfig::default());
+        session.run(input.as_bytes(), output.clone()).unwrap();
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let mut responses: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        responses.sort_by_key(|r| r["id"].as_i64());
+        responses
+    }
+
+    #[test]
+    fn test_methods() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let responses = run(&[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "analyze", "params": {"path": temp.path()}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "analyzeBuffer",
+                   "params": {"path": "unsaved.py", "text": "# hi\nx = 1\n\n"}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "analyzeBuffer",
+                   "params": {"language": "golang", "text": "package main\n"}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "detectLanguage", "params": {"path": "a.ts"}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "detectLanguage", "params": {"path": "a.zzz"}}),
+            json!({"jsonrpc": "2.0", "method": "detectLanguage", "params": {"path": "note.md"}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "nope"}),
+            json!({"jsonrpc": "2.0", "id": 7, "method": "analyzeBuffer", "params": {"text": "x"}}),
+        ]);
+
+        assert_eq!(responses.len(), 7);
+        assert_eq!(responses[0]["result"]["Rust"]["code"], 1);
+        let buffer = &responses[1]["result"];
+        assert_eq!(buffer["language"], "Python");
+        assert_eq!(
+            (&buffer["code"], &buffer["comments"], &buffer["blanks"]),
+            (&json!(1), &json!(1), &json!(1))
+        );
+        assert_eq!(responses[2]["result"]["language"], "Go");
+        assert_eq!(responses[3]["result"]["language"], "TypeScript");
+        assert_eq!(responses[4]["result"]["language"], Value::Null);
+        assert_eq!(responses[5]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[6]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_malformed_messages() {
+        let output = SharedBuffer::default();
+        let session = RpcSession::new(WalkerConfig::default(), CountConfig::default());
+        session
+            .run(&b"{not json\n\"\xff\"\n{\"id\": 1}\n"[..], output.clone())
+            .unwrap();
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let codes: Vec<i64> = output
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<Value>(line).unwrap()["error"]["code"]
+                    .as_i64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(codes, [PARSE_ERROR, PARSE_ERROR, INVALID_REQUEST]);
+    }
+
+    #[test]
+    fn test_analyze_honours_command_line() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(temp.path().join("tool.py"), "x = 1\n").unwrap();
+        let walker_config = WalkerConfig {
+            exclude_langs: vec!["Python".to_string()],
+            ..Default::default()
+        };
+        let session = RpcSession::new(walker_config, CountConfig::default());
+
+        let report = session
+            .analyze(json!({"path": temp.path()}), CancellationToken::new())
+            .unwrap();
+        assert_eq!(report["Rust"]["code"], 1);
+        assert!(report.get("Python").is_none());
+
+        let options = json!({"path": temp.path(), "options": {"exclude_langs": ["Rust"]}});
+        let result = session.analyze(options, CancellationToken::new());
+        assert_eq!(result.unwrap_err().0, ANALYSIS_FAILED);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let session = RpcSession::new(WalkerConfig::default(), CountConfig::default());
+        let token = CancellationToken::new();
+        session
+            .running
+            .lock()
+            .unwrap()
+            .insert(json!(9).to_string(), token.clone());
+        assert_eq!(
+            session.dispatch("cancel", json!({"id": 9})),
+            Ok(Value::Null)
+        );
+        assert!(token.is_cancelled());
+
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let result = session.analyze(json!({"path": temp.path()}), token);
+        assert_eq!(result.unwrap_err().0, REQUEST_CANCELLED);
+    }
+}
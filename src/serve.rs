@@ -0,0 +1,511 @@
+//! `rloc serve`: a small HTTP/JSON API for editors and dashboards.
+//!
+//! - `POST /analyze` with `{"path": "...", "options": {...}}` counts `path` and answers with
+//!   the report `rloc --json` prints.
+//! - `GET /languages` lists the known languages with their category and extensions.
+//!
+//! Every request shares one [`FileCache`], so repeated queries only re-read files that
+//! changed. Connections are handled by a fixed pool of worker threads, each closed after one
+//! response; when [`QUEUE_LEN`] connections are already waiting, new ones get status 503.
+//!
+//! The server is meant for the local machine: requests from browser pages of any other
+//! origin are refused, and `POST` bodies must be sent as `application/json`, which a page
+//! cannot do cross-origin without a preflight the server never answers.
+
+use crate::cache::FileCache;
+use crate::counter::{CountConfig, TestMode};
+use crate::stats::{JsonOutput, Summary};
+use crate::walker::WalkerConfig;
+use crate::{Analysis, AnalyzeConfig, Error, LANGUAGES, list_extensions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Largest request body accepted, in bytes.
+pub const MAX_BODY_BYTES: u64 = 1024 * 1024;
+/// Largest request line plus headers accepted, in bytes.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+/// Connections accepted but not yet picked up by a worker.
+pub const QUEUE_LEN: usize = 64;
+/// How long reading a request or writing a response may stall.
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Body of `POST /analyze`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnalyzeRequest {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub options: AnalyzeOptions,
+}
+
+/// Counting options of `POST /analyze`, named after the [`AnalyzeConfig`] fields.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzeOptions {
+    /// Added to the default excluded directories.
+    pub exclude_dirs: Vec<String>,
+    pub include_langs: Vec<String>,
+    pub exclude_langs: Vec<String>,
+    pub include_exts: Vec<String>,
+    pub exclude_exts: Vec<String>,
+    pub include_globs: Vec<String>,
+    pub exclude_globs: Vec<String>,
+    pub max_depth: Option<usize>,
+    pub max_file_size: Option<u64>,
+    pub hidden: bool,
+    pub follow_symlinks: bool,
+    pub skip_gitignore: bool,
+    pub skip_uniqueness: bool,
+    pub exclude_generated: bool,
+    pub skip_minified: bool,
+    pub exclude_vendored: bool,
+    pub split_tests: bool,
+    pub exclude_tests: bool,
+    pub logical_lines: bool,
+    pub docstrings_as_code: bool,
+}
+
+impl AnalyzeRequest {
+    /// The analysis this request asks for, counting through `cache`.
+    pub fn to_config(&self, cache: &FileCache) -> AnalyzeConfig {
+        let options = self.options.clone();
+        let mut config = AnalyzeConfig::new(&self.path).cache(cache.clone());
+        config.exclude_dirs.extend(options.exclude_dirs);
+        AnalyzeConfig {
+            include_langs: options.include_langs,
+            exclude_langs: options.exclude_langs,
+            include_exts: options.include_exts,
+            exclude_exts: options.exclude_exts,
+            include_globs: options.include_globs,
+            exclude_globs: options.exclude_globs,
+            max_depth: options.max_depth,
+            max_file_size: options.max_file_size,
+            hidden: options.hidden,
+            follow_symlinks: options.follow_symlinks,
+            skip_gitignore: options.skip_gitignore,
+            skip_uniqueness: options.skip_uniqueness,
+            exclude_generated: options.exclude_generated,
+            skip_minified: options.skip_minified,
+            exclude_vendored: options.exclude_vendored,
+            split_tests: options.split_tests,
+            exclude_tests: options.exclude_tests,
+            logical_lines: options.logical_lines,
+            docstrings_as_code: options.docstrings_as_code,
+            ..config
+        }
+    }
+
+    /// `walker` and `count` (the command line's settings) with this request's path and
+    /// options applied on top: filters are added to, flags switched on and limits replaced.
+    pub(crate) fn apply_to(
+        &self,
+        walker: &WalkerConfig,
+        count: &CountConfig,
+    ) -> (WalkerConfig, CountConfig) {
+        let options = self.options.clone();
+        let mut walker = WalkerConfig {
+            paths: vec![self.path.clone()],
+            list_file: None,
+            ..walker.clone()
+        };
+        walker.exclude_dirs.extend(options.exclude_dirs);
+        walker.include_langs.extend(options.include_langs);
+        walker.exclude_langs.extend(options.exclude_langs);
+        walker.include_exts.extend(options.include_exts);
+        walker.exclude_exts.extend(options.exclude_exts);
+        walker.include_globs.extend(options.include_globs);
+        walker.exclude_globs.extend(options.exclude_globs);
+        walker.max_depth = options.max_depth.or(walker.max_depth);
+        walker.max_file_size = options.max_file_size.or(walker.max_file_size);
+        walker.hidden |= options.hidden;
+        walker.follow_symlinks |= options.follow_symlinks;
+        walker.skip_gitignore |= options.skip_gitignore;
+        walker.skip_uniqueness |= options.skip_uniqueness;
+        walker.exclude_vendored |= options.exclude_vendored;
+        walker.exclude_tests |= options.exclude_tests;
+
+        let mut count = count.clone();
+        count.logical_lines |= options.logical_lines;
+        count.docstrings_as_code |= options.docstrings_as_code;
+        if options.exclude_tests {
+            count.tests = TestMode::Exclude;
+        } else if options.split_tests && count.tests == TestMode::Include {
+            count.tests = TestMode::Split;
+        }
+        (walker, count)
+    }
+}
+
+/// One entry of `GET /languages`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageInfo {
+    pub name: &'static str,
+    pub category: &'static str,
+    pub extensions: Vec<&'static str>,
+}
+
+/// All built-in languages, sorted by name, with their extensions sorted.
+pub fn language_list() -> Vec<LanguageInfo> {
+    let mut extensions: BTreeMap<&str, Vec<&'static str>> = BTreeMap::new();
+    for (ext, name) in list_extensions() {
+        extensions.entry(name).or_default().push(ext);
+    }
+    let mut languages: Vec<LanguageInfo> = LANGUAGES
+        .values()
+        .map(|lang| {
+            let mut extensions = extensions.remove(lang.name).unwrap_or_default();
+            extensions.sort_unstable();
+            LanguageInfo {
+                name: lang.name,
+                category: lang.category.name(),
+                extensions,
+            }
+        })
+        .collect();
+    languages.sort_by(|a, b| a.name.cmp(b.name));
+    languages
+}
+
+/// A bound API server; [`Server::run`] serves until the process exits.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    cache: FileCache,
+}
+
+impl Server {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            cache: FileCache::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The cache shared by all requests.
+    pub fn cache(&self) -> &FileCache {
+        &self.cache
+    }
+
+    pub fn run(self) -> io::Result<()> {
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(QUEUE_LEN);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..num_cpus::get().max(2) {
+            let receiver = Arc::clone(&receiver);
+            let cache = self.cache.clone();
+            thread::spawn(move || {
+                loop {
+                    let stream = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    let Ok(stream) = stream else { return };
+                    // A panicking request must not shrink the pool.
+                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        handle_connection(stream, &cache)
+                    }));
+                }
+            });
+        }
+
+        for stream in self.listener.incoming() {
+            // A failed accept only affects that client.
+            let Ok(stream) = stream else { continue };
+            if stream.set_read_timeout(Some(IO_TIMEOUT)).is_err()
+                || stream.set_write_timeout(Some(IO_TIMEOUT)).is_err()
+            {
+                continue;
+            }
+            match sender.try_send(stream) {
+                Ok(()) => {}
+                Err(TrySendError::Full(stream)) => {
+                    let (status, body) = error_response(503, "server busy");
+                    let _ = write_response(stream, status, &body);
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    return Err(io::Error::other("every worker thread has stopped"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Status code and JSON body for one request.
+pub fn handle_request(method: &str, path: &str, body: &[u8], cache: &FileCache) -> (u16, String) {
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    match (method, path) {
+        ("GET", "/languages") => json_response(200, &language_list()),
+        ("POST", "/analyze") => analyze(body, cache),
+        (_, "/languages" | "/analyze") => error_response(405, "method not allowed"),
+        _ => error_response(404, "not found"),
+    }
+}
+
+fn analyze(body: &[u8], cache: &FileCache) -> (u16, String) {
+    let request: AnalyzeRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return error_response(400, &format!("invalid request: {}", e)),
+    };
+    match crate::analyze_with_config(request.to_config(cache)) {
+        Ok(analysis) => json_response(200, &report(analysis)),
+        Err(e @ Error::NoSourceFiles) => error_response(404, &e.to_string()),
+        Err(e @ Error::InvalidConfig(_)) => error_response(400, &e.to_string()),
+        Err(e) => error_response(500, &e.to_string()),
+    }
+}
+
+/// The `rloc --json` report of `analysis`.
+pub fn report(analysis: Analysis) -> JsonOutput {
+    let summary = Summary::from_file_stats(analysis.files)
+        .with_warnings(analysis.warnings)
+        .with_truncated(analysis.truncated);
+    JsonOutput::from(&summary)
+}
+
+fn json_response(status: u16, value: &impl Serialize) -> (u16, String) {
+    match serde_json::to_string(value) {
+        Ok(body) => (status, body),
+        Err(e) => error_response(500, &e.to_string()),
+    }
+}
+
+fn error_response(status: u16, message: &str) -> (u16, String) {
+    (status, serde_json::json!({ "error": message }).to_string())
+}
+
+fn handle_connection(stream: TcpStream, cache: &FileCache) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (status, body) = match read_request(&mut reader) {
+        Ok((method, path, body)) => handle_request(&method, &path, &body, cache),
+        Err((status, message)) => error_response(status, &message),
+    };
+    write_response(stream, status, &body)
+}
+
+/// Method, target and body of one HTTP/1.x request, or the status to reject it with.
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, Vec<u8>), (u16, String)> {
+    let bad_request = |message: &str| (400, message.to_string());
+    let mut head_bytes = 0;
+    let mut read_line = |reader: &mut dyn BufRead| -> Result<String, (u16, String)> {
+        let mut line = String::new();
+        let limit = (MAX_HEAD_BYTES - head_bytes) as u64 + 1;
+        reader
+            .take(limit)
+            .read_line(&mut line)
+            .map_err(|e| (400, e.to_string()))?;
+        head_bytes += line.len();
+        if head_bytes > MAX_HEAD_BYTES {
+            return Err((431, "request header too large".to_string()));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    };
+
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(bad_request("malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err((505, "HTTP version not supported".to_string()));
+    }
+
+    let mut content_length = 0;
+    let mut json_body = false;
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(bad_request("malformed header"));
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| bad_request("invalid Content-Length"))?;
+        } else if name.trim().eq_ignore_ascii_case("transfer-encoding") {
+            return Err((411, "Content-Length required".to_string()));
+        } else if name.trim().eq_ignore_ascii_case("content-type") {
+            let media_type = value.split(';').next().unwrap_or_default().trim();
+            json_body = media_type.eq_ignore_ascii_case("application/json");
+        } else if name.trim().eq_ignore_ascii_case("origin") && !is_local_origin(value.trim()) {
+            return Err((403, "cross-origin requests are not allowed".to_string()));
+        }
+    }
+    if method == "POST" && !json_body {
+        return Err((415, "Content-Type must be application/json".to_string()));
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err((413, "request body too large".to_string()));
+    }
+
+    let mut body = vec![0; content_length as usize];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad_request("incomplete request body"))?;
+    Ok((method.to_string(), target.to_string(), body))
+}
+
+/// Whether `origin` is a page served from this machine (`http://localhost:3000`, ...).
+fn is_local_origin(origin: &str) -> bool {
+    let Some((_, host)) = origin.split_once("://") else {
+        return false;
+    };
+    let host = match host.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn analyze_body(path: &std::path::Path, options: &str) -> Vec<u8> {
+        let path = serde_json::to_string(path).unwrap();
+        format!(r#"{{"path": {}, "options": {}}}"#, path, options).into_bytes()
+    }
+
+    #[test]
+    fn test_routes() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}\n// hi\n").unwrap();
+        fs::write(temp.path().join("tool.py"), "x = 1\n").unwrap();
+        let cache = FileCache::new();
+
+        let (status, body) = handle_request(
+            "POST",
+            "/analyze",
+            &analyze_body(temp.path(), r#"{"include_langs": ["Rust"]}"#),
+            &cache,
+        );
+        assert_eq!(status, 200);
+        let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(report["Rust"]["code"], 1);
+        assert_eq!(report["Rust"]["comment"], 1);
+        assert!(report.get("Python").is_none());
+        assert_eq!(cache.len(), 1);
+
+        let (status, body) = handle_request("GET", "/languages?x=1", b"", &cache);
+        assert_eq!(status, 200);
+        let languages: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+        let rust = languages.iter().find(|l| l["name"] == "Rust").unwrap();
+        assert_eq!(rust["category"], "Programming");
+        assert!(
+            rust["extensions"]
+                .as_array()
+                .unwrap()
+                .contains(&"rs".into())
+        );
+
+        let bad = analyze_body(temp.path(), r#"{"no_such_option": true}"#);
+        assert_eq!(handle_request("POST", "/analyze", &bad, &cache).0, 400);
+        let empty = analyze_body(&temp.path().join("missing"), "{}");
+        assert_eq!(handle_request("POST", "/analyze", &empty, &cache).0, 404);
+        assert_eq!(handle_request("GET", "/analyze", b"", &cache).0, 405);
+        assert_eq!(handle_request("GET", "/", b"", &cache).0, 404);
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw = b"POST /analyze HTTP/1.1\r\nHost: x\r\ncontent-length: 2\r\n\
+                    Content-Type: application/json; charset=utf-8\r\n\
+                    Origin: http://localhost:3000\r\n\r\n{}";
+        let (method, path, body) = read_request(&mut &raw[..]).unwrap();
+        assert_eq!((method.as_str(), path.as_str()), ("POST", "/analyze"));
+        assert_eq!(body, b"{}");
+
+        let form = b"POST /analyze HTTP/1.1\r\nContent-Type: text/plain\r\n\
+                     Content-Length: 2\r\n\r\n{}";
+        assert_eq!(read_request(&mut &form[..]).unwrap_err().0, 415);
+        let foreign = b"GET /languages HTTP/1.1\r\nOrigin: https://example.com\r\n\r\n";
+        assert_eq!(read_request(&mut &foreign[..]).unwrap_err().0, 403);
+        assert!(is_local_origin("http://[::1]:8080"));
+        assert!(is_local_origin("http://127.0.0.1"));
+        assert!(!is_local_origin("http://localhost.example.com"));
+        assert!(!is_local_origin("null"));
+
+        let too_large = format!(
+            "POST /analyze HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_BYTES + 1
+        );
+        assert_eq!(read_request(&mut too_large.as_bytes()).unwrap_err().0, 413);
+
+        let long_header = format!(
+            "GET / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD_BYTES)
+        );
+        assert_eq!(
+            read_request(&mut long_header.as_bytes()).unwrap_err().0,
+            431
+        );
+
+        assert_eq!(
+            read_request(&mut &b"garbage\r\n\r\n"[..]).unwrap_err().0,
+            400
+        );
+    }
+
+    #[test]
+    fn test_server_round_trip() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /languages HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/json"));
+        assert!(response.contains(r#""name":"Rust""#));
+    }
+}
@@ -0,0 +1,286 @@
+//! Per-file snapshots for `rloc snapshot`.
+//!
+//! `rloc snapshot save` writes the counts and a content hash of every counted file to a JSON
+//! file. `rloc snapshot compare` counts again and reports which files were added, removed or
+//! changed since, with the change in lines. Unlike `--diff`, this needs no git history and
+//! no copy of the old tree, only the snapshot.
+//!
+//! Hashes are SHA-256 (see [`sha256_hex`](crate::counter::sha256_hex)), taken from the same
+//! bytes that were counted. Files counted without
+//! [`CountConfig::content_hash`](crate::counter::CountConfig::content_hash) are stored without
+//! a hash and compared by their counts.
+
+use crate::stats::{SCHEMA_VERSION, Summary};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Counts of every file of one run, keyed by path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub schema_version: u32,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub files: BTreeMap<String, SnapshotFile>,
+}
+
+/// One file of a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    pub language: String,
+    /// SHA-256 of the file's bytes as 64 hex digits; absent when it could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    pub blank: u64,
+    pub comment: u64,
+    pub code: u64,
+}
+
+/// How a file differs from the snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChange {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl FileChange {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileChange::Added => "added",
+            FileChange::Removed => "removed",
+            FileChange::Changed => "changed",
+        }
+    }
+}
+
+/// A file that differs from the snapshot, with its code before and after.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SnapshotChange {
+    pub path: String,
+    pub change: FileChange,
+    pub language: String,
+    pub code_before: u64,
+    pub code_after: u64,
+    /// Code lines added (or removed, when negative).
+    pub code_delta: i64,
+}
+
+/// `rloc snapshot compare`: the files that differ, by path, and the change in totals.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SnapshotDiff {
+    pub schema_version: u32,
+    pub added: u64,
+    pub removed: u64,
+    pub changed: u64,
+    pub unchanged: u64,
+    pub blank_delta: i64,
+    pub comment_delta: i64,
+    pub code_delta: i64,
+    pub files: Vec<SnapshotChange>,
+}
+
+impl Snapshot {
+    /// A snapshot of the files in `summary`, with the content hashes recorded while counting.
+    pub fn new(summary: &Summary) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let files = summary
+            .file_stats
+            .iter()
+            .map(|file| {
+                let entry = SnapshotFile {
+                    language: file.language.clone(),
+                    hash: file.sha256.clone(),
+                    blank: file.blanks,
+                    comment: file.comments,
+                    code: file.code,
+                };
+                (file.path.clone(), entry)
+            })
+            .collect();
+        Snapshot {
+            schema_version: SCHEMA_VERSION,
+            timestamp,
+            files,
+        }
+    }
+
+    /// Write the snapshot to `path` as pretty-printed JSON.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self).map_err(io::Error::other)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Read a snapshot written by [`save`](Self::save); malformed JSON is `InvalidData`.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_reader(BufReader::new(File::open(path)?))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// What changed from this snapshot to `current`, files sorted by path.
+    pub fn compare(&self, current: &Snapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff {
+            schema_version: SCHEMA_VERSION,
+            ..Default::default()
+        };
+        let mut record =
+            |path: &str, change, before: Option<&SnapshotFile>, after: Option<&SnapshotFile>| {
+                let counts = |file: Option<&SnapshotFile>| {
+                    file.map_or((0, 0, 0), |f| {
+                        (f.blank as i64, f.comment as i64, f.code as i64)
+                    })
+                };
+                let (blank_before, comment_before, code_before) = counts(before);
+                let (blank_after, comment_after, code_after) = counts(after);
+                diff.blank_delta += blank_after - blank_before;
+                diff.comment_delta += comment_after - comment_before;
+                diff.code_delta += code_after - code_before;
+                match change {
+                    FileChange::Added => diff.added += 1,
+                    FileChange::Removed => diff.removed += 1,
+                    FileChange::Changed => diff.changed += 1,
+                }
+                diff.files.push(SnapshotChange {
+                    path: path.to_string(),
+                    change,
+                    language: after
+                        .or(before)
+                        .map(|f| f.language.clone())
+                        .unwrap_or_default(),
+                    code_before: code_before as u64,
+                    code_after: code_after as u64,
+                    code_delta: code_after - code_before,
+                });
+            };
+
+        for (path, before) in &self.files {
+            match current.files.get(path) {
+                None => record(path, FileChange::Removed, Some(before), None),
+                Some(after) if before.differs(after) => {
+                    record(path, FileChange::Changed, Some(before), Some(after))
+                }
+                Some(_) => diff.unchanged += 1,
+            }
+        }
+        for (path, after) in &current.files {
+            if !self.files.contains_key(path) {
+                record(path, FileChange::Added, None, Some(after));
+            }
+        }
+        diff.files.sort_by(|a, b| a.path.cmp(&b.path));
+        diff
+    }
+}
+
+impl SnapshotFile {
+    /// Whether the content (or, without both hashes, the counts) differ.
+    fn differs(&self, other: &SnapshotFile) -> bool {
+        match (&self.hash, &other.hash) {
+            (Some(a), Some(b)) => a != b,
+            _ => {
+                self.language != other.language
+                    || (self.blank, self.comment, self.code)
+                        != (other.blank, other.comment, other.code)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn file(language: &str, hash: &str, code: u64) -> SnapshotFile {
+        SnapshotFile {
+            language: language.into(),
+            hash: Some(hash.into()),
+            blank: 1,
+            comment: 0,
+            code,
+        }
+    }
+
+    fn snapshot(files: &[(&str, SnapshotFile)]) -> Snapshot {
+        Snapshot {
+            schema_version: SCHEMA_VERSION,
+            timestamp: 0,
+            files: files
+                .iter()
+                .map(|(path, file)| (path.to_string(), file.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let before = snapshot(&[
+            ("a.rs", file("Rust", "1", 10)),
+            ("b.rs", file("Rust", "2", 5)),
+            ("c.py", file("Python", "3", 7)),
+        ]);
+        let after = snapshot(&[
+            ("a.rs", file("Rust", "1", 10)),
+            ("b.rs", file("Rust", "9", 8)),
+            ("d.go", file("Go", "4", 4)),
+        ]);
+
+        let diff = before.compare(&after);
+        assert_eq!(
+            (diff.added, diff.removed, diff.changed, diff.unchanged),
+            (1, 1, 1, 1)
+        );
+        assert_eq!((diff.code_delta, diff.blank_delta), (0, 0));
+        let files: Vec<_> = diff
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.change, f.code_delta))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("b.rs", FileChange::Changed, 3),
+                ("c.py", FileChange::Removed, -7),
+                ("d.go", FileChange::Added, 4),
+            ]
+        );
+
+        // Without a hash, files are compared by their counts.
+        let unhashed = |code| SnapshotFile {
+            hash: None,
+            ..file("Rust", "", code)
+        };
+        let before = snapshot(&[("a.rs", unhashed(3))]);
+        assert_eq!(
+            before.compare(&snapshot(&[("a.rs", unhashed(3))])).changed,
+            0
+        );
+        assert_eq!(
+            before.compare(&snapshot(&[("a.rs", unhashed(4))])).changed,
+            1
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("snap.json");
+        let saved = snapshot(&[("a.rs", file("Rust", "00ff", 2))]);
+        saved.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        assert_eq!(loaded.files, saved.files);
+
+        fs::write(&path, "{").unwrap();
+        let error = Snapshot::load(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,9 +1,21 @@
-use crate::counter::FileStats;
+use crate::counter::{FileStats, IndentStats, LineEndings};
+use crate::languages::Category;
 use ahash::AHashMap;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+/// Summary bucket that collects files detected as generated code.
+pub const GENERATED_LANGUAGE: &str = "Generated";
+
+/// Summary bucket that collects vendored/third-party files.
+pub const VENDORED_LANGUAGE: &str = "Vendored";
+
+/// Suffix of the per-language buckets for minified files, e.g. "JavaScript (minified)".
+pub const MINIFIED_SUFFIX: &str = " (minified)";
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct LanguageStats {
     pub name: String,
@@ -11,21 +23,216 @@ pub struct LanguageStats {
     pub code: u64,
     pub comments: u64,
     pub blanks: u64,
+    /// Documentation comment lines; a subset of `comments`.
+    pub doc_comments: u64,
+    pub logical: u64,
+    /// Test code, when split out from `code` (see `TestMode::Split`).
+    pub test_code: u64,
+    /// Lines excluded by `rloc:ignore-*` directives; not part of [`total`](Self::total).
+    pub ignored: u64,
+    pub bytes: u64,
+    /// Bytes in lines, excluding line terminators.
+    pub line_bytes: u64,
+    pub max_line_length: u64,
+    /// Line terminators by kind, when counted.
+    pub line_endings: LineEndings,
+    /// Tabs versus spaces and indent widths, when counted.
+    pub indent: IndentStats,
 }
 
 impl LanguageStats {
     pub fn total(&self) -> u64 {
-        self.code + self.comments + self.blanks
+        self.code + self.test_code + self.comments + self.blanks
     }
 
+    /// Average bytes per line across all files, excluding line terminators.
+    pub fn avg_line_length(&self) -> f64 {
+        crate::counter::average(self.line_bytes, self.total())
+    }
+
+    /// Comment lines per line of code, a rough documentation density.
+    pub fn comment_ratio(&self) -> f64 {
+        crate::counter::average(self.comments, self.code)
+    }
+
+    /// Fraction of all lines that are blank.
+    pub fn blank_ratio(&self) -> f64 {
+        crate::counter::average(self.blanks, self.total())
+    }
+
+    /// Average lines per file.
+    pub fn avg_file_lines(&self) -> f64 {
+        crate::counter::average(self.total(), self.files)
+    }
+
+    /// Add a file; one ignored entirely only adds to `ignored`.
     pub fn add(&mut self, file_stats: &FileStats) {
-        self.files += 1;
+        self.files += u64::from(!file_stats.is_ignored());
+        self.add_embedded(file_stats);
+    }
+
+    /// Add the lines of code embedded in another file, without counting a file.
+    pub fn add_embedded(&mut self, file_stats: &FileStats) {
         self.code += file_stats.code;
         self.comments += file_stats.comments;
         self.blanks += file_stats.blanks;
+        self.doc_comments += file_stats.doc_comments;
+        self.logical += file_stats.logical;
+        self.test_code += file_stats.test_code;
+        self.ignored += file_stats.ignored;
+        self.bytes += file_stats.bytes;
+        self.line_bytes += file_stats.line_bytes;
+        self.max_line_length = self.max_line_length.max(file_stats.max_line_length);
+        self.line_endings.add(&file_stats.line_endings);
+        self.indent.add(&file_stats.indent);
+    }
+
+    /// Fold another entry's counts into this one (used for categories and "Other").
+    pub fn merge(&mut self, other: &LanguageStats) {
+        self.files += other.files;
+        self.code += other.code;
+        self.comments += other.comments;
+        self.blanks += other.blanks;
+        self.doc_comments += other.doc_comments;
+        self.logical += other.logical;
+        self.test_code += other.test_code;
+        self.ignored += other.ignored;
+        self.bytes += other.bytes;
+        self.line_bytes += other.line_bytes;
+        self.max_line_length = self.max_line_length.max(other.max_line_length);
+        self.line_endings.add(&other.line_endings);
+        self.indent.add(&other.indent);
+    }
+}
+
+/// Why a file that was found during the walk was not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Looked like binary data (see `CountConfig::binary_null_ratio`).
+    Binary,
+    /// Larger than the configured maximum file size.
+    TooLarge,
+    /// Could not be read.
+    Unreadable,
+    /// No language matched the file.
+    Unrecognized,
+}
+
+/// Number of files skipped, by [`SkipReason`].
+///
+/// Files left out on purpose (excluded directories, extensions, languages, ...) are not
+/// included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedFiles {
+    pub binary: u64,
+    pub too_large: u64,
+    pub unreadable: u64,
+    pub unrecognized: u64,
+}
+
+impl SkippedFiles {
+    pub fn total(&self) -> u64 {
+        self.binary + self.too_large + self.unreadable + self.unrecognized
+    }
+
+    pub fn record(&mut self, reason: SkipReason) {
+        match reason {
+            SkipReason::Binary => self.binary += 1,
+            SkipReason::TooLarge => self.too_large += 1,
+            SkipReason::Unreadable => self.unreadable += 1,
+            SkipReason::Unrecognized => self.unrecognized += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for SkippedFiles {
+    /// Only the non-zero reasons, e.g. `3 binary, 1 unrecognized`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = [
+            (self.binary, "binary"),
+            (self.too_large, "too large"),
+            (self.unreadable, "unreadable"),
+            (self.unrecognized, "unrecognized"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// A file that was found but could not be read or counted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Warning {
+    pub path: String,
+    /// The [`std::io::ErrorKind`] of the failure, e.g. `permission denied`.
+    pub kind: String,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn from_io(path: &Path, error: &std::io::Error) -> Self {
+        Self {
+            path: path.display().to_string(),
+            kind: error.kind().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
     }
 }
 
+/// Key used in [`extension_histogram`] for files without an extension.
+pub const NO_EXTENSION: &str = "(none)";
+
+/// Count files by lowercased extension, most common first (ties by extension).
+pub fn extension_histogram<P: AsRef<std::path::Path>>(paths: &[P]) -> Vec<(String, u64)> {
+    let mut counts: AHashMap<String, u64> = AHashMap::new();
+    for path in paths {
+        *counts.entry(extension_key(path.as_ref())).or_insert(0) += 1;
+    }
+
+    let mut histogram: Vec<_> = counts.into_iter().collect();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    histogram
+}
+
+/// The lowercased extension of `path`, or [`NO_EXTENSION`].
+fn extension_key(path: &std::path::Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| NO_EXTENSION.to_string())
+}
+
+/// Rows of `files` grouped by lowercased extension ([`NO_EXTENSION`] for none), most code
+/// first. Embedded languages count under the extension of the file containing them.
+pub fn by_extension(files: &[FileStats]) -> Vec<LanguageStats> {
+    let mut by_extension: AHashMap<String, LanguageStats> = AHashMap::new();
+    for file in files {
+        let name = extension_key(std::path::Path::new(&file.path));
+        let entry = by_extension
+            .entry(name.clone())
+            .or_insert_with(|| LanguageStats {
+                name,
+                ..Default::default()
+            });
+        entry.add(file);
+        for child in &file.embedded {
+            entry.add_embedded(child);
+        }
+    }
+
+    let mut extensions: Vec<_> = by_extension.into_values().collect();
+    extensions.sort_by(|a, b| b.code.cmp(&a.code).then_with(|| a.name.cmp(&b.name)));
+    extensions
+}
+
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct Summary {
     pub languages: Vec<LanguageStats>,
@@ -33,10 +240,57 @@ pub struct Summary {
     pub total_code: u64,
     pub total_comments: u64,
     pub total_blanks: u64,
+    pub total_doc_comments: u64,
+    pub total_logical: u64,
+    pub total_test_code: u64,
+    /// Lines excluded by `rloc:ignore-*` directives.
+    pub total_ignored: u64,
+    pub total_bytes: u64,
+    /// Bytes in lines, excluding line terminators.
+    pub total_line_bytes: u64,
+    pub max_line_length: u64,
+    /// Line terminators by kind, when counted.
+    pub line_endings: LineEndings,
+    /// Tabs versus spaces and indent widths, when counted.
+    pub indent: IndentStats,
     #[serde(skip)]
     pub elapsed: Option<Duration>,
     #[serde(skip)]
+    pub skipped: SkippedFiles,
+    /// Files that could not be read, sorted by path.
+    #[serde(skip)]
+    pub warnings: Vec<Warning>,
+    #[serde(skip)]
     pub file_stats: Vec<FileStats>,
+    /// Counting stopped early at `--max-files` or `--timeout`.
+    #[serde(skip)]
+    pub truncated: bool,
+}
+
+pub(crate) fn largest_first(a: &FileStats, b: &FileStats) -> std::cmp::Ordering {
+    b.code.cmp(&a.code).then_with(|| a.path.cmp(&b.path))
+}
+
+/// The summary row a file is counted under: its language, or one of the
+/// Vendored / "<Lang> (minified)" / Generated buckets.
+fn row_name(file: &FileStats) -> String {
+    if file.vendored {
+        VENDORED_LANGUAGE.to_string()
+    } else if file.minified {
+        format!("{}{}", file.language, MINIFIED_SUFFIX)
+    } else if file.generated {
+        GENERATED_LANGUAGE.to_string()
+    } else {
+        file.language.clone()
+    }
+}
+
+/// `name` after the first `(from, into)` pair whose `from` matches it, ignoring case.
+fn merged_name<'a>(name: &'a str, merges: &'a [(String, String)]) -> &'a str {
+    merges
+        .iter()
+        .find(|(from, _)| from.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, into)| into.as_str())
 }
 
 impl Summary {
@@ -46,29 +300,67 @@ impl Summary {
     }
 
     pub fn total_lines(&self) -> u64 {
-        self.total_code + self.total_comments + self.total_blanks
+        self.total_code + self.total_test_code + self.total_comments + self.total_blanks
     }
 
-    pub fn from_file_stats(stats: Vec<FileStats>) -> Self {
+    /// Aggregate per-file stats; files are kept sorted by path so per-file output does not
+    /// depend on the order they were counted in.
+    pub fn from_file_stats(mut stats: Vec<FileStats>) -> Self {
+        let _span = tracing::info_span!("aggregate", files = stats.len()).entered();
+        stats.sort_by(|a, b| a.path.cmp(&b.path));
         let mut by_language: AHashMap<String, LanguageStats> = AHashMap::new();
 
         for file_stat in &stats {
+            let name = row_name(file_stat);
             let entry = by_language
-                .entry(file_stat.language.clone())
+                .entry(name.clone())
                 .or_insert_with(|| LanguageStats {
-                    name: file_stat.language.clone(),
+                    name,
                     ..Default::default()
                 });
             entry.add(file_stat);
+
+            for child in &file_stat.embedded {
+                // Children of a bucketed file (vendored, generated, ...) stay in its bucket.
+                let name = if row_name(file_stat) == file_stat.language {
+                    row_name(child)
+                } else {
+                    row_name(file_stat)
+                };
+                by_language
+                    .entry(name.clone())
+                    .or_insert_with(|| LanguageStats {
+                        name,
+                        ..Default::default()
+                    })
+                    .add_embedded(child);
+            }
         }
 
         let mut languages: Vec<_> = by_language.into_values().collect();
-        languages.sort_by(|a, b| b.code.cmp(&a.code));
+        languages.sort_by(|a, b| b.code.cmp(&a.code).then_with(|| a.name.cmp(&b.name)));
 
         let total_files = languages.iter().map(|l| l.files).sum();
         let total_code = languages.iter().map(|l| l.code).sum();
         let total_comments = languages.iter().map(|l| l.comments).sum();
         let total_blanks = languages.iter().map(|l| l.blanks).sum();
+        let total_doc_comments = languages.iter().map(|l| l.doc_comments).sum();
+        let total_logical = languages.iter().map(|l| l.logical).sum();
+        let total_test_code = languages.iter().map(|l| l.test_code).sum();
+        let total_ignored = languages.iter().map(|l| l.ignored).sum();
+        let total_bytes = languages.iter().map(|l| l.bytes).sum();
+        let total_line_bytes = languages.iter().map(|l| l.line_bytes).sum();
+        let max_line_length = languages
+            .iter()
+            .map(|l| l.max_line_length)
+            .max()
+            .unwrap_or(0);
+        let mut line_endings = LineEndings::default();
+        let mut indent = IndentStats::default();
+        for lang in &languages {
+            line_endings.add(&lang.line_endings);
+            indent.add(&lang.indent);
+        }
 
         Summary {
             languages,
@@ -76,11 +368,230 @@ impl Summary {
             total_code,
             total_comments,
             total_blanks,
+            total_doc_comments,
+            total_logical,
+            total_test_code,
+            total_ignored,
+            total_bytes,
+            total_line_bytes,
+            max_line_length,
+            line_endings,
+            indent,
             elapsed: None,
+            skipped: SkippedFiles::default(),
+            warnings: Vec::new(),
             file_stats: stats,
+            truncated: false,
         }
     }
 
+    /// Split the per-file stats by the root each file was found under, in `roots` order.
+    ///
+    /// A file under nested roots belongs to the innermost one. Files under no root are
+    /// dropped; skipped-file counts are not split.
+    pub fn group_by_root(&self, roots: &[PathBuf]) -> Vec<(PathBuf, Summary)> {
+        let mut groups: Vec<Vec<FileStats>> = vec![Vec::new(); roots.len()];
+        for file in &self.file_stats {
+            let path = Path::new(&file.path);
+            let root = roots
+                .iter()
+                .enumerate()
+                .filter(|(_, root)| path.starts_with(root))
+                .max_by_key(|(_, root)| root.components().count());
+            if let Some((index, _)) = root {
+                groups[index].push(file.clone());
+            }
+        }
+
+        roots
+            .iter()
+            .cloned()
+            .zip(groups.into_iter().map(Summary::from_file_stats))
+            .collect()
+    }
+
+    /// Per-language rows, each with the same counts split by `project_of` (e.g. a project
+    /// name per file). Languages keep their order; projects are sorted by code.
+    pub fn by_project(
+        &self,
+        mut project_of: impl FnMut(&FileStats) -> String,
+    ) -> Vec<(LanguageStats, Vec<LanguageStats>)> {
+        let mut projects: AHashMap<String, AHashMap<String, LanguageStats>> = AHashMap::new();
+        for file in &self.file_stats {
+            let project = project_of(file);
+            projects
+                .entry(row_name(file))
+                .or_default()
+                .entry(project.clone())
+                .or_insert_with(|| LanguageStats {
+                    name: project,
+                    ..Default::default()
+                })
+                .add(file);
+        }
+
+        self.languages
+            .iter()
+            .map(|lang| {
+                let mut rows: Vec<_> = projects
+                    .remove(&lang.name)
+                    .map(|rows| rows.into_values().collect())
+                    .unwrap_or_default();
+                rows.sort_by(|a: &LanguageStats, b| b.code.cmp(&a.code).then(a.name.cmp(&b.name)));
+                (lang.clone(), rows)
+            })
+            .collect()
+    }
+
+    /// Files grouped under their summary row (language or bucket), each with a subtotal of
+    /// just those files. Groups are sorted by code, files by code then path.
+    pub fn files_by_language(&self) -> Vec<(LanguageStats, Vec<&FileStats>)> {
+        let mut groups: AHashMap<String, (LanguageStats, Vec<&FileStats>)> = AHashMap::new();
+        for file in &self.file_stats {
+            let name = row_name(file);
+            let (subtotal, files) = groups.entry(name.clone()).or_insert_with(|| {
+                let subtotal = LanguageStats {
+                    name,
+                    ..Default::default()
+                };
+                (subtotal, Vec::new())
+            });
+            subtotal.add(file);
+            files.push(file);
+        }
+
+        let mut groups: Vec<_> = groups.into_values().collect();
+        for (_, files) in &mut groups {
+            files.sort_by(|a, b| largest_first(a, b));
+        }
+        groups.sort_by(|(a, _), (b, _)| b.code.cmp(&a.code).then(a.name.cmp(&b.name)));
+        groups
+    }
+
+    /// The `n` files with the most code lines, largest first; ties are broken by path.
+    pub fn largest_files(&self, n: usize) -> Vec<&FileStats> {
+        let mut files: Vec<&FileStats> = self.file_stats.iter().collect();
+        files.sort_by(|a, b| largest_first(a, b));
+        files.truncate(n);
+        files
+    }
+
+    /// Language rows with the `from` row of each `(from, into)` pair (matched ignoring case)
+    /// folded into `into`, e.g. `("C Header", "C")`. Other rows are kept as they are.
+    pub fn merge_languages(&self, merges: &[(String, String)]) -> Vec<LanguageStats> {
+        let mut merged: Vec<LanguageStats> = Vec::new();
+        for lang in &self.languages {
+            let name = merged_name(&lang.name, merges);
+            match merged.iter_mut().find(|row| row.name == name) {
+                Some(row) => row.merge(lang),
+                None => merged.push(LanguageStats {
+                    name: name.to_string(),
+                    ..lang.clone()
+                }),
+            }
+        }
+        merged.sort_by(|a, b| b.code.cmp(&a.code).then_with(|| a.name.cmp(&b.name)));
+        merged
+    }
+
+    /// Each language row with its files' own lines split by lowercased extension
+    /// ([`NO_EXTENSION`] for none), most code first, to check what a language was detected
+    /// from. `merges` are the pairs the rows were merged with (see [`Self::merge_languages`]);
+    /// rows without files of their own, like folded ones, get no extensions.
+    pub fn extensions_by_language(
+        &self,
+        merges: &[(String, String)],
+    ) -> Vec<(LanguageStats, Vec<LanguageStats>)> {
+        let mut rows: AHashMap<String, AHashMap<String, LanguageStats>> = AHashMap::new();
+        for file in &self.file_stats {
+            let extension = extension_key(std::path::Path::new(&file.path));
+            rows.entry(merged_name(&row_name(file), merges).to_string())
+                .or_default()
+                .entry(extension.clone())
+                .or_insert_with(|| LanguageStats {
+                    name: extension,
+                    ..Default::default()
+                })
+                .add(file);
+        }
+
+        self.languages
+            .iter()
+            .map(|lang| {
+                let mut extensions: Vec<_> = rows
+                    .remove(&lang.name)
+                    .map(|rows| rows.into_values().collect())
+                    .unwrap_or_default();
+                extensions.sort_by(|a: &LanguageStats, b| {
+                    b.code.cmp(&a.code).then_with(|| a.name.cmp(&b.name))
+                });
+                (lang.clone(), extensions)
+            })
+            .collect()
+    }
+
+    /// Roll languages up into their categories, named after `Category::name`.
+    pub fn by_category(&self) -> Vec<LanguageStats> {
+        let mut by_category: AHashMap<Category, LanguageStats> = AHashMap::new();
+
+        for lang in &self.languages {
+            let category = Category::of(&lang.name);
+            let entry = by_category
+                .entry(category)
+                .or_insert_with(|| LanguageStats {
+                    name: category.name().to_string(),
+                    ..Default::default()
+                });
+            entry.merge(lang);
+        }
+
+        let mut categories: Vec<_> = by_category.into_values().collect();
+        categories.sort_by(|a, b| b.code.cmp(&a.code).then_with(|| a.name.cmp(&b.name)));
+        categories
+    }
+
+    /// Counted files grouped by extension instead of language (see [`by_extension`]).
+    pub fn by_extension(&self) -> Vec<LanguageStats> {
+        by_extension(&self.file_stats)
+    }
+
+    /// Average bytes per line across all files, excluding line terminators.
+    pub fn avg_line_length(&self) -> f64 {
+        crate::counter::average(self.total_line_bytes, self.total_lines())
+    }
+
+    /// Comment lines per line of code across all languages.
+    pub fn comment_ratio(&self) -> f64 {
+        crate::counter::average(self.total_comments, self.total_code)
+    }
+
+    /// Fraction of all lines that are blank.
+    pub fn blank_ratio(&self) -> f64 {
+        crate::counter::average(self.total_blanks, self.total_lines())
+    }
+
+    /// Average lines per file.
+    pub fn avg_file_lines(&self) -> f64 {
+        crate::counter::average(self.total_lines(), self.total_files)
+    }
+
+    pub fn with_skipped(mut self, skipped: SkippedFiles) -> Self {
+        self.skipped = skipped;
+        self
+    }
+
+    /// Attach read failures, sorted by path.
+    pub fn with_warnings(mut self, mut warnings: Vec<Warning>) -> Self {
+        warnings.sort_by(|a, b| a.path.cmp(&b.path));
+        self.warnings = warnings;
+        self
+    }
+
+    pub fn with_truncated(mut self, truncated: bool) -> Self {
+        self.truncated = truncated;
+        self
+    }
+
     pub fn with_elapsed(mut self, elapsed: Duration) -> Self {
         self.elapsed = Some(elapsed);
         self
@@ -109,17 +620,34 @@ impl Summary {
     }
 }
 
+/// Version of the JSON report layout, written as `schema_version`.
+///
+/// Bump this whenever the meaning or shape of existing fields changes; reports without the
+/// field (version 0) come from older rloc releases or cloc.
+pub const SCHEMA_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonOutput {
+    #[serde(default)]
+    pub schema_version: u32,
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub header: Option<JsonHeader>,
+    /// Languages in the order of `Summary::languages`, so reports are byte-stable.
     #[serde(flatten)]
-    pub languages: HashMap<String, JsonLanguageStats>,
+    pub languages: IndexMap<String, JsonLanguageStats>,
     #[serde(rename = "SUM")]
     pub sum: JsonLanguageStats,
+    /// Files that could not be read; omitted when there are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    /// The run stopped early at `--max-files` or `--timeout`; omitted when it did not.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
+/// Report header; every field is optional when reading so cloc reports parse too.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct JsonHeader {
     pub cloc_version: String,
     pub elapsed_seconds: f64,
@@ -129,13 +657,263 @@ pub struct JsonHeader {
     pub lines_per_second: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 pub struct JsonLanguageStats {
     #[serde(rename = "nFiles")]
     pub n_files: u64,
     pub blank: u64,
     pub comment: u64,
     pub code: u64,
+    /// Only present when logical lines were counted.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub logical: u64,
+    /// Only present when test code was split out.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub tests: u64,
+    /// Lines excluded by `rloc:ignore-*` directives; only present when there are any.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub ignored: u64,
+    /// Tab-indented lines; this and the next two are only present with `--indent-stats`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent_tabs: u64,
+    /// Lines indented with spaces only.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent_spaces: u64,
+    /// Most common space indent step.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent_width: u64,
+    /// Blank, comment and code lines together; not part of cloc's format, so only present
+    /// with `--extended-json`, like `bytes` and `avg_line_len`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    /// Average bytes per line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_line_len: Option<f64>,
+    /// Share of the report's files, in percent; only present with `--by-percent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files_pct: Option<f64>,
+    /// Share of the report's code lines, in percent; only present with `--by-percent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_pct: Option<f64>,
+    /// Comment lines per code line; this and the next two are only present with `--ratios`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment_ratio: Option<f64>,
+    /// Fraction of lines that are blank.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blank_ratio: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_file_lines: Option<f64>,
+    /// The language's files by extension, most code first; only present with `-v`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<IndexMap<String, JsonLanguageStats>>,
+}
+
+/// `value` rounded to two decimals, as ratios are reported.
+pub fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// `value` as a percentage of `total`, rounded to two decimals; 0 when `total` is 0.
+pub fn percent_of(value: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (value as f64 / total as f64 * 10_000.0).round() / 100.0
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// cloc-style `--by-file` JSON: one entry per file path plus `SUM`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonFileOutput {
+    #[serde(default)]
+    pub schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub header: Option<JsonHeader>,
+    #[serde(flatten)]
+    pub files: BTreeMap<String, JsonFileStats>,
+    #[serde(rename = "SUM")]
+    pub sum: JsonLanguageStats,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// Per-file entry; the size fields default to zero so cloc `--by-file` reports parse too.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct JsonFileStats {
+    pub language: String,
+    pub blank: u64,
+    pub comment: u64,
+    #[serde(default)]
+    pub doc_comment: u64,
+    pub code: u64,
+    #[serde(default)]
+    pub bytes: u64,
+    #[serde(default)]
+    pub avg_line_length: f64,
+    #[serde(default)]
+    pub max_line_length: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub ignored: u64,
+    /// `lf`, `crlf`, `cr` or `mixed`; only present when line endings were counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_endings: Option<String>,
+    /// Tab-indented lines; this and the next two are only present with `--indent-stats`.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent_tabs: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent_spaces: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub indent_width: u64,
+}
+
+impl From<&LanguageStats> for JsonLanguageStats {
+    fn from(lang: &LanguageStats) -> Self {
+        JsonLanguageStats {
+            n_files: lang.files,
+            blank: lang.blanks,
+            comment: lang.comments,
+            code: lang.code,
+            logical: lang.logical,
+            tests: lang.test_code,
+            ignored: lang.ignored,
+            indent_tabs: lang.indent.tabs,
+            indent_spaces: lang.indent.spaces,
+            indent_width: lang.indent.width().unwrap_or(0),
+            ..Default::default()
+        }
+    }
+}
+
+impl JsonLanguageStats {
+    /// Fill in the fields cloc does not emit: `total`, `bytes` and `avg_line_len`.
+    fn set_sizes(&mut self, total: u64, bytes: u64, avg_line_len: f64) {
+        self.total = Some(total);
+        self.bytes = Some(bytes);
+        self.avg_line_len = Some(avg_line_len);
+    }
+
+    /// Bytes in lines, recovered from `avg_line_len` and `total` when the report has them.
+    fn line_bytes(&self) -> u64 {
+        self.avg_line_len
+            .zip(self.total)
+            .map_or(0, |(avg, total)| (avg * total as f64).round() as u64)
+    }
+}
+
+impl From<&FileStats> for JsonFileStats {
+    fn from(file: &FileStats) -> Self {
+        JsonFileStats {
+            language: file.language.clone(),
+            blank: file.blanks,
+            comment: file.comments,
+            doc_comment: file.doc_comments,
+            code: file.code,
+            bytes: file.bytes,
+            avg_line_length: file.avg_line_length(),
+            max_line_length: file.max_line_length,
+            ignored: file.ignored,
+            line_endings: file.line_endings.style().map(str::to_string),
+            indent_tabs: file.indent.tabs,
+            indent_spaces: file.indent.spaces,
+            indent_width: file.indent.width().unwrap_or(0),
+        }
+    }
+}
+
+/// `--by-file-by-lang --json`: each language's files with the language's own `SUM`, plus
+/// the grand `SUM`.
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonFileByLangOutput {
+    pub schema_version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<JsonHeader>,
+    /// Languages in `Summary::files_by_language` order.
+    #[serde(flatten)]
+    pub languages: IndexMap<String, JsonLanguageFiles>,
+    #[serde(rename = "SUM")]
+    pub sum: JsonLanguageStats,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonLanguageFiles {
+    pub files: BTreeMap<String, JsonFileStats>,
+    #[serde(rename = "SUM")]
+    pub sum: JsonLanguageStats,
+}
+
+impl From<&Summary> for JsonFileByLangOutput {
+    fn from(summary: &Summary) -> Self {
+        let JsonOutput {
+            schema_version,
+            header,
+            sum,
+            warnings,
+            truncated,
+            ..
+        } = JsonOutput::from(summary);
+        let languages = summary
+            .files_by_language()
+            .into_iter()
+            .map(|(lang, files)| {
+                let files = files
+                    .into_iter()
+                    .map(|file| (file.path.clone(), JsonFileStats::from(file)))
+                    .collect();
+                let sum = JsonLanguageStats::from(&lang);
+                (lang.name, JsonLanguageFiles { files, sum })
+            })
+            .collect();
+
+        JsonFileByLangOutput {
+            schema_version,
+            header,
+            languages,
+            sum,
+            warnings,
+            truncated,
+        }
+    }
+}
+
+impl From<&Summary> for JsonFileOutput {
+    fn from(summary: &Summary) -> Self {
+        let JsonOutput {
+            schema_version,
+            header,
+            sum,
+            warnings,
+            truncated,
+            ..
+        } = JsonOutput::from(summary);
+        let files = summary
+            .file_stats
+            .iter()
+            .map(|file| (file.path.clone(), JsonFileStats::from(file)))
+            .collect();
+
+        JsonFileOutput {
+            schema_version,
+            header,
+            files,
+            sum,
+            warnings,
+            truncated,
+        }
+    }
 }
 
 impl From<&Summary> for JsonOutput {
@@ -149,20 +927,10 @@ impl From<&Summary> for JsonOutput {
             lines_per_second: summary.lines_per_second().unwrap_or(0.0),
         });
 
-        let languages: HashMap<String, JsonLanguageStats> = summary
+        let languages: IndexMap<String, JsonLanguageStats> = summary
             .languages
             .iter()
-            .map(|lang| {
-                (
-                    lang.name.clone(),
-                    JsonLanguageStats {
-                        n_files: lang.files,
-                        blank: lang.blanks,
-                        comment: lang.comments,
-                        code: lang.code,
-                    },
-                )
-            })
+            .map(|lang| (lang.name.clone(), JsonLanguageStats::from(lang)))
             .collect();
 
         let sum = JsonLanguageStats {
@@ -170,20 +938,253 @@ impl From<&Summary> for JsonOutput {
             blank: summary.total_blanks,
             comment: summary.total_comments,
             code: summary.total_code,
+            logical: summary.total_logical,
+            tests: summary.total_test_code,
+            ignored: summary.total_ignored,
+            indent_tabs: summary.indent.tabs,
+            indent_spaces: summary.indent.spaces,
+            indent_width: summary.indent.width().unwrap_or(0),
+            ..Default::default()
         };
 
         JsonOutput {
+            schema_version: SCHEMA_VERSION,
             header,
             languages,
             sum,
+            warnings: summary.warnings.clone(),
+            truncated: summary.truncated,
+        }
+    }
+}
+
+impl JsonOutput {
+    /// The report with `total`, `bytes` and `avg_line_len` added to every language and the
+    /// `SUM` (`--extended-json`). cloc does not read these fields.
+    pub fn extended(summary: &Summary) -> Self {
+        let mut output = JsonOutput::from(summary);
+        for lang in &summary.languages {
+            if let Some(entry) = output.languages.get_mut(&lang.name) {
+                entry.set_sizes(lang.total(), lang.bytes, lang.avg_line_length());
+            }
+        }
+        output.sum.set_sizes(
+            summary.total_lines(),
+            summary.total_bytes,
+            summary.avg_line_length(),
+        );
+        output
+    }
+
+    /// Add `comment_ratio`, `blank_ratio` and `avg_file_lines` to every language and the
+    /// `SUM` (`--ratios`).
+    pub fn with_ratios(mut self, summary: &Summary) -> Self {
+        for lang in &summary.languages {
+            if let Some(entry) = self.languages.get_mut(&lang.name) {
+                entry.comment_ratio = Some(round2(lang.comment_ratio()));
+                entry.blank_ratio = Some(round2(lang.blank_ratio()));
+                entry.avg_file_lines = Some(round2(lang.avg_file_lines()));
+            }
+        }
+        self.sum.comment_ratio = Some(round2(summary.comment_ratio()));
+        self.sum.blank_ratio = Some(round2(summary.blank_ratio()));
+        self.sum.avg_file_lines = Some(round2(summary.avg_file_lines()));
+        self
+    }
+
+    /// Add `extensions`, each language's files split by extension (`-v`); `merges` are the
+    /// pairs the summary's rows were merged with.
+    pub fn with_extensions(mut self, summary: &Summary, merges: &[(String, String)]) -> Self {
+        for (lang, extensions) in summary.extensions_by_language(merges) {
+            if let Some(entry) = self.languages.get_mut(&lang.name) {
+                entry.extensions = Some(
+                    extensions
+                        .iter()
+                        .map(|ext| (ext.name.clone(), JsonLanguageStats::from(ext)))
+                        .collect(),
+                );
+            }
+        }
+        self
+    }
+
+    /// Add `files_pct` and `code_pct`, each language's share of the `SUM` (`--by-percent`).
+    pub fn with_percentages(mut self) -> Self {
+        for lang in self.languages.values_mut() {
+            lang.files_pct = Some(percent_of(lang.n_files, self.sum.n_files));
+            lang.code_pct = Some(percent_of(lang.code, self.sum.code));
+        }
+        self
+    }
+}
+
+impl Default for JsonHeader {
+    fn default() -> Self {
+        Self {
+            cloc_version: String::new(),
+            elapsed_seconds: 0.0,
+            n_files: 0,
+            n_lines: 0,
+            files_per_second: 0.0,
+            lines_per_second: 0.0,
+        }
+    }
+}
+
+impl From<&JsonOutput> for Summary {
+    /// Rebuild language totals from a saved report. Per-file data is not available.
+    fn from(report: &JsonOutput) -> Self {
+        let mut languages: Vec<LanguageStats> = report
+            .languages
+            .iter()
+            .map(|(name, stats)| LanguageStats {
+                name: name.clone(),
+                files: stats.n_files,
+                code: stats.code,
+                comments: stats.comment,
+                blanks: stats.blank,
+                logical: stats.logical,
+                test_code: stats.tests,
+                ignored: stats.ignored,
+                bytes: stats.bytes.unwrap_or(0),
+                line_bytes: stats.line_bytes(),
+                ..Default::default()
+            })
+            .collect();
+        languages.sort_by(|a, b| b.code.cmp(&a.code).then_with(|| a.name.cmp(&b.name)));
+
+        Summary {
+            languages,
+            total_files: report.sum.n_files,
+            total_code: report.sum.code,
+            total_comments: report.sum.comment,
+            total_blanks: report.sum.blank,
+            total_logical: report.sum.logical,
+            total_test_code: report.sum.tests,
+            total_ignored: report.sum.ignored,
+            total_bytes: report.sum.bytes.unwrap_or(0),
+            total_line_bytes: report.sum.line_bytes(),
+            warnings: report.warnings.clone(),
+            truncated: report.truncated,
+            ..Default::default()
+        }
+    }
+}
+
+/// `--group-by-path --json`: one report per analyzed root plus the grand total.
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonGroupedOutput {
+    pub schema_version: u32,
+    pub groups: Vec<JsonGroup>,
+    pub total: JsonOutput,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonGroup {
+    pub path: String,
+    pub report: JsonOutput,
+}
+
+impl JsonGroupedOutput {
+    pub fn new(groups: &[(String, Summary)], total: &Summary) -> Self {
+        JsonGroupedOutput {
+            schema_version: SCHEMA_VERSION,
+            groups: groups
+                .iter()
+                .map(|(path, summary)| JsonGroup {
+                    path: path.clone(),
+                    report: JsonOutput::from(summary),
+                })
+                .collect(),
+            total: JsonOutput::from(total),
         }
     }
 }
 
+/// Signed change in one language's counts between two reports.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LanguageDelta {
+    pub name: String,
+    pub files: i64,
+    pub code: i64,
+    pub comments: i64,
+    pub blanks: i64,
+}
+
+impl LanguageDelta {
+    fn between(name: &str, old: Option<&LanguageStats>, new: Option<&LanguageStats>) -> Self {
+        let diff = |field: fn(&LanguageStats) -> u64| {
+            new.map_or(0, field) as i64 - old.map_or(0, field) as i64
+        };
+        LanguageDelta {
+            name: name.to_string(),
+            files: diff(|l| l.files),
+            code: diff(|l| l.code),
+            comments: diff(|l| l.comments),
+            blanks: diff(|l| l.blanks),
+        }
+    }
+
+    pub fn total(&self) -> i64 {
+        self.code + self.comments + self.blanks
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.files == 0 && self.code == 0 && self.comments == 0 && self.blanks == 0
+    }
+}
+
+/// Per-language changes from an old report to a new one (e.g. a stored baseline to the
+/// current tree). Unchanged languages are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReportDelta {
+    /// Sorted by the size of the code change, largest first.
+    pub languages: Vec<LanguageDelta>,
+    pub total: LanguageDelta,
+}
+
+impl ReportDelta {
+    pub fn between(old: &Summary, new: &Summary) -> Self {
+        let mut names: Vec<&str> = old
+            .languages
+            .iter()
+            .chain(&new.languages)
+            .map(|l| l.name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let find = |summary: &'_ Summary, name: &str| -> Option<LanguageStats> {
+            summary.languages.iter().find(|l| l.name == name).cloned()
+        };
+        let mut languages: Vec<LanguageDelta> = names
+            .into_iter()
+            .map(|name| {
+                LanguageDelta::between(name, find(old, name).as_ref(), find(new, name).as_ref())
+            })
+            .filter(|delta| !delta.is_zero())
+            .collect();
+        languages.sort_by_key(|l| std::cmp::Reverse(l.code.unsigned_abs()));
+
+        let totals = |summary: &Summary| LanguageStats {
+            files: summary.total_files,
+            code: summary.total_code,
+            comments: summary.total_comments,
+            blanks: summary.total_blanks,
+            ..Default::default()
+        };
+        let total = LanguageDelta::between("SUM", Some(&totals(old)), Some(&totals(new)));
+
+        ReportDelta { languages, total }
+    }
+}
+
 impl JsonOutput {
     pub fn sum_reports(reports: Vec<JsonOutput>) -> Self {
-        let mut combined_langs: HashMap<String, JsonLanguageStats> = HashMap::new();
+        let mut combined_langs: IndexMap<String, JsonLanguageStats> = IndexMap::new();
         let mut total_sum = JsonLanguageStats::default();
+        let mut warnings = Vec::new();
+        let mut truncated = false;
 
         for report in reports {
             for (name, stats) in report.languages {
@@ -192,17 +1193,32 @@ impl JsonOutput {
                 entry.blank += stats.blank;
                 entry.comment += stats.comment;
                 entry.code += stats.code;
+                entry.logical += stats.logical;
+                entry.tests += stats.tests;
+                entry.ignored += stats.ignored;
+                entry.indent_tabs += stats.indent_tabs;
+                entry.indent_spaces += stats.indent_spaces;
             }
             total_sum.n_files += report.sum.n_files;
             total_sum.blank += report.sum.blank;
             total_sum.comment += report.sum.comment;
             total_sum.code += report.sum.code;
+            total_sum.logical += report.sum.logical;
+            total_sum.tests += report.sum.tests;
+            total_sum.ignored += report.sum.ignored;
+            total_sum.indent_tabs += report.sum.indent_tabs;
+            total_sum.indent_spaces += report.sum.indent_spaces;
+            warnings.extend(report.warnings);
+            truncated |= report.truncated;
         }
 
         JsonOutput {
+            schema_version: SCHEMA_VERSION,
             header: None,
             languages: combined_langs,
             sum: total_sum,
+            warnings,
+            truncated,
         }
     }
 }
@@ -220,6 +1236,7 @@ mod tests {
                 code: 100,
                 comments: 20,
                 blanks: 10,
+                ..Default::default()
             },
             FileStats {
                 path: "b.rs".into(),
@@ -227,6 +1244,7 @@ mod tests {
                 code: 50,
                 comments: 10,
                 blanks: 5,
+                ..Default::default()
             },
             FileStats {
                 path: "c.py".into(),
@@ -234,6 +1252,7 @@ mod tests {
                 code: 30,
                 comments: 5,
                 blanks: 3,
+                ..Default::default()
             },
         ];
 
@@ -245,4 +1264,418 @@ mod tests {
         assert_eq!(summary.total_blanks, 18);
         assert_eq!(summary.languages.len(), 2);
     }
+
+    #[test]
+    fn test_generated_files_bucketed() {
+        let stats = vec![
+            FileStats {
+                path: "a.py".into(),
+                language: "Python".into(),
+                code: 30,
+                ..Default::default()
+            },
+            FileStats {
+                path: "a_pb2.py".into(),
+                language: "Python".into(),
+                code: 40,
+                generated: true,
+                ..Default::default()
+            },
+        ];
+
+        let summary = Summary::from_file_stats(stats);
+        assert_eq!(summary.total_code, 70);
+
+        let python = summary
+            .languages
+            .iter()
+            .find(|l| l.name == "Python")
+            .unwrap();
+        assert_eq!(python.code, 30);
+        let generated = summary
+            .languages
+            .iter()
+            .find(|l| l.name == GENERATED_LANGUAGE)
+            .unwrap();
+        assert_eq!(generated.files, 1);
+        assert_eq!(generated.code, 40);
+    }
+
+    #[test]
+    fn test_vendored_takes_precedence() {
+        let stats = vec![FileStats {
+            path: "third_party/gen.c".into(),
+            language: "C".into(),
+            code: 10,
+            generated: true,
+            vendored: true,
+            ..Default::default()
+        }];
+
+        let summary = Summary::from_file_stats(stats);
+        assert_eq!(summary.languages.len(), 1);
+        assert_eq!(summary.languages[0].name, VENDORED_LANGUAGE);
+    }
+
+    #[test]
+    fn test_minified_files_bucketed() {
+        let stats = vec![
+            FileStats {
+                path: "app.js".into(),
+                language: "JavaScript".into(),
+                code: 30,
+                ..Default::default()
+            },
+            FileStats {
+                path: "bundle.js".into(),
+                language: "JavaScript".into(),
+                code: 1,
+                minified: true,
+                ..Default::default()
+            },
+        ];
+
+        let summary = Summary::from_file_stats(stats);
+        let names: Vec<_> = summary.languages.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["JavaScript", "JavaScript (minified)"]);
+    }
+
+    #[test]
+    fn test_by_category() {
+        let stats = vec![
+            FileStats {
+                path: "main.rs".into(),
+                language: "Rust".into(),
+                code: 100,
+                ..Default::default()
+            },
+            FileStats {
+                path: "Cargo.toml".into(),
+                language: "TOML".into(),
+                code: 10,
+                ..Default::default()
+            },
+            FileStats {
+                path: "config.yaml".into(),
+                language: "YAML".into(),
+                code: 5,
+                ..Default::default()
+            },
+            FileStats {
+                path: "README.md".into(),
+                language: "Markdown".into(),
+                code: 20,
+                ..Default::default()
+            },
+        ];
+
+        let categories = Summary::from_file_stats(stats).by_category();
+        let names: Vec<_> = categories.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Programming", "Documentation", "Data/Config"]);
+
+        let data = &categories[2];
+        assert_eq!(data.files, 2);
+        assert_eq!(data.code, 15);
+    }
+
+    #[test]
+    fn test_merge_languages() {
+        let file = |path: &str, language: &str, code| FileStats {
+            path: path.into(),
+            language: language.into(),
+            code,
+            ..Default::default()
+        };
+        let summary = Summary::from_file_stats(vec![
+            file("a.c", "C", 10),
+            file("a.h", "C Header", 5),
+            file("b.hpp", "C++ Header", 30),
+            file("x.py", "Python", 1),
+        ]);
+
+        let merges = [
+            ("c header".to_string(), "C".to_string()),
+            ("C++ Header".to_string(), "C++".to_string()),
+        ];
+        let rows: Vec<_> = summary
+            .merge_languages(&merges)
+            .iter()
+            .map(|l| (l.name.clone(), l.files, l.code))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("C++".to_string(), 1, 30),
+                ("C".to_string(), 2, 15),
+                ("Python".to_string(), 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extensions_by_language() {
+        let file = |path: &str, language: &str, code| FileStats {
+            path: path.into(),
+            language: language.into(),
+            code,
+            ..Default::default()
+        };
+        let summary = Summary::from_file_stats(vec![
+            file("a.ts", "TypeScript", 10),
+            file("b.MTS", "TypeScript", 4),
+            file("c.mts", "TypeScript", 1),
+            file("d.h", "C Header", 5),
+            file("e.c", "C", 2),
+        ]);
+        let merges = [("C Header".to_string(), "C".to_string())];
+        let summary = Summary {
+            languages: summary.merge_languages(&merges),
+            ..summary
+        };
+
+        let rows: Vec<_> = summary
+            .extensions_by_language(&merges)
+            .into_iter()
+            .map(|(lang, extensions)| {
+                let extensions: Vec<_> = extensions
+                    .iter()
+                    .map(|ext| (ext.name.clone(), ext.files, ext.code))
+                    .collect();
+                (lang.name, extensions)
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (
+                    "TypeScript".to_string(),
+                    vec![("ts".to_string(), 1, 10), ("mts".to_string(), 2, 5)]
+                ),
+                (
+                    "C".to_string(),
+                    vec![("h".to_string(), 1, 5), ("c".to_string(), 1, 2)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_by_extension() {
+        let file = |path: &str, language: &str, code| FileStats {
+            path: path.into(),
+            language: language.into(),
+            code,
+            ..Default::default()
+        };
+        let mut notebook = file("notes.Rmd", "Rmd", 0);
+        notebook.embedded = vec![file("notes.Rmd", "R", 4)];
+        let stats = vec![
+            file("src/a.h", "C", 10),
+            file("src/b.h", "C++", 20),
+            file("src/c.c", "C", 25),
+            file("Makefile", "Make", 3),
+            notebook,
+        ];
+
+        let summary = Summary::from_file_stats(stats);
+        let rows: Vec<_> = summary
+            .by_extension()
+            .iter()
+            .map(|e| (e.name.clone(), e.files, e.code))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("h".to_string(), 2, 30),
+                ("c".to_string(), 1, 25),
+                ("rmd".to_string(), 1, 4),
+                (NO_EXTENSION.to_string(), 1, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skipped_files_display() {
+        let mut skipped = SkippedFiles::default();
+        skipped.record(SkipReason::Binary);
+        skipped.record(SkipReason::Binary);
+        skipped.record(SkipReason::Unrecognized);
+        assert_eq!(skipped.total(), 3);
+        assert_eq!(skipped.to_string(), "2 binary, 1 unrecognized");
+    }
+
+    #[test]
+    fn test_warnings_sorted_by_path() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let missing = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let summary = Summary::default().with_warnings(vec![
+            Warning::from_io(Path::new("src/z.rs"), &denied),
+            Warning::from_io(Path::new("src/a.rs"), &missing),
+        ]);
+
+        let paths: Vec<_> = summary.warnings.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(paths, ["src/a.rs", "src/z.rs"]);
+        assert_eq!(summary.warnings[0].kind, "entity not found");
+        assert_eq!(summary.warnings[0].to_string(), "src/a.rs: gone");
+    }
+
+    #[test]
+    fn test_extension_histogram() {
+        let paths = ["a.FOO", "b.foo", "c.bar", "Makefile.local", "LICENSE"];
+        assert_eq!(
+            extension_histogram(&paths),
+            vec![
+                ("foo".to_string(), 2),
+                ("(none)".to_string(), 1),
+                ("bar".to_string(), 1),
+                ("local".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summary_from_cloc_report() {
+        let cloc = r#"{
+            "header": {"cloc_url": "github.com/AlDanial/cloc", "cloc_version": "2.00",
+                       "elapsed_seconds": 0.1, "n_files": 3, "n_lines": 60},
+            "Python": {"nFiles": 1, "blank": 2, "comment": 3, "code": 10},
+            "Rust": {"nFiles": 2, "blank": 5, "comment": 0, "code": 40},
+            "SUM": {"blank": 7, "comment": 3, "code": 50, "nFiles": 3}
+        }"#;
+        let report: JsonOutput = serde_json::from_str(cloc).unwrap();
+        let summary = Summary::from(&report);
+
+        let names: Vec<_> = summary.languages.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["Rust", "Python"]);
+        assert_eq!(summary.total_files, 3);
+        assert_eq!(summary.total_code, 50);
+    }
+
+    #[test]
+    fn test_group_by_root() {
+        let file = |path: &str, code| FileStats {
+            path: path.into(),
+            language: "Rust".into(),
+            code,
+            ..Default::default()
+        };
+        let summary = Summary::from_file_stats(vec![
+            file("app/src/main.rs", 10),
+            file("lib/a.rs", 5),
+            file("lib/core/b.rs", 7),
+        ]);
+        let roots = [
+            PathBuf::from("app"),
+            PathBuf::from("lib"),
+            PathBuf::from("lib/core"),
+        ];
+
+        let totals: Vec<_> = summary
+            .group_by_root(&roots)
+            .iter()
+            .map(|(root, group)| (root.to_string_lossy().into_owned(), group.total_code))
+            .collect();
+        assert_eq!(
+            totals,
+            [
+                ("app".to_string(), 10),
+                ("lib".to_string(), 5),
+                ("lib/core".to_string(), 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_by_project() {
+        let file = |path: &str, language: &str, code| FileStats {
+            path: path.into(),
+            language: language.into(),
+            code,
+            ..Default::default()
+        };
+        let summary = Summary::from_file_stats(vec![
+            file("core/a.rs", "Rust", 10),
+            file("cli/b.rs", "Rust", 20),
+            file("core/c.rs", "Rust", 5),
+            file("core/d.py", "Python", 1),
+        ]);
+
+        let nested = summary.by_project(|f| f.path.split('/').next().unwrap().to_string());
+        let rows: Vec<_> = nested
+            .iter()
+            .map(|(lang, projects)| {
+                let projects: Vec<_> = projects.iter().map(|p| (p.name.as_str(), p.code)).collect();
+                (lang.name.as_str(), projects)
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("Rust", vec![("cli", 20), ("core", 15)]),
+                ("Python", vec![("core", 1)])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_delta() {
+        let lang = |name: &str, files, code| FileStats {
+            path: format!("{}.{}", name, files),
+            language: name.into(),
+            code,
+            ..Default::default()
+        };
+        let old = Summary::from_file_stats(vec![lang("Rust", 1, 100), lang("Python", 1, 50)]);
+        let new = Summary::from_file_stats(vec![
+            lang("Rust", 1, 100),
+            lang("Rust", 2, 30),
+            lang("Go", 1, 10),
+        ]);
+
+        let delta = ReportDelta::between(&old, &new);
+        let changes: Vec<_> = delta
+            .languages
+            .iter()
+            .map(|l| (l.name.as_str(), l.files, l.code))
+            .collect();
+        assert_eq!(
+            changes,
+            [("Python", -1, -50), ("Rust", 1, 30), ("Go", 1, 10)]
+        );
+        assert_eq!(delta.total.files, 1);
+        assert_eq!(delta.total.code, -10);
+    }
+
+    #[test]
+    fn test_byte_and_line_length_totals() {
+        let stats = vec![
+            FileStats {
+                path: "a.rs".into(),
+                language: "Rust".into(),
+                code: 10,
+                bytes: 200,
+                line_bytes: 190,
+                max_line_length: 40,
+                ..Default::default()
+            },
+            FileStats {
+                path: "b.rs".into(),
+                language: "Rust".into(),
+                code: 10,
+                bytes: 400,
+                line_bytes: 390,
+                max_line_length: 90,
+                ..Default::default()
+            },
+        ];
+
+        let summary = Summary::from_file_stats(stats);
+        let rust = &summary.languages[0];
+        assert_eq!(rust.bytes, 600);
+        assert_eq!(rust.max_line_length, 90);
+        assert!((rust.avg_line_length() - 29.0).abs() < f64::EPSILON);
+        assert_eq!(summary.total_bytes, 600);
+        assert!((summary.avg_line_length() - 29.0).abs() < f64::EPSILON);
+        assert_eq!(summary.max_line_length, 90);
+    }
 }
@@ -1,84 +1,384 @@
-use crate::counter::{LineType, State, classify_line};
+use crate::counter::{DocMarkers, LineSplitter};
 use crate::languages::Language;
+use serde::Serialize;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StripMode {
     Comments,
     Code,
 }
 
+/// Strip `path` into `path.with_extension(output_ext)`.
 pub fn strip_file(
     path: &Path,
     language: &Language,
     mode: StripMode,
     output_ext: &str,
 ) -> std::io::Result<()> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    strip_file_to(path, language, mode, &path.with_extension(output_ext))
+}
+
+/// Strip `path` into `output_path`, creating its parent directories. Fails without touching
+/// either file when `output_path` is `path` itself (use [`strip_file_in_place`] for that).
+pub fn strip_file_to(
+    path: &Path,
+    language: &Language,
+    mode: StripMode,
+    output_path: &Path,
+) -> std::io::Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if is_same_file(path, output_path) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{} would overwrite its own source; use --in-place",
+                output_path.display()
+            ),
+        ));
+    }
+    let mut output = BufWriter::new(File::create(output_path)?);
+    strip_to_writer(reader, language, mode, &mut output)?;
+    output.flush()
+}
 
-    let output_path = path.with_extension(output_ext);
-    let mut output = File::create(&output_path)?;
+/// Whether `output` resolves to the existing file `input`, through symlinks and `..`.
+/// `output` need not exist yet, but its parent directory must.
+fn is_same_file(input: &Path, output: &Path) -> bool {
+    let Ok(input) = input.canonicalize() else {
+        return false;
+    };
+    let output = match output.canonicalize() {
+        Ok(output) => output,
+        Err(_) => {
+            let parent = match output.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            match (parent.canonicalize(), output.file_name()) {
+                (Ok(parent), Some(name)) => parent.join(name),
+                _ => return false,
+            }
+        }
+    };
+    input == output
+}
 
-    let mut state = State::Code;
+/// Strip `path` and write the result back over it. The original is first copied to
+/// `<path>.<backup_ext>` when `backup_ext` is given. Files that stripping leaves unchanged
+/// are not rewritten or backed up; returns whether `path` was rewritten.
+pub fn strip_file_in_place(
+    path: &Path,
+    language: &Language,
+    mode: StripMode,
+    backup_ext: Option<&str>,
+) -> std::io::Result<bool> {
+    let content = std::fs::read(path)?;
+    let mut stripped = Vec::with_capacity(content.len());
+    strip_to_writer(content.as_slice(), language, mode, &mut stripped)?;
+    if stripped == content {
+        return Ok(false);
+    }
 
-    for line in reader.lines() {
-        let line = line?;
-        let trimmed = line.trim();
+    if let Some(ext) = backup_ext.filter(|ext| !ext.is_empty()) {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".");
+        backup.push(ext);
+        std::fs::write(backup, &content)?;
+    }
+    std::fs::write(path, stripped)?;
+    Ok(true)
+}
+
+/// Strip everything read from `reader` and write the result to `output`.
+///
+/// Blank lines are kept in both modes. Lines left empty by stripping are dropped. Each line
+/// kept ends the way it did in the input (`\n`, `\r\n` or nothing at the end of input).
+pub fn strip_to_writer(
+    mut reader: impl BufRead,
+    language: &Language,
+    mode: StripMode,
+    output: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut splitter = LineSplitter::new(language);
+    let mut buf = Vec::new();
 
-        if trimmed.is_empty() {
-            writeln!(output)?;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let (line, ending) = split_line_ending(&buf);
+        let line = std::str::from_utf8(line)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if line.trim().is_empty() {
+            output.write_all(ending)?;
             continue;
         }
 
-        let (new_state, line_type) = classify_line(trimmed, state, language);
-        state = new_state;
-
-        match mode {
-            StripMode::Comments => match line_type {
-                LineType::Code | LineType::Blank => writeln!(output, "{}", line)?,
-                LineType::Mixed => {
-                    if let Some(stripped) = strip_comment_from_line(&line, language) {
-                        writeln!(output, "{}", stripped)?;
-                    } else {
-                        writeln!(output, "{}", line)?;
-                    }
-                }
-                LineType::Comment => {}
-            },
-            StripMode::Code => match line_type {
-                LineType::Comment => writeln!(output, "{}", line)?,
-                LineType::Mixed => {
-                    if let Some(comment) = extract_comment_from_line(&line, language) {
-                        writeln!(output, "{}", comment)?;
-                    }
-                }
-                LineType::Code | LineType::Blank => {}
-            },
-        }
+        let segments = splitter.split(line);
+        let has_code = !segments.code.trim().is_empty();
+        let has_comment = !segments.comment.trim().is_empty();
+
+        let kept = match mode {
+            StripMode::Comments if !has_comment => line,
+            StripMode::Comments if has_code => segments.code.trim_end(),
+            StripMode::Code if !has_code => line,
+            StripMode::Code if has_comment => segments.comment.trim_end(),
+            StripMode::Comments | StripMode::Code => continue,
+        };
+        output.write_all(kept.as_bytes())?;
+        output.write_all(ending)?;
     }
 
     Ok(())
 }
 
-fn strip_comment_from_line(line: &str, lang: &Language) -> Option<String> {
-    for &comment_start in lang.line_comments {
-        if let Some(pos) = line.find(comment_start) {
-            let before = &line[..pos];
-            if !before.trim().is_empty() {
-                return Some(before.trim_end().to_string());
+/// `line` without its trailing `\n` or `\r\n`, and that line ending.
+fn split_line_ending(line: &[u8]) -> (&[u8], &[u8]) {
+    let body = line
+        .strip_suffix(b"\r\n")
+        .or_else(|| line.strip_suffix(b"\n"))
+        .unwrap_or(line);
+    line.split_at(body.len())
+}
+
+/// One comment found by [`comment_spans`]: a block comment, a run of whole-line
+/// comments on consecutive lines, or a comment trailing code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommentSpan {
+    /// The comment with its markers, one trimmed source line per line.
+    pub text: String,
+    /// 1-based first line.
+    pub start_line: usize,
+    /// 1-based last line, inclusive.
+    pub end_line: usize,
+    /// Documentation comment (`///`, `/** */`, docstrings, ...).
+    pub doc: bool,
+}
+
+/// The comments in everything read from `reader`, in source order, recognized with the
+/// same rules as [`strip_to_writer`].
+pub fn comment_spans(
+    reader: impl BufRead,
+    language: &Language,
+) -> std::io::Result<Vec<CommentSpan>> {
+    let doc_markers = DocMarkers::for_language(language.name);
+    let mut spans: Vec<CommentSpan> = Vec::new();
+    // Whether the last span holds whole-line comments only, so the next one can join it.
+    let mut joinable = false;
+    let mut splitter = LineSplitter::new(language);
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        let in_block = splitter.in_comment();
+        let segments = splitter.split(&line);
+
+        let comment = segments.comment.trim();
+        let has_code = !segments.code.trim().is_empty();
+        let last = spans.last_mut().filter(|span| span.end_line + 1 == number);
+
+        // Lines inside a block comment, blank ones included, continue it.
+        if in_block {
+            if let Some(span) = last {
+                span.text.push('\n');
+                span.text.push_str(comment);
+                span.end_line = number;
+                joinable &= !has_code;
+                continue;
+            }
+        }
+        if comment.is_empty() {
+            continue;
+        }
+
+        let doc = doc_markers.starts_line(comment) || doc_markers.starts_block(comment);
+        match last {
+            Some(span) if joinable && !has_code && span.doc == doc => {
+                span.text.push('\n');
+                span.text.push_str(comment);
+                span.end_line = number;
+            }
+            _ => {
+                spans.push(CommentSpan {
+                    text: comment.to_string(),
+                    start_line: number,
+                    end_line: number,
+                    doc,
+                });
+                joinable = !has_code;
             }
         }
     }
-    None
+
+    Ok(spans)
 }
 
-fn extract_comment_from_line(line: &str, lang: &Language) -> Option<String> {
-    for &comment_start in lang.line_comments {
-        if let Some(pos) = line.find(comment_start) {
-            return Some(line[pos..].to_string());
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LANGUAGES;
+
+    fn strip(source: &str, lang: &str, mode: StripMode) -> String {
+        let lang = LANGUAGES.get(lang).unwrap();
+        let mut out = Vec::new();
+        strip_to_writer(source.as_bytes(), lang, mode, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_strip_inline_block_comments() {
+        let source = "int a = 1; /* one */\n/* start\n   end */ int b = 2;\nint c /* x */ = 3;\n";
+        assert_eq!(
+            strip(source, "C", StripMode::Comments),
+            "int a = 1;\n int b = 2;\nint c  = 3;\n"
+        );
+        assert_eq!(
+            strip(source, "C", StripMode::Code),
+            "/* one */\n/* start\n   end */\n/* x */\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_fixed_form_fortran() {
+        let source = "C     Comment\n      CALL FOO() ! trailing\n";
+        assert_eq!(
+            strip(source, "Fortran 77", StripMode::Comments),
+            "      CALL FOO()\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_keeps_heredoc_bodies() {
+        let source = "cat <<EOF # usage\n# shown\nEOF\n# hidden\n";
+        assert_eq!(
+            strip(source, "Bash", StripMode::Comments),
+            "cat <<EOF\n# shown\nEOF\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_ignores_comment_tokens_in_strings() {
+        let source = "let url = \"http://example.com\"; // link\nlet s = \"/* not */\";\n";
+        assert_eq!(
+            strip(source, "Rust", StripMode::Comments),
+            "let url = \"http://example.com\";\nlet s = \"/* not */\";\n"
+        );
+        assert_eq!(strip(source, "Rust", StripMode::Code), "// link\n");
+    }
+
+    #[test]
+    fn test_strip_keeps_assigned_triple_quoted_strings() {
+        let source = "x = \"\"\"a\nb # not a comment\n\"\"\"\ny = '''c'''\n";
+        assert_eq!(strip(source, "Python", StripMode::Comments), source);
+        assert_eq!(strip(source, "Python", StripMode::Code), "");
+    }
+
+    #[test]
+    fn test_strip_python_docstrings() {
+        let source = "def f():\n    \"\"\"Doc\n    more.\n    \"\"\"\n    return 1  # one\n";
+        assert_eq!(
+            strip(source, "Python", StripMode::Comments),
+            "def f():\n    return 1\n"
+        );
+        assert_eq!(
+            strip(source, "Python", StripMode::Code),
+            "    \"\"\"Doc\n    more.\n    \"\"\"\n# one\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_nested_comments() {
+        let source = "/* a /* b */ still */ fn x() {}\n";
+        assert_eq!(strip(source, "Rust", StripMode::Comments), " fn x() {}\n");
+    }
+
+    #[test]
+    fn test_comment_spans() {
+        let rust = LANGUAGES.get("Rust").unwrap();
+        let source = "//! Crate docs.\n\n/// Adds one.\n/// Really.\n// plain\nfn add(a: i32) -> i32 {\n    a + 1 // sum\n}\n/* a\n\n   b */ fn c() {}\nlet s = \"// not\";\n";
+        let spans = comment_spans(source.as_bytes(), rust).unwrap();
+        let summary: Vec<_> = spans
+            .iter()
+            .map(|s| (s.text.as_str(), s.start_line, s.end_line, s.doc))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("//! Crate docs.", 1, 1, true),
+                ("/// Adds one.\n/// Really.", 3, 4, true),
+                ("// plain", 5, 5, false),
+                ("// sum", 7, 7, false),
+                ("/* a\n\nb */", 9, 11, false),
+            ]
+        );
+
+        let python = LANGUAGES.get("Python").unwrap();
+        let spans = comment_spans("# one\n# two\nx = 1  # three\n".as_bytes(), python).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].text, "# one\n# two");
+        assert_eq!(
+            (spans[1].start_line, spans[1].text.as_str()),
+            (3, "# three")
+        );
+    }
+
+    #[test]
+    fn test_strip_refuses_to_overwrite_source() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("main.rs");
+        std::fs::write(&path, "// header\nfn main() {}\n").unwrap();
+        let rust = LANGUAGES.get("Rust").unwrap();
+
+        let error = strip_file(&path, rust, StripMode::Comments, "rs").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        let same = temp.path().join(".").join("main.rs");
+        assert!(strip_file_to(&path, rust, StripMode::Comments, &same).is_err());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "// header\nfn main() {}\n"
+        );
+
+        strip_file(&path, rust, StripMode::Comments, "stripped").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(temp.path().join("main.stripped")).unwrap(),
+            "fn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_strip_in_place_with_backup() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("main.rs");
+        std::fs::write(&path, "// header\nfn main() {}\n").unwrap();
+
+        let rust = LANGUAGES.get("Rust").unwrap();
+        assert!(strip_file_in_place(&path, rust, StripMode::Comments, Some("bak")).unwrap());
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+        assert_eq!(
+            std::fs::read_to_string(temp.path().join("main.rs.bak")).unwrap(),
+            "// header\nfn main() {}\n"
+        );
+
+        // Nothing left to strip: neither rewritten nor backed up again.
+        std::fs::remove_file(temp.path().join("main.rs.bak")).unwrap();
+        assert!(!strip_file_in_place(&path, rust, StripMode::Comments, Some("bak")).unwrap());
+        assert!(!temp.path().join("main.rs.bak").exists());
+    }
+
+    #[test]
+    fn test_strip_keeps_line_endings() {
+        let source = "// header\r\nfn main() {} // end\r\n\r\nlet x = 1;";
+        assert_eq!(
+            strip(source, "Rust", StripMode::Comments),
+            "fn main() {}\r\n\r\nlet x = 1;"
+        );
     }
-    None
 }
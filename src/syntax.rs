@@ -0,0 +1,142 @@
+//! Parse-tree based comment detection for `--accurate` (the `tree-sitter` feature).
+//!
+//! The line classifier in [`crate::counter`] works on one line at a time and can be fooled
+//! by comment tokens inside strings, template literals or heredocs. For languages with a
+//! bundled grammar, the comment ranges come from a real parse instead; the counter still
+//! does the per-line bookkeeping.
+
+use std::ops::Range;
+use tree_sitter::{Language, Node, Parser};
+
+/// Languages with a bundled grammar.
+pub const SUPPORTED_LANGUAGES: &[&str] = &[
+    "Bash",
+    "C",
+    "C Header",
+    "Go",
+    "JavaScript",
+    "JSX",
+    "Python",
+    "Ruby",
+    "Rust",
+    "Shell",
+    "TSX",
+    "TypeScript",
+];
+
+fn grammar(language: &str) -> Option<Language> {
+    let grammar = match language {
+        "Bash" | "Shell" => tree_sitter_bash::LANGUAGE,
+        "C" | "C Header" => tree_sitter_c::LANGUAGE,
+        "Go" => tree_sitter_go::LANGUAGE,
+        "JavaScript" | "JSX" => tree_sitter_javascript::LANGUAGE,
+        "Python" => tree_sitter_python::LANGUAGE,
+        "Ruby" => tree_sitter_ruby::LANGUAGE,
+        "Rust" => tree_sitter_rust::LANGUAGE,
+        "TypeScript" => tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+        "TSX" => tree_sitter_typescript::LANGUAGE_TSX,
+        _ => return None,
+    };
+    Some(grammar.into())
+}
+
+/// Byte ranges of the comments in `content`, in order, or `None` when `language` has no
+/// grammar. Python docstrings count as comments when `docstrings` is set, matching the line
+/// classifier.
+pub fn comment_ranges(
+    content: &[u8],
+    language: &str,
+    docstrings: bool,
+) -> Option<Vec<Range<usize>>> {
+    let mut parser = Parser::new();
+    parser.set_language(&grammar(language)?).ok()?;
+    let tree = parser.parse(content, None)?;
+
+    let mut ranges = Vec::new();
+    let mut cursor = tree.walk();
+    'walk: loop {
+        let node = cursor.node();
+        if node.kind().contains("comment")
+            || (docstrings && language == "Python" && is_docstring(node))
+        {
+            ranges.push(node.byte_range());
+        } else if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+    Some(ranges)
+}
+
+/// A bare string that is the first statement of a module, class or function body.
+fn is_docstring(node: Node) -> bool {
+    let is_string = node.kind() == "expression_statement"
+        && node.named_child_count() == 1
+        && node
+            .named_child(0)
+            .is_some_and(|child| child.kind() == "string");
+    let in_body = node.parent().is_some_and(|parent| match parent.kind() {
+        "module" => true,
+        "block" => parent.parent().is_some_and(|definition| {
+            matches!(
+                definition.kind(),
+                "function_definition" | "class_definition"
+            )
+        }),
+        _ => false,
+    });
+    let mut previous = node.prev_named_sibling();
+    while let Some(sibling) = previous.filter(|s| s.kind() == "comment") {
+        previous = sibling.prev_named_sibling();
+    }
+    is_string && in_body && previous.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comments<'a>(content: &'a str, language: &str) -> Vec<&'a str> {
+        comment_ranges(content.as_bytes(), language, true)
+            .unwrap()
+            .into_iter()
+            .map(|range| &content[range])
+            .collect()
+    }
+
+    #[test]
+    fn test_comment_tokens_in_strings() {
+        assert_eq!(
+            comments("let url = \"http://x\"; // real\n", "Rust"),
+            ["// real"]
+        );
+        assert_eq!(
+            comments("const s = `\n// not a comment\n`;\n/* c */\n", "JavaScript"),
+            ["/* c */"]
+        );
+        assert_eq!(
+            comments("cat <<EOF\n# kept\nEOF\n# comment\n", "Bash"),
+            ["# comment"]
+        );
+        assert_eq!(
+            comments("def f():\n    \"\"\"Doc.\"\"\"\n    return '#'\n", "Python"),
+            ["\"\"\"Doc.\"\"\""]
+        );
+        assert!(comment_ranges(b"x", "COBOL", true).is_none());
+    }
+
+    #[test]
+    fn test_docstring_positions() {
+        let source = "\"\"\"Module.\"\"\"\nclass A:\n    # note\n    \"\"\"Class.\"\"\"\n    def f(self):\n        x = 1\n        \"\"\"Not a docstring.\"\"\"\n";
+        assert_eq!(
+            comments(source, "Python"),
+            ["\"\"\"Module.\"\"\"", "# note", "\"\"\"Class.\"\"\""]
+        );
+        let ranges = comment_ranges(source.as_bytes(), "Python", false).unwrap();
+        assert_eq!(ranges.len(), 1);
+    }
+}
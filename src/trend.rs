@@ -0,0 +1,247 @@
+//! Line-count history for `rloc trend`.
+//!
+//! `rloc trend record` appends one JSON line per run to a store file: the time, the git
+//! commit checked out in the first analyzed path, and the run's JSON report. `rloc trend
+//! show` reads the store back and renders the runs with their deltas and sparklines.
+
+use crate::stats::{JsonOutput, SCHEMA_VERSION, Summary};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Store used when `--store` is not given, relative to the working directory.
+pub const DEFAULT_STORE: &str = ".rloc-trend.jsonl";
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One recorded run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrendEntry {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// `HEAD` of the analyzed working copy; absent outside git.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub report: JsonOutput,
+}
+
+impl TrendEntry {
+    /// An entry for `summary`, stamped with the current time.
+    pub fn new(summary: &Summary, commit: Option<String>) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        TrendEntry {
+            timestamp,
+            commit,
+            report: JsonOutput::from(summary),
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary::from(&self.report)
+    }
+}
+
+/// Totals of one run with the change in code since the previous one, as `trend show`
+/// reports them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrendPoint {
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub files: u64,
+    pub blank: u64,
+    pub comment: u64,
+    pub code: u64,
+    /// Code lines added (or removed, when negative) since the previous run.
+    pub code_delta: i64,
+}
+
+/// `rloc trend show --json`: the recorded runs, oldest first.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrendReport {
+    pub schema_version: u32,
+    pub runs: Vec<TrendPoint>,
+}
+
+impl TrendReport {
+    pub fn new(entries: &[TrendEntry]) -> Self {
+        TrendReport {
+            schema_version: SCHEMA_VERSION,
+            runs: points(entries),
+        }
+    }
+}
+
+/// Totals of each entry, in recorded order; the first run has a delta of 0.
+pub fn points(entries: &[TrendEntry]) -> Vec<TrendPoint> {
+    let mut previous = None;
+    entries
+        .iter()
+        .map(|entry| {
+            let sum = &entry.report.sum;
+            let code_delta = previous.map_or(0, |prev| sum.code as i64 - prev as i64);
+            previous = Some(sum.code);
+            TrendPoint {
+                timestamp: entry.timestamp,
+                commit: entry.commit.clone(),
+                files: sum.n_files,
+                blank: sum.blank,
+                comment: sum.comment,
+                code: sum.code,
+                code_delta,
+            }
+        })
+        .collect()
+}
+
+/// The commit checked out in the working copy containing `path`, if it is a git one.
+pub fn head_commit(path: &Path) -> Option<String> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!commit.is_empty()).then_some(commit)
+}
+
+/// Append `entry` to `store`, creating the file if needed.
+pub fn append(store: &Path, entry: &TrendEntry) -> io::Result<()> {
+    let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(store)?;
+    writeln!(file, "{}", line)
+}
+
+/// All entries in `store`, oldest first. Blank lines are skipped; a malformed line is an
+/// `InvalidData` error naming its line number.
+pub fn load(store: &Path) -> io::Result<Vec<TrendEntry>> {
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(File::open(store)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, e),
+            )
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// One block character per value, scaled between the smallest and the largest value.
+pub fn sparkline(values: &[u64]) -> String {
+    let (Some(&min), Some(&max)) = (values.iter().min(), values.iter().max()) else {
+        return String::new();
+    };
+    let top = SPARKS.len() - 1;
+    values
+        .iter()
+        .map(|&value| {
+            if max == min {
+                SPARKS[top / 2]
+            } else {
+                SPARKS[((value - min) * top as u64 / (max - min)) as usize]
+            }
+        })
+        .collect()
+}
+
+/// `timestamp` as a UTC `YYYY-MM-DD HH:MM`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let minutes = timestamp % 86_400 / 60;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::FileStats;
+    use tempfile::TempDir;
+
+    fn summary(code: u64) -> Summary {
+        Summary::from_file_stats(vec![FileStats {
+            path: "main.rs".into(),
+            language: "Rust".into(),
+            code,
+            ..Default::default()
+        }])
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let store = temp.path().join("trend.jsonl");
+        append(&store, &TrendEntry::new(&summary(10), Some("abc".into()))).unwrap();
+        append(&store, &TrendEntry::new(&summary(25), None)).unwrap();
+        append(&store, &TrendEntry::new(&summary(20), None)).unwrap();
+
+        let entries = load(&store).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].commit.as_deref(), Some("abc"));
+        assert_eq!(entries[1].summary().languages[0].code, 25);
+
+        let deltas: Vec<_> = points(&entries).iter().map(|p| p.code_delta).collect();
+        assert_eq!(deltas, [0, 15, -5]);
+    }
+
+    #[test]
+    fn test_load_reports_bad_line() {
+        let temp = TempDir::new().unwrap();
+        let store = temp.path().join("trend.jsonl");
+        std::fs::write(&store, "\n{not json}\n").unwrap();
+        let error = load(&store).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 2:"));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[0, 7, 14]), "▁▄█");
+        assert_eq!(sparkline(&[5, 5]), "▄▄");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14 22:13");
+    }
+}
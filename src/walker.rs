@@ -1,42 +1,217 @@
-use crate::languages::{Language, detect_language, get_language_ignore_case};
+use crate::cancel::{CancellationToken, is_cancelled};
+use crate::custom_langs::CustomLanguageRegistry;
+use crate::languages::{
+    Language, detect_language, detect_language_by_filename, detect_language_by_modeline,
+    get_language, get_language_ignore_case,
+};
+use crate::stats::SkipReason;
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
+use ignore::gitignore::{Gitignore, GitignoreBuilder, gitconfig_excludes_path};
 use ignore::overrides::OverrideBuilder;
+use rayon::prelude::*;
 use regex::Regex;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex, mpsc};
+use tracing::{debug, trace, warn};
 
 #[derive(Debug, Clone)]
 pub struct WalkerConfig {
     pub paths: Vec<PathBuf>,
+    /// File listing the paths to count instead of walking; `-` reads stdin. Relative paths
+    /// are resolved against the list file's directory.
     pub list_file: Option<PathBuf>,
+    /// The list file is NUL-delimited (`find -print0`) rather than one path per line.
+    pub list_file_nul: bool,
+    /// Only count files with staged changes (`git diff --cached`), as they are in the working
+    /// tree. Deleted files are left out, and so are roots outside a git repository.
+    pub staged: bool,
     pub exclude_dirs: Vec<String>,
     pub exclude_exts: Vec<String>,
     pub exclude_langs: Vec<String>,
     pub include_exts: Vec<String>,
     pub include_langs: Vec<String>,
     pub force_lang: HashMap<String, String>,
+    /// `(glob, language)` rules checked before any other detection; the glob matches the
+    /// path relative to the analyzed root and the last matching rule wins.
+    pub force_lang_globs: Vec<(String, String)>,
+    /// Extensions removed from the extension map; such files are only detected by file name.
+    pub unmapped_exts: Vec<String>,
     pub match_dir: Option<Regex>,
     pub not_match_dir: Vec<Regex>,
     pub match_file: Option<Regex>,
     pub not_match_file: Vec<Regex>,
+    /// Path globs (relative to the analyzed root); when set, only matching files are counted.
+    pub include_globs: Vec<String>,
+    /// Path globs (relative to the analyzed root) excluding matching files.
+    pub exclude_globs: Vec<String>,
     pub include_content: Option<Regex>,
     pub exclude_content: Option<Regex>,
     pub vcs: Option<VcsMode>,
+    /// Directory symlinks are followed; each directory is still visited at most once.
     pub follow_symlinks: bool,
+    /// Don't descend into directories on a different file system than their root.
+    pub one_file_system: bool,
     pub hidden: bool,
     pub fullpath: bool,
     pub max_depth: Option<usize>,
     pub skip_gitignore: bool,
+    /// Don't apply the global git excludes file (`core.excludesFile`).
+    pub skip_global_gitignore: bool,
+    /// Don't apply the repository's `.git/info/exclude`.
+    pub skip_git_exclude: bool,
+    /// Extra files of gitignore patterns, anchored at each analyzed root.
+    pub custom_ignore_files: Vec<PathBuf>,
     pub skip_uniqueness: bool,
     pub include_submodules: bool,
     pub max_file_size: Option<u64>,
+    pub vendor_patterns: Vec<String>,
+    pub exclude_vendored: bool,
+    /// Path globs (relative to the analyzed root) classified as test code.
+    pub test_patterns: Vec<String>,
+    pub exclude_tests: bool,
+    /// Skip nested git repositories and submodules below the roots.
+    pub exclude_nested_repos: bool,
+    /// Let Emacs/Vim modelines override the language detected from the file name. Files
+    /// whose name matches no language are always checked for a modeline.
+    pub modelines: bool,
+    pub custom_languages: Option<Arc<CustomLanguageRegistry>>,
+    /// Stops the walk early; callers should check the token before using the result.
+    pub cancel: Option<CancellationToken>,
+}
+
+/// Path globs (relative to the analyzed root) classified as vendored/third-party code.
+pub const DEFAULT_VENDOR_PATTERNS: &[&str] = &[
+    "**/vendor/**",
+    "**/vendored/**",
+    "**/third_party/**",
+    "**/third-party/**",
+    "**/thirdparty/**",
+    "**/deps/**",
+    "**/node_modules/**",
+    "**/bower_components/**",
+    "**/*.min.js",
+    "**/*.min.css",
+];
+
+/// Path globs (relative to the analyzed root) classified as test code.
+pub const DEFAULT_TEST_PATTERNS: &[&str] = &[
+    "**/tests/**",
+    "**/test/**",
+    "**/__tests__/**",
+    "**/spec/**",
+    "**/*_test.go",
+    "**/test_*.py",
+    "**/*_test.py",
+    "**/*.test.{js,jsx,ts,tsx}",
+    "**/*.spec.{js,jsx,ts,tsx}",
+    "**/*Test.java",
+    "**/*Tests.cs",
+    "**/*_spec.rb",
+];
+
+/// Default excluded directories that hold vendored code; walked again with `--include-vendored`.
+pub const VENDOR_DIRS: &[&str] = &["vendor", "node_modules"];
+
+/// Version control metadata directories, excluded by every built-in profile.
+const MINIMAL_EXCLUDE_DIRS: &[&str] = &[".git", ".svn", ".hg"];
+
+/// Dependency, build output and virtualenv directories excluded by default.
+const DEFAULT_EXCLUDE_DIRS: &[&str] = &[
+    "node_modules",
+    "target",
+    "vendor",
+    "dist",
+    "build",
+    "__pycache__",
+    ".tox",
+    ".eggs",
+    "venv",
+    ".venv",
+    "env",
+    ".env",
+];
+
+/// Tool caches, coverage reports and IDE/framework output, excluded by `aggressive`.
+const AGGRESSIVE_EXCLUDE_DIRS: &[&str] = &[
+    "coverage",
+    "htmlcov",
+    ".nyc_output",
+    ".next",
+    ".nuxt",
+    ".svelte-kit",
+    ".angular",
+    ".terraform",
+    ".gradle",
+    ".cache",
+    ".pytest_cache",
+    ".mypy_cache",
+    ".dart_tool",
+    "Pods",
+    "Carthage",
+    "DerivedData",
+    "bower_components",
+    "obj",
+];
+
+/// Built-in sets of directories skipped while walking (`--profile`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExcludeProfile {
+    /// Only version control metadata (`.git`, `.svn`, `.hg`).
+    Minimal,
+    /// Also dependencies, build output and virtualenvs.
+    #[default]
+    Default,
+    /// Also caches, coverage reports and framework/IDE output.
+    Aggressive,
+}
+
+impl ExcludeProfile {
+    pub const ALL: [ExcludeProfile; 3] = [Self::Minimal, Self::Default, Self::Aggressive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Minimal => "minimal",
+            Self::Default => "default",
+            Self::Aggressive => "aggressive",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn exclude_dirs(self) -> Vec<String> {
+        let extra: &[&[&str]] = match self {
+            Self::Minimal => &[],
+            Self::Default => &[DEFAULT_EXCLUDE_DIRS],
+            Self::Aggressive => &[DEFAULT_EXCLUDE_DIRS, AGGRESSIVE_EXCLUDE_DIRS],
+        };
+        std::iter::once(MINIMAL_EXCLUDE_DIRS)
+            .chain(extra.iter().copied())
+            .flatten()
+            .map(|dir| dir.to_string())
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum VcsMode {
+    /// Use whichever VCS each root belongs to (git, Mercurial or Subversion).
     Auto,
     Git,
+    Hg,
+    Svn,
     None,
 }
 
@@ -45,122 +220,527 @@ impl Default for WalkerConfig {
         Self {
             paths: vec![PathBuf::from(".")],
             list_file: None,
-            exclude_dirs: vec![
-                ".git".into(),
-                ".svn".into(),
-                ".hg".into(),
-                "node_modules".into(),
-                "target".into(),
-                "vendor".into(),
-                "dist".into(),
-                "build".into(),
-                "__pycache__".into(),
-                ".tox".into(),
-                ".eggs".into(),
-                "venv".into(),
-                ".venv".into(),
-                "env".into(),
-                ".env".into(),
-            ],
+            list_file_nul: false,
+            staged: false,
+            exclude_dirs: ExcludeProfile::Default.exclude_dirs(),
             exclude_exts: vec![],
             exclude_langs: vec![],
             include_exts: vec![],
             include_langs: vec![],
             force_lang: HashMap::new(),
+            force_lang_globs: vec![],
+            unmapped_exts: vec![],
             match_dir: None,
             not_match_dir: vec![],
             match_file: None,
             not_match_file: vec![],
+            include_globs: vec![],
+            exclude_globs: vec![],
             include_content: None,
             exclude_content: None,
             vcs: None,
             follow_symlinks: false,
+            one_file_system: false,
             hidden: false,
             fullpath: false,
             max_depth: None,
             skip_gitignore: false,
+            skip_global_gitignore: false,
+            skip_git_exclude: false,
+            custom_ignore_files: vec![],
             skip_uniqueness: false,
             include_submodules: false,
             max_file_size: None,
+            vendor_patterns: DEFAULT_VENDOR_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            exclude_vendored: false,
+            test_patterns: DEFAULT_TEST_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            exclude_tests: false,
+            exclude_nested_repos: false,
+            modelines: false,
+            custom_languages: None,
+            cancel: None,
         }
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct FileEntry {
     pub path: PathBuf,
     pub language: &'static Language,
+    pub vendored: bool,
+    /// Matched a test path pattern.
+    pub test: bool,
+}
+
+fn build_globset(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        if let Ok(glob) = path_glob(pattern) {
+            builder.add(glob);
+        }
+    }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+/// `pattern` as a path glob: `*` and `?` stop at `/`, only `**` crosses directories.
+pub(crate) fn path_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+/// Strip whichever configured root `path` lives under, so root directories
+/// themselves never influence path classification.
+pub fn relative_to_root<'a>(path: &'a Path, roots: &[PathBuf]) -> &'a Path {
+    roots
+        .iter()
+        .filter_map(|root| path.strip_prefix(root).ok())
+        .find(|rel| !rel.as_os_str().is_empty())
+        .unwrap_or(path)
 }
 
 pub fn walk_files(config: &WalkerConfig) -> Vec<FileEntry> {
+    let mut files = Vec::new();
+    walk_files_with(config, |entry| files.push(entry));
+    files
+}
+
+/// Walk like [`walk_files`], handing each matching file to `emit` as soon as it is found
+/// so callers can start counting before the walk finishes.
+pub fn walk_files_with(config: &WalkerConfig, emit: impl FnMut(FileEntry)) {
+    walk_files_reporting(config, emit, |_, _| {});
+}
+
+/// Like [`walk_files_with`], also passing files that are found but cannot be counted (too
+/// large, no matching language) to `skipped`. Files excluded by the configured filters are
+/// not reported.
+pub fn walk_files_reporting(
+    config: &WalkerConfig,
+    mut emit: impl FnMut(FileEntry),
+    mut skipped: impl FnMut(PathBuf, SkipReason),
+) {
+    let filter = FileFilter::new(config);
+    let mut emit_path = |path: PathBuf| match filter.check(path) {
+        Checked::Accept(entry) => {
+            trace!(path = %entry.path.display(), language = entry.language.name, "found file");
+            emit(entry)
+        }
+        Checked::Skip(path, reason) => {
+            trace!(path = %path.display(), ?reason, "skipped file");
+            skipped(path, reason)
+        }
+        Checked::Reject => {}
+    };
+
     if let Some(ref list_file) = config.list_file {
-        return walk_list_file(list_file, config);
+        return walk_list_file(list_file, config, &mut emit_path);
     }
 
-    if let Some(VcsMode::Git) = config.vcs {
-        return walk_git_files(config);
+    if config.staged {
+        return walk_vcs_files(config, VcsMode::Git, &mut emit_path);
     }
 
-    if let Some(VcsMode::Auto) = config.vcs {
-        if Path::new(".git").exists() {
-            return walk_git_files(config);
-        }
+    match config.vcs {
+        Some(VcsMode::None) | None => walk_filesystem(config, &config.paths, &mut emit_path),
+        Some(vcs) => walk_vcs_files(config, vcs, &mut emit_path),
     }
+}
 
-    walk_filesystem(config)
+/// Files buffered between the walking thread and the counting threads.
+pub const PIPELINE_CAPACITY: usize = 4096;
+
+/// Walk on a background thread while `process` runs on the current rayon pool for each file
+/// as soon as it is discovered. `on_found` is called on the walking thread for every file
+/// (e.g. to grow a progress bar) and `on_skipped` for every file that cannot be counted.
+/// Returns the number of files discovered and the results. A panic on the walking thread is
+/// resumed on the caller's once counting has drained the files it found.
+pub fn walk_files_parallel<T, F>(
+    config: &WalkerConfig,
+    mut on_found: impl FnMut(&FileEntry) + Send,
+    on_skipped: impl FnMut(PathBuf, SkipReason) + Send,
+    process: F,
+) -> (usize, Vec<T>)
+where
+    T: Send,
+    F: Fn(FileEntry) -> Option<T> + Sync + Send,
+{
+    let (tx, rx) = mpsc::sync_channel(PIPELINE_CAPACITY);
+    let parent = tracing::Span::current();
+
+    std::thread::scope(|scope| {
+        let walk_span = tracing::info_span!(parent: &parent, "walk");
+        let walker = scope.spawn(move || {
+            let _span = walk_span.entered();
+            let mut discovered = 0;
+            walk_files_reporting(
+                config,
+                |entry| {
+                    discovered += 1;
+                    on_found(&entry);
+                    let _ = tx.send(entry);
+                },
+                on_skipped,
+            );
+            debug!(files = discovered, "walk finished");
+            discovered
+        });
+
+        let results = tracing::info_span!("count")
+            .in_scope(|| rx.into_iter().par_bridge().filter_map(process).collect());
+        let discovered = walker
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        (discovered, results)
+    })
 }
 
-fn walk_list_file(list_file: &Path, config: &WalkerConfig) -> Vec<FileEntry> {
-    let content = match std::fs::read_to_string(list_file) {
-        Ok(c) => c,
-        Err(_) => return Vec::new(),
+fn walk_list_file(list_file: &Path, config: &WalkerConfig, emit: &mut dyn FnMut(PathBuf)) {
+    let from_stdin = list_file == Path::new("-");
+    let content = if from_stdin {
+        let mut content = Vec::new();
+        match std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut content) {
+            Ok(_) => content,
+            Err(error) => {
+                warn!(%error, "cannot read file list from stdin");
+                return;
+            }
+        }
+    } else {
+        match std::fs::read(list_file) {
+            Ok(c) => c,
+            Err(error) => {
+                warn!(path = %list_file.display(), %error, "cannot read file list");
+                return;
+            }
+        }
     };
+    let base = list_file.parent().filter(|_| !from_stdin);
 
-    let files: Vec<PathBuf> = content
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(PathBuf::from)
-        .collect();
+    for entry in list_entries(&content, config.list_file_nul) {
+        if is_cancelled(&config.cancel) {
+            return;
+        }
+        let path = crate::path_from_bytes(entry);
+        match base {
+            Some(base) if path.is_relative() => emit(base.join(path)),
+            _ => emit(path),
+        }
+    }
+}
 
-    filter_files(files, config)
+/// Paths in a list file. Newline-delimited lists skip blank lines and `#` comments and
+/// tolerate CRLF; NUL-delimited lists are taken literally apart from empty entries.
+fn list_entries(content: &[u8], nul: bool) -> Vec<&[u8]> {
+    if nul {
+        return content
+            .split(|&b| b == 0)
+            .filter(|entry| !entry.is_empty())
+            .collect();
+    }
+    content
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| {
+            let trimmed = line.trim_ascii();
+            !trimmed.is_empty() && !trimmed.starts_with(b"#")
+        })
+        .collect()
 }
 
-fn walk_git_files(config: &WalkerConfig) -> Vec<FileEntry> {
-    let mut args = vec!["ls-files", "--cached", "--others", "--exclude-standard"];
-    if config.include_submodules {
-        args.push("--recurse-submodules");
+/// List each root's files with its version control system, then filter the list like a
+/// file system walk would (`max_depth`, `exclude_dirs`, custom ignore files). Roots where the VCS command is
+/// missing or fails (e.g. outside a working copy) are walked on the file system instead.
+fn walk_vcs_files(config: &WalkerConfig, vcs: VcsMode, emit: &mut dyn FnMut(PathBuf)) {
+    let ignore = custom_ignore(config);
+    for root in &config.paths {
+        if is_cancelled(&config.cancel) {
+            return;
+        }
+        if root.is_file() {
+            emit(root.clone());
+            continue;
+        }
+
+        let vcs = match vcs {
+            VcsMode::Auto => detect_vcs(root),
+            vcs => Some(vcs),
+        };
+        let Some(files) = vcs.and_then(|vcs| vcs_files(config, vcs, root)) else {
+            debug!(root = %root.display(), ?vcs, "no VCS file list, walking the file system");
+            // Walking everything would defeat the point of `--staged`.
+            if !config.staged {
+                walk_filesystem(config, std::slice::from_ref(root), emit);
+            }
+            continue;
+        };
+
+        let nested = if config.exclude_nested_repos {
+            let paths: Vec<PathBuf> = files.iter().map(|file| root.join(file)).collect();
+            nested_repos(
+                paths.iter().map(PathBuf::as_path),
+                std::slice::from_ref(root),
+            )
+        } else {
+            Vec::new()
+        };
+
+        for relative in files {
+            if is_cancelled(&config.cancel) {
+                return;
+            }
+            let path = root.join(&relative);
+            if vcs_path_allowed(config, &relative)
+                && !nested.iter().any(|r| path.starts_with(r))
+                && !ignore
+                    .matched_path_or_any_parents(&relative, false)
+                    .is_ignore()
+            {
+                emit(path);
+            }
+        }
     }
+}
 
-    let output = Command::new("git").args(&args).output();
+/// Whether `dir` holds a git repository of its own: a clone, or a submodule (whose `.git`
+/// is a file pointing into the parent repository).
+pub fn is_repo_root(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
 
-    let files = match output {
-        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
-            .lines()
-            .map(PathBuf::from)
-            .collect::<Vec<_>>(),
-        _ => return walk_filesystem(config),
+/// Git repositories and submodules nested below `roots` (not the roots themselves) that
+/// contain any of `files`, sorted. Each directory is checked once.
+pub fn nested_repos<'a>(
+    files: impl IntoIterator<Item = &'a Path>,
+    roots: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut checked: HashSet<&Path> = HashSet::new();
+    let mut repos = Vec::new();
+    for file in files {
+        for dir in file.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() || roots.iter().any(|root| root == dir) {
+                break;
+            }
+            // Its ancestors were checked along with it.
+            if !checked.insert(dir) {
+                break;
+            }
+            if is_repo_root(dir) {
+                repos.push(dir.to_path_buf());
+            }
+        }
+    }
+    repos.sort();
+    repos
+}
+
+/// The VCS of the working copy containing `root`, found by its metadata directory.
+pub(crate) fn detect_vcs(root: &Path) -> Option<VcsMode> {
+    let root = std::path::absolute(root).ok()?;
+    root.ancestors().find_map(|dir| {
+        [
+            (".git", VcsMode::Git),
+            (".hg", VcsMode::Hg),
+            (".svn", VcsMode::Svn),
+        ]
+        .into_iter()
+        .find(|(marker, _)| dir.join(marker).exists())
+        .map(|(_, vcs)| vcs)
+    })
+}
+
+/// `config.custom_ignore_files`, matched against paths relative to the analyzed root.
+/// Unreadable files are skipped.
+fn custom_ignore(config: &WalkerConfig) -> Gitignore {
+    let mut builder = GitignoreBuilder::new("");
+    for file in &config.custom_ignore_files {
+        let _ = builder.add(file);
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Files tracked below `root`, relative to it; `None` if the command is unavailable or fails.
+pub(crate) fn vcs_files(config: &WalkerConfig, vcs: VcsMode, root: &Path) -> Option<Vec<PathBuf>> {
+    let mut command = match vcs {
+        VcsMode::Git if config.staged => {
+            let mut command = Command::new("git");
+            command.arg("-C").arg(root).args([
+                "diff",
+                "--cached",
+                "--name-only",
+                "-z",
+                "--diff-filter=d",
+                "--relative",
+            ]);
+            command
+        }
+        VcsMode::Git => {
+            let mut command = Command::new("git");
+            command.arg("-C").arg(root);
+            if config.skip_global_gitignore {
+                command.args(["-c", "core.excludesFile="]);
+            }
+            // NUL-separated, so paths with non-ASCII bytes are not quoted.
+            command.args(["ls-files", "-z", "--cached", "--others"]);
+            if config.skip_git_exclude {
+                // `--exclude-standard` without `.git/info/exclude`.
+                command.arg("--exclude-per-directory=.gitignore");
+                if let Some(global) = gitconfig_excludes_path()
+                    .filter(|path| !config.skip_global_gitignore && path.is_file())
+                {
+                    command.arg("-X").arg(global);
+                }
+            } else {
+                command.arg("--exclude-standard");
+            }
+            if config.include_submodules {
+                command.arg("--recurse-submodules");
+            }
+            command
+        }
+        VcsMode::Hg => {
+            let mut command = Command::new("hg");
+            command.arg("--cwd").arg(root).args(["files", "."]);
+            command
+        }
+        VcsMode::Svn => {
+            let mut command = Command::new("svn");
+            command.args(["ls", "-R"]).arg(root);
+            command
+        }
+        VcsMode::Auto | VcsMode::None => return None,
     };
 
-    filter_files(files, config)
+    let output = match command.output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!(
+                root = %root.display(),
+                ?vcs,
+                stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+                "VCS command failed"
+            );
+            return None;
+        }
+        Err(error) => {
+            debug!(root = %root.display(), ?vcs, %error, "VCS command unavailable");
+            return None;
+        }
+    };
+    let separator = if vcs == VcsMode::Git { b'\0' } else { b'\n' };
+    let files: Vec<PathBuf> = output
+        .stdout
+        .split(|&b| b == separator)
+        .map(|entry| entry.strip_suffix(b"\r").unwrap_or(entry))
+        // `svn ls` also lists directories, marked with a trailing `/`.
+        .filter(|entry| !entry.is_empty() && !entry.ends_with(b"/"))
+        .map(crate::path_from_bytes)
+        .collect();
+    debug!(root = %root.display(), ?vcs, files = files.len(), "listed VCS files");
+    Some(files)
 }
 
-fn walk_filesystem(config: &WalkerConfig) -> Vec<FileEntry> {
-    let mut files = Vec::new();
+/// The content of `path` as a count reads it: the blob staged in the index with `staged`,
+/// otherwise the file on disk.
+pub fn read_file(config: &WalkerConfig, path: &Path) -> std::io::Result<crate::counter::Source> {
+    if !config.staged {
+        return crate::counter::read_source(path);
+    }
+    crate::hook::staged_content(path)
+        .map(crate::counter::Source::Owned)
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "not staged in the git index")
+        })
+}
 
-    for start_path in &config.paths {
-        let mut builder = WalkBuilder::new(start_path);
+/// Apply the directory and hidden file filters a file system walk enforces to a VCS-listed
+/// path.
+fn vcs_path_allowed(config: &WalkerConfig, relative: &Path) -> bool {
+    let depth = relative.components().count();
+    if config.max_depth.is_some_and(|max| depth > max) {
+        return false;
+    }
+    let hidden = |component: std::path::Component| {
+        component
+            .as_os_str()
+            .as_encoded_bytes()
+            .first()
+            .is_some_and(|&b| b == b'.')
+    };
+    if !config.hidden && relative.components().any(hidden) {
+        return false;
+    }
+    let Some(parent) = relative.parent() else {
+        return true;
+    };
+    !parent.components().any(|component| {
+        config
+            .exclude_dirs
+            .iter()
+            .any(|dir| component.as_os_str() == dir.as_str())
+    })
+}
+
+fn walk_filesystem(config: &WalkerConfig, roots: &[PathBuf], emit: &mut dyn FnMut(PathBuf)) {
+    // Directories already walked, so symlinks back into the tree can't loop or repeat work.
+    let visited: Arc<Mutex<HashSet<FileId>>> = Arc::default();
+
+    for start_path in roots {
+        // Walked as an extended-length path on Windows; files are reported below `start_path`.
+        let root = long_path(start_path);
+        let mut builder = WalkBuilder::new(&root);
+
+        if config.follow_symlinks {
+            if let Some(id) = file_id(start_path) {
+                if !visited.lock().unwrap().insert(id) {
+                    continue;
+                }
+            }
+        }
+        if config.follow_symlinks || config.exclude_nested_repos {
+            let visited = Arc::clone(&visited);
+            let follow_symlinks = config.follow_symlinks;
+            let exclude_nested_repos = config.exclude_nested_repos;
+            builder.filter_entry(move |entry| {
+                if !entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                    return true;
+                }
+                if exclude_nested_repos && entry.depth() > 0 && is_repo_root(entry.path()) {
+                    return false;
+                }
+                if !follow_symlinks {
+                    return true;
+                }
+                match file_id(entry.path()) {
+                    Some(id) => visited.lock().unwrap().insert(id),
+                    None => true,
+                }
+            });
+        }
 
         builder
             .hidden(!config.hidden)
             .follow_links(config.follow_symlinks)
+            .same_file_system(config.one_file_system)
             .git_ignore(!config.skip_gitignore)
-            .git_global(!config.skip_gitignore)
-            .git_exclude(!config.skip_gitignore);
+            .git_global(!config.skip_gitignore && !config.skip_global_gitignore)
+            .git_exclude(!config.skip_gitignore && !config.skip_git_exclude)
+            // Global and custom ignore files are matched relative to this root, not the CWD.
+            .current_dir(root.to_path_buf());
+        for file in &config.custom_ignore_files {
+            if let Some(error) = builder.add_ignore(file) {
+                warn!(path = %file.display(), %error, "cannot use ignore file");
+            }
+        }
 
         if let Some(depth) = config.max_depth {
             builder.max_depth(Some(depth));
         }
 
-        let mut overrides = OverrideBuilder::new(start_path);
+        let mut overrides = OverrideBuilder::new(&root);
 
         for dir in &config.exclude_dirs {
             let _ = overrides.add(&format!("!**/{}/", dir));
@@ -171,162 +751,605 @@ fn walk_filesystem(config: &WalkerConfig) -> Vec<FileEntry> {
             builder.overrides(ovr);
         }
 
-        for entry in builder.build().filter_map(Result::ok) {
+        for entry in builder.build() {
+            if is_cancelled(&config.cancel) {
+                return;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    warn!(%error, "cannot walk entry");
+                    continue;
+                }
+            };
             if entry.file_type().map(|ft| ft.is_file()).unwrap_or(false) {
-                files.push(entry.into_path());
+                emit(below_root(entry.into_path(), &root, start_path));
             }
         }
     }
+}
 
-    filter_files(files, config)
+/// Path usable for file system calls regardless of length.
+///
+/// On Windows, absolute paths are rewritten to the `\\?\` extended-length form
+/// (`\\?\C:\...`, `\\?\UNC\server\share\...`) so deep trees such as `node_modules` work
+/// past the 260 character `MAX_PATH` limit and network shares behave like local drives.
+/// Elsewhere, and for paths that are already verbatim, the path is returned unchanged.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    // `absolute` also resolves `.`/`..` and separators, which verbatim paths don't allow.
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    let mut components = absolute.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Cow::Borrowed(path);
+    };
+    let mut long = OsString::from(r"\\?\");
+    match prefix.kind() {
+        Prefix::Disk(_) => long.push(prefix.as_os_str()),
+        Prefix::UNC(server, share) => {
+            long.push(r"UNC\");
+            long.push(server);
+            long.push(r"\");
+            long.push(share);
+        }
+        _ => return Cow::Borrowed(path),
+    }
+    long.push(components.as_path().as_os_str());
+    Cow::Owned(PathBuf::from(long))
 }
 
-fn filter_files(files: Vec<PathBuf>, config: &WalkerConfig) -> Vec<FileEntry> {
-    let include_langs_lower: Vec<String> = config
-        .include_langs
-        .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
-    let exclude_langs_lower: Vec<String> = config
-        .exclude_langs
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// Maps a path found under the walked `root` back below the `start_path` it was derived from.
+fn below_root(path: PathBuf, root: &Path, start_path: &Path) -> PathBuf {
+    if root == start_path {
+        return path;
+    }
+    match path.strip_prefix(root) {
+        Ok(rest) if rest.as_os_str().is_empty() => start_path.to_path_buf(),
+        Ok(rest) => start_path.join(rest),
+        Err(_) => path,
+    }
+}
+
+/// Identity of a directory after resolving symlinks: `(device, inode)` on Unix.
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(long_path(path)).ok()?;
+    Some((meta.dev(), meta.ino()))
+}
+
+/// Elsewhere only the walker's own ancestor check guards against symlink loops.
+#[cfg(not(unix))]
+fn file_id(_path: &Path) -> Option<FileId> {
+    None
+}
+
+/// The language `path` would be counted as under `config`'s language overrides
+/// (`force_lang`, `force_lang_globs`, unmapped extensions, custom languages), ignoring every path filter.
+/// The file does not need to exist.
+pub fn detect_path_language(config: &WalkerConfig, path: &Path) -> Option<&'static Language> {
+    FileFilter::new(config).detect(path)
+}
+
+/// Decides which entries of an archive streamed on stdin are counted, without touching disk.
+///
+/// Entry paths get the same directory exclusions, path and language filters and vendored and
+/// test patterns as walked files; ignore files do not apply.
+pub struct EntryFilter<'a>(FileFilter<'a>);
+
+impl<'a> EntryFilter<'a> {
+    pub fn new(config: &'a WalkerConfig) -> Self {
+        Self(FileFilter::new(config))
+    }
+
+    /// The entry at `path` holding `content`: `None` if it is excluded, an error if it is
+    /// found but cannot be counted.
+    pub fn check(&self, path: PathBuf, content: &[u8]) -> Option<Result<FileEntry, SkipReason>> {
+        let filter = &self.0;
+        if !vcs_path_allowed(filter.config, &path) || !filter.matches_path(&path) {
+            return None;
+        }
+        let Some(language) = filter.sniff(&path, || Some(modeline_window(content))) else {
+            return Some(Err(SkipReason::Unrecognized));
+        };
+        let entry = match filter.classify(path, language) {
+            Checked::Accept(entry) => entry,
+            Checked::Skip(_, reason) => return Some(Err(reason)),
+            Checked::Reject => return None,
+        };
+        if filter
+            .max_bytes
+            .is_some_and(|max| content.len() as u64 > max)
+        {
+            return Some(Err(SkipReason::TooLarge));
+        }
+        filter.matches_content(content).then_some(Ok(entry))
+    }
+}
+
+/// Outcome of [`FileFilter::check`].
+enum Checked {
+    Accept(FileEntry),
+    Skip(PathBuf, SkipReason),
+    /// Excluded by the configured filters.
+    Reject,
+}
+
+/// Lowercased names for `--include-lang`/`--exclude-lang`, with aliases (`cpp`, `golang`)
+/// resolved to the canonical language. Unknown names are kept so they can still match
+/// buckets like `Generated`.
+pub(crate) fn canonical_language_names(
+    custom_languages: Option<&CustomLanguageRegistry>,
+    names: &[String],
+) -> Vec<String> {
+    names
         .iter()
-        .map(|s| s.to_lowercase())
-        .collect();
+        .map(|name| {
+            custom_languages
+                .and_then(|registry| registry.get(name))
+                .or_else(|| get_language_ignore_case(name))
+                .map_or(name.as_str(), |lang| lang.name)
+                .to_lowercase()
+        })
+        .collect()
+}
 
-    let max_bytes = config.max_file_size.map(|mb| mb * 1024 * 1024);
+/// Bytes read from each end of a file to look for a modeline.
+const MODELINE_WINDOW: usize = 4096;
 
-    files
-        .into_iter()
-        .filter(|path| {
-            // Check file size first (if configured)
-            if let Some(max) = max_bytes {
-                if let Ok(meta) = path.metadata() {
-                    if meta.len() > max {
-                        return false;
-                    }
-                }
+/// The first and last [`MODELINE_WINDOW`] bytes of `content`.
+fn modeline_window(content: &[u8]) -> Vec<u8> {
+    if content.len() <= 2 * MODELINE_WINDOW {
+        return content.to_vec();
+    }
+    join_ends(
+        &content[..MODELINE_WINDOW],
+        &content[content.len() - MODELINE_WINDOW..],
+    )
+}
+
+/// [`modeline_window`] of the file at `path`, reading only the two ends.
+pub(crate) fn read_modeline_window(path: &Path) -> Option<Vec<u8>> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = std::fs::File::open(long_path(path)).ok()?;
+    let len = file.metadata().ok()?.len();
+    if len <= 2 * MODELINE_WINDOW as u64 {
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).ok()?;
+        return Some(content);
+    }
+    let mut head = vec![0; MODELINE_WINDOW];
+    let mut tail = vec![0; MODELINE_WINDOW];
+    file.read_exact(&mut head).ok()?;
+    file.seek(SeekFrom::End(-(MODELINE_WINDOW as i64))).ok()?;
+    file.read_exact(&mut tail).ok()?;
+    Some(join_ends(&head, &tail))
+}
+
+/// `head` and `tail` without their partial lines, joined by a line break.
+fn join_ends(head: &[u8], tail: &[u8]) -> Vec<u8> {
+    let head = &head[..head.iter().rposition(|&b| b == b'\n').unwrap_or(head.len())];
+    let tail = &tail[tail.iter().position(|&b| b == b'\n').unwrap_or(tail.len())..];
+    [head, tail].concat()
+}
+
+fn bytes_regex(regex: &Regex) -> regex::bytes::Regex {
+    regex::bytes::Regex::new(regex.as_str()).expect("pattern already compiled as a str regex")
+}
+
+/// Compiled `force_lang_globs` rules; invalid globs are dropped.
+struct ForcedLanguages<'a> {
+    globs: GlobSet,
+    patterns: Vec<&'a str>,
+    languages: Vec<&'a str>,
+}
+
+impl<'a> ForcedLanguages<'a> {
+    fn new(rules: &'a [(String, String)]) -> Self {
+        let mut builder = GlobSetBuilder::new();
+        let mut patterns = Vec::new();
+        let mut languages = Vec::new();
+        for (pattern, language) in rules {
+            if let Ok(glob) = path_glob(pattern) {
+                builder.add(glob);
+                patterns.push(pattern.as_str());
+                languages.push(language.as_str());
             }
+        }
+        Self {
+            globs: builder.build().unwrap_or_else(|_| GlobSet::empty()),
+            patterns,
+            languages,
+        }
+    }
 
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if !config.include_exts.is_empty()
-                    && !config
-                        .include_exts
-                        .iter()
-                        .any(|e| e.eq_ignore_ascii_case(ext))
-                {
-                    return false;
-                }
-                if config
-                    .exclude_exts
+    /// Language of the last rule matching `path` below its root. Rules are also matched
+    /// against the path starting with the root's own name and against the path as given, so
+    /// `cpp/**/*.h` applies whether the tree is counted from `cpp/`, from inside it or from
+    /// its parent.
+    fn find(&self, path: &Path, roots: &[PathBuf]) -> Option<&'a str> {
+        self.find_rule(path, roots).map(|(_, language)| language)
+    }
+
+    /// The `(glob, language)` rule behind [`find`](Self::find).
+    fn find_rule(&self, path: &Path, roots: &[PathBuf]) -> Option<(&'a str, &'a str)> {
+        if self.languages.is_empty() {
+            return None;
+        }
+        let relative = relative_to_root(path, roots);
+        let with_root = roots
+            .iter()
+            .find(|root| path.strip_prefix(root).ok() == Some(relative))
+            .and_then(|root| root.file_name())
+            .map(|name| Path::new(name).join(relative));
+        let index = [Some(relative), with_root.as_deref(), Some(path)]
+            .into_iter()
+            .flatten()
+            .flat_map(|candidate| self.globs.matches(candidate))
+            .max()?;
+        Some((self.patterns[index], self.languages[index]))
+    }
+}
+
+/// Which rule of [`FileFilter::detect_with_rule`] chose a language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DetectionRule<'a> {
+    /// A `force_lang_globs` rule, by its glob.
+    ForcedGlob(&'a str),
+    /// `force_lang` for the file's extension.
+    ForcedExtension,
+    /// The file name alone, as the extension is in `unmapped_exts`.
+    Unmapped,
+    /// The file name or extension, custom languages first.
+    Name,
+}
+
+/// Per-walk state for deciding whether a path is counted and as which language.
+pub(crate) struct FileFilter<'a> {
+    config: &'a WalkerConfig,
+    include_langs_lower: Vec<String>,
+    exclude_langs_lower: Vec<String>,
+    unmapped_exts_lower: Vec<String>,
+    force_lang_globs: ForcedLanguages<'a>,
+    pub(crate) max_bytes: Option<u64>,
+    vendor_globs: GlobSet,
+    test_globs: GlobSet,
+    include_globs: Option<GlobSet>,
+    exclude_globs: GlobSet,
+    /// `include_content`/`exclude_content` compiled for bytes, so content is matched in
+    /// place (memory-mapped for large files) without being decoded first.
+    include_content: Option<regex::bytes::Regex>,
+    exclude_content: Option<regex::bytes::Regex>,
+}
+
+impl<'a> FileFilter<'a> {
+    pub(crate) fn new(config: &'a WalkerConfig) -> Self {
+        Self {
+            config,
+            include_langs_lower: canonical_language_names(
+                config.custom_languages.as_deref(),
+                &config.include_langs,
+            ),
+            exclude_langs_lower: canonical_language_names(
+                config.custom_languages.as_deref(),
+                &config.exclude_langs,
+            ),
+            unmapped_exts_lower: config
+                .unmapped_exts
+                .iter()
+                .map(|s| s.trim_start_matches('.').to_lowercase())
+                .collect(),
+            force_lang_globs: ForcedLanguages::new(&config.force_lang_globs),
+            max_bytes: config.max_file_size.map(|mb| mb * 1024 * 1024),
+            vendor_globs: build_globset(&config.vendor_patterns),
+            test_globs: build_globset(&config.test_patterns),
+            include_globs: (!config.include_globs.is_empty())
+                .then(|| build_globset(&config.include_globs)),
+            exclude_globs: build_globset(&config.exclude_globs),
+            include_content: config.include_content.as_ref().map(bytes_regex),
+            exclude_content: config.exclude_content.as_ref().map(bytes_regex),
+        }
+    }
+
+    /// Filters run from cheapest to dearest: path filters, language detection and language
+    /// filters, then file size (one `stat`) and finally the content regexes, which read the
+    /// file. Excluded files are never read, and oversized files are not read either; only
+    /// modeline detection reads the start and end of a file before the language filters.
+    fn check(&self, path: PathBuf) -> Checked {
+        if !self.matches_path(&path) {
+            return Checked::Reject;
+        }
+        let Some(language) = self.sniff(&path, || read_modeline_window(&path)) else {
+            return Checked::Skip(path, SkipReason::Unrecognized);
+        };
+        let entry = match self.classify(path, language) {
+            Checked::Accept(entry) => entry,
+            other => return other,
+        };
+
+        if let Some(max) = self.max_bytes {
+            if long_path(&entry.path)
+                .metadata()
+                .is_ok_and(|meta| meta.len() > max)
+            {
+                return Checked::Skip(entry.path, SkipReason::TooLarge);
+            }
+        }
+
+        if self.matches_file_content(&entry.path) {
+            Checked::Accept(entry)
+        } else {
+            Checked::Reject
+        }
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        self.path_rejection(path).is_none()
+    }
+
+    /// Why the extension, glob and name filters exclude `path`, or `None` if they pass it.
+    pub(crate) fn path_rejection(&self, path: &Path) -> Option<String> {
+        let config = self.config;
+
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            if !config.include_exts.is_empty()
+                && !config
+                    .include_exts
                     .iter()
                     .any(|e| e.eq_ignore_ascii_case(ext))
-                {
-                    return false;
-                }
-            } else if !config.include_exts.is_empty() {
-                return false;
+            {
+                return Some(format!(
+                    "extension .{} is not included (--include-ext)",
+                    ext
+                ));
             }
-
-            if let Some(ref regex) = config.match_file {
-                let name = if config.fullpath {
-                    path.to_string_lossy()
-                } else {
-                    path.file_name()
-                        .map(|n| n.to_string_lossy())
-                        .unwrap_or_default()
-                };
-                if !regex.is_match(&name) {
-                    return false;
-                }
+            if config
+                .exclude_exts
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(ext))
+            {
+                return Some(format!("extension .{} is excluded (--exclude-ext)", ext));
             }
+        } else if !config.include_exts.is_empty() {
+            return Some("no extension, and only some are included (--include-ext)".to_string());
+        }
 
-            for regex in &config.not_match_file {
-                let name = if config.fullpath {
-                    path.to_string_lossy()
-                } else {
-                    path.file_name()
-                        .map(|n| n.to_string_lossy())
-                        .unwrap_or_default()
-                };
-                if regex.is_match(&name) {
-                    return false;
+        if self.include_globs.is_some() || !self.exclude_globs.is_empty() {
+            let relative = relative_to_root(path, &config.paths);
+            if let Some(ref include) = self.include_globs {
+                if !include.is_match(relative) {
+                    return Some(format!(
+                        "{} matches no include glob (--include-glob)",
+                        relative.display()
+                    ));
                 }
             }
+            if self.exclude_globs.is_match(relative) {
+                let pattern = config.exclude_globs.iter().find(|pattern| {
+                    path_glob(pattern).is_ok_and(|glob| glob.compile_matcher().is_match(relative))
+                });
+                return Some(format!(
+                    "{} matches exclude glob {} (--exclude-glob)",
+                    relative.display(),
+                    pattern.map_or("", String::as_str)
+                ));
+            }
+        }
 
-            if let Some(ref regex) = config.match_dir {
-                let dir = path
-                    .parent()
-                    .map(|p| p.to_string_lossy())
-                    .unwrap_or_default();
-                if !regex.is_match(&dir) {
-                    return false;
-                }
+        let file_name = if config.fullpath {
+            path.to_string_lossy()
+        } else {
+            path.file_name()
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default()
+        };
+        if let Some(ref regex) = config.match_file {
+            if !regex.is_match(&file_name) {
+                return Some(format!("{} does not match --match-f {}", file_name, regex));
             }
+        }
+        if let Some(regex) = config
+            .not_match_file
+            .iter()
+            .find(|r| r.is_match(&file_name))
+        {
+            return Some(format!("{} matches --not-match-f {}", file_name, regex));
+        }
 
-            for regex in &config.not_match_dir {
-                let dir_name = if config.fullpath {
-                    path.parent()
-                        .map(|p| p.to_string_lossy())
-                        .unwrap_or_default()
-                } else {
-                    path.parent()
-                        .and_then(|p| p.file_name())
-                        .map(|n| n.to_string_lossy())
-                        .unwrap_or_default()
-                };
-                if regex.is_match(&dir_name) {
-                    return false;
-                }
+        if let Some(ref regex) = config.match_dir {
+            let dir = path
+                .parent()
+                .map(|p| p.to_string_lossy())
+                .unwrap_or_default();
+            if !regex.is_match(&dir) {
+                return Some(format!("{} does not match --match-d {}", dir, regex));
             }
+        }
 
-            if config.include_content.is_some() || config.exclude_content.is_some() {
-                if let Ok(content) = std::fs::read_to_string(path) {
-                    if let Some(ref regex) = config.include_content {
-                        if !regex.is_match(&content) {
-                            return false;
-                        }
-                    }
-                    if let Some(ref regex) = config.exclude_content {
-                        if regex.is_match(&content) {
-                            return false;
-                        }
-                    }
-                } else {
-                    return false;
-                }
+        let dir_name = if config.fullpath {
+            path.parent()
+                .map(|p| p.to_string_lossy())
+                .unwrap_or_default()
+        } else {
+            path.parent()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy())
+                .unwrap_or_default()
+        };
+        if let Some(regex) = config.not_match_dir.iter().find(|r| r.is_match(&dir_name)) {
+            return Some(format!("{} matches --not-match-d {}", dir_name, regex));
+        }
+
+        None
+    }
+
+    /// `--include-content` / `--exclude-content` for the file at `path`; unreadable files
+    /// fail when either is set.
+    pub(crate) fn matches_file_content(&self, path: &Path) -> bool {
+        !self.filters_content()
+            || read_file(self.config, path).is_ok_and(|content| self.matches_content(&content))
+    }
+
+    pub(crate) fn filters_content(&self) -> bool {
+        self.include_content.is_some() || self.exclude_content.is_some()
+    }
+
+    /// Matching stops at the first match, so an included file is usually decided from its
+    /// first lines; only files without a match are searched to the end.
+    fn matches_content(&self, content: &[u8]) -> bool {
+        self.include_content
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(content))
+            && !self
+                .exclude_content
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(content))
+    }
+
+    /// [`detect`](Self::detect), falling back to a modeline in `window` (the start and end of
+    /// the content) when the name matches no language. With `modelines`, a modeline also
+    /// overrides the detected language unless it was forced; for a header (`.h`) the modeline
+    /// language's header is used, so `-*- C++ -*-` makes a C++ Header.
+    pub(crate) fn sniff(
+        &self,
+        path: &Path,
+        window: impl FnOnce() -> Option<Vec<u8>>,
+    ) -> Option<&'static Language> {
+        let detected = self.detect(path);
+        if detected.is_some() && (!self.config.modelines || self.is_forced(path)) {
+            return detected;
+        }
+        let Some(modeline) = window().and_then(|window| detect_language_by_modeline(&window))
+        else {
+            return detected;
+        };
+        match detected {
+            Some(language) if language.name.ends_with(" Header") => {
+                get_language(&format!("{} Header", modeline.name)).or(Some(modeline))
             }
+            _ => Some(modeline),
+        }
+    }
 
-            true
-        })
-        .filter_map(|path| {
-            let language = if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if let Some(forced_lang) = config.force_lang.get(&ext.to_lowercase()) {
-                    get_language_ignore_case(forced_lang)
-                } else {
-                    detect_language(&path)
-                }
-            } else {
-                detect_language(&path)
-            }?;
+    /// Whether `path`'s language is set by `force_lang_globs` or `force_lang`.
+    fn is_forced(&self, path: &Path) -> bool {
+        let config = self.config;
+        self.force_lang_globs.find(path, &config.paths).is_some()
+            || path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|ext| config.force_lang.contains_key(&ext.to_lowercase()))
+    }
 
-            if !include_langs_lower.is_empty()
-                && !include_langs_lower
-                    .iter()
-                    .any(|l| l.eq_ignore_ascii_case(language.name))
-            {
-                return None;
+    fn detect(&self, path: &Path) -> Option<&'static Language> {
+        self.detect_with_rule(path).map(|(language, _)| language)
+    }
+
+    /// [`detect`](Self::detect) with the rule that chose the language.
+    pub(crate) fn detect_with_rule(
+        &self,
+        path: &Path,
+    ) -> Option<(&'static Language, DetectionRule<'a>)> {
+        let config = self.config;
+        let custom = config.custom_languages.as_deref();
+        let detect = |path: &Path| match custom {
+            Some(registry) => registry.detect(path),
+            None => detect_language(path),
+        };
+        let lookup = |name: &str| {
+            custom
+                .and_then(|registry| registry.get(name))
+                .or_else(|| get_language_ignore_case(name))
+        };
+
+        if let Some((pattern, name)) = self.force_lang_globs.find_rule(path, &config.paths) {
+            return lookup(name).map(|language| (language, DetectionRule::ForcedGlob(pattern)));
+        }
+
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            let ext = ext.to_lowercase();
+            if let Some(forced_lang) = config.force_lang.get(&ext) {
+                lookup(forced_lang).map(|language| (language, DetectionRule::ForcedExtension))
+            } else if self.unmapped_exts_lower.contains(&ext) {
+                custom
+                    .and_then(|registry| registry.get_by_path(path))
+                    .or_else(|| detect_language_by_filename(path))
+                    .map(|language| (language, DetectionRule::Unmapped))
+            } else {
+                detect(path).map(|language| (language, DetectionRule::Name))
             }
+        } else {
+            detect(path).map(|language| (language, DetectionRule::Name))
+        }
+    }
+
+    fn classify(&self, path: PathBuf, language: &'static Language) -> Checked {
+        if self.language_rejection(language).is_some() {
+            return Checked::Reject;
+        }
+        match self.classification(&path) {
+            Ok((vendored, test)) => Checked::Accept(FileEntry {
+                path,
+                language,
+                vendored,
+                test,
+            }),
+            Err(_) => Checked::Reject,
+        }
+    }
 
-            if exclude_langs_lower
+    /// Why the language filters exclude `language`, or `None` if they pass it.
+    pub(crate) fn language_rejection(&self, language: &Language) -> Option<String> {
+        if !self.include_langs_lower.is_empty()
+            && !self
+                .include_langs_lower
                 .iter()
                 .any(|l| l.eq_ignore_ascii_case(language.name))
-            {
-                return None;
-            }
+        {
+            return Some(format!(
+                "{} is not an included language (--include-lang)",
+                language.name
+            ));
+        }
+        if self
+            .exclude_langs_lower
+            .iter()
+            .any(|l| l.eq_ignore_ascii_case(language.name))
+        {
+            return Some(format!(
+                "{} is an excluded language (--exclude-lang)",
+                language.name
+            ));
+        }
+        None
+    }
 
-            Some(FileEntry { path, language })
-        })
-        .collect()
+    /// Whether `path` is vendored and whether it is test code, or why that excludes it.
+    pub(crate) fn classification(&self, path: &Path) -> Result<(bool, bool), String> {
+        let config = self.config;
+        let relative = relative_to_root(path, &config.paths);
+        let vendored = self.vendor_globs.is_match(relative);
+        if vendored && config.exclude_vendored {
+            return Err("vendored code is excluded (--exclude-vendored)".to_string());
+        }
+        let test = self.test_globs.is_match(relative);
+        if test && config.exclude_tests {
+            return Err("test code is excluded (--exclude-tests)".to_string());
+        }
+        Ok((vendored, test))
+    }
 }
 
 #[cfg(test)]
@@ -450,6 +1473,68 @@ mod tests {
         assert_eq!(files[0].language.name, "Rust");
     }
 
+    #[test]
+    fn test_language_filter_aliases() {
+        let temp = TempDir::new().unwrap();
+        create_test_files(temp.path());
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.include_langs = vec!["py".to_string(), "JS".to_string()];
+        let mut names: Vec<_> = walk_files(&config)
+            .iter()
+            .map(|f| f.language.name)
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["JavaScript", "Python"]);
+
+        config.include_langs.clear();
+        config.exclude_langs = vec!["ts".to_string()];
+        assert!(
+            walk_files(&config)
+                .iter()
+                .all(|f| f.language.name != "TypeScript")
+        );
+    }
+
+    #[test]
+    fn test_include_exclude_globs() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("src/generated")).unwrap();
+        fs::create_dir_all(root.join("tests")).unwrap();
+        fs::write(root.join("src/lib.rs"), "fn a() {}").unwrap();
+        fs::write(root.join("src/generated/api.rs"), "fn b() {}").unwrap();
+        fs::write(root.join("src/util.py"), "x = 1").unwrap();
+        fs::write(root.join("tests/it.rs"), "fn c() {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![root.to_path_buf()];
+        config.include_globs = vec!["src/**/*.rs".to_string()];
+        config.exclude_globs = vec!["**/generated/**".to_string()];
+
+        let files = walk_files(&config);
+        let paths: Vec<_> = files
+            .iter()
+            .map(|f| relative_to_root(&f.path, &config.paths))
+            .collect();
+        assert_eq!(paths, [Path::new("src/lib.rs")]);
+
+        // `*` stays within one directory.
+        config.include_globs = vec!["src/**/*.rs".to_string(), "tests/*".to_string()];
+        config.exclude_globs = vec!["src/*.rs".to_string()];
+        let files = walk_files(&config);
+        let mut paths: Vec<_> = files
+            .iter()
+            .map(|f| relative_to_root(&f.path, &config.paths))
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [Path::new("src/generated/api.rs"), Path::new("tests/it.rs")]
+        );
+    }
+
     #[test]
     fn test_exclude_languages() {
         let temp = TempDir::new().unwrap();
@@ -503,6 +1588,530 @@ mod tests {
         assert!(files[0].path.file_name().unwrap() == "main.rs");
     }
 
+    #[test]
+    fn test_vendored_classification() {
+        let temp = TempDir::new().unwrap();
+        let third_party = temp.path().join("third_party");
+        fs::create_dir(&third_party).unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}").unwrap();
+        fs::write(temp.path().join("jquery.min.js"), "var x=1;").unwrap();
+        fs::write(third_party.join("lib.c"), "int x;").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 3);
+        for file in &files {
+            let is_main = file.path.file_name().unwrap() == "main.rs";
+            assert_eq!(file.vendored, !is_main, "{}", file.path.display());
+        }
+
+        config.exclude_vendored = true;
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.file_name().unwrap() == "main.rs");
+    }
+
+    #[test]
+    fn test_test_file_classification() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir(temp.path().join("tests")).unwrap();
+        fs::write(temp.path().join("main.go"), "package main").unwrap();
+        fs::write(temp.path().join("main_test.go"), "package main").unwrap();
+        fs::write(temp.path().join("app.spec.ts"), "it('x');").unwrap();
+        fs::write(temp.path().join("tests").join("cli.rs"), "fn t() {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 4);
+        for file in &files {
+            let is_main = file.path.file_name().unwrap() == "main.go";
+            assert_eq!(file.test, !is_main, "{}", file.path.display());
+        }
+
+        config.exclude_tests = true;
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.file_name().unwrap() == "main.go");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks_cycle() {
+        let temp = TempDir::new().unwrap();
+        let sub = temp.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("lib.rs"), "fn lib() {}").unwrap();
+        std::os::unix::fs::symlink(temp.path(), sub.join("loop")).unwrap();
+        std::os::unix::fs::symlink(&sub, temp.path().join("alias")).unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.follow_symlinks = true;
+
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_path_prefixes() {
+        assert_eq!(
+            long_path(Path::new(r"C:\src\app\..\lib.rs")),
+            Path::new(r"\\?\C:\src\lib.rs")
+        );
+        assert_eq!(
+            long_path(Path::new(r"\\server\share\src\lib.rs")),
+            Path::new(r"\\?\UNC\server\share\src\lib.rs")
+        );
+        let verbatim = Path::new(r"\\?\C:\src\lib.rs");
+        assert_eq!(long_path(verbatim), verbatim);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_walk_past_max_path() {
+        let temp = TempDir::new().unwrap();
+        let mut deep = temp.path().to_path_buf();
+        while deep.as_os_str().len() < 300 {
+            deep.push("node_modules_package_directory");
+        }
+        fs::create_dir_all(long_path(&deep)).unwrap();
+        fs::write(long_path(&deep.join("index.js")), "var a = 1;\n").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.exclude_dirs.clear();
+
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.starts_with(temp.path()));
+        let source = crate::counter::read_source(&files[0].path).unwrap();
+        assert_eq!(&*source, b"var a = 1;\n");
+    }
+
+    #[test]
+    fn test_list_file() {
+        let temp = TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a.rs"), "fn a() {}").unwrap();
+        fs::write(src.join("b c.rs"), "fn b() {}").unwrap();
+        let list = src.join("files.txt");
+        fs::write(&list, "# sources\r\na.rs\r\n\r\n  \n").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.list_file = Some(list.clone());
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, src.join("a.rs"));
+
+        fs::write(&list, format!("a.rs\0{}\0", src.join("b c.rs").display())).unwrap();
+        config.list_file_nul = true;
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn test_vcs_falls_back_to_filesystem() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}").unwrap();
+
+        for vcs in [VcsMode::Hg, VcsMode::Svn] {
+            let mut config = WalkerConfig::default();
+            config.paths = vec![temp.path().to_path_buf()];
+            config.vcs = Some(vcs);
+            assert_eq!(walk_files(&config).len(), 1, "{:?}", vcs);
+        }
+    }
+
+    #[test]
+    fn test_git_files_per_root() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let git_init = Command::new("git").arg("-C").arg(root).arg("init").output();
+        if !git_init.is_ok_and(|out| out.status.success()) {
+            return;
+        }
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "ignored.rs\n").unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("ignored.rs"), "fn x() {}").unwrap();
+        fs::write(root.join("sub/deep.rs"), "fn d() {}").unwrap();
+        fs::write(root.join("target/out.rs"), "fn o() {}").unwrap();
+        // Listed quoted by git without `-z`.
+        fs::write(root.join("ä.rs"), "fn a() {}").unwrap();
+        fs::create_dir_all(root.join(".tools")).unwrap();
+        fs::write(root.join(".tools/gen.rs"), "fn g() {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![root.to_path_buf()];
+        config.vcs = Some(VcsMode::Auto);
+
+        let mut files: Vec<_> = walk_files(&config).into_iter().map(|f| f.path).collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                root.join("main.rs"),
+                root.join("sub/deep.rs"),
+                root.join("ä.rs")
+            ]
+        );
+
+        config.hidden = true;
+        assert_eq!(walk_files(&config).len(), 4);
+
+        config.hidden = false;
+        config.max_depth = Some(1);
+        let mut files: Vec<_> = walk_files(&config).into_iter().map(|f| f.path).collect();
+        files.sort();
+        assert_eq!(files, [root.join("main.rs"), root.join("ä.rs")]);
+    }
+
+    #[test]
+    fn test_staged_files() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(root)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .output()
+                .is_ok_and(|out| out.status.success())
+        };
+        if !git(&["init"]) {
+            return;
+        }
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("old.rs"), "fn old() {}").unwrap();
+        fs::write(root.join("gone.rs"), "fn gone() {}").unwrap();
+        fs::write(root.join("sub/kept.rs"), "fn kept() {}").unwrap();
+        assert!(git(&["add", "."]) && git(&["commit", "-qm", "init"]));
+
+        fs::write(root.join("new.rs"), "fn new() {}").unwrap();
+        fs::write(root.join("sub/kept.rs"), "fn kept() { 1; }").unwrap();
+        fs::write(root.join("unstaged.rs"), "fn u() {}").unwrap();
+        fs::remove_file(root.join("gone.rs")).unwrap();
+        assert!(git(&["add", "new.rs", "sub/kept.rs", "gone.rs"]));
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![root.to_path_buf()];
+        config.staged = true;
+        let mut files: Vec<_> = walk_files(&config).into_iter().map(|f| f.path).collect();
+        files.sort();
+        assert_eq!(files, [root.join("new.rs"), root.join("sub/kept.rs")]);
+
+        // Paths are relative to each root, which may be a subdirectory.
+        config.paths = vec![root.join("sub")];
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, root.join("sub").join("kept.rs"));
+
+        // The staged blob is read, not the working tree.
+        fs::write(root.join("new.rs"), "fn new() {}\nfn more() {}\n").unwrap();
+        let staged = read_file(&config, &root.join("new.rs")).unwrap();
+        assert_eq!(&*staged, b"fn new() {}");
+
+        // Outside a repository nothing is counted.
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("main.rs"), "fn main() {}").unwrap();
+        config.paths = vec![outside.path().to_path_buf()];
+        assert!(walk_files(&config).is_empty());
+    }
+
+    #[test]
+    fn test_ignore_sources() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("repo");
+        fs::create_dir_all(root.join("gen")).unwrap();
+        let git_init = Command::new("git")
+            .arg("-C")
+            .arg(&root)
+            .arg("init")
+            .output();
+        if !git_init.is_ok_and(|out| out.status.success()) {
+            return;
+        }
+        fs::write(root.join(".git/info/exclude"), "local.rs\n").unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("local.rs"), "fn l() {}").unwrap();
+        fs::write(root.join("gen/out.rs"), "fn o() {}").unwrap();
+        // Anchored at the analyzed root, whatever the current directory is.
+        let ignore_file = temp.path().join("custom-ignore");
+        fs::write(&ignore_file, "/gen/\n").unwrap();
+
+        for vcs in [None, Some(VcsMode::Git)] {
+            let mut config = WalkerConfig::default();
+            config.paths = vec![root.clone()];
+            config.vcs = vcs;
+            let walk = |config: &WalkerConfig| {
+                let mut files: Vec<_> = walk_files(config).into_iter().map(|f| f.path).collect();
+                files.sort();
+                files
+            };
+            assert_eq!(
+                walk(&config),
+                [root.join("gen/out.rs"), root.join("main.rs")]
+            );
+
+            config.skip_git_exclude = true;
+            config.custom_ignore_files = vec![ignore_file.clone()];
+            assert_eq!(
+                walk(&config),
+                [root.join("local.rs"), root.join("main.rs")],
+                "{:?}",
+                vcs
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_repos() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        fs::create_dir_all(root.join("libs/clone/.git")).unwrap();
+        fs::create_dir_all(root.join("libs/sub/src")).unwrap();
+        fs::write(root.join("libs/sub/.git"), "gitdir: ../../.git/modules/sub").unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("libs/clone/a.rs"), "fn a() {}").unwrap();
+        fs::write(root.join("libs/sub/src/b.rs"), "fn b() {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![root.to_path_buf()];
+        let files: Vec<_> = walk_files(&config).into_iter().map(|f| f.path).collect();
+        assert_eq!(files.len(), 3);
+        assert_eq!(
+            nested_repos(files.iter().map(PathBuf::as_path), &config.paths),
+            [root.join("libs/clone"), root.join("libs/sub")]
+        );
+
+        config.exclude_nested_repos = true;
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.file_name().unwrap() == "main.rs");
+    }
+
+    #[test]
+    fn test_vendored_root_not_classified() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path().join("deps").join("project");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![root];
+
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert!(!files[0].vendored);
+    }
+
+    #[test]
+    fn test_custom_languages_per_config() {
+        use crate::custom_langs::LanguageDef;
+
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("a.myl"), "x").unwrap();
+        fs::write(temp.path().join("b.rs"), "fn b() {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        assert_eq!(walk_files(&config).len(), 1);
+
+        config.custom_languages = Some(Arc::new(
+            CustomLanguageRegistry::new().add(LanguageDef::new("MyLang").extensions(["myl"])),
+        ));
+        let mut names: Vec<_> = walk_files(&config)
+            .iter()
+            .map(|f| f.language.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["MyLang", "Rust"]);
+    }
+
+    #[test]
+    fn test_unmapped_exts() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("notes.txt"), "hello").unwrap();
+        fs::write(temp.path().join("CMakeLists.txt"), "project(x)").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.unmapped_exts = vec!["txt".into()];
+
+        let files = walk_files(&config);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].language.name, "CMake");
+    }
+
+    #[test]
+    fn test_skipped_files_reported() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("main.rs"), "fn main() {}").unwrap();
+        fs::write(temp.path().join("data.unknownext"), "???").unwrap();
+        fs::write(temp.path().join("big.rs"), "x".repeat(2 * 1024 * 1024)).unwrap();
+        // Oversized, but excluded by language: not reported as too large.
+        fs::write(temp.path().join("excluded.py"), "x".repeat(2 * 1024 * 1024)).unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.max_file_size = Some(1);
+        config.exclude_langs = vec!["Python".into()];
+
+        let mut skipped = Vec::new();
+        let mut found = Vec::new();
+        walk_files_reporting(
+            &config,
+            |entry| found.push(entry.path),
+            |path, reason| skipped.push((path.file_name().unwrap().to_owned(), reason)),
+        );
+        skipped.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            skipped,
+            vec![
+                ("big.rs".into(), SkipReason::TooLarge),
+                ("data.unknownext".into(), SkipReason::Unrecognized),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_filters_after_language_filters() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("todo.rs"), "// TODO\nfn main() {}").unwrap();
+        fs::write(temp.path().join("done.rs"), "fn main() {}").unwrap();
+        fs::write(temp.path().join("latin1.rs"), b"// caf\xe9 TODO\n").unwrap();
+        fs::write(temp.path().join("todo.py"), "# TODO").unwrap();
+        fs::write(temp.path().join("big.rs"), "x".repeat(2 * 1024 * 1024)).unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.include_content = Some(Regex::new("TODO").unwrap());
+        config.exclude_langs = vec!["Python".into()];
+        config.max_file_size = Some(1);
+
+        let mut skipped = Vec::new();
+        let mut found = Vec::new();
+        walk_files_reporting(
+            &config,
+            |entry| found.push(entry.path.file_name().unwrap().to_owned()),
+            |path, reason| skipped.push((path.file_name().unwrap().to_owned(), reason)),
+        );
+        found.sort();
+
+        // Content that is not valid UTF-8 is still searched.
+        assert_eq!(found, ["latin1.rs", "todo.rs"]);
+        // Size is checked before the content is read.
+        assert_eq!(skipped, [("big.rs".into(), SkipReason::TooLarge)]);
+
+        let filter = EntryFilter::new(&config);
+        let check = |name: &str, content: &[u8]| {
+            filter
+                .check(PathBuf::from(name), content)
+                .map(|result| result.is_ok())
+        };
+        assert_eq!(check("a.rs", b"// TODO"), Some(true));
+        assert_eq!(check("a.rs", b"fn main() {}"), None);
+        assert_eq!(check("a.py", b"# TODO"), None);
+        assert_eq!(check("big.py", &vec![b'x'; 2 * 1024 * 1024]), None);
+        assert_eq!(check("a.unknownext", b"TODO"), Some(false));
+    }
+
+    #[test]
+    fn test_modelines() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("tool"), "# -*- mode: python -*-\nx = 1\n").unwrap();
+        fs::write(
+            temp.path().join("vector.h"),
+            "// -*- C++ -*-\nclass A {};\n",
+        )
+        .unwrap();
+        fs::write(temp.path().join("notes.txt"), "x\n# vim: ft=ruby\n").unwrap();
+        let long = format!("{}\n# vim: ft=sh\n", "echo hi\n".repeat(2000));
+        fs::write(temp.path().join("build"), long).unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        let languages = |config: &WalkerConfig| {
+            let mut files: Vec<_> = walk_files(config)
+                .into_iter()
+                .map(|f| (f.path.file_name().unwrap().to_owned(), f.language.name))
+                .collect();
+            files.sort();
+            files
+        };
+
+        // Files without a recognized name are always checked; others only with `modelines`.
+        assert_eq!(
+            languages(&config),
+            [
+                ("build".into(), "Shell"),
+                ("notes.txt".into(), "Text"),
+                ("tool".into(), "Python"),
+                ("vector.h".into(), "C Header"),
+            ]
+        );
+        config.modelines = true;
+        config.force_lang.insert("txt".into(), "Text".into());
+        assert_eq!(
+            languages(&config),
+            [
+                ("build".into(), "Shell"),
+                ("notes.txt".into(), "Text"),
+                ("tool".into(), "Python"),
+                ("vector.h".into(), "C++ Header"),
+            ]
+        );
+
+        let filter = EntryFilter::new(&config);
+        let entry = filter.check(PathBuf::from("run"), b"#!/bin/sh\n# vim: ft=perl\n");
+        assert_eq!(entry.unwrap().unwrap().language.name, "Perl");
+    }
+
+    #[test]
+    fn test_walk_files_parallel() {
+        let temp = TempDir::new().unwrap();
+        for i in 0..20 {
+            fs::write(temp.path().join(format!("f{}.rs", i)), "fn f() {}").unwrap();
+        }
+        fs::write(temp.path().join("skip.py"), "x = 1").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+
+        let mut found = 0;
+        let (discovered, mut names) = walk_files_parallel(
+            &config,
+            |_| found += 1,
+            |_, _| {},
+            |entry| (entry.language.name == "Rust").then_some(entry.path),
+        );
+        names.sort();
+        assert_eq!(discovered, 21);
+        assert_eq!(found, 21);
+        assert_eq!(names.len(), 20);
+
+        // A walk that dies is not reported as a finished one.
+        let walk = std::panic::catch_unwind(|| {
+            walk_files_parallel(
+                &config,
+                |_| panic!("walker failed"),
+                |_, _| {},
+                |entry| Some(entry.path),
+            )
+        });
+        assert!(walk.is_err());
+    }
+
     #[test]
     fn test_force_lang_invalid_language_excluded() {
         let temp = TempDir::new().unwrap();
@@ -521,4 +2130,72 @@ mod tests {
             "Files with invalid force_lang should be excluded"
         );
     }
+
+    #[test]
+    fn test_force_lang_globs() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir_all(temp.path().join("ci")).unwrap();
+        fs::create_dir_all(temp.path().join("config/deep")).unwrap();
+        fs::write(temp.path().join("ci/Jenkinsfile.release"), "node {}").unwrap();
+        fs::write(temp.path().join("config/site.conf"), "server {}").unwrap();
+        // `*` does not reach into subdirectories.
+        fs::write(temp.path().join("config/deep/db.conf"), "server {}").unwrap();
+        fs::write(temp.path().join("other.conf"), "server {}").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.paths = vec![temp.path().to_path_buf()];
+        config.force_lang_globs = vec![
+            ("**/Jenkinsfile*".to_string(), "groovy".to_string()),
+            ("config/*.conf".to_string(), "Python".to_string()),
+            ("config/site.*".to_string(), "Rust".to_string()),
+        ];
+
+        let mut files: Vec<_> = walk_files(&config)
+            .into_iter()
+            .map(|f| {
+                let name = relative_to_root(&f.path, &config.paths).to_path_buf();
+                (name, f.language.name)
+            })
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                (PathBuf::from("ci/Jenkinsfile.release"), "Groovy"),
+                (PathBuf::from("config/deep/db.conf"), "INI"),
+                (PathBuf::from("config/site.conf"), "Rust"),
+                (PathBuf::from("other.conf"), "INI"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_force_lang_globs_by_directory() {
+        let temp = TempDir::new().unwrap();
+        fs::create_dir_all(temp.path().join("cpp/detail")).unwrap();
+        fs::create_dir_all(temp.path().join("c")).unwrap();
+        fs::write(temp.path().join("cpp/detail/a.h"), "int a;").unwrap();
+        fs::write(temp.path().join("c/b.h"), "int b;").unwrap();
+
+        let mut config = WalkerConfig::default();
+        config.force_lang_globs = vec![
+            ("cpp/**/*.h".to_string(), "C++ Header".to_string()),
+            ("c/**/*.h".to_string(), "C Header".to_string()),
+        ];
+        let languages = |config: &WalkerConfig| {
+            let mut files: Vec<_> = walk_files(config)
+                .into_iter()
+                .map(|f| (f.path.file_name().unwrap().to_owned(), f.language.name))
+                .collect();
+            files.sort();
+            files
+        };
+        let expected = [("a.h".into(), "C++ Header"), ("b.h".into(), "C Header")];
+
+        // From the parent, and with each directory as its own root.
+        config.paths = vec![temp.path().to_path_buf()];
+        assert_eq!(languages(&config), expected);
+        config.paths = vec![temp.path().join("cpp"), temp.path().join("c")];
+        assert_eq!(languages(&config), expected);
+    }
 }
@@ -1,3 +1,6 @@
+//! End-to-end tests of the `rloc` binary, which needs the `cli` feature.
+#![cfg(feature = "cli")]
+
 use assert_cmd::Command;
 use predicates::prelude::*;
 use std::fs;
@@ -60,6 +63,116 @@ fn test_csv_output() {
         ));
 }
 
+#[test]
+fn test_color_control() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--color", "always"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[38;5;10m"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--color", "always", "--code-color", "none"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[38;5;10m").not());
+
+    rloc()
+        .arg(temp.path())
+        .env("NO_COLOR", "1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b[").not());
+}
+
+#[test]
+fn test_show_largest() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("big.rs"),
+        "fn a() {}\nfn b() {}\nfn c() {}\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("small.rs"), "fn a() {}\n").unwrap();
+    fs::write(temp.path().join("tool.py"), "a = 1\n").unwrap();
+
+    let output = rloc()
+        .arg(temp.path())
+        .args(["--show-largest", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let (_, largest) = stdout.split_once("Largest Files ").unwrap();
+    let (overall, per_language) = largest.split_once("Largest Files by Language").unwrap();
+    assert!(overall.contains("big.rs"));
+    assert!(!overall.contains("small.rs"));
+    assert!(!overall.contains("tool.py"));
+    assert!(per_language.contains("big.rs"));
+    assert!(per_language.contains("tool.py"));
+    assert!(!per_language.contains("small.rs"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--show-largest", "1", "--json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--show-largest only applies to the table format",
+        ));
+}
+
+#[test]
+fn test_churn_hotspots() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(["-c", "commit.gpgsign=false"])
+            .args(args)
+            .output()
+            .is_ok_and(|out| out.status.success())
+    };
+    if !git(&["init"]) {
+        return;
+    }
+    fs::write(root.join("hot.rs"), "fn a() {}\n").unwrap();
+    fs::write(root.join("big.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+    assert!(git(&["add", "."]) && git(&["commit", "-m", "one"]));
+    for body in [
+        "fn a() {}\nfn b() {}\n",
+        "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n",
+    ] {
+        fs::write(root.join("hot.rs"), body).unwrap();
+        assert!(git(&["commit", "-am", "change"]));
+    }
+
+    let output = rloc()
+        .arg(root)
+        .args(["--churn", "--since", "1y", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["since"], "1y");
+    let hotspots = json["hotspots"].as_array().unwrap();
+    assert_eq!(hotspots.len(), 2);
+    assert!(hotspots[0]["path"].as_str().unwrap().ends_with("hot.rs"));
+    assert_eq!(hotspots[0]["commits"], 3);
+    assert_eq!(hotspots[0]["score"], 12);
+    assert_eq!(hotspots[1]["score"], 3);
+
+    rloc().arg(root).args(["--since", "1y"]).assert().failure();
+}
+
 #[test]
 fn test_yaml_output() {
     let temp = TempDir::new().unwrap();
@@ -116,6 +229,27 @@ fn test_force_lang_exact_case() {
         .stdout(predicate::str::contains("TypeScript"));
 }
 
+#[test]
+fn test_force_lang_def_glob() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("Jenkinsfile.deploy"), "node {\n}\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--force-lang-def=**/Jenkinsfile*:groovy")
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Groovy"));
+
+    rloc()
+        .arg(temp.path())
+        .arg("--force-lang-def=**/Jenkinsfile*:NotALanguage")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown language 'NotALanguage'"));
+}
+
 #[test]
 fn test_include_lang() {
     let temp = TempDir::new().unwrap();
@@ -211,6 +345,44 @@ fn test_quiet_mode() {
     rloc().arg(temp.path()).arg("--quiet").assert().success();
 }
 
+#[test]
+fn test_log_level() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    let output = rloc()
+        .env_remove("RLOC_LOG")
+        .arg(temp.path())
+        .args(["--json", "--log-level", "debug"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("walk finished files=4"), "{}", stderr);
+    assert!(stderr.contains("aggregate{files=4}"), "{}", stderr);
+    assert!(!stderr.contains("found file"), "{}", stderr);
+    // Logs never end up in the report.
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["SUM"]["nFiles"], 4);
+
+    rloc()
+        .env("RLOC_LOG", "rloc::walker=trace")
+        .arg(temp.path())
+        .arg("--quiet")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("found file"))
+        .stderr(predicate::str::contains("aggregate").not());
+
+    rloc()
+        .env_remove("RLOC_LOG")
+        .arg(temp.path())
+        .arg("--quiet")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("walk").not());
+}
+
 #[test]
 fn test_no_files_found() {
     let temp = TempDir::new().unwrap();
@@ -221,6 +393,28 @@ fn test_no_files_found() {
         .assert()
         .success()
         .stderr(predicate::str::contains("No source files found"));
+
+    rloc()
+        .arg(temp.path())
+        .arg("--fail-on-empty")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("No source files found"));
+}
+
+#[test]
+fn test_exit_codes() {
+    rloc()
+        .arg("--no-such-flag")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("--no-such-flag"));
+
+    rloc()
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit codes:"));
 }
 
 #[test]
@@ -272,3 +466,1876 @@ fn test_xml_output() {
         .stdout(predicate::str::contains("<?xml version"))
         .stdout(predicate::str::contains("<languages>"));
 }
+
+#[test]
+fn test_badge_output() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    rloc()
+        .arg(temp.path())
+        .arg("--format=badge")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"schemaVersion\":1"))
+        .stdout(predicate::str::contains("\"label\":\"lines of code\""));
+}
+
+#[test]
+fn test_template_output() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    let template_dir = TempDir::new().unwrap();
+    let template = template_dir.path().join("report.hbs");
+    fs::write(&template, "total code: {{total.code}}\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--template")
+        .arg(&template)
+        .assert()
+        .success()
+        .stdout(predicate::str::diff("total code: 6\n"));
+}
+
+#[test]
+fn test_ndjson_output() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    let output = rloc()
+        .arg(temp.path())
+        .arg("--format=ndjson")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4);
+    for line in lines {
+        let json: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(json.get("path").is_some());
+        assert!(json.get("language").is_some());
+        assert!(json.get("code").is_some());
+    }
+}
+
+#[test]
+fn test_ndjson_write_errors() {
+    use std::process::{Command, Stdio};
+    let temp = TempDir::new().unwrap();
+    for i in 0..200 {
+        fs::write(
+            temp.path().join(format!("f{}.rs", i)),
+            format!("fn f{}() {{}}\n", i),
+        )
+        .unwrap();
+    }
+
+    // A reader that went away is a clean exit.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rloc"))
+        .arg(temp.path())
+        .arg("--format=ndjson")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(child.stdout.take());
+    assert!(child.wait().unwrap().success());
+
+    // Any other write error fails the run.
+    if let Ok(full) = fs::File::create("/dev/full") {
+        let status = Command::new(env!("CARGO_BIN_EXE_rloc"))
+            .arg(temp.path())
+            .arg("--format=ndjson")
+            .stdout(full)
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+    }
+}
+
+#[test]
+fn test_columns_selection() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    rloc()
+        .arg(temp.path())
+        .arg("--csv")
+        .arg("--columns=code,files")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("Language,Code,Files\n"));
+}
+
+#[test]
+fn test_top_languages_json() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    rloc()
+        .arg(temp.path())
+        .arg("--top=1")
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"Rust\""))
+        .stdout(predicate::str::contains("\"Other\""))
+        .stdout(predicate::str::contains("\"Python\"").not());
+}
+
+#[test]
+fn test_project_config_file() {
+    let temp = TempDir::new().unwrap();
+    let xdg = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    fs::write(
+        temp.path().join("rloc.toml"),
+        "format = \"csv\"\nexclude-lang = [\"Python\", \"TOML\"]\n",
+    )
+    .unwrap();
+
+    rloc()
+        .env("XDG_CONFIG_HOME", xdg.path())
+        .arg(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Language,Files,Blank,Comment,Code",
+        ))
+        .stdout(predicate::str::contains("Python").not());
+
+    // Command-line flags win over the config file
+    rloc()
+        .env("XDG_CONFIG_HOME", xdg.path())
+        .arg(temp.path())
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"SUM\""));
+
+    rloc()
+        .env("XDG_CONFIG_HOME", xdg.path())
+        .arg(temp.path())
+        .arg("--no-config")
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python"));
+}
+
+#[test]
+fn test_generated_files() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    fs::write(
+        temp.path().join("types_pb2.py"),
+        "# Generated by the protocol buffer compiler.  DO NOT EDIT!\nx = 1\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"Generated\""));
+
+    rloc()
+        .arg(temp.path())
+        .arg("--no-generated")
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"Generated\"").not());
+}
+
+#[test]
+fn test_vendored_files() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    let vendor = temp.path().join("vendor");
+    fs::create_dir(&vendor).unwrap();
+    fs::write(vendor.join("lib.rs"), "fn lib() {}\n").unwrap();
+    fs::write(temp.path().join("app.min.js"), "var a=1;\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"Vendored\""));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--include-vendored", "--by-file", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--exclude-vendored", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"Vendored\"").not());
+}
+
+#[test]
+fn test_split_tests() {
+    let temp = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join("tests")).unwrap();
+    fs::write(
+        temp.path().join("lib.rs"),
+        "pub fn one() -> u32 {\n    1\n}\n\n#[cfg(test)]\nmod tests {\n    #[test]\n    fn one() {}\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("tests").join("it.rs"),
+        "#[test]\nfn it() {}\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--split-tests", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Language,Files,Blank,Comment,Code,Tests",
+        ))
+        .stdout(predicate::str::contains("Rust,2,1,0,3,7"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--exclude-tests", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust,1,1,0,3"));
+}
+
+#[test]
+fn test_stdin() {
+    rloc()
+        .args(["-", "--stdin-name", "tool.py", "--csv"])
+        .write_stdin("import os\n\n# note\nprint(os.name)\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python,1,1,1,2"));
+
+    rloc()
+        .args(["-", "--force-lang", "c", "--csv"])
+        .write_stdin("int x;\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("C,1,0,0,1"));
+
+    rloc()
+        .arg("-")
+        .write_stdin("x\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--stdin-name"));
+}
+
+#[test]
+fn test_stdin_tar() {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, content) in [
+        ("app/main.rs", "fn main() {}\n\n// done\n"),
+        ("app/node_modules/dep/index.js", "x();\n"),
+        ("app/notes.unknownext", "?\n"),
+        ("app/tool.py", "print(1)\n"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, name, content.as_bytes())
+            .unwrap();
+    }
+    let tar = builder.into_inner().unwrap();
+
+    rloc()
+        .args(["--stdin-format", "tar", "--csv", "--by-file"])
+        .write_stdin(tar.clone())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app/main.rs,Rust,1,1,1"))
+        .stdout(predicate::str::contains("app/tool.py,Python,0,0,1"))
+        .stdout(predicate::str::contains("node_modules").not());
+
+    rloc()
+        .args(["--stdin-format", "tar", "--exclude-lang", "Python", "--csv"])
+        .write_stdin(tar)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SUM,1,1,1,1"));
+
+    rloc()
+        .args(["--stdin-format", "tar", "src"])
+        .write_stdin("")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("takes no PATH"));
+    rloc()
+        .args(["--stdin-format", "tar"])
+        .write_stdin("fn main() {}\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown archive format"));
+}
+
+#[test]
+fn test_list_file0_stdin() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    let list = format!("{}\0", temp.path().join("main.rs").display());
+
+    rloc()
+        .args(["--list-file0", "-", "--csv"])
+        .write_stdin(list)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust,1,"));
+}
+
+#[test]
+fn test_nested_repos() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    let nested = temp.path().join("deps-src").join("tool");
+    fs::create_dir_all(nested.join(".git")).unwrap();
+    fs::write(nested.join("tool.go"), "package tool\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--nested-repos", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{},Go,1,0,0,1",
+            nested.display()
+        )));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--exclude-nested-repos", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Go,").not());
+}
+
+#[test]
+fn test_by_category() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+    fs::write(temp.path().join("README.md"), "# Title\n\nText\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--by-category", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Category,"))
+        .stdout(predicate::str::contains("Programming,"))
+        .stdout(predicate::str::contains("Documentation,"))
+        .stdout(predicate::str::contains("Rust").not());
+}
+
+#[test]
+fn test_by_ext() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("main.c"), "int main;\n").unwrap();
+    fs::write(temp.path().join("util.h"), "int a;\nint b;\n").unwrap();
+    fs::write(temp.path().join("Makefile"), "all:\n\techo\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--by-ext", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Extension,"))
+        .stdout(predicate::str::contains("h,1,0,0,2"))
+        .stdout(predicate::str::contains("c,1,0,0,1"))
+        .stdout(predicate::str::contains("(none),"))
+        .stdout(predicate::str::contains("C Header").not());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--by-ext", "--by-category"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_by_file_size_columns() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--by-file", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Bytes,Avg Line,Max Line"))
+        // The newline counts towards the size but not the line lengths.
+        .stdout(predicate::str::contains(",13,12,12"));
+}
+
+#[test]
+fn test_minified_files() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("app.js"), "let a = 1;\n").unwrap();
+    fs::write(
+        temp.path().join("bundle.js"),
+        "let b=2;\n//# sourceMappingURL=bundle.js.map\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("JavaScript (minified),1,"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--skip-minified"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("JavaScript,1,"))
+        .stdout(predicate::str::contains("minified").not());
+}
+
+#[test]
+fn test_exclude_profiles() {
+    let temp = TempDir::new().unwrap();
+    for dir in ["src", "node_modules/dep", "coverage", "gen"] {
+        fs::create_dir_all(temp.path().join(dir)).unwrap();
+        fs::write(
+            temp.path().join(dir).join("x.js"),
+            format!("let {} = 1;\n", dir.replace('/', "_")),
+        )
+        .unwrap();
+    }
+    let count = |args: &[&str], files: &str| {
+        rloc()
+            .arg(temp.path())
+            .args(["--no-config", "--csv"])
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("JavaScript,{},", files)));
+    };
+    count(&[], "3");
+    // node_modules is walked again, but its files are reported as vendored code.
+    count(&["--profile", "minimal"], "3");
+    rloc()
+        .arg(temp.path())
+        .args(["--no-config", "--csv", "--profile", "minimal"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Vendored,1,"));
+    count(&["--profile", "aggressive"], "2");
+
+    fs::write(
+        temp.path().join("rloc.toml"),
+        "profile = \"strict\"\n\n[profiles.strict]\nextends = \"aggressive\"\nexclude-dir = [\"gen\"]\n",
+    )
+    .unwrap();
+    rloc()
+        .arg(temp.path())
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("JavaScript,1,"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--profile", "huge"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown profile 'huge'"));
+}
+
+#[test]
+fn test_max_files_and_timeout() {
+    let temp = TempDir::new().unwrap();
+    for i in 0..4 {
+        fs::write(
+            temp.path().join(format!("f{}.py", i)),
+            format!("x = {}\n", i),
+        )
+        .unwrap();
+    }
+
+    rloc()
+        .arg(temp.path())
+        .args(["--max-files", "3", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"truncated\": true"))
+        .stdout(predicate::str::contains("\"nFiles\": 3"))
+        .stderr(predicate::str::contains("results are partial"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--max-files", "4", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("truncated").not());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--timeout", "0", "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"truncated\": true"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--timeout", "soon"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_custom_ignore() {
+    let temp = TempDir::new().unwrap();
+    for dir in ["a/gen", "a/src/gen", "b/gen"] {
+        fs::create_dir_all(temp.path().join(dir)).unwrap();
+        fs::write(
+            temp.path().join(dir).join("x.rs"),
+            format!("fn {}() {{}}\n", dir.replace('/', "_")),
+        )
+        .unwrap();
+    }
+    fs::write(temp.path().join("ignore"), "/gen/\n").unwrap();
+
+    // `/gen/` is anchored at each analyzed path, so only the nested `src/gen` is counted.
+    rloc()
+        .current_dir(temp.path())
+        .args(["a", "b", "--custom-ignore", "ignore", "--by-file", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a/src/gen/x.rs"))
+        .stdout(predicate::str::contains("a/gen/").not())
+        .stdout(predicate::str::contains("b/gen/").not());
+
+    rloc()
+        .current_dir(temp.path())
+        .args(["a", "--custom-ignore", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Ignore file 'missing' not found"));
+}
+
+#[test]
+fn test_skipped_files_reported() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temp.path().join("blob.rs"), [0u8; 64]).unwrap();
+    fs::write(temp.path().join("notes.unknownext"), "text\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "2 files skipped (1 binary, 1 unrecognized)",
+        ));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--binary-null-ratio", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--binary-null-ratio"));
+}
+
+#[test]
+fn test_show_unrecognized() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temp.path().join("a.weird"), "x\n").unwrap();
+    fs::write(temp.path().join("b.weird"), "y\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--show-unrecognized")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.weird"))
+        .stdout(predicate::str::contains("main.rs").not())
+        .stdout(predicate::str::is_match(r"weird\D+2").unwrap());
+}
+
+#[test]
+fn test_list_only() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temp.path().join("empty.py"), "").unwrap();
+    fs::write(temp.path().join("a.weird"), "x\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--list-only")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("main.rs\tRust\n"))
+        .stdout(predicate::str::contains("empty.py\tPython\n"))
+        .stdout(predicate::str::contains("a.weird").not())
+        .stdout(predicate::str::contains("SUM").not());
+
+    let output = rloc()
+        .arg(temp.path())
+        .args(["--list-only", "--json", "--include-lang", "Rust"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let files = json.as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["language"], "Rust");
+}
+
+#[test]
+fn test_explain() {
+    let temp = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join("src")).unwrap();
+    fs::write(temp.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+
+    rloc()
+        .arg("explain")
+        .arg(temp.path().join("src/main.rs"))
+        .arg(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust, by extension .rs"))
+        .stdout(predicate::str::ends_with("Counted as Rust\n"));
+
+    let output = rloc()
+        .current_dir(temp.path())
+        .args(["--exclude-ext", "rs", "--json", "explain", "src/main.rs"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["counted"], false);
+    let last = json["steps"].as_array().unwrap().last().unwrap().clone();
+    assert_eq!(last["check"], "path filters");
+    assert_eq!(last["outcome"], "excluded");
+}
+
+#[test]
+fn test_bench() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    rloc()
+        .args([
+            "bench",
+            "--runs",
+            "1",
+            "--warmup",
+            "0",
+            "--compare-threads",
+            "1,2",
+        ])
+        .arg(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("4 files"))
+        .stdout(predicate::str::contains("Classify"))
+        .stdout(predicate::str::contains("Fastest with --threads"));
+
+    let output = rloc()
+        .args(["--json", "bench", "--runs", "2", "--compare-threads", "1"])
+        .arg(temp.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["files"], 4);
+    assert_eq!(json["results"].as_array().unwrap().len(), 1);
+    assert_eq!(json["results"][0]["threads"], 1);
+    assert_eq!(json["results"][0]["runs"].as_array().unwrap().len(), 2);
+    assert!(json["results"][0]["median"]["walk"].is_number());
+}
+
+#[test]
+fn test_completions_and_manpage() {
+    // A broken config file does not stop either.
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("rloc.toml"), "not = [valid").unwrap();
+
+    rloc()
+        .current_dir(temp.path())
+        .args(["completions", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("_rloc()"))
+        .stdout(predicate::str::contains("--exclude-dir"));
+    rloc()
+        .args(["completions", "fish"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("complete -c rloc"));
+    rloc().args(["completions", "tcsh"]).assert().failure();
+
+    // A reader that went away (`rloc completions bash | head -1`) is a clean exit.
+    for args in [&["completions", "bash"][..], &["manpage"]] {
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_rloc"))
+            .args(args)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        drop(child.stdout.take());
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert!(!String::from_utf8_lossy(&output.stderr).contains("panicked"));
+    }
+
+    rloc()
+        .current_dir(temp.path())
+        .arg("manpage")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".TH rloc 1"));
+
+    let man = temp.path().join("man");
+    rloc()
+        .arg("manpage")
+        .arg("--dir")
+        .arg(&man)
+        .assert()
+        .success();
+    assert!(man.join("rloc.1").is_file());
+    assert!(man.join("rloc-check.1").is_file());
+    assert!(man.join("rloc-hook-run.1").is_file());
+}
+
+#[test]
+fn test_strip_comments_output_dir() {
+    let temp = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join("src")).unwrap();
+    fs::write(temp.path().join("src/main.rs"), "// header\nfn main() {}\n").unwrap();
+    fs::write(temp.path().join("notes.py"), "# note\nx = 1\n").unwrap();
+    let out = TempDir::new().unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args([
+            "--strip-comments=",
+            "--include-lang",
+            "Rust",
+            "--strip-output-dir",
+        ])
+        .arg(out.path())
+        .assert()
+        .success();
+
+    let stripped = fs::read_to_string(out.path().join("src/main.rs")).unwrap();
+    assert_eq!(stripped, "fn main() {}\n");
+    assert!(!out.path().join("notes.py").exists());
+
+    // An output directory that is the source root would overwrite the sources.
+    rloc()
+        .arg(temp.path())
+        .args(["--strip-comments=", "--include-lang", "Rust", "-v"])
+        .arg("--strip-output-dir")
+        .arg(temp.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("overwrite its own source"));
+    let source = fs::read_to_string(temp.path().join("src/main.rs")).unwrap();
+    assert_eq!(source, "// header\nfn main() {}\n");
+}
+
+#[test]
+fn test_strip_stdout() {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("main.rs");
+    fs::write(&file, "// header\nfn main() {}\n").unwrap();
+    fs::write(temp.path().join("lib.rs"), "fn lib() {}\n").unwrap();
+
+    rloc()
+        .arg(&file)
+        .args(["--strip-code=x", "--stdout"])
+        .assert()
+        .success()
+        .stdout("// header\n");
+
+    rloc()
+        .arg(temp.path())
+        .args(["--strip-code=x", "--stdout"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("exactly one file"));
+}
+
+#[test]
+fn test_strip_in_place() {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("main.c");
+    fs::write(&file, "int x /* note */ = 1; // end\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--strip-comments=", "--in-place"])
+        .assert()
+        .success();
+
+    assert_eq!(fs::read_to_string(&file).unwrap(), "int x  = 1;\n");
+    assert!(temp.path().join("main.c.bak").exists());
+}
+
+#[test]
+fn test_strip_outputs_require_a_strip_mode() {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("main.c");
+    fs::write(&file, "int x = 1; // end\n").unwrap();
+
+    for args in [
+        vec!["--in-place"],
+        vec!["--stdout"],
+        vec!["--strip-output-dir", "out"],
+    ] {
+        rloc()
+            .arg(temp.path())
+            .args(&args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--strip-comments"));
+    }
+    assert_eq!(fs::read_to_string(&file).unwrap(), "int x = 1; // end\n");
+    assert!(!temp.path().join("main.c.bak").exists());
+}
+
+#[test]
+fn test_sum_reports() {
+    let temp = TempDir::new().unwrap();
+    let rloc_report = temp.path().join("a.json");
+    fs::write(
+        &rloc_report,
+        r#"{"Rust": {"nFiles": 2, "blank": 1, "comment": 1, "code": 30},
+            "SUM": {"nFiles": 2, "blank": 1, "comment": 1, "code": 30}}"#,
+    )
+    .unwrap();
+    let cloc_report = r#"{"header": {"cloc_url": "github.com/AlDanial/cloc", "cloc_version": "2.00"},
+        "Rust": {"nFiles": 1, "blank": 0, "comment": 0, "code": 12},
+        "Python": {"nFiles": 1, "blank": 0, "comment": 0, "code": 5},
+        "SUM": {"nFiles": 2, "blank": 0, "comment": 0, "code": 17}}"#;
+
+    rloc()
+        .arg("--sum-reports")
+        .arg(&rloc_report)
+        .args(["--sum-reports", "-", "--csv"])
+        .write_stdin(cloc_report)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust,3,1,1,42"))
+        .stdout(predicate::str::contains("Python,1,0,0,5"))
+        .stdout(predicate::str::contains("SUM,4,1,1,47"));
+}
+
+#[test]
+fn test_diff_report() {
+    let temp = TempDir::new().unwrap();
+    let old = temp.path().join("old.json");
+    fs::write(
+        &old,
+        r#"{"Rust": {"nFiles": 2, "blank": 1, "comment": 1, "code": 30},
+            "Python": {"nFiles": 1, "blank": 0, "comment": 0, "code": 5},
+            "SUM": {"nFiles": 3, "blank": 1, "comment": 1, "code": 35}}"#,
+    )
+    .unwrap();
+    let new = r#"{"Rust": {"nFiles": 3, "blank": 1, "comment": 4, "code": 42},
+        "SUM": {"nFiles": 3, "blank": 1, "comment": 4, "code": 42}}"#;
+
+    rloc()
+        .arg("--diff-report")
+        .arg(&old)
+        .args(["-", "--csv"])
+        .write_stdin(new)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust,1,0,3,12"))
+        .stdout(predicate::str::contains("Python,-1,0,0,-5"))
+        .stdout(predicate::str::contains("SUM,0,0,3,7"));
+}
+
+#[test]
+fn test_check_budgets() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("main.rs"), "fn main() {\n}\n").unwrap();
+    let baseline = temp.path().join("baseline.json");
+    fs::write(
+        &baseline,
+        r#"{"Rust": {"nFiles": 1, "blank": 0, "comment": 0, "code": 1},
+            "SUM": {"nFiles": 1, "blank": 0, "comment": 0, "code": 1}}"#,
+    )
+    .unwrap();
+
+    rloc()
+        .args(["check", "--max-code", "10", "--max-lang", "Rust=2"])
+        .arg(temp.path().join("main.rs"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok   total code: 2 (limit 10)"));
+
+    rloc()
+        .args(["check", "--max-lang", "rust=1"])
+        .arg(temp.path().join("main.rs"))
+        .assert()
+        .code(3)
+        .stdout(predicate::str::contains("FAIL rust code: 2 (limit 1)"))
+        .stderr(predicate::str::contains("1 of 1 budgets exceeded"));
+
+    rloc()
+        .arg("check")
+        .arg("--fail-on-growth")
+        .arg(&baseline)
+        .args(["--max-growth", "50"])
+        .arg(temp.path().join("main.rs"))
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "FAIL code growth: +100.0% (limit 50.0%)",
+        ));
+
+    // No budget: rejected while parsing, before anything is counted.
+    rloc()
+        .arg("check")
+        .arg(temp.path().join("main.rs"))
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("--max-code"));
+}
+
+#[test]
+fn test_group_headers() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("a.c"), "int a;\n").unwrap();
+    fs::write(temp.path().join("a.h"), "int b;\nint c;\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--group-headers"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("C,2,0,0,3"))
+        .stdout(predicate::str::contains("C Header").not());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--by-file", "--merge-lang", "C Header=c"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("a.h,C Header,"));
+}
+
+#[test]
+fn test_verbose_extensions() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("a.ts"), "let a = 1;\nlet b = 2;\n").unwrap();
+    fs::write(temp.path().join("b.mts"), "export {};\n").unwrap();
+
+    let output = rloc()
+        .arg(temp.path())
+        .args(["--json", "-v"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let extensions = &json["TypeScript"]["extensions"];
+    assert_eq!(extensions["ts"]["code"], 2);
+    assert_eq!(extensions["mts"]["nFiles"], 1);
+    assert!(json["SUM"].get("extensions").is_none());
+
+    rloc()
+        .arg(temp.path())
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Language / Extension"))
+        .stdout(predicate::str::contains("  mts"));
+
+    rloc()
+        .arg(temp.path())
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("extensions").not());
+}
+
+#[test]
+fn test_rename_languages() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("a.js"), "let a = 1;\n").unwrap();
+    fs::write(temp.path().join("b.ts"), "let b = 1;\nlet c = 2;\n").unwrap();
+    fs::write(temp.path().join("c.py"), "x = 1\n").unwrap();
+    fs::write(
+        temp.path().join("rloc.toml"),
+        "[rename]\nJavaScript = \"JavaScript family\"\nTypeScript = \"JavaScript family\"\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("JavaScript family,2,0,0,3"))
+        .stdout(predicate::str::contains("Python,1,0,0,1"));
+
+    // The command line wins, and per-file rows keep the detected language.
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--rename-lang", "typescript=TS"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("TS,1,0,0,2"))
+        .stdout(predicate::str::contains("JavaScript family,1,0,0,1"));
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--by-file"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("b.ts,TypeScript,"));
+}
+
+#[test]
+fn test_hook_run() {
+    let temp = TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .current_dir(temp.path())
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(args)
+            .output()
+            .is_ok_and(|out| out.status.success())
+    };
+    if !git(&["init"]) {
+        return;
+    }
+    fs::write(temp.path().join("main.rs"), "fn main() {\n}\n").unwrap();
+    assert!(git(&["add", "."]) && git(&["commit", "-qm", "init"]));
+    fs::write(
+        temp.path().join("main.rs"),
+        "fn main() {\n    a();\n    b();\n}\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("unstaged.py"), "x = 1\n").unwrap();
+    assert!(git(&["add", "main.rs"]));
+    // Only the staged content is compared against HEAD.
+    fs::write(
+        temp.path().join("main.rs"),
+        "fn main() {\n    a();\n    b();\n    c();\n    d();\n}\n",
+    )
+    .unwrap();
+
+    rloc()
+        .current_dir(temp.path())
+        .args(["hook", "run"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--max-added"));
+
+    rloc()
+        .current_dir(temp.path())
+        .args(["hook", "run", "--max-added", "2", "--max-lang", "Python=0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ok   Python code: 0 (limit 0)"))
+        .stdout(predicate::str::contains("ok   added code: 2 (limit 2)"));
+
+    rloc()
+        .current_dir(temp.path())
+        .args(["hook", "run", "--max-file-code", "3"])
+        .assert()
+        .code(3)
+        .stdout(predicate::str::contains("FAIL largest file"));
+
+    rloc()
+        .current_dir(temp.path())
+        .args(["hook", "install", "--max-added", "500"])
+        .assert()
+        .success();
+    let hook = fs::read_to_string(temp.path().join(".git/hooks/pre-commit")).unwrap();
+    assert!(hook.ends_with("exec rloc hook run --max-added 500\n"));
+}
+
+#[test]
+fn test_trend_record_and_show() {
+    let temp = TempDir::new().unwrap();
+    let src = temp.path().join("src");
+    fs::create_dir(&src).unwrap();
+    fs::write(src.join("main.rs"), "fn main() {\n}\n").unwrap();
+    let store = temp.path().join("trend.jsonl");
+
+    let record = |expected: &str| {
+        rloc()
+            .args(["trend", "record", "--store"])
+            .arg(&store)
+            .arg(&src)
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(expected.to_string()));
+    };
+    record("Recorded 2 lines of code");
+    fs::write(src.join("lib.py"), "a = 1\nb = 2\nc = 3\n").unwrap();
+    record("Recorded 5 lines of code");
+
+    rloc()
+        .args(["trend", "show", "--store"])
+        .arg(&store)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("+3"))
+        .stdout(predicate::str::contains("Python"))
+        .stdout(predicate::str::contains("▁█"));
+
+    let output = rloc()
+        .args(["--json", "trend", "show", "--last", "1", "--store"])
+        .arg(&store)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let runs = json["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0]["code"], 5);
+
+    rloc()
+        .args(["trend", "show", "--store"])
+        .arg(temp.path().join("missing.jsonl"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_snapshot_save_and_compare() {
+    let temp = TempDir::new().unwrap();
+    let src = temp.path().join("src");
+    fs::create_dir(&src).unwrap();
+    fs::write(src.join("main.rs"), "fn main() {\n}\n").unwrap();
+    fs::write(src.join("old.py"), "a = 1\n").unwrap();
+    fs::write(src.join("same.go"), "package x\n").unwrap();
+    let snap = temp.path().join("snap.json");
+
+    rloc()
+        .args(["snapshot", "save"])
+        .arg(&snap)
+        .arg(&src)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Saved 3 files"));
+
+    fs::write(src.join("main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+    fs::remove_file(src.join("old.py")).unwrap();
+    fs::write(src.join("new.sh"), "echo 1\necho 2\n").unwrap();
+
+    rloc()
+        .args(["snapshot", "compare"])
+        .arg(&snap)
+        .arg(&src)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "1 added, 1 removed, 1 changed, 1 unchanged; code +2",
+        ));
+
+    let output = rloc()
+        .args(["--json", "snapshot", "compare"])
+        .arg(&snap)
+        .arg(&src)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let changes: Vec<_> = json["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| {
+            (
+                f["change"].as_str().unwrap(),
+                f["code_delta"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(changes, [("changed", 1), ("added", 2), ("removed", -1)]);
+
+    // An edit that keeps the counts is still a change.
+    fs::write(src.join("same.go"), "package y\n").unwrap();
+    rloc()
+        .args(["snapshot", "compare"])
+        .arg(&snap)
+        .arg(&src)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "1 added, 1 removed, 2 changed, 0 unchanged; code +2",
+        ));
+
+    rloc()
+        .args(["snapshot", "compare"])
+        .arg(temp.path().join("missing.json"))
+        .arg(&src)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_annotate() {
+    let temp = TempDir::new().unwrap();
+    let file = temp.path().join("lib.rs");
+    fs::write(&file, "/// Doc\nfn a() {}\n\n// note\n").unwrap();
+
+    rloc()
+        .arg("annotate")
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 doc      | /// Doc"))
+        .stdout(predicate::str::contains("2 code     | fn a() {}"))
+        .stdout(predicate::str::contains("3 blank    |\n"))
+        .stdout(predicate::str::contains("4 comment  | // note"));
+
+    let output = rloc()
+        .args(["--json", "annotate"])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["language"], "Rust");
+    assert_eq!(json["lines"][0]["kind"], "doc_comment");
+    assert_eq!(json["lines"][3]["line"], 4);
+
+    let unknown = temp.path().join("notes.unknown");
+    fs::write(&unknown, "x\n").unwrap();
+    rloc()
+        .arg("annotate")
+        .arg(&unknown)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--force-lang"));
+    rloc()
+        .args(["--force-lang", "Python", "annotate"])
+        .arg(&unknown)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 code     | x"));
+}
+
+#[test]
+fn test_stdio_rpc() {
+    let input = concat!(
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "analyzeBuffer", "params": {"path": "a.rs", "text": "// x\nfn a() {}\n"}}"#,
+        "\n",
+        r#"{"jsonrpc": "2.0", "id": "two", "method": "detectLanguage", "params": {"path": "b.zig"}}"#,
+        "\n",
+    );
+    let output = rloc()
+        .args(["--stdio-rpc", "--force-lang", "Rust,zig"])
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let responses: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(responses[0]["id"], 1);
+    assert_eq!(responses[0]["result"]["code"], 1);
+    assert_eq!(responses[0]["result"]["comments"], 1);
+    assert_eq!(responses[1]["id"], "two");
+    assert_eq!(responses[1]["result"]["language"], "Rust");
+}
+
+#[test]
+fn test_include_exclude_globs() {
+    let temp = TempDir::new().unwrap();
+    create_test_project(temp.path());
+
+    rloc()
+        .arg(temp.path())
+        .args([
+            "--include-glob",
+            "*.{rs,py}",
+            "--exclude-glob",
+            "**/*.py",
+            "--csv",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust"))
+        .stdout(predicate::str::contains("Python").not());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--include-glob", "src/[oops"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --include-glob glob"));
+}
+
+#[test]
+fn test_group_by_path() {
+    let temp = TempDir::new().unwrap();
+    let app = temp.path().join("app");
+    let tools = temp.path().join("tools");
+    fs::create_dir_all(&app).unwrap();
+    fs::create_dir_all(&tools).unwrap();
+    fs::write(app.join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(tools.join("gen.py"), "x = 1\ny = 2\n").unwrap();
+
+    rloc()
+        .arg(&app)
+        .arg(&tools)
+        .args(["--group-by-path", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{},Rust,1,0,0,1",
+            app.display()
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{},Python,1,0,0,2",
+            tools.display()
+        )))
+        .stdout(predicate::str::contains("Total,SUM,2,0,0,3"));
+}
+
+#[test]
+fn test_by_project() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("crates/core/src")).unwrap();
+    fs::create_dir_all(root.join("tools")).unwrap();
+    fs::write(
+        root.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"crates/*\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("crates/core/Cargo.toml"),
+        "[package]\nname = \"core\"\n",
+    )
+    .unwrap();
+    fs::write(root.join("crates/core/src/lib.rs"), "pub fn a() {}\n").unwrap();
+    fs::write(root.join("tools/go.mod"), "module example.com/tools\n").unwrap();
+    fs::write(
+        root.join("tools/main.go"),
+        "package main\n\nfunc main() {}\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(root)
+        .args(["--by-project", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Language,Project,Files,Blank,Comment,Code",
+        ))
+        .stdout(predicate::str::contains("Rust,core,1,0,0,1"))
+        .stdout(predicate::str::contains("Go,example.com/tools,2,1,0,3"))
+        .stdout(predicate::str::contains("TOML,(no project),1,0,0,2"));
+}
+
+#[test]
+fn test_doc_comments_column() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("lib.rs"),
+        "//! Crate\n/// Item\n// note\npub fn x() {}\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--columns", "comments,doc-comments"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Language,Comment,Doc"))
+        .stdout(predicate::str::contains("Rust,3,2"));
+}
+
+#[test]
+fn test_logical_lines() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("main.c"),
+        "int main() {\n    int a = 1; int b = 2;\n    return a\n        + b;\n}\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--logical-lines", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Language,Files,Blank,Comment,Code,Logical",
+        ))
+        .stdout(predicate::str::contains("C,1,0,0,5,4"));
+}
+
+#[test]
+fn test_ignore_directives() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("a.rs"),
+        "fn a() {}\n// rloc:ignore-start\nconst T: [u8; 2] = [1, 2];\n// rloc:ignore-end\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("b.py"), "# rloc:ignore-file\nx = 1\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5 lines ignored"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--columns", "files,code,ignored"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust,1,1,3"))
+        .stdout(predicate::str::contains("Python,0,0,2"))
+        .stdout(predicate::str::contains("SUM,1,1,5"));
+
+    rloc()
+        .arg(temp.path())
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"ignored\": 5"));
+}
+
+#[test]
+fn test_long_line_limit() {
+    let temp = TempDir::new().unwrap();
+    let line = "var a = 1; ".repeat(20_000);
+    fs::write(
+        temp.path().join("app.js"),
+        format!("{}/* tail\n*/\nrun();\n", line),
+    )
+    .unwrap();
+
+    for limit in ["0", "100"] {
+        rloc()
+            .arg(temp.path())
+            .args(["--csv", "--long-line-limit", limit])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("JavaScript (minified),1,0,1,2"));
+    }
+}
+
+#[test]
+fn test_indent_stats() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("a.py"),
+        "def a():\n  if x:\n    y()\n  return 1\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("b.go"), "func b() {\n\tc()\n}\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--indent-stats"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Language,Files,Blank,Comment,Code,Tabs,Spaces,Indent",
+        ))
+        .stdout(predicate::str::contains("Python,1,0,0,4,0,3,2"))
+        .stdout(predicate::str::contains("Go,1,0,0,3,1,0,0"));
+
+    let output = rloc()
+        .arg(temp.path())
+        .args(["--json", "--indent-stats"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["Python"]["indent_width"], 2);
+    assert_eq!(json["SUM"]["indent_tabs"], 1);
+}
+
+#[test]
+fn test_comment_policy() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("up.sql"),
+        "-- migrate:up\nCREATE TABLE t (id int);\n\n",
+    )
+    .unwrap();
+    fs::write(temp.path().join("lib.rs"), "// note\nfn a() {}\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--comments-as-code", "sql"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SQL,1,1,0,2"))
+        .stdout(predicate::str::contains("Rust,1,0,1,1"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--comments-as-code", "sql", "--ignore-comments"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SQL,1,1,0,2"))
+        .stdout(predicate::str::contains("Rust,1,1,0,1"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--comments-as-code", "sqll"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Unknown language 'sqll' in --comments-as-code",
+        ));
+}
+
+#[test]
+fn test_line_endings_and_final_newline() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("mac.py"), "a = 1\r# b\rc = 3").unwrap();
+    fs::write(temp.path().join("dos.py"), "a = 1\r\nb = 2\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--line-endings"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Language,Files,Blank,Comment,Code,LF,CRLF,CR",
+        ))
+        .stdout(predicate::str::contains("Python,2,0,1,4,1,1,2"));
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--require-final-newline"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python,2,0,1,3"));
+
+    let output = rloc()
+        .arg(temp.path())
+        .args(["--by-file", "--json", "--line-endings"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let style = |name: &str| {
+        let (_, file) = json
+            .as_object()
+            .unwrap()
+            .iter()
+            .find(|(path, _)| path.ends_with(name))
+            .unwrap();
+        file["line_endings"].as_str().unwrap().to_string()
+    };
+    assert_eq!(style("mac.py"), "cr");
+    assert_eq!(style("dos.py"), "mixed");
+}
+
+#[test]
+fn test_follow_origins() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("parse.c"),
+        "/* generated */\nint state;\n#line 10 \"parse.y\"\nint action(void) { return 1; }\n\
+         #line 5 \"parse.c\"\nint table[2];\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("C,1,0,1,5"))
+        .stdout(predicate::str::contains("Yacc").not());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--follow-origins"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("C,1,0,1,4"))
+        .stdout(predicate::str::contains("Yacc,0,0,0,1"));
+}
+
+#[test]
+fn test_docstrings_as_code() {
+    let temp = TempDir::new().unwrap();
+    let xdg = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("app.py"),
+        "\"\"\"Docs.\"\"\"\nQUERY = \"\"\"\nSELECT 1\n\"\"\"\n",
+    )
+    .unwrap();
+
+    rloc()
+        .env("XDG_CONFIG_HOME", xdg.path())
+        .arg(temp.path())
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python,1,0,1,3"));
+
+    fs::write(temp.path().join("rloc.toml"), "docstrings-as-code = true\n").unwrap();
+    rloc()
+        .env("XDG_CONFIG_HOME", xdg.path())
+        .arg(temp.path().join("app.py"))
+        .current_dir(temp.path())
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python,1,0,0,4"));
+
+    rloc()
+        .env("XDG_CONFIG_HOME", xdg.path())
+        .arg(temp.path().join("app.py"))
+        .current_dir(temp.path())
+        .args(["--csv", "--docstrings-as-comments"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python,1,0,1,3"));
+}
+
+#[test]
+fn test_config_flags_can_be_negated() {
+    let temp = TempDir::new().unwrap();
+    let config = TempDir::new().unwrap();
+    fs::create_dir(temp.path().join(".hidden")).unwrap();
+    fs::write(temp.path().join(".hidden/lib.rs"), "fn main() {}\n").unwrap();
+    let config = config.path().join("rloc.toml");
+    fs::write(&config, "hidden = true\nfail-on-empty = true\n").unwrap();
+
+    let run = |args: &[&str]| {
+        let mut cmd = rloc();
+        cmd.arg(temp.path()).arg("--config").arg(&config).args(args);
+        cmd.assert()
+    };
+
+    run(&["--csv"])
+        .success()
+        .stdout(predicate::str::contains("Rust,1,0,0,1"));
+    run(&["--no-hidden"]).code(2);
+    run(&["--no-hidden", "--no-fail-on-empty"]).success();
+    // The last of a flag and its negation wins.
+    run(&["--no-hidden", "--no-fail-on-empty", "--fail-on-empty"]).code(2);
+    run(&["--hidden", "--no-hidden", "--no-fail-on-empty"]).success();
+}
+
+#[test]
+fn test_notebooks_count_embedded_languages() {
+    let temp = TempDir::new().unwrap();
+    fs::write(
+        temp.path().join("analysis.ipynb"),
+        r##"{"metadata": {"kernelspec": {"language": "python"}},
+            "cells": [{"cell_type": "markdown", "source": ["# Title\n", "Text"]},
+                      {"cell_type": "code", "source": ["import os\n", "# note\n", "os.getcwd()"]}]}"##,
+    )
+    .unwrap();
+    fs::write(
+        temp.path().join("report.Rmd"),
+        "# Report\n\n```{r}\nx <- 1\n```\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--csv", "--hide-rate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Python,0,0,1,2"))
+        .stdout(predicate::str::contains("Markdown,0,1,0,3"))
+        .stdout(predicate::str::contains("R,0,0,0,1"))
+        .stdout(predicate::str::contains("Jupyter Notebook,1,0,0,0"))
+        .stdout(predicate::str::contains("R Markdown,1,0,0,2"))
+        .stdout(predicate::str::contains("SUM,2,1,1,8"));
+}
+
+#[test]
+fn test_read_lang_def() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("a.myl"), "%% note\nvalue\n").unwrap();
+    let defs = TempDir::new().unwrap();
+    let defs_path = defs.path().join("langs.yaml");
+    fs::write(
+        &defs_path,
+        "MyLang:\n  extensions: [myl]\n  line_comments: [\"%%\"]\n",
+    )
+    .unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .arg("--read-lang-def")
+        .arg(&defs_path)
+        .args(["--csv", "--hide-rate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MyLang,1,0,1,1"));
+}
+
+#[test]
+fn test_langs_file_show_lang() {
+    let defs = TempDir::new().unwrap();
+    let defs_path = defs.path().join("langs.json");
+    fs::write(&defs_path, r#"{"MyLang": {"extensions": ["myl"]}}"#).unwrap();
+
+    rloc()
+        .arg("--langs-file")
+        .arg(&defs_path)
+        .arg("--show-lang")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MyLang (custom)"));
+}
+
+#[test]
+fn test_map_and_unmap_ext() {
+    let temp = TempDir::new().unwrap();
+    fs::write(temp.path().join("a.h"), "int x;\n").unwrap();
+    fs::write(temp.path().join("notes.txt"), "hello\n").unwrap();
+
+    rloc()
+        .arg(temp.path())
+        .args(["--map-ext", "h=c++ header", "--unmap-ext", "txt", "--csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("C++ Header,1"))
+        .stdout(predicate::str::contains("Text").not());
+
+    rloc()
+        .arg(temp.path())
+        .args(["--map-ext", "h=NoSuchLang"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown language 'NoSuchLang'"));
+}
+
+fn write_tar_gz(path: &std::path::Path, files: &[(&str, &str)]) {
+    let encoder = flate2::write::GzEncoder::new(
+        fs::File::create(path).unwrap(),
+        flate2::Compression::default(),
+    );
+    let mut builder = tar::Builder::new(encoder);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, content.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn test_diff_archives() {
+    let temp = TempDir::new().unwrap();
+    let old = temp.path().join("app-1.0.tar.gz");
+    let new = temp.path().join("app-1.1.tar.gz");
+    write_tar_gz(
+        &old,
+        &[
+            ("app-1.0/src/main.rs", "fn main() {}\n"),
+            ("app-1.0/src/lib.rs", "pub fn a() {}\n"),
+        ],
+    );
+    write_tar_gz(
+        &new,
+        &[
+            ("app-1.1/src/main.rs", "fn main() {}\n"),
+            ("app-1.1/src/lib.rs", "pub fn a() {}\npub fn b() {}\n"),
+            ("app-1.1/tool.py", "print(1)\n"),
+        ],
+    );
+
+    rloc()
+        .arg("--diff")
+        .arg(&old)
+        .arg(&new)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"Rust\s+1\s+1\s+-\s+-").unwrap())
+        .stdout(predicate::str::is_match(r"Python\s+-\s+-\s+1\s+-").unwrap());
+}
+
+#[test]
+fn test_diff_subcommand() {
+    let temp = TempDir::new().unwrap();
+    let (old, new) = (temp.path().join("old"), temp.path().join("new"));
+    for dir in [&old, &new] {
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {}\n").unwrap();
+    }
+    fs::write(old.join("lib.rs"), "pub fn a() {}\n").unwrap();
+    fs::write(new.join("lib.rs"), "pub fn a() {}\npub fn b() {}\n").unwrap();
+    fs::write(new.join("tool.py"), "print(1)\n").unwrap();
+    fs::write(new.join("scripts/dep.py"), "x = 1\ny = 2\n").unwrap();
+
+    rloc()
+        .args(["--exclude-dir", "scripts", "--by-file", "diff"])
+        .arg(&old)
+        .arg(&new)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"Rust\s+1\s+1\s+-\s+-").unwrap())
+        .stdout(predicate::str::is_match(r"lib\.rs\s+Rust\s+modified\s+1\s+2").unwrap())
+        .stdout(predicate::str::is_match(r"tool\.py\s+Python\s+added\s+-\s+1").unwrap())
+        .stdout(predicate::str::contains("dep.py").not());
+
+    // Options work after the subcommand and its paths too.
+    let output = rloc()
+        .arg("diff")
+        .arg(&old)
+        .arg(&new)
+        .args(["--json", "--exclude-dir", "scripts"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["totals"]["added"]["code"], 1);
+    assert!(json.get("files").is_none());
+
+    rloc()
+        .args(["--by-file", "--csv", "diff"])
+        .arg(&old)
+        .arg(&new)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("lib.rs,Rust,modified,0,0,1,0,0,2"))
+        .stdout(predicate::str::contains(format!(
+            "scripts{}dep.py,Python,added,,,,0,0,2",
+            std::path::MAIN_SEPARATOR
+        )));
+
+    rloc()
+        .args(["--xml", "diff"])
+        .arg(&old)
+        .arg(&new)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("diff supports"));
+}